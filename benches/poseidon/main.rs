@@ -57,14 +57,14 @@ impl<F: PrimeFieldBits + FromUniformBytes<64>> StepCircuit<ARITY, F> for TestPos
         z_in: &[AssignedCell<F, F>; ARITY],
     ) -> Result<[AssignedCell<F, F>; ARITY], SynthesisError> {
         let spec = Spec::<F, T1, RATE1>::new(R_F1, R_P1);
-        let mut pchip = PoseidonChip::new(config.pconfig, spec);
         let input = z_in.iter().map(|x| x.into()).collect::<Vec<WrapValue<F>>>();
-        pchip.update(&input);
         let output = layouter
             .assign_region(
                 || "poseidon hash",
                 |region| {
                     let ctx = &mut RegionCtx::new(region, 0);
+                    let mut pchip = PoseidonChip::new(config.pconfig.clone(), spec.clone());
+                    pchip.update(&input);
                     pchip.squeeze(ctx)
                 },
             )
@@ -1,6 +1,7 @@
 use std::mem;
 
-use halo2_proofs::plonk::Error;
+use halo2_proofs::plonk::{Any, Column, Error};
+use tracing::*;
 
 /// Wrapper for [`halo2_proofs::plonk::Error`] to
 /// impl [`PartialEq`] & [`Eq`] and be able to use
@@ -36,3 +37,34 @@ impl From<halo2_proofs::plonk::Error> for Halo2PlonkError {
         Self(value)
     }
 }
+
+/// [`halo2_proofs::plonk::Error`] with the context of the cell assignment or constraint that
+/// caused it
+///
+/// Returned by all methods of [`crate::main_gate::RegionCtx`] that touch the region, `column` is
+/// `None` for a selector. Converts back into [`halo2_proofs::plonk::Error`], logging the
+/// context, so `?` keeps working in halo2 code.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{err} while assign `{annotation}` into {} at offset {offset}",
+    .column.map_or_else(|| "selector".to_owned(), |column| format!("{column:?}"))
+)]
+pub struct AssignmentError {
+    pub err: Error,
+    pub column: Option<Column<Any>>,
+    pub offset: usize,
+    pub annotation: String,
+}
+
+impl From<AssignmentError> for halo2_proofs::plonk::Error {
+    fn from(err: AssignmentError) -> Self {
+        error!("{err}");
+        err.err
+    }
+}
+
+impl From<AssignmentError> for Halo2PlonkError {
+    fn from(err: AssignmentError) -> Self {
+        Self(err.into())
+    }
+}
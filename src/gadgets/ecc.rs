@@ -49,7 +49,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
 
     pub fn assign_from_curve<AN: Into<String>>(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         annotation: impl Fn() -> AN,
         value: &C,
     ) -> Result<AssignedPoint<C>, Error> {
@@ -62,7 +62,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
 
     pub fn assign_point<AN: Into<String>>(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         annotation: impl Fn() -> AN,
         coords: Option<(C::Base, C::Base)>,
     ) -> Result<AssignedPoint<C>, Error> {
@@ -84,7 +84,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
     // optimization here is analogous to https://github.com/arkworks-rs/r1cs-std/blob/6d64f379a27011b3629cf4c9cb38b7b7b695d5a0/src/groups/curves/short_weierstrass/mod.rs#L295
     pub fn scalar_mul(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         p0: &AssignedPoint<C>,
         scalar_bits: &[AssignedValue<C::Base>],
     ) -> Result<AssignedPoint<C>, Error> {
@@ -170,7 +170,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
     // we assume the point p0 is not infinity here
     pub fn scalar_mul_non_zero(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         p0: &AssignedPoint<C>,
         scalar_bits: &[AssignedValue<C::Base>],
     ) -> Result<AssignedPoint<C>, Error> {
@@ -247,7 +247,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
 
     pub fn negate(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         p: &AssignedPoint<C>,
     ) -> Result<AssignedPoint<C>, Error> {
         let x = p.clone().x;
@@ -264,14 +264,14 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
 
     pub fn add(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         p: &AssignedPoint<C>,
         q: &AssignedPoint<C>,
     ) -> Result<AssignedPoint<C>, Error> {
         let is_p_iden = self.main_gate.is_infinity_point(ctx, &p.x, &p.y)?;
         let is_q_iden = self.main_gate.is_infinity_point(ctx, &q.x, &q.y)?;
-        let is_equal_x = self.main_gate.is_equal_term(ctx, &p.x, &q.x)?;
-        let is_equal_y = self.main_gate.is_equal_term(ctx, &p.y, &q.y)?;
+        let is_equal_x = self.main_gate.is_equal(ctx, &p.x, &q.x)?;
+        let is_equal_y = self.main_gate.is_equal(ctx, &p.y, &q.y)?;
 
         let inf = self.assign_point(ctx, || "inf", None)?;
         let r = self._add_unsafe(ctx, p, q)?;
@@ -307,7 +307,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
 
     fn _add_unsafe(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         p: &AssignedPoint<C>,
         q: &AssignedPoint<C>,
     ) -> Result<AssignedPoint<C>, Error> {
@@ -325,7 +325,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
 
     fn double(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         p: &AssignedPoint<C>,
     ) -> Result<AssignedPoint<C>, Error> {
         let is_inf = self.main_gate.is_infinity_point(ctx, &p.x, &p.y)?;
@@ -344,11 +344,13 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
     // assume a = 0 in weierstrass curve y^2 = x^3 + ax + b
     fn _double_unsafe(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         p: &AssignedPoint<C>,
     ) -> Result<AssignedPoint<C>, Error> {
         let xp2 = self.main_gate.square(ctx, &p.x)?;
-        let lnum = self.main_gate.mul_by_const(ctx, &xp2, C::Base::from(3))?;
+        let lnum = self
+            .main_gate
+            .mul_by_constant(ctx, &xp2, C::Base::from(3))?;
         let lden = self.main_gate.add(ctx, &p.y, &p.y)?;
         let lambda = self.main_gate.divide(ctx, &lnum, &lden)?;
         let lambda2 = self.main_gate.square(ctx, &lambda)?;
@@ -363,7 +365,7 @@ impl<C: CurveAffine<Base = F>, F: PrimeFieldBits, const T: usize> EccChip<C, F,
 
     pub fn conditional_select(
         &self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         lhs: &AssignedPoint<C>,
         rhs: &AssignedPoint<C>,
         condition: &AssignedValue<C::Base>,
@@ -247,7 +247,7 @@ impl<F: PrimeField> BigUint<F> {
 
     pub fn assign_advice(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         annotation: &str,
         column: &Column<Advice>,
         limb_index: usize,
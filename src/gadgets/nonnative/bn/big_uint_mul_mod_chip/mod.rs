@@ -13,6 +13,7 @@ use tracing::*;
 
 use super::big_uint::{self, BigUint};
 use crate::{
+    error::AssignmentError,
     ff::{PrimeField, PrimeFieldBits},
     main_gate::{AssignAdviceFrom, MainGate, MainGateConfig, RegionCtx},
     util,
@@ -41,6 +42,12 @@ pub enum Error {
     },
 }
 
+impl From<AssignmentError> for Error {
+    fn from(err: AssignmentError) -> Self {
+        Self::Halo2(err.into())
+    }
+}
+
 pub const MAIN_GATE_T: usize = 4;
 
 /// Multiplication of two large natural numbers by mod
@@ -97,7 +104,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     ///     - i in [l..k)
     pub fn assign_sum<'a>(
         &self,
-        ctx: &mut RegionCtx<'a, F>,
+        ctx: &mut RegionCtx<'_, 'a, F>,
         lhs: &OverflowingBigUint<F>,
         rhs: &[impl AssignAdviceFrom<'a, F> + Clone + fmt::Debug],
     ) -> Result<SumContext<F>, Error> {
@@ -213,7 +220,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// Returns the cells that contain the product coefficients
     fn assign_mult<'a>(
         &self,
-        ctx: &mut RegionCtx<'a, F>,
+        ctx: &mut RegionCtx<'_, 'a, F>,
         lhs: &[impl AssignAdviceFrom<'a, F> + Clone + fmt::Debug],
         rhs: &[impl AssignAdviceFrom<'a, F> + Clone + fmt::Debug],
         lhs_max_word: &F,
@@ -370,7 +377,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// - `limb_shift` - (2 ^ limb_width) ^ limb_index_in_group`
     fn group_limbs(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         bignat_cells: OverflowingBigUint<F>,
         limbs_per_group: NonZeroUsize,
     ) -> Result<GroupedBigUint<F>, Error> {
@@ -540,7 +547,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// - in the last step, we check that `carry[n] = m[n]`
     fn is_equal(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         input_lhs: OverflowingBigUint<F>,
         input_rhs: OverflowingBigUint<F>,
     ) -> Result<(), Error> {
@@ -786,7 +793,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// Return cells with bits
     fn assign_and_check_bits(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         buffer: &[u8],
         expected_bits_count: NonZeroUsize,
     ) -> Result<Vec<AssignedCell<F, F>>, Error> {
@@ -858,7 +865,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// At the end, a constraint is made that the incoming cell is equal to the new counted cell.
     pub fn decompose_in_bits(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         cell: AssignedCell<F, F>,
         expected_bits_count: NonZeroUsize,
     ) -> Result<Vec<AssignedCell<F, F>>, Error> {
@@ -1038,7 +1045,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// The `s_n` it's equal with original input and check by copy constraint
     pub fn from_assigned_cell_to_limbs(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         input: &AssignedCell<F, F>,
     ) -> Result<Vec<AssignedCell<F, F>>, Error> {
         debug!("Input: {:?}", input.value().unwrap());
@@ -1183,7 +1190,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// or if the assignment of multiplication and addition fails
     pub fn mult_mod(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         lhs: &[AssignedCell<F, F>],
         rhs: &[AssignedCell<F, F>],
         mod_bn: &BigUint<F>,
@@ -1298,7 +1305,7 @@ impl<F: PrimeField> BigUintMulModChip<F> {
     /// `modulus` into `BigUint`, or if the assignment of multiplication and addition fails.
     pub fn red_mod(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         val: OverflowingBigUint<F>,
         mod_bn: &BigUint<F>,
     ) -> Result<ModOperationResult<F>, Error> {
@@ -1375,7 +1382,7 @@ impl<F: PrimeFieldBits> BigUintMulModChip<F> {
     /// Use [`MainGate::le_num_to_bits`] per each limb and concat all results
     pub fn to_le_bits(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         limbs: &[AssignedCell<F, F>],
     ) -> Result<Vec<AssignedCell<F, F>>, Error> {
         limbs.iter().try_fold(
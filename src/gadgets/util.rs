@@ -1,17 +1,106 @@
+use std::{array, cmp, collections::BTreeSet, iter};
+
 use halo2_proofs::{
     circuit::{Chip, Value},
     plonk::Error,
 };
+use tracing::*;
 
 use crate::{
-    ff::PrimeField,
-    main_gate::{AssignedValue, MainGate, RegionCtx},
+    constants::NUM_CHALLENGE_BITS,
+    ff::{FromUniformBytes, PrimeField, PrimeFieldBits},
+    main_gate::{AssignedBit, AssignedValue, MainGate, MainGateRow, RegionCtx},
+    polynomial::{ColumnIndex, Expression},
+    poseidon::ROCircuitTrait,
+    util,
 };
 
+/// `values` padded by `zero` up to `T` elements
+fn padded<V: Copy, const T: usize>(values: &[V], zero: V) -> [V; T] {
+    array::from_fn(|i| values.get(i).copied().unwrap_or(zero))
+}
+
+/// `2^n` in the field
+fn pow_of_two<F: PrimeField>(n: usize) -> F {
+    (0..n).fold(F::ONE, |acc, _| acc.double())
+}
+
+/// `limb_bits` wide limbs covering `num_bits`, the top one narrowed to the remaining bits
+fn limb_widths(limb_bits: usize, num_limbs: usize, num_bits: usize) -> Vec<usize> {
+    (0..num_limbs)
+        .map(|i| cmp::min(limb_bits, num_bits - i * limb_bits))
+        .collect()
+}
+
+/// Little-endian limbs of `a` of the given `widths`
+fn le_limbs<F: PrimeFieldBits>(a: &F, widths: &[usize]) -> Vec<F> {
+    let le_bits = a.to_le_bits().into_iter().collect::<Vec<bool>>();
+
+    let mut offset = 0;
+    widths
+        .iter()
+        .map(|width| {
+            let limb = le_bits
+                .iter()
+                .skip(offset)
+                .take(*width)
+                .rev()
+                .fold(F::ZERO, |limb, bit| limb.double() + F::from(*bit as u64));
+            offset += width;
+            limb
+        })
+        .collect()
+}
+
+/// [`le_limbs`] of an assigned value, one witness per limb
+fn le_limb_values<F: PrimeFieldBits>(a: Value<&F>, widths: &[usize]) -> Vec<Value<F>> {
+    let limbs = a.map(|a| le_limbs(a, widths));
+    (0..widths.len())
+        .map(|i| limbs.as_ref().map(|limbs| limbs[i]))
+        .collect()
+}
+
 impl<F: PrimeField, const T: usize> MainGate<F, T> {
+    /// Lay out one complete row with `state` & `input` copied from assigned cells
+    ///
+    /// Selectors are taken from `selectors`, its advice values are ignored. `out` is the witness
+    /// of the `out` cell, which is returned.
+    fn copy_row(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[&AssignedValue<F>],
+        input: Option<&AssignedValue<F>>,
+        selectors: MainGateRow<F, T>,
+        out: Value<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        let values = state
+            .iter()
+            .map(|cell| cell.value().copied())
+            .collect::<Vec<_>>();
+
+        let row = ctx.assign_row(
+            self.config(),
+            MainGateRow {
+                state: padded(&values, Value::known(F::ZERO)),
+                input: input.map_or(Value::known(F::ZERO), |input| input.value().copied()),
+                out,
+                ..selectors
+            },
+        )?;
+
+        for (copy, cell) in row.state.iter().zip(state) {
+            ctx.constrain_cells_equal(copy, cell)?;
+        }
+        if let Some(input) = input {
+            ctx.constrain_cells_equal(&row.input, input)?;
+        }
+
+        Ok(row.out)
+    }
+
     pub fn assign_value(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         a: Value<F>,
     ) -> Result<AssignedValue<F>, Error> {
         let out = ctx.assign_advice(|| "out", self.config().out, a)?;
@@ -19,246 +108,3756 @@ impl<F: PrimeField, const T: usize> MainGate<F, T> {
         Ok(out)
     }
 
+    /// Constrain `a` to be boolean, by one row
+    ///
+    /// ```markdown
+    /// a * a - a = 0
+    /// ```
+    /// Cells already known to be boolean (see [`RegionCtx::register_bit`]) cost nothing
+    pub fn assert_bit(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+    ) -> Result<(), Error> {
+        if !ctx.register_bit(a.cell()) {
+            return Ok(());
+        }
+
+        self.copy_row(
+            ctx,
+            &[a, a],
+            None,
+            MainGateRow {
+                q_m: [F::ONE, F::ZERO],
+                q_1: padded(&[-F::ONE], F::ZERO),
+                ..Default::default()
+            },
+            Value::known(F::ZERO),
+        )?;
+
+        Ok(())
+    }
+
+    /// Assign a fresh bit constrained to be boolean, by one row
     pub fn assign_bit(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: Value<F>,
-    ) -> Result<AssignedValue<F>, Error> {
-        // s0*s1 - out = 0
-        let s0 = ctx.assign_advice(|| "is_inf", self.config().state[0], a)?;
-        let s1 = ctx.assign_advice(|| "is_inf", self.config().state[0], a)?;
-        let out = ctx.assign_advice(|| "is_inf", self.config().out, a)?;
-        ctx.constrain_equal(s0.cell(), out.cell())?;
-        ctx.constrain_equal(s1.cell(), out.cell())?;
+        ctx: &mut RegionCtx<'_, '_, F>,
+        bit: Value<bool>,
+    ) -> Result<AssignedBit<F>, Error> {
+        let bit = bit.map(|bit| if bit { F::ONE } else { F::ZERO });
+
+        // bit * bit - bit = 0
+        let row = ctx.assign_row(
+            self.config(),
+            MainGateRow {
+                state: padded(&[bit, bit], Value::known(F::ZERO)),
+                q_m: [F::ONE, F::ZERO],
+                q_1: padded(&[-F::ONE], F::ZERO),
+                ..Default::default()
+            },
+        )?;
+        ctx.constrain_cells_equal(&row.state[0], &row.state[1])?;
+        ctx.register_bit(row.state[0].cell());
+
+        Ok(row.state[0].clone())
+    }
+
+    /// Boolean gate over bits `a` & `b`, by one row
+    ///
+    /// Inputs not known to be bits get a booleanity row each, see [`MainGate::assert_bit`]
+    ///
+    /// ```markdown
+    /// q_m * a * b + q_1 * (a + b) - out = 0
+    /// ```
+    fn bit_gate(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedBit<F>,
+        b: &AssignedBit<F>,
+        q_m: F,
+        q_1: F,
+    ) -> Result<AssignedBit<F>, Error> {
+        self.assert_bit(ctx, a)?;
+        self.assert_bit(ctx, b)?;
+
+        let (a_val, b_val) = (a.value().copied(), b.value().copied());
+        let out = self.copy_row(
+            ctx,
+            &[a, b],
+            None,
+            MainGateRow {
+                q_m: [q_m, F::ZERO],
+                q_1: padded(&[q_1, q_1], F::ZERO),
+                q_o: -F::ONE,
+                ..Default::default()
+            },
+            a_val * b_val * Value::known(q_m) + (a_val + b_val) * Value::known(q_1),
+        )?;
+        ctx.register_bit(out.cell());
 
-        ctx.assign_fixed(|| "q_m", self.config().q_m[0], F::ONE)?;
-        ctx.assign_fixed(|| "q_o", self.config().q_m[0], -F::ONE)?;
-        ctx.next();
         Ok(out)
     }
 
+    /// `a & b` (`a * b`), by one row
+    pub fn and(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedBit<F>,
+        b: &AssignedBit<F>,
+    ) -> Result<AssignedBit<F>, Error> {
+        self.bit_gate(ctx, a, b, F::ONE, F::ZERO)
+    }
+
+    /// `a | b` (`a + b - a * b`), by one row
+    pub fn or(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedBit<F>,
+        b: &AssignedBit<F>,
+    ) -> Result<AssignedBit<F>, Error> {
+        self.bit_gate(ctx, a, b, -F::ONE, F::ONE)
+    }
+
+    /// `a ^ b` (`a + b - 2 * a * b`), by one row
+    ///
+    /// The `2` fits into `q_m`, so unlike the two multiplications form it needs no extra row
+    pub fn xor(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedBit<F>,
+        b: &AssignedBit<F>,
+    ) -> Result<AssignedBit<F>, Error> {
+        self.bit_gate(ctx, a, b, -F::ONE.double(), F::ONE)
+    }
+
+    /// `!a` (`1 - a`), by one row plus booleanity row if `a` isn't known to be a bit
+    pub fn not(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedBit<F>,
+    ) -> Result<AssignedBit<F>, Error> {
+        self.assert_bit(ctx, a)?;
+        let out = self.affine(ctx, a, -F::ONE, F::ONE)?;
+        ctx.register_bit(out.cell());
+        Ok(out)
+    }
+
+    /// Constrain `a` to the constant `c`, by one row
+    ///
+    /// ```markdown
+    /// a - c = 0
+    /// ```
+    /// If a cell constrained to `c` is already cached in `ctx`, `a` is just copy-constrained to
+    /// it and no row is laid out. Otherwise `a` itself gets cached.
     pub fn assert_equal_const(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: AssignedValue<F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
         c: F,
     ) -> Result<(), Error> {
-        self.apply(ctx, (None, None, None), Some(c), (-F::ONE, a.into()))?;
+        if let Some(cached) = ctx.cached_constant(c).cloned() {
+            return Ok(ctx.constrain_cells_equal(a, &cached)?);
+        }
+
+        self.copy_row(
+            ctx,
+            &[a],
+            None,
+            MainGateRow {
+                q_1: padded(&[F::ONE], F::ZERO),
+                rc: -c,
+                ..Default::default()
+            },
+            Value::known(F::ZERO),
+        )?;
+        ctx.cache_constant(c, a);
+
         Ok(())
     }
 
-    // r = 1 <=> zero; r = 0 <=> non-zero
-    pub fn invert_with_flag(
+    /// Cell constrained to the constant `c`, by one row for the first use of `c` in `ctx`
+    ///
+    /// Unlike [`RegionCtx::assign_constant`] it doesn't need a constants column
+    pub fn assign_constant(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        c: F,
+    ) -> Result<AssignedValue<F>, Error> {
+        if let Some(cached) = ctx.cached_constant(c) {
+            return Ok(cached.clone());
+        }
+
+        // s[0] - c = 0
+        let row = ctx.assign_row(
+            self.config(),
+            MainGateRow {
+                state: padded(&[Value::known(c)], Value::known(F::ZERO)),
+                q_1: padded(&[F::ONE], F::ZERO),
+                rc: -c,
+                ..Default::default()
+            },
+        )?;
+        ctx.cache_constant(c, &row.state[0]);
+
+        Ok(row.state[0].clone())
+    }
+
+    /// `constant + sum_i c_i * a_i` over `terms` of `(c_i, a_i)`, by `terms.len().div_ceil(T)`
+    /// rows
+    ///
+    /// Packs `T` terms per row into the `q_1` columns and carries the partial sum through
+    /// `input`:
+    /// ```markdown
+    /// sum_j c_(T*k + j) * s[j] + acc_(k-1) - acc_k = 0
+    /// ```
+    /// `constant` goes into `rc` of the first row. Empty `terms` give the constant of
+    /// [`MainGate::assign_constant`].
+    pub fn lc(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: &[(F, AssignedValue<F>)],
+        constant: F,
+    ) -> Result<AssignedValue<F>, Error> {
+        let terms = terms.iter().map(|(c, a)| (*c, a)).collect::<Vec<_>>();
+        self.running_sum(ctx, &terms, constant)
+    }
+
+    /// `sum_i terms[i]`, by `terms.len().div_ceil(T)` rows
+    ///
+    /// [`MainGate::lc`] with all coefficients one, the unused `q_1` of the last row stay zero
+    pub fn sum(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        let terms = terms.iter().map(|a| (F::ONE, a)).collect::<Vec<_>>();
+        self.running_sum(ctx, &terms, F::ZERO)
+    }
+
+    fn running_sum(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: &[(F, &AssignedValue<F>)],
+        constant: F,
+    ) -> Result<AssignedValue<F>, Error> {
+        if terms.is_empty() {
+            return self.assign_constant(ctx, constant);
+        }
+
+        let mut acc: Option<AssignedValue<F>> = None;
+        for chunk in terms.chunks(T) {
+            let (coeffs, cells): (Vec<F>, Vec<&AssignedValue<F>>) = chunk.iter().copied().unzip();
+            let rc = if acc.is_some() { F::ZERO } else { constant };
+
+            let out = chunk.iter().fold(
+                acc.as_ref()
+                    .map_or(Value::known(rc), |acc| acc.value().copied()),
+                |sum, (c, a)| sum + a.value().map(|a| *a * c),
+            );
+
+            acc = Some(self.copy_row(
+                ctx,
+                &cells,
+                acc.as_ref(),
+                MainGateRow {
+                    q_1: padded(&coeffs, F::ZERO),
+                    q_i: if acc.is_some() { F::ONE } else { F::ZERO },
+                    q_o: -F::ONE,
+                    rc,
+                    ..Default::default()
+                },
+                out,
+            )?);
+        }
+
+        Ok(acc.expect("unreachable: at least one term"))
+    }
+
+    /// `sum_i 2^i * bits[i]`, by `bits.len().div_ceil(T)` rows
+    ///
+    /// Bits not known to be boolean get a booleanity row each, see [`MainGate::assert_bit`].
+    /// No bits give the zero of [`MainGate::assign_constant`].
+    pub fn from_le_bits(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        bits: &[AssignedBit<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        for bit in bits {
+            self.assert_bit(ctx, bit)?;
+        }
+
+        let terms = util::get_power_of_two_iter::<F>()
+            .zip(bits.iter())
+            .collect::<Vec<_>>();
+        self.running_sum(ctx, &terms, F::ZERO)
+    }
+
+    /// `sum_i 256^i * bytes[i]`, by `bytes.len().div_ceil(T)` rows
+    ///
+    /// `bytes` are assumed to be in range already (e.g. returned by [`MainGate::to_le_bytes`]),
+    /// they aren't range-checked here. No bytes give the zero of [`MainGate::assign_constant`].
+    pub fn from_le_bytes(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        bytes: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        let base = F::from(256);
+        let terms = iter::successors(Some(F::ONE), |w| Some(*w * base))
+            .zip(bytes.iter())
+            .collect::<Vec<_>>();
+        self.running_sum(ctx, &terms, F::ZERO)
+    }
+
+    /// `(is_zero, inv)` of `a`, by two rows
+    ///
+    /// ```markdown
+    /// a * inv + is_zero - 1 = 0
+    /// is_zero * a           = 0
+    /// ```
+    /// For non-zero `a` it forces `is_zero = 0` & `inv = 1/a`, for zero `a` it forces
+    /// `is_zero = 1` and `inv` is unconstrained, its witness is zero
+    pub fn invert(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: AssignedValue<F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
     ) -> Result<(AssignedValue<F>, AssignedValue<F>), Error> {
-        let (one, zero) = (F::ONE, F::ZERO);
-        let (r, a_inv) = a
-            .value()
-            .map(|a| {
-                Option::from(a.invert())
-                    .map(|a_inverted| (zero, a_inverted))
-                    .unwrap_or_else(|| (one, one))
+        let (is_zero, inv) = Self::invert_witness(a, F::ZERO);
+        self.invert_rows(ctx, a, is_zero, inv)
+    }
+
+    /// `(is_zero, inv)` witnesses of [`MainGate::invert`], with `zero_inv` as `inv` of zero `a`
+    fn invert_witness(a: &AssignedValue<F>, zero_inv: F) -> (Value<F>, Value<F>) {
+        a.value()
+            .map(|a| match Option::<F>::from(a.invert()) {
+                Some(inv) => (F::ZERO, inv),
+                None => (F::ONE, zero_inv),
             })
-            .unzip();
+            .unzip()
+    }
 
-        let r = self.assign_bit(ctx, r)?;
-        let a_inv = self.assign_value(ctx, a_inv)?;
+    fn invert_rows(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        is_zero: Value<F>,
+        inv: Value<F>,
+    ) -> Result<(AssignedValue<F>, AssignedValue<F>), Error> {
+        let row = ctx.assign_row(
+            self.config(),
+            MainGateRow {
+                state: padded(&[a.value().copied(), inv], Value::known(F::ZERO)),
+                input: is_zero,
+                q_m: [F::ONE, F::ZERO],
+                q_i: F::ONE,
+                rc: -F::ONE,
+                ..Default::default()
+            },
+        )?;
+        ctx.constrain_cells_equal(&row.state[0], a)?;
+        let (inv, is_zero) = (row.state[1].clone(), row.input);
 
-        // a * a' = 1 - r
-        let state = Some(vec![a.clone().into(), a_inv.clone().into()]);
-        let state_terms = (None, Some(vec![F::ONE]), state);
-        self.apply(ctx, state_terms, Some(-F::ONE), (F::ONE, r.clone().into()))?;
+        self.copy_row(
+            ctx,
+            &[&is_zero, a],
+            None,
+            MainGateRow {
+                q_m: [F::ONE, F::ZERO],
+                ..Default::default()
+            },
+            Value::known(F::ZERO),
+        )?;
 
-        // r * a' = r
-        let state = Some(vec![r.clone().into(), a_inv.clone().into()]);
-        let state_terms = (None, Some(vec![F::ONE]), state);
-        self.apply(ctx, state_terms, None, (-F::ONE, r.clone().into()))?;
-        Ok((r, a_inv))
+        Ok((is_zero, inv))
     }
 
-    pub fn is_zero_term(
+    /// `1` if `a` is zero, `0` otherwise, by two rows
+    ///
+    /// This is the `is_zero` flag of [`MainGate::invert`]: its rows already force the flag to be
+    /// `0` or `1`, so no extra booleanity row is needed
+    pub fn is_zero(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: AssignedValue<F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        let (r, _) = self.invert_with_flag(ctx, a)?;
-        Ok(r)
+        let (is_zero, _) = self.invert(ctx, a)?;
+        Ok(is_zero)
     }
 
-    pub fn is_equal_term(
+    /// `1` if `a == b`, `0` otherwise, by three rows
+    pub fn is_equal(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         a: &AssignedValue<F>,
         b: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
         let diff = self.sub(ctx, a, b)?;
-        self.is_zero_term(ctx, diff)
+        self.is_zero(ctx, &diff)
     }
 
-    // cond must be either 0 or 1 (e.g. return value from is_zero_term)
-    // require T >= 4
-    pub fn conditional_select(
+    /// `cond * a + (1 - cond) * b`, i.e. `a` if `cond` is one and `b` if it's zero
+    ///
+    /// `cond` is assumed to be boolean (e.g. returned by [`MainGate::is_zero`]), it isn't
+    /// constrained here.
+    ///
+    /// Costs one row for `T >= 4`, where both products fit into the gate:
+    /// ```markdown
+    /// cond * a - cond * b + b - out = 0
+    /// ```
+    /// and two rows otherwise (`a - b`, then [`MainGate::mul_add`] by `cond` with `b`).
+    pub fn select(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        cond: &AssignedValue<F>,
         a: &AssignedValue<F>,
         b: &AssignedValue<F>,
-        cond: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        let state = Some(vec![a.into(), cond.into(), b.into(), cond.into()]);
-        // val = cond * a + (1-cond) * b
-        let val = cond.value().copied() * a.value().copied()
-            + (Value::known(F::ONE) - cond.value().copied()) * b.value().copied();
-        self.apply(
+        if T < 4 {
+            let diff = self.sub(ctx, a, b)?;
+            return self.mul_add(ctx, cond, &diff, b);
+        }
+
+        let (cond_val, a_val, b_val) = (
+            cond.value().copied(),
+            a.value().copied(),
+            b.value().copied(),
+        );
+        self.copy_row(
             ctx,
-            (
-                Some(vec![F::ZERO, F::ZERO, F::ONE]),
-                Some(vec![F::ONE, -F::ONE]),
-                state,
-            ),
-            None,
-            (-F::ONE, val.into()),
+            &[cond, a, cond, b],
+            Some(b),
+            MainGateRow {
+                q_m: [F::ONE, -F::ONE],
+                q_i: F::ONE,
+                q_o: -F::ONE,
+                ..Default::default()
+            },
+            cond_val * a_val + (Value::known(F::ONE) - cond_val) * b_val,
         )
     }
 
+    /// [`MainGate::select`] with `cond` last
+    pub fn conditional_select(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        cond: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        self.select(ctx, cond, a, b)
+    }
+
+    /// `(a, b)` if `cond` is zero and `(b, a)` if it's one
+    ///
+    /// Both outputs are [`MainGate::select`] over the same `cond`, which is constrained to be
+    /// boolean once
+    pub fn cond_swap(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        cond: &AssignedValue<F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+    ) -> Result<(AssignedValue<F>, AssignedValue<F>), Error> {
+        self.assert_bit(ctx, cond)?;
+
+        Ok((self.select(ctx, cond, b, a)?, self.select(ctx, cond, a, b)?))
+    }
+
     // is_inf => 1, otherwise => 0
     pub fn is_infinity_point(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         x: &AssignedValue<F>,
         y: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        let r1 = self.is_zero_term(ctx, x.clone())?;
-        let r2 = self.is_zero_term(ctx, y.clone())?;
+        let r1 = self.is_zero(ctx, x)?;
+        let r2 = self.is_zero(ctx, y)?;
         self.mul(ctx, &r1, &r2)
     }
 
+    /// Constrain `a` to be non-zero, by one row
+    ///
+    /// ```markdown
+    /// a * a_inv - 1 = 0
+    /// ```
+    /// For zero `a` the inverse witness is unknown, so synthesis of the proof fails instead of
+    /// panicking
     pub fn assert_not_zero(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: AssignedValue<F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
     ) -> Result<(), Error> {
-        let r = self.is_zero_term(ctx, a)?;
-        // r = 0
-        self.assert_equal_const(ctx, r, F::ZERO)?;
+        let a_inv = match a.value().unwrap() {
+            Some(a) => Option::<F>::from(a.invert()).map_or(Value::unknown(), Value::known),
+            None => Value::unknown(),
+        };
+
+        self.assert_inverse(ctx, a, a_inv)?;
         Ok(())
     }
 
+    fn assert_inverse(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        a_inv: Value<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        let row = ctx.assign_row(
+            self.config(),
+            MainGateRow {
+                state: padded(&[a.value().copied(), a_inv], Value::known(F::ZERO)),
+                q_m: [F::ONE, F::ZERO],
+                rc: -F::ONE,
+                ..Default::default()
+            },
+        )?;
+        ctx.constrain_cells_equal(&row.state[0], a)?;
+
+        Ok(row.state[1].clone())
+    }
+
     pub fn assert_not_equal(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         a: &AssignedValue<F>,
         b: &AssignedValue<F>,
     ) -> Result<(), Error> {
         let diff = self.sub(ctx, a, b)?;
-        self.assert_not_zero(ctx, diff)?; // We know diff is not None
-        Ok(())
+        self.assert_not_zero(ctx, &diff)
     }
 
+    /// `a + b`, by one row
     pub fn add(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         a: &AssignedValue<F>,
         b: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        // a + b = r
-        let q_1 = Some(vec![F::ONE, F::ONE]);
-        let state = Some(vec![a.clone().into(), b.clone().into()]);
-        let state_terms = (q_1, None, state);
-        let out_val = a.value().copied() + b.value().copied();
-        let out_terms = (-F::ONE, out_val.into());
-        let out = self.apply(ctx, state_terms, None, out_terms)?;
-        Ok(out)
+        // a + b - out = 0
+        self.copy_row(
+            ctx,
+            &[a, b],
+            None,
+            MainGateRow {
+                q_1: padded(&[F::ONE, F::ONE], F::ZERO),
+                q_o: -F::ONE,
+                ..Default::default()
+            },
+            a.value().copied() + b.value().copied(),
+        )
     }
 
+    /// `a - b`, by one row
     pub fn sub(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         a: &AssignedValue<F>,
         b: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        // a - b = r
-        let q_1 = Some(vec![F::ONE, -F::ONE]);
-        let state = Some(vec![a.clone().into(), b.clone().into()]);
-        let state_terms = (q_1, None, state);
-        let out_val = a.value().copied() - b.value().copied();
-        let out_terms = (-F::ONE, out_val.into());
-        let out = self.apply(ctx, state_terms, None, out_terms)?;
-        Ok(out)
+        // a - b - out = 0
+        self.copy_row(
+            ctx,
+            &[a, b],
+            None,
+            MainGateRow {
+                q_1: padded(&[F::ONE, -F::ONE], F::ZERO),
+                q_o: -F::ONE,
+                ..Default::default()
+            },
+            a.value().copied() - b.value().copied(),
+        )
+    }
+
+    /// `-a`, by one row
+    pub fn neg(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        // -a - out = 0
+        self.copy_row(
+            ctx,
+            &[a],
+            None,
+            MainGateRow {
+                q_1: padded(&[-F::ONE], F::ZERO),
+                q_o: -F::ONE,
+                ..Default::default()
+            },
+            -a.value().copied(),
+        )
     }
 
+    /// `a * b`, by one row
+    ///
+    /// `a` & `b` may be the same cell, in which case both `state[0]` & `state[1]` are copied from
+    /// it
     pub fn mul(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         a: &AssignedValue<F>,
         b: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        let state = Some(vec![a.clone().into(), b.clone().into()]);
-        let state_terms = (None, Some(vec![F::ONE]), state);
-        let out_val = a.value().copied() * b.value().copied();
-        let out_terms = (-F::ONE, out_val.into());
-        let out = self.apply(ctx, state_terms, None, out_terms)?;
-        Ok(out)
+        // a * b - out = 0
+        self.copy_row(
+            ctx,
+            &[a, b],
+            None,
+            MainGateRow {
+                q_m: [F::ONE, F::ZERO],
+                q_o: -F::ONE,
+                ..Default::default()
+            },
+            a.value().copied() * b.value().copied(),
+        )
+    }
+
+    /// `a * b + c`, by one row
+    ///
+    /// `a` & `b` are copied into `state[0]` & `state[1]`, `c` into `input`
+    pub fn mul_add(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        c: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        // a * b + c - out = 0
+        self.copy_row(
+            ctx,
+            &[a, b],
+            Some(c),
+            MainGateRow {
+                q_m: [F::ONE, F::ZERO],
+                q_i: F::ONE,
+                q_o: -F::ONE,
+                ..Default::default()
+            },
+            a.value().copied() * b.value().copied() + c.value().copied(),
+        )
+    }
+
+    /// `a^5`, the quintic S-box, by one row through `q_5`
+    pub fn pow5(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        self.pow5_with_constant(ctx, a, F::ZERO)
     }
 
-    /// Add `lhs` assigned value to `rhs` constant
+    /// `a^5 + rc` for constant `rc`, by one row
     ///
-    /// By one row with simple expression
     /// ```markdown
-    /// input * q_i + output * q_o + rc  = 0
-    /// lhs   * 1   + sum    * -1  + rhs = 0
+    /// q_5[0] * s[0]^5 + rc + q_o * out = 0
+    /// 1      * a^5    + rc + -1  * out = 0
     /// ```
-    pub fn add_with_const(
+    pub fn pow5_with_constant(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        rc: F,
+    ) -> Result<AssignedValue<F>, Error> {
+        self.copy_row(
+            ctx,
+            &[a],
+            None,
+            MainGateRow {
+                q_5: padded(&[F::ONE], F::ZERO),
+                q_o: -F::ONE,
+                rc,
+                ..Default::default()
+            },
+            a.value().map(|a| a.pow_vartime([5]) + rc),
+        )
+    }
+
+    /// `a^e` by square-and-multiply over [`MainGate::mul`]
+    ///
+    /// Costs `floor(log2(e))` squarings plus one multiplication per set bit of `e` below the
+    /// top one, so at most `2 * floor(log2(e))` rows. `e = 1` returns `a` itself and `e = 0` the
+    /// constant one of [`MainGate::assign_constant`], which takes a row only if it isn't cached.
+    pub fn pow_const(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        lhs: &AssignedValue<F>,
-        rhs: F,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        e: u64,
     ) -> Result<AssignedValue<F>, Error> {
-        let config = self.config();
-        ctx.assign_fixed(|| "q_i", config.q_i, F::ONE)?;
-        ctx.assign_fixed(|| "q_o", config.q_o, -F::ONE)?;
+        if e == 0 {
+            return self.assign_constant(ctx, F::ONE);
+        }
+
+        let top = u64::BITS - 1 - e.leading_zeros();
+        (0..top).rev().try_fold(a.clone(), |acc, i| {
+            let acc = self.square(ctx, &acc)?;
+            match (e >> i) & 1 {
+                1 => self.mul(ctx, &acc, a),
+                _ => Ok(acc),
+            }
+        })
+    }
 
-        let assigned_lhs =
-            ctx.assign_advice_from(|| "lhs for sum with const", config.input, lhs)?;
-        let assigned_rhs = ctx.assign_fixed(|| "rhs for sum with const", config.rc, rhs)?;
+    /// `sum_i a[i] * b[i]`, by one row per term
+    ///
+    /// The first row is [`MainGate::mul`], the following ones [`MainGate::mul_add`] with the
+    /// accumulator as addend. Empty inputs give the constant zero of
+    /// [`MainGate::assign_constant`]. Fails with [`Error::Synthesis`] on length mismatch.
+    pub fn inner_product(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &[AssignedValue<F>],
+        b: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        if a.len() != b.len() {
+            error!(
+                "inner product of vectors of different lengths: {} & {}",
+                a.len(),
+                b.len()
+            );
+            return Err(Error::Synthesis);
+        }
 
-        let sum = assigned_lhs.value().copied() + assigned_rhs.value();
-        let assigned_res = ctx.assign_advice(|| "result for sum with const", config.input, sum)?;
+        let mut terms = a.iter().zip(b.iter());
+        let Some((a0, b0)) = terms.next() else {
+            return self.assign_constant(ctx, F::ZERO);
+        };
 
-        ctx.next();
-        Ok(assigned_res)
+        let first = self.mul(ctx, a0, b0)?;
+        terms.try_fold(first, |acc, (a, b)| self.mul_add(ctx, a, b, &acc))
     }
 
-    pub fn mul_by_const(
+    /// `prod_i terms[i]`, by `terms.len() - 1` [`MainGate::mul`] rows
+    ///
+    /// A single term is returned as is, no terms give the constant one of
+    /// [`MainGate::assign_constant`]
+    pub fn product(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: &AssignedValue<F>,
-        b: F,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: &[AssignedValue<F>],
     ) -> Result<AssignedValue<F>, Error> {
-        let state = Some(vec![a.clone().into()]);
-        let state_terms = (Some(vec![b]), None, state);
-        let out_val = a.value().copied() * Value::known(b);
-        let out_terms = (-F::ONE, out_val.into());
-        let out = self.apply(ctx, state_terms, None, out_terms)?;
-        Ok(out)
+        let Some((first, rest)) = terms.split_first() else {
+            return self.assign_constant(ctx, F::ONE);
+        };
+
+        rest.iter()
+            .try_fold(first.clone(), |acc, term| self.mul(ctx, &acc, term))
     }
 
-    pub fn square(
+    /// Inverses of all `terms`, by one row per term
+    ///
+    /// The inverses are witnessed by Montgomery's trick, with a single native inversion, and
+    /// each is checked by its own `t * t_inv - 1 = 0` row (see [`MainGate::assert_not_zero`]).
+    /// That's cheaper in rows than redoing the trick in-circuit (`3n - 2` rows) or
+    /// [`MainGate::invert`] per term (`2n` rows). Any zero term makes the circuit
+    /// unsatisfiable.
+    pub fn batch_invert(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: &AssignedValue<F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: &[AssignedValue<F>],
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        let inverses = terms
+            .iter()
+            .map(|term| term.value().unwrap().copied())
+            .collect::<Option<Vec<F>>>()
+            .map(|values| {
+                let prefix = values
+                    .iter()
+                    .scan(F::ONE, |acc, value| {
+                        *acc *= value;
+                        Some(*acc)
+                    })
+                    .collect::<Vec<_>>();
+
+                // zero inverse for zero product, so the check rows fail
+                let mut acc_inv = prefix
+                    .last()
+                    .map_or(F::ONE, |product| product.invert().unwrap_or(F::ZERO));
+
+                let mut inverses = vec![F::ZERO; values.len()];
+                for i in (0..values.len()).rev() {
+                    inverses[i] = acc_inv * if i > 0 { prefix[i - 1] } else { F::ONE };
+                    acc_inv *= values[i];
+                }
+                inverses
+            });
+
+        terms
+            .iter()
+            .enumerate()
+            .map(|(i, term)| {
+                let inv = inverses
+                    .as_ref()
+                    .map_or(Value::unknown(), |inverses| Value::known(inverses[i]));
+                self.assert_inverse(ctx, term, inv)
+            })
+            .collect()
+    }
+
+    /// `sum_i coeffs[i] * x^i`, coefficients lowest degree first, by `coeffs.len() - 1`
+    /// [`MainGate::mul_add`] rows in Horner form
+    ///
+    /// Both coefficients & `x` are assigned cells, so `x` can be a witnessed challenge. A single
+    /// coefficient is returned as is, no coefficients give the constant zero of
+    /// [`MainGate::assign_constant`].
+    pub fn horner(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        coeffs: &[AssignedValue<F>],
+        x: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        self.mul(ctx, a, a)
+        let Some((highest, rest)) = coeffs.split_last() else {
+            return self.assign_constant(ctx, F::ZERO);
+        };
+
+        rest.iter().rev().try_fold(highest.clone(), |acc, coeff| {
+            self.mul_add(ctx, &acc, x, coeff)
+        })
     }
 
-    pub fn divide(
+    /// `[1, r, r^2, ..., r^{n-1}]` for a witnessed challenge `r`
+    ///
+    /// One comes from [`MainGate::assign_constant`], `r` is returned as is and every higher power
+    /// is a [`MainGate::mul`] row by `r` against the previous one, so `n - 2` rows plus the
+    /// constant one for `n >= 2`.
+    pub fn powers(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        a: &AssignedValue<F>,
-        b: &AssignedValue<F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        r: &AssignedValue<F>,
+        n: usize,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        let mut powers = Vec::with_capacity(n);
+        if n == 0 {
+            return Ok(powers);
+        }
+
+        powers.push(self.assign_constant(ctx, F::ONE)?);
+        if n > 1 {
+            powers.push(r.clone());
+        }
+        while powers.len() < n {
+            let next = self.mul(ctx, &powers[powers.len() - 1], r)?;
+            powers.push(next);
+        }
+
+        Ok(powers)
+    }
+
+    /// `sum_i terms[i] * r^i` absorbing two terms per row, same result as [`MainGate::horner`]
+    ///
+    /// `r` is a witness, so its powers can't live in fixed columns: `r^2` is assigned once and
+    /// the terms are folded in pairs, Horner-style in `r^2`:
+    /// ```markdown
+    /// acc * r^2 + r * terms[2j + 1] + terms[2j] - out = 0
+    /// ```
+    /// The gate has only two products, so for `T >= 4` it costs `1 + terms.len() / 2` rows for
+    /// three or more terms (more state columns don't help), otherwise it falls back to
+    /// [`MainGate::horner`].
+    pub fn rlc_wide(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: &[AssignedValue<F>],
+        r: &AssignedValue<F>,
     ) -> Result<AssignedValue<F>, Error> {
-        let (_, b_inv) = self.invert_with_flag(ctx, b.clone())?;
-        self.mul(ctx, a, &b_inv)
+        let n = terms.len();
+        if T < 4 || n < 3 {
+            return self.horner(ctx, terms, r);
+        }
+
+        let r_square = self.square(ctx, r)?;
+
+        let (acc, rest) = if n % 2 == 1 {
+            (terms[n - 1].clone(), &terms[..n - 1])
+        } else {
+            (
+                self.mul_add(ctx, &terms[n - 1], r, &terms[n - 2])?,
+                &terms[..n - 2],
+            )
+        };
+
+        rest.chunks_exact(2).rev().try_fold(acc, |acc, pair| {
+            let (lo, hi) = (&pair[0], &pair[1]);
+            self.copy_row(
+                ctx,
+                &[&acc, &r_square, r, hi],
+                Some(lo),
+                MainGateRow {
+                    q_m: [F::ONE, F::ONE],
+                    q_i: F::ONE,
+                    q_o: -F::ONE,
+                    ..Default::default()
+                },
+                acc.value().copied() * r_square.value().copied()
+                    + r.value().copied() * hi.value().copied()
+                    + lo.value().copied(),
+            )
+        })
+    }
+
+    /// `v[j] + r * w[j]` element-wise, by one [`MainGate::mul_add`] row per element
+    ///
+    /// Fails with [`Error::Synthesis`] on length mismatch
+    pub fn fold_vectors(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        v: &[AssignedValue<F>],
+        w: &[AssignedValue<F>],
+        r: &AssignedValue<F>,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        if v.len() != w.len() {
+            error!(
+                "fold of vectors of different lengths: {} & {}",
+                v.len(),
+                w.len()
+            );
+            return Err(Error::Synthesis);
+        }
+
+        v.iter()
+            .zip(w.iter())
+            .map(|(v, w)| self.mul_add(ctx, w, r, v))
+            .collect()
+    }
+
+    /// `sum_j r^j * (sum_k r^k * vectors[k][j])`: the vectors folded element-wise under `r` by
+    /// [`MainGate::fold_vectors`], then combined by [`MainGate::rlc_assigned`] under the same `r`
+    ///
+    /// No vectors give the constant zero of [`MainGate::assign_constant`]. Fails with
+    /// [`Error::Synthesis`] if the vectors have different lengths.
+    pub fn rlc_many(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        vectors: &[Vec<AssignedValue<F>>],
+        r: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        let Some((last, rest)) = vectors.split_last() else {
+            return self.assign_constant(ctx, F::ZERO);
+        };
+
+        let folded = rest
+            .iter()
+            .rev()
+            .try_fold(last.clone(), |acc, v| self.fold_vectors(ctx, v, &acc, r))?;
+
+        self.rlc_assigned(ctx, &folded, r)
+    }
+
+    /// Constrain `a` & `b` to be permutations of each other by the grand-product argument
+    /// `prod_i (a[i] + gamma) = prod_i (b[i] + gamma)`
+    ///
+    /// Sound only if `gamma` is derived from `a` & `b` after they're fixed (e.g. squeezed from a
+    /// transcript that absorbed both, see [`MainGate::assert_permutation_with_ro`]): a prover who
+    /// knows `gamma` in advance can pick non-permuted vectors with equal products. Fails with
+    /// [`Error::Synthesis`] on length mismatch.
+    pub fn assert_permutation(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &[AssignedValue<F>],
+        b: &[AssignedValue<F>],
+        gamma: &AssignedValue<F>,
+    ) -> Result<(), Error> {
+        if a.len() != b.len() {
+            error!(
+                "permutation of vectors of different lengths: {} & {}",
+                a.len(),
+                b.len()
+            );
+            return Err(Error::Synthesis);
+        }
+
+        let mut grand_product = |values: &[AssignedValue<F>]| {
+            let shifted = values
+                .iter()
+                .map(|value| self.add(ctx, value, gamma))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.product(ctx, &shifted)
+        };
+
+        let lhs = grand_product(a)?;
+        let rhs = grand_product(b)?;
+        Ok(ctx.constrain_cells_equal(&lhs, &rhs)?)
+    }
+
+    /// Evaluate `expr` over assigned cells: `Z_i` (a [`Expression::Polynomial`] with
+    /// `index: i`) is read from `vars[i]` and `r_i` from `challenges[i]`
+    ///
+    /// The expression tree is walked as is, one row per sum, product, negation or scaling and
+    /// one per distinct constant, so common subexpressions are evaluated every time they occur.
+    /// Fails with [`Error::Synthesis`] if `vars` or `challenges` don't cover every index of
+    /// `expr`, or if it queries a rotation other than the current row or a halo2 challenge.
+    pub fn eval_expression(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        expr: &Expression<F>,
+        vars: &[AssignedValue<F>],
+        challenges: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        let mut indexes = BTreeSet::new();
+        expr.poly_set(&mut indexes);
+
+        for index in indexes {
+            let covered = match index {
+                ColumnIndex::Polynominal {
+                    rotation: 0,
+                    column_index,
+                } => column_index < vars.len(),
+                ColumnIndex::Polynominal { .. } => false,
+                ColumnIndex::Challenge { column_index } => column_index < challenges.len(),
+                ColumnIndex::Halo2Challenge { .. } => false,
+            };
+
+            if !covered {
+                error!(
+                    "{index:?} of expression isn't covered by {} vars & {} challenges",
+                    vars.len(),
+                    challenges.len()
+                );
+                return Err(Error::Synthesis);
+            }
+        }
+
+        self.eval_expression_unchecked(ctx, expr, vars, challenges)
+    }
+
+    fn eval_expression_unchecked(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        expr: &Expression<F>,
+        vars: &[AssignedValue<F>],
+        challenges: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        let eval = |ctx: &mut RegionCtx<'_, '_, F>, expr: &Expression<F>| {
+            self.eval_expression_unchecked(ctx, expr, vars, challenges)
+        };
+
+        match expr {
+            Expression::Constant(c) => self.assign_constant(ctx, *c),
+            Expression::Polynomial(query) => Ok(vars[query.index].clone()),
+            Expression::Challenge(index) => Ok(challenges[*index].clone()),
+            Expression::Halo2Challenge(index) => {
+                error!("halo2 challenge {index} of expression can't be evaluated");
+                Err(Error::Synthesis)
+            }
+            Expression::Negated(a) => {
+                let a = eval(ctx, a)?;
+                self.neg(ctx, &a)
+            }
+            Expression::Sum(a, b) => {
+                let (a, b) = (eval(ctx, a)?, eval(ctx, b)?);
+                self.add(ctx, &a, &b)
+            }
+            Expression::Product(a, b) => {
+                let (a, b) = (eval(ctx, a)?, eval(ctx, b)?);
+                self.mul(ctx, &a, &b)
+            }
+            Expression::Scaled(a, k) => {
+                let a = eval(ctx, a)?;
+                self.mul_by_constant(ctx, &a, *k)
+            }
+        }
+    }
+
+    /// `k * a + c` for constants `k` & `c`, by one row
+    ///
+    /// Constants live only in fixed columns, so the layout doesn't depend on the witness
+    pub fn affine(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        k: F,
+        c: F,
+    ) -> Result<AssignedValue<F>, Error> {
+        // k * a + c - out = 0
+        self.copy_row(
+            ctx,
+            &[a],
+            None,
+            MainGateRow {
+                q_1: padded(&[k], F::ZERO),
+                q_o: -F::ONE,
+                rc: c,
+                ..Default::default()
+            },
+            a.value().map(|a| k * a + c),
+        )
+    }
+
+    /// `a + c` for constant `c`, see [`MainGate::affine`]
+    pub fn add_constant(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        c: F,
+    ) -> Result<AssignedValue<F>, Error> {
+        self.affine(ctx, a, F::ONE, c)
+    }
+
+    /// `k * a` for constant `k`, see [`MainGate::affine`]
+    pub fn mul_by_constant(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        k: F,
+    ) -> Result<AssignedValue<F>, Error> {
+        self.affine(ctx, a, k, F::ZERO)
+    }
+
+    pub fn square(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        self.mul(ctx, a, a)
+    }
+
+    /// `a / b`, or `a` for zero `b`
+    ///
+    /// Prefer [`MainGate::div`] unless zero `b` is a legal input whose result is discarded later
+    pub fn divide(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        // the rows of [`MainGate::invert`] with one as the inverse witness of zero `b`
+        let (is_zero, b_inv) = Self::invert_witness(b, F::ONE);
+        let (_, b_inv) = self.invert_rows(ctx, b, is_zero, b_inv)?;
+        self.mul(ctx, a, &b_inv)
+    }
+
+    /// `a / b`, by four rows
+    ///
+    /// Zero `b` makes the circuit unsatisfiable: its `is_zero` flag from [`MainGate::invert`] is
+    /// constrained to zero
+    pub fn div(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        let (is_zero, b_inv) = self.invert(ctx, b)?;
+        // is_zero = 0
+        self.copy_row(
+            ctx,
+            &[&is_zero],
+            None,
+            MainGateRow {
+                q_1: padded(&[F::ONE], F::ZERO),
+                ..Default::default()
+            },
+            Value::known(F::ZERO),
+        )?;
+        self.mul(ctx, a, &b_inv)
+    }
+}
+
+impl<F: PrimeFieldBits, const T: usize> MainGate<F, T> {
+    /// Little-endian `num_bits` bits of `a`, by `num_bits + num_bits.div_ceil(T)` rows
+    ///
+    /// Each bit is assigned by [`MainGate::assign_bit`] and composed back to `a` by
+    /// [`MainGate::from_le_bits`], so an `a` that doesn't fit into `num_bits` is unsatisfiable.
+    /// For `num_bits == F::NUM_BITS` the bits are also composed into 64 bits limbs constrained
+    /// by [`MainGate::assert_limbs_canonical`], since `2^num_bits` exceeds the modulus.
+    pub fn to_le_bits(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<Vec<AssignedBit<F>>, Error> {
+        if num_bits == 0 || num_bits > F::NUM_BITS as usize {
+            error!(
+                "can't decompose into {num_bits} bits, up to {} supported",
+                F::NUM_BITS
+            );
+            return Err(Error::Synthesis);
+        }
+
+        let le_bits = a.value().map(|a| {
+            a.to_le_bits()
+                .into_iter()
+                .take(num_bits)
+                .collect::<Vec<bool>>()
+        });
+
+        self.constrain_le_bits(
+            ctx,
+            a,
+            (0..num_bits)
+                .map(|i| le_bits.as_ref().map(|bits| bits[i]))
+                .collect(),
+        )
+    }
+
+    fn constrain_le_bits(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        le_bits: Vec<Value<bool>>,
+    ) -> Result<Vec<AssignedBit<F>>, Error> {
+        let bits = le_bits
+            .into_iter()
+            .map(|bit| self.assign_bit(ctx, bit))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sum = self.from_le_bits(ctx, &bits)?;
+        ctx.constrain_cells_equal(&sum, a)?;
+
+        if bits.len() >= F::NUM_BITS as usize {
+            // `a + p` composes to `a` as well, only its canonical bits are accepted
+            let chunks = bits.chunks(u64::BITS as usize);
+            let widths = chunks.clone().map(<[_]>::len).collect::<Vec<_>>();
+            let limbs = chunks
+                .map(|chunk| self.from_le_bits(ctx, chunk))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.assert_limbs_canonical(ctx, &limbs, &widths)?;
+        }
+
+        Ok(bits)
+    }
+
+    /// `1` if `a < b`, `0` otherwise, for `a` & `b` of `num_bits` bits
+    ///
+    /// Both inputs are range-checked by [`MainGate::assert_in_range`], then
+    /// `a - b + 2^num_bits` is decomposed into `num_bits + 1` bits: its top bit is set iff
+    /// `a >= b`. Requires `num_bits + 1 < F::NUM_BITS`, so the shifted difference can't wrap.
+    pub fn lt(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<AssignedBit<F>, Error> {
+        let diff = self.shifted_diff(ctx, a, b, num_bits)?;
+        let bits = self.to_le_bits(ctx, &diff, num_bits + 1)?;
+        self.not(ctx, &bits[num_bits])
+    }
+
+    /// `1` if `a <= b`, `0` otherwise, see [`MainGate::lt`]
+    pub fn leq(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<AssignedBit<F>, Error> {
+        let gt = self.lt(ctx, b, a, num_bits)?;
+        self.not(ctx, &gt)
+    }
+
+    /// `1` if `a > b`, `0` otherwise, see [`MainGate::lt`]
+    pub fn gt(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<AssignedBit<F>, Error> {
+        self.lt(ctx, b, a, num_bits)
+    }
+
+    /// `1` if `a >= b`, `0` otherwise, see [`MainGate::lt`]
+    pub fn geq(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<AssignedBit<F>, Error> {
+        let lt = self.lt(ctx, a, b, num_bits)?;
+        self.not(ctx, &lt)
+    }
+
+    /// The smaller of `a` & `b` of `num_bits` bits, by [`MainGate::lt`] & [`MainGate::select`]
+    ///
+    /// Which input is returned depends on the witness while the permutation is fixed at keygen,
+    /// so no copy constraint can tie the result to the selected input. It's a fresh cell forced
+    /// equal to `a` or `b` by the select row, copy it to keep it in the permutation
+    pub fn min(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<AssignedValue<F>, Error> {
+        let lt = self.lt(ctx, a, b, num_bits)?;
+        self.select(ctx, &lt, a, b)
+    }
+
+    /// The larger of `a` & `b` of `num_bits` bits, see [`MainGate::min`]
+    pub fn max(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<AssignedValue<F>, Error> {
+        let lt = self.lt(ctx, a, b, num_bits)?;
+        self.select(ctx, &lt, b, a)
+    }
+
+    /// `table[index]` for `index` given by its little-endian `index_bits`
+    ///
+    /// A tree of [`MainGate::select`], one level per bit. Every bit is constrained to be boolean
+    /// by [`MainGate::assert_bit`]. If `table.len()` isn't `2^index_bits.len()`, `index` is also
+    /// constrained to be less than `table.len()` by [`MainGate::lt`], so an out-of-range index is
+    /// unsatisfiable rather than wrapping. Fails with [`Error::Synthesis`] for an empty table or
+    /// too few bits to index every entry.
+    pub fn select_from(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        index_bits: &[AssignedBit<F>],
+        table: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        let capacity = 1usize.checked_shl(index_bits.len() as u32);
+        if table.is_empty() || capacity.is_some_and(|capacity| capacity < table.len()) {
+            error!(
+                "can't select from table of {} entries by {} bits",
+                table.len(),
+                index_bits.len()
+            );
+            return Err(Error::Synthesis);
+        }
+
+        for bit in index_bits {
+            self.assert_bit(ctx, bit)?;
+        }
+
+        if capacity != Some(table.len()) {
+            let index = self.from_le_bits(ctx, index_bits)?;
+            let len = self.assign_constant(ctx, F::from(table.len() as u64))?;
+            let in_range = self.lt(ctx, &index, &len, index_bits.len())?;
+            self.assert_equal_const(ctx, &in_range, F::ONE)?;
+        }
+
+        // an odd entry out at a level has no pair, it's only reachable with the bit unset
+        let mut level = table.to_vec();
+        for bit in index_bits {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [lo, hi] => self.select(ctx, bit, hi, lo),
+                    [single] => Ok(single.clone()),
+                    _ => unreachable!("chunks of two"),
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(level.swap_remove(0))
+    }
+
+    /// `a - b + 2^num_bits` for range-checked `a` & `b`
+    fn shifted_diff(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        b: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<AssignedValue<F>, Error> {
+        if num_bits == 0 || num_bits + 1 >= F::NUM_BITS as usize {
+            error!(
+                "can't compare values of {num_bits} bits, less than {} supported",
+                F::NUM_BITS - 1
+            );
+            return Err(Error::Synthesis);
+        }
+
+        self.assert_in_range(ctx, a, num_bits)?;
+        self.assert_in_range(ctx, b, num_bits)?;
+
+        let shift = (0..num_bits).fold(F::ONE, |shift, _| shift.double());
+        // a - b + 2^num_bits - out = 0
+        self.copy_row(
+            ctx,
+            &[a, b],
+            None,
+            MainGateRow {
+                q_1: padded(&[F::ONE, -F::ONE], F::ZERO),
+                q_o: -F::ONE,
+                rc: shift,
+                ..Default::default()
+            },
+            a.value().copied() - b.value().copied() + Value::known(shift),
+        )
+    }
+
+    /// Constrain `a < 2^num_bits`, by [`MainGate::to_le_bits`] with the bits dropped
+    ///
+    /// Any field element fits into `F::NUM_BITS` bits, so it lays out nothing from there on
+    pub fn assert_in_range(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<(), Error> {
+        if num_bits >= F::NUM_BITS as usize {
+            return Ok(());
+        }
+
+        self.to_le_bits(ctx, a, num_bits)?;
+        Ok(())
+    }
+
+    /// Constrain `a < 2^num_bits` by lookups of its limbs into the range table of
+    /// [`MainGate::configure_with_range_table`]
+    ///
+    /// `a` is split into `num_bits.div_ceil(bits)` limbs of table width `bits`, the top one
+    /// narrower if `num_bits` isn't a multiple of it. The limbs are composed back by a running
+    /// sum whose rows are looked up as a whole, so it costs only
+    /// `num_bits.div_ceil(bits).div_ceil(T)` rows. A narrower top limb of width `w` costs one
+    /// more row, looking up `2^{bits - w} * limb` as well. Like [`MainGate::assert_in_range`]
+    /// it lays out nothing for `num_bits >= F::NUM_BITS`.
+    pub fn range_check_lookup(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<(), Error> {
+        let Some(range_table) = self.config().range_table else {
+            error!("range table isn't configured");
+            return Err(Error::Synthesis);
+        };
+        if num_bits >= F::NUM_BITS as usize {
+            return Ok(());
+        }
+        if num_bits == 0 {
+            error!("can't range check 0 bits");
+            return Err(Error::Synthesis);
+        }
+
+        let widths = limb_widths(
+            range_table.bits,
+            num_bits.div_ceil(range_table.bits),
+            num_bits,
+        );
+        let limbs = le_limb_values(a.value(), &widths);
+
+        let shift = pow_of_two::<F>(range_table.bits);
+        let mut weights = iter::successors(Some(F::ONE), |w| Some(*w * shift));
+
+        let mut acc: Option<AssignedValue<F>> = None;
+        let mut top = None;
+        for chunk in limbs.chunks(T) {
+            let weights = weights.by_ref().take(chunk.len()).collect::<Vec<_>>();
+            let input = acc
+                .as_ref()
+                .map_or(Value::known(F::ZERO), |acc| acc.value().copied());
+            let out = chunk
+                .iter()
+                .zip(weights.iter())
+                .fold(input, |sum, (limb, weight)| {
+                    sum + limb.map(|limb| limb * weight)
+                });
+
+            ctx.assign_fixed(|| "q_range", range_table.q_range, F::ONE)?;
+            let row = ctx.assign_row(
+                self.config(),
+                MainGateRow {
+                    state: padded(chunk, Value::known(F::ZERO)),
+                    input,
+                    out,
+                    q_1: padded(&weights, F::ZERO),
+                    q_i: if acc.is_some() { F::ONE } else { F::ZERO },
+                    q_o: -F::ONE,
+                    ..Default::default()
+                },
+            )?;
+
+            if let Some(prev) = &acc {
+                ctx.constrain_cells_equal(&row.input, prev)?;
+            }
+            top = Some(row.state[chunk.len() - 1].clone());
+            acc = Some(row.out);
+        }
+
+        ctx.constrain_cells_equal(&acc.expect("unreachable: at least one limb"), a)?;
+
+        let top_bits = widths[widths.len() - 1];
+        if top_bits < range_table.bits {
+            // both `limb` & `2^{bits - w} * limb` in the table iff `limb < 2^w`
+            let top = top.expect("unreachable: at least one limb");
+            let scale = pow_of_two::<F>(range_table.bits - top_bits);
+            ctx.assign_fixed(|| "q_range", range_table.q_range, F::ONE)?;
+            let row = ctx.assign_row(
+                self.config(),
+                MainGateRow {
+                    state: padded(
+                        &[top.value().map(|top| *top * scale)],
+                        Value::known(F::ZERO),
+                    ),
+                    out: top.value().copied(),
+                    q_1: padded(&[F::ONE], F::ZERO),
+                    q_o: -scale,
+                    ..Default::default()
+                },
+            )?;
+            ctx.constrain_cells_equal(&row.out, &top)?;
+        }
+
+        Ok(())
+    }
+
+    /// Little-endian `num_bytes` bytes of `a`
+    ///
+    /// Costs `num_bytes * (9 + 8.div_ceil(T)) + num_bytes.div_ceil(T)` rows without a range
+    /// table, `2 * num_bytes + num_bytes.div_ceil(T)` with one.
+    ///
+    /// Each byte is range-checked by the range table if configured, by
+    /// [`MainGate::assert_in_range`] otherwise, & composed back to `a` by
+    /// [`MainGate::from_le_bytes`], so an `a` that doesn't fit into `num_bytes` is
+    /// unsatisfiable. For `8 * num_bytes >= F::NUM_BITS` the bytes are also constrained by
+    /// [`MainGate::assert_limbs_canonical`], since `256^num_bytes` exceeds the modulus.
+    pub fn to_le_bytes(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        num_bytes: usize,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        let le_bits = a
+            .value()
+            .map(|a| a.to_le_bits().into_iter().collect::<Vec<bool>>());
+        let bytes = (0..num_bytes)
+            .map(|i| {
+                le_bits.as_ref().map(|bits| {
+                    bits.iter()
+                        .skip(i * 8)
+                        .take(8)
+                        .rev()
+                        .fold(F::ZERO, |byte, bit| byte.double() + F::from(*bit as u64))
+                })
+            })
+            .collect();
+
+        self.constrain_le_bytes(ctx, a, bytes)
+    }
+
+    fn constrain_le_bytes(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        bytes: Vec<Value<F>>,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        let max_bytes = (F::NUM_BITS as usize).div_ceil(8);
+        if bytes.is_empty() || bytes.len() > max_bytes {
+            error!(
+                "can't decompose into {} bytes, up to {max_bytes} supported",
+                bytes.len()
+            );
+            return Err(Error::Synthesis);
+        }
+
+        let bytes = bytes
+            .into_iter()
+            .map(|byte| {
+                let byte = self.assign_value(ctx, byte)?;
+                self.range_check_limb(ctx, &byte, 8)?;
+                Ok(byte)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let sum = self.from_le_bytes(ctx, &bytes)?;
+        ctx.constrain_cells_equal(&sum, a)?;
+
+        if bytes.len() * 8 >= F::NUM_BITS as usize {
+            // `a + p` composes to `a` as well, only its canonical bytes are accepted
+            self.assert_limbs_canonical(ctx, &bytes, &vec![8; bytes.len()])?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Little-endian limbs of `a`, `limb_bits` each, for the whole field
+    ///
+    /// `num_limbs` limbs must cover `F::NUM_BITS`, with the top limb narrowed to the remaining
+    /// bits. Every limb is range-checked by [`MainGate::range_check_lookup`] if the range table
+    /// is configured, by [`MainGate::assert_in_range`] otherwise. On top of composing back to `a`,
+    /// the limbs are constrained to be at most `p - 1` by a borrow chain against the limbs of
+    /// `p - 1`, so `a + p` can't be decomposed instead of `a`.
+    ///
+    /// Fails with [`Error::Synthesis`] if the limbs don't cover the field or the top limb would be
+    /// empty, see [`MainGate::to_limbs_bounded`] for values with fewer bits.
+    pub fn to_limbs(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        let num_bits = F::NUM_BITS as usize;
+        if limb_bits == 0
+            || num_limbs == 0
+            || limb_bits * (num_limbs - 1) >= num_bits
+            || limb_bits * num_limbs < num_bits
+        {
+            error!(
+                "can't decompose into {num_limbs} limbs of {limb_bits} bits, they must cover \
+                 {num_bits} bits with a non-empty top limb"
+            );
+            return Err(Error::Synthesis);
+        }
+
+        let widths = limb_widths(limb_bits, num_limbs, num_bits);
+        let limbs = le_limb_values(a.value(), &widths);
+        self.constrain_limbs(ctx, a, limbs, &widths)
+    }
+
+    /// [`MainGate::to_limbs`] for `a < 2^(limb_bits * num_limbs)`, a bound below the modulus
+    ///
+    /// Every limb is range-checked by `limb_bits`, which already rules out overflow, so there's
+    /// no borrow chain. An `a` that doesn't fit is unsatisfiable. Fails with
+    /// [`Error::Synthesis`] unless `limb_bits * num_limbs < F::NUM_BITS`.
+    pub fn to_limbs_bounded(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        if limb_bits == 0 || num_limbs == 0 || limb_bits * num_limbs >= F::NUM_BITS as usize {
+            error!(
+                "can't decompose into {num_limbs} limbs of {limb_bits} bits, they must fit into \
+                 {} bits",
+                F::NUM_BITS - 1
+            );
+            return Err(Error::Synthesis);
+        }
+
+        let widths = vec![limb_bits; num_limbs];
+        let limbs = le_limb_values(a.value(), &widths);
+        self.constrain_limbs(ctx, a, limbs, &widths)
+    }
+
+    fn constrain_limbs(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        limbs: Vec<Value<F>>,
+        widths: &[usize],
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        let limbs = limbs
+            .into_iter()
+            .zip(widths)
+            .map(|(limb, width)| {
+                let limb = self.assign_value(ctx, limb)?;
+                self.range_check_limb(ctx, &limb, *width)?;
+                Ok(limb)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut shift = F::ONE;
+        let terms = limbs
+            .iter()
+            .zip(widths)
+            .map(|(limb, width)| {
+                let term = (shift, limb.clone());
+                shift *= pow_of_two::<F>(*width);
+                term
+            })
+            .collect::<Vec<_>>();
+        let sum = self.lc(ctx, &terms, F::ZERO)?;
+        ctx.constrain_cells_equal(&sum, a)?;
+
+        if widths.iter().sum::<usize>() >= F::NUM_BITS as usize {
+            self.assert_limbs_canonical(ctx, &limbs, widths)?;
+        }
+
+        Ok(limbs)
+    }
+
+    /// Constrain the integer `sum_i 2^{offset_i} * limbs[i]` to be at most `p - 1`
+    ///
+    /// Subtracts the limbs from the limbs of `p - 1` with borrows, each difference is
+    /// range-checked by the width of its limb & the top one may not borrow
+    fn assert_limbs_canonical(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        limbs: &[AssignedValue<F>],
+        widths: &[usize],
+    ) -> Result<(), Error> {
+        let max_limbs = le_limbs(&-F::ONE, widths);
+
+        let mut borrow: Option<AssignedBit<F>> = None;
+        for (i, ((limb, width), max)) in limbs.iter().zip(widths).zip(max_limbs).enumerate() {
+            // `max - limb - borrow`, wraps around the field iff `limb + borrow > max`
+            let borrow_in = borrow
+                .as_ref()
+                .map_or(Value::known(F::ZERO), |borrow| borrow.value().copied());
+            let diff = borrow_in.zip(limb.value()).map(|(b, limb)| max - limb - b);
+
+            let mut terms = vec![(-F::ONE, limb.clone())];
+            terms.extend(borrow.take().map(|borrow| (-F::ONE, borrow)));
+            if i + 1 < limbs.len() {
+                let borrow_out = self.assign_bit(
+                    ctx,
+                    diff.map(|diff| diff.to_le_bits().into_iter().skip(*width).any(|bit| bit)),
+                )?;
+                terms.push((pow_of_two::<F>(*width), borrow_out.clone()));
+                borrow = Some(borrow_out);
+            }
+
+            // max - limb - borrow_in + 2^width * borrow_out in [0, 2^width)
+            let diff = self.lc(ctx, &terms, max)?;
+            self.range_check_limb(ctx, &diff, *width)?;
+        }
+
+        Ok(())
+    }
+
+    /// Constrain `a < 2^num_bits` by the range table if configured, by bits otherwise
+    fn range_check_limb(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        a: &AssignedValue<F>,
+        num_bits: usize,
+    ) -> Result<(), Error> {
+        match self.config().range_table {
+            Some(_) => self.range_check_lookup(ctx, a, num_bits),
+            None => self.assert_in_range(ctx, a, num_bits),
+        }
+    }
+}
+
+impl<F: PrimeFieldBits + FromUniformBytes<64>, const T: usize> MainGate<F, T> {
+    /// [`MainGate::assert_permutation`] with `gamma` squeezed from `ro` after absorbing `a` & `b`
+    ///
+    /// Returns `gamma`, so the caller can reuse it as a challenge
+    pub fn assert_permutation_with_ro(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        ro: &mut impl ROCircuitTrait<F>,
+        a: &[AssignedValue<F>],
+        b: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        let bits = ro
+            .absorb_iter(a.iter())
+            .absorb_iter(b.iter())
+            .squeeze_n_bits(ctx, NUM_CHALLENGE_BITS)?;
+        let gamma = self.from_le_bits(ctx, &bits)?;
+
+        self.assert_permutation(ctx, a, b, &gamma)?;
+        Ok(gamma)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp;
+
+    use halo2_proofs::{
+        circuit::{Cell, Layouter, SimpleFloorPlanner},
+        dev::{MockProver, VerifyFailure},
+        plonk::{Advice, Circuit, Column, ConstraintSystem},
+        poly::Rotation,
+    };
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        ff::Field,
+        halo2curves::pasta::Fp,
+        main_gate::{
+            tests::{assign_values, main_gate_expressions, tamper, verify_gadget, GadgetCircuit},
+            MainGateConfig,
+        },
+        polynomial::Query,
+        poseidon::{poseidon_circuit::PoseidonChip, Spec},
+        table::CircuitRunner,
+    };
+
+    const K: u32 = 5;
+
+    #[test]
+    fn add_chain() {
+        let values = [1, 2, 3, 4, 5];
+        let check = |expected: u64| {
+            verify_gadget::<2>(K, vec![Fp::from(expected)], |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &values)?;
+                let sum = cells[1..]
+                    .iter()
+                    .try_fold(cells[0].clone(), |acc, cell| main_gate.add(ctx, &acc, cell))?;
+                Ok(vec![sum])
+            })
+        };
+
+        assert_eq!(check(values.iter().sum()), Ok(()));
+        assert!(check(values.iter().sum::<u64>() + 1).is_err());
+    }
+
+    /// [`verify_gadget`] of the output of `gadget`, [`tamper`]-ed to `out + 1` if `tampered`
+    ///
+    /// The output isn't public, so only the gate of its row can reject the tampered one
+    fn verify_tampered_out<const T: usize>(
+        tampered: bool,
+        gadget: impl Clone
+            + Fn(&MainGate<Fp, T>, &mut RegionCtx<'_, '_, Fp>) -> Result<AssignedValue<Fp>, Error>,
+    ) -> Result<(), Vec<VerifyFailure>> {
+        verify_gadget::<T>(K, vec![], move |main_gate, ctx| {
+            let out = gadget(main_gate, ctx)?;
+            if tampered {
+                tamper(ctx, out.cell(), out.value().map(|value| *value + Fp::ONE))?;
+            }
+            Ok(vec![])
+        })
+    }
+
+    #[test]
+    fn add_tampered_out() {
+        let check = |tampered| {
+            verify_tampered_out::<2>(tampered, |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[2, 3])?;
+                main_gate.add(ctx, &cells[0], &cells[1])
+            })
+        };
+
+        assert_eq!(check(false), Ok(()));
+        assert!(check(true).is_err());
+    }
+
+    #[test]
+    fn sub_after_add() {
+        let check = |expected: Fp| {
+            verify_gadget::<2>(K, vec![expected], |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[2, 3, 10])?;
+                let sum = main_gate.add(ctx, &cells[0], &cells[1])?;
+                Ok(vec![main_gate.sub(ctx, &sum, &cells[2])?])
+            })
+        };
+
+        assert_eq!(check(-Fp::from(5)), Ok(()));
+        assert!(check(Fp::from(5)).is_err());
+    }
+
+    #[test]
+    fn neg() {
+        let check = |expected: [Fp; 2]| {
+            verify_gadget::<2>(K, expected.to_vec(), |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[7])?;
+                let neg = main_gate.neg(ctx, &cells[0])?;
+                let double_neg = main_gate.neg(ctx, &neg)?;
+                Ok(vec![neg, double_neg])
+            })
+        };
+
+        assert_eq!(check([-Fp::from(7), Fp::from(7)]), Ok(()));
+        assert!(check([Fp::from(7), Fp::from(7)]).is_err());
+    }
+
+    #[test]
+    fn sub_neg_tampered_out() {
+        let sub = |tampered| {
+            verify_tampered_out::<2>(tampered, |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[5, 3])?;
+                main_gate.sub(ctx, &cells[0], &cells[1])
+            })
+        };
+        let neg = |tampered| {
+            verify_tampered_out::<2>(tampered, |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[5])?;
+                main_gate.neg(ctx, &cells[0])
+            })
+        };
+
+        assert_eq!(sub(false), Ok(()));
+        assert!(sub(true).is_err());
+        assert_eq!(neg(false), Ok(()));
+        assert!(neg(true).is_err());
+    }
+
+    #[test]
+    fn mul_chain() {
+        let values = [2, 3, 5, 7, 11];
+        let check = |expected: Fp| {
+            verify_gadget::<2>(K, vec![expected], |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &values)?;
+                let product = cells[1..]
+                    .iter()
+                    .try_fold(cells[0].clone(), |acc, cell| main_gate.mul(ctx, &acc, cell))?;
+                Ok(vec![product])
+            })
+        };
+
+        let expected = values.iter().map(|v| Fp::from(*v)).product::<Fp>();
+        assert_eq!(check(expected), Ok(()));
+        assert!(check(expected + Fp::ONE).is_err());
+    }
+
+    #[test]
+    fn mul_same_cell() {
+        let check = |expected: u64| {
+            verify_gadget::<2>(K, vec![Fp::from(expected)], |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[9])?;
+                Ok(vec![main_gate.square(ctx, &cells[0])?])
+            })
+        };
+
+        assert_eq!(check(81), Ok(()));
+        assert!(check(18).is_err());
+    }
+
+    #[test]
+    fn mul_tampered_out() {
+        let check = |tampered| {
+            verify_tampered_out::<2>(tampered, |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[4, 5])?;
+                main_gate.mul(ctx, &cells[0], &cells[1])
+            })
+        };
+
+        assert_eq!(check(false), Ok(()));
+        assert!(check(true).is_err());
+    }
+
+    #[test]
+    fn mul_add() {
+        let check = |expected: u64| {
+            verify_gadget::<2>(K, vec![Fp::from(expected)], |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[3, 4, 5])?;
+                Ok(vec![
+                    main_gate.mul_add(ctx, &cells[0], &cells[1], &cells[2])?
+                ])
+            })
+        };
+
+        assert_eq!(check(17), Ok(()));
+        assert!(check(23).is_err());
+    }
+
+    #[test]
+    fn mul_add_tampered_out() {
+        let check = |tampered| {
+            verify_tampered_out::<2>(tampered, |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[3, 4, 5])?;
+                main_gate.mul_add(ctx, &cells[0], &cells[1], &cells[2])
+            })
+        };
+
+        assert_eq!(check(false), Ok(()));
+        assert!(check(true).is_err());
+    }
+
+    #[test]
+    fn mul_add_copy_constraints() {
+        fn collect_permutation(circuit: impl Circuit<Fp>) -> Vec<(usize, usize, Fp)> {
+            CircuitRunner::new(K, circuit, vec![Fp::from(17)])
+                .try_collect_plonk_structure()
+                .unwrap()
+                .permutation_matrix
+        }
+
+        let gadget = GadgetCircuit::<2, _>::new(
+            |main_gate: &MainGate<Fp, 2>,
+             ctx: &mut RegionCtx<'_, '_, Fp>|
+             -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let cells = assign_values(main_gate, ctx, &[3, 4, 5])?;
+                Ok(vec![
+                    main_gate.mul_add(ctx, &cells[0], &cells[1], &cells[2])?
+                ])
+            },
+        );
+
+        // The same row, with every input linked to its source by hand
+        let manual = GadgetCircuit::<2, _>::new(
+            |main_gate: &MainGate<Fp, 2>,
+             ctx: &mut RegionCtx<'_, '_, Fp>|
+             -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let cells = assign_values(main_gate, ctx, &[3, 4, 5])?;
+                let row = ctx.assign_row(
+                    main_gate.config(),
+                    MainGateRow {
+                        state: [Value::known(Fp::from(3)), Value::known(Fp::from(4))],
+                        input: Value::known(Fp::from(5)),
+                        out: Value::known(Fp::from(17)),
+                        q_m: [Fp::ONE, Fp::ZERO],
+                        q_i: Fp::ONE,
+                        q_o: -Fp::ONE,
+                        ..Default::default()
+                    },
+                )?;
+                ctx.constrain_cells_equal(&row.state[0], &cells[0])?;
+                ctx.constrain_cells_equal(&row.state[1], &cells[1])?;
+                ctx.constrain_cells_equal(&row.input, &cells[2])?;
+                Ok(vec![row.out])
+            },
+        );
+
+        assert_eq!(collect_permutation(gadget), collect_permutation(manual));
+    }
+
+    #[test]
+    fn affine() {
+        let check = |expected: [u64; 3]| {
+            verify_gadget::<2>(K, expected.map(Fp::from).to_vec(), |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[5])?;
+                Ok(vec![
+                    main_gate.affine(ctx, &cells[0], Fp::from(3), Fp::from(2))?,
+                    main_gate.add_constant(ctx, &cells[0], Fp::from(7))?,
+                    main_gate.mul_by_constant(ctx, &cells[0], Fp::from(4))?,
+                ])
+            })
+        };
+
+        assert_eq!(check([17, 12, 20]), Ok(()));
+        assert!(check([17, 12, 21]).is_err());
+        assert!(check([15, 12, 20]).is_err());
+    }
+
+    #[test]
+    fn affine_fixed_independent_of_witness() {
+        let collect_fixed = |value: Value<Fp>| {
+            let circuit = GadgetCircuit::<2, _>::new(
+                move |main_gate: &MainGate<Fp, 2>,
+                      ctx: &mut RegionCtx<'_, '_, Fp>|
+                      -> Result<Vec<AssignedValue<Fp>>, Error> {
+                    let a = main_gate.assign_value(ctx, value)?;
+                    let b = main_gate.affine(ctx, &a, Fp::from(1u64 << 8), Fp::from(42))?;
+                    let c = main_gate.add_constant(ctx, &b, -Fp::ONE)?;
+                    Ok(vec![main_gate.mul_by_constant(ctx, &c, Fp::from(3))?])
+                },
+            );
+
+            CircuitRunner::new(K, circuit, vec![Fp::ZERO])
+                .try_collect_plonk_structure()
+                .unwrap()
+                .fixed_columns
+        };
+
+        // keygen doesn't know witness, proving does
+        assert_eq!(
+            collect_fixed(Value::unknown()),
+            collect_fixed(Value::known(Fp::from(9)))
+        );
+    }
+
+    #[test]
+    fn invert() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let check = |a: Fp| {
+            let (is_zero, inv) =
+                Option::<Fp>::from(a.invert()).map_or((Fp::ONE, Fp::ZERO), |inv| (Fp::ZERO, inv));
+
+            verify_gadget::<2>(K, vec![is_zero, inv], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(a))?;
+                let (is_zero, inv) = main_gate.invert(ctx, &a)?;
+                Ok(vec![is_zero, inv])
+            })
+        };
+
+        for a in [Fp::ZERO, Fp::ONE, Fp::random(&mut rng)] {
+            assert_eq!(check(a), Ok(()), "{a:?}");
+        }
+    }
+
+    #[test]
+    fn invert_malicious_witness() {
+        let check = |a: u64, is_zero: u64, inv: Fp| {
+            verify_gadget::<2>(K, vec![], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(Fp::from(a)))?;
+                main_gate.invert_rows(
+                    ctx,
+                    &a,
+                    Value::known(Fp::from(is_zero)),
+                    Value::known(inv),
+                )?;
+                Ok(vec![])
+            })
+        };
+
+        let half = Fp::from(2).invert().unwrap();
+        assert_eq!(check(2, 0, half), Ok(()));
+        // zero claimed to be non-zero
+        assert!(check(0, 0, Fp::ONE).is_err());
+        // non-zero claimed to be zero
+        assert!(check(2, 1, Fp::ZERO).is_err());
+        // wrong inverse
+        assert!(check(2, 0, Fp::from(3)).is_err());
+    }
+
+    #[test]
+    fn divide() {
+        let check = |a: u64, b: u64, expected: Fp| {
+            verify_gadget::<2>(K, vec![expected], move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[a, b])?;
+                Ok(vec![main_gate.divide(ctx, &cells[0], &cells[1])?])
+            })
+        };
+
+        assert_eq!(check(6, 3, Fp::from(2)), Ok(()));
+        assert!(check(6, 3, Fp::from(3)).is_err());
+        // the inverse witness of zero is one
+        assert_eq!(check(6, 0, Fp::from(6)), Ok(()));
+    }
+
+    #[test]
+    fn div() {
+        let check = |a: u64, b: u64, expected: Fp| {
+            verify_gadget::<2>(K, vec![expected], move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[a, b])?;
+                Ok(vec![main_gate.div(ctx, &cells[0], &cells[1])?])
+            })
+        };
+
+        assert_eq!(check(6, 3, Fp::from(2)), Ok(()));
+        assert_eq!(check(1, 2, Fp::from(2).invert().unwrap()), Ok(()));
+        assert!(check(6, 3, Fp::from(3)).is_err());
+        // zero divisor is unsatisfiable, whatever the result
+        assert!(check(6, 0, Fp::ZERO).is_err());
+    }
+
+    #[test]
+    fn is_zero_is_equal() {
+        let check = |a: u64, b: u64| {
+            let expected = [a == 0, a == b].map(|flag| Fp::from(flag as u64)).to_vec();
+            verify_gadget::<2>(K, expected, move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[a, b])?;
+                Ok(vec![
+                    main_gate.is_zero(ctx, &cells[0])?,
+                    main_gate.is_equal(ctx, &cells[0], &cells[1])?,
+                ])
+            })
+        };
+
+        for (a, b) in [(0, 0), (0, 1), (1, 1), (5, 3)] {
+            assert_eq!(check(a, b), Ok(()), "{a} {b}");
+        }
+    }
+
+    #[test]
+    fn is_zero_bogus_inverse() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // is_zero = 0 for zero `a`: there is no inverse to satisfy `a * inv = 1`
+        for inv in [Fp::ZERO, Fp::ONE, Fp::random(&mut rng)] {
+            assert!(verify_gadget::<2>(K, vec![], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(Fp::ZERO))?;
+                main_gate.invert_rows(ctx, &a, Value::known(Fp::ZERO), Value::known(inv))?;
+                Ok(vec![])
+            })
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn is_equal_then_select() {
+        let check = |a: u64, b: u64, expected: u64| {
+            verify_gadget::<4>(K, vec![Fp::from(expected)], move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[a, b, 10, 20])?;
+                let cond = main_gate.is_equal(ctx, &cells[0], &cells[1])?;
+                Ok(vec![
+                    main_gate.conditional_select(ctx, &cells[2], &cells[3], &cond)?
+                ])
+            })
+        };
+
+        assert_eq!(check(3, 3, 10), Ok(()));
+        assert_eq!(check(3, 4, 20), Ok(()));
+        assert!(check(3, 4, 10).is_err());
+    }
+
+    fn select_vector<const T: usize>(
+        cond: u64,
+        expected: &[u64],
+    ) -> Result<(), Vec<VerifyFailure>> {
+        let public_inputs = expected.iter().copied().map(Fp::from).collect();
+        verify_gadget::<T>(K, public_inputs, move |main_gate, ctx| {
+            let cond = main_gate.assign_value(ctx, Value::known(Fp::from(cond)))?;
+            let lhs = assign_values(main_gate, ctx, &[4, 5, 6])?;
+            let rhs = assign_values(main_gate, ctx, &[1, 2, 3])?;
+            lhs.iter()
+                .zip(rhs.iter())
+                .map(|(a, b)| main_gate.select(ctx, &cond, a, b))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn select() {
+        assert_eq!(select_vector::<4>(1, &[4, 5, 6]), Ok(()));
+        assert_eq!(select_vector::<4>(0, &[1, 2, 3]), Ok(()));
+        assert!(select_vector::<4>(1, &[4, 2, 6]).is_err());
+
+        // two rows layout
+        assert_eq!(select_vector::<2>(1, &[4, 5, 6]), Ok(()));
+        assert_eq!(select_vector::<2>(0, &[1, 2, 3]), Ok(()));
+        assert!(select_vector::<2>(0, &[1, 5, 3]).is_err());
+    }
+
+    #[test]
+    fn select_non_boolean_cond() {
+        // cond = 2 can't pick either side, the result is pinned to `2 * a - b`
+        for expected in [[4, 5, 6], [1, 2, 3]] {
+            assert!(select_vector::<4>(2, &expected).is_err());
+            assert!(select_vector::<2>(2, &expected).is_err());
+        }
+        assert_eq!(select_vector::<4>(2, &[7, 8, 9]), Ok(()));
+        assert_eq!(select_vector::<2>(2, &[7, 8, 9]), Ok(()));
+    }
+
+    #[test]
+    fn cond_swap() {
+        let check = |cond: u64, a: u64, b: u64, expected: [u64; 2]| {
+            verify_gadget::<4>(K, expected.map(Fp::from).to_vec(), move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[cond, a, b])?;
+                let (first, second) = main_gate.cond_swap(ctx, &cells[0], &cells[1], &cells[2])?;
+                Ok(vec![first, second])
+            })
+        };
+
+        for (a, b) in [(3, 8), (8, 3)] {
+            assert_eq!(check(0, a, b, [a, b]), Ok(()));
+            assert_eq!(check(1, a, b, [b, a]), Ok(()));
+            assert!(check(0, a, b, [b, a]).is_err());
+            assert!(check(1, a, b, [b, b]).is_err());
+        }
+
+        // cond = 2 would give `(2b - a, 2a - b)` without the booleanity row
+        assert!(check(2, 5, 4, [3, 6]).is_err());
+    }
+
+    #[test]
+    fn assert_bit() {
+        let check = |value: u64| {
+            verify_gadget::<2>(K, vec![], move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[value])?;
+                main_gate.assert_bit(ctx, &cells[0])?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(0), Ok(()));
+        assert_eq!(check(1), Ok(()));
+        assert!(check(2).is_err());
+    }
+
+    #[test]
+    fn assign_bit() {
+        for bit in [false, true] {
+            assert_eq!(
+                verify_gadget::<2>(K, vec![Fp::from(bit as u64)], move |main_gate, ctx| {
+                    Ok(vec![main_gate.assign_bit(ctx, Value::known(bit))?])
+                }),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn assert_bit_cached() {
+        assert_eq!(
+            verify_gadget::<2>(K, vec![], |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[1])?;
+                main_gate.assert_bit(ctx, &cells[0])?;
+                let offset = ctx.offset();
+
+                main_gate.assert_bit(ctx, &cells[0])?;
+                assert_eq!(ctx.offset(), offset);
+
+                let bit = main_gate.assign_bit(ctx, Value::known(true))?;
+                let offset = ctx.offset();
+                main_gate.assert_bit(ctx, &bit)?;
+                assert_eq!(ctx.offset(), offset);
+
+                Ok(vec![])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn bit_logic_truth_table() {
+        for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            let expected = [a & b, a | b, a ^ b, 1 - a].map(Fp::from).to_vec();
+            assert_eq!(
+                verify_gadget::<2>(K, expected, move |main_gate, ctx| {
+                    let cells = assign_values(main_gate, ctx, &[a, b])?;
+                    let (a, b) = (&cells[0], &cells[1]);
+                    Ok(vec![
+                        main_gate.and(ctx, a, b)?,
+                        main_gate.or(ctx, a, b)?,
+                        main_gate.xor(ctx, a, b)?,
+                        main_gate.not(ctx, a)?,
+                    ])
+                }),
+                Ok(()),
+                "{a} {b}"
+            );
+        }
+    }
+
+    #[test]
+    fn bit_logic_non_boolean_input() {
+        assert!(verify_gadget::<2>(K, vec![Fp::from(2)], |main_gate, ctx| {
+            let cells = assign_values(main_gate, ctx, &[2, 1])?;
+            Ok(vec![main_gate.and(ctx, &cells[0], &cells[1])?])
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn bit_logic_adder() {
+        const K: u32 = 7;
+
+        let add = |lhs: u64, rhs: u64| {
+            let expected = (0..5).map(|i| Fp::from(((lhs + rhs) >> i) & 1)).collect();
+            verify_gadget::<2>(K, expected, move |main_gate, ctx| {
+                let bits = |value: u64| (0..4).map(move |i| Value::known((value >> i) & 1 == 1));
+                let lhs = bits(lhs)
+                    .map(|bit| main_gate.assign_bit(ctx, bit))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let rhs = bits(rhs)
+                    .map(|bit| main_gate.assign_bit(ctx, bit))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut carry = main_gate.assign_bit(ctx, Value::known(false))?;
+                let mut sum = vec![];
+                for (a, b) in lhs.iter().zip(rhs.iter()) {
+                    let half = main_gate.xor(ctx, a, b)?;
+                    sum.push(main_gate.xor(ctx, &half, &carry)?);
+                    let generate = main_gate.and(ctx, a, b)?;
+                    let propagate = main_gate.and(ctx, &half, &carry)?;
+                    carry = main_gate.or(ctx, &generate, &propagate)?;
+                }
+                sum.push(carry);
+
+                Ok(sum)
+            })
+        };
+
+        for (lhs, rhs) in [(0, 0), (5, 9), (15, 1), (15, 15)] {
+            assert_eq!(add(lhs, rhs), Ok(()), "{lhs} + {rhs}");
+        }
+    }
+
+    #[test]
+    fn le_bits_round_trip() {
+        let check = |value: Fp, num_bits: usize| {
+            verify_gadget::<2>(11, vec![value], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                let bits = main_gate.to_le_bits(ctx, &a, num_bits)?;
+                assert_eq!(bits.len(), num_bits);
+                Ok(vec![main_gate.from_le_bits(ctx, &bits)?])
+            })
+        };
+
+        assert_eq!(check(Fp::from(0b1011), 4), Ok(()));
+        assert_eq!(check(Fp::from(0b1011), 5), Ok(()));
+        assert_eq!(check(Fp::ZERO, 1), Ok(()));
+        assert_eq!(check(Fp::from(u64::MAX), 64), Ok(()));
+        assert_eq!(check(-Fp::ONE, Fp::NUM_BITS as usize), Ok(()));
+    }
+
+    #[test]
+    fn to_le_bits_overflow() {
+        let check = |value: Fp, num_bits: usize| {
+            verify_gadget::<2>(9, vec![], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                main_gate.to_le_bits(ctx, &a, num_bits)?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(Fp::from(15), 4), Ok(()));
+        assert!(check(Fp::from(16), 4).is_err());
+        assert!(check(-Fp::ONE, Fp::NUM_BITS as usize - 1).is_err());
+    }
+
+    #[test]
+    fn le_bits_non_canonical() {
+        // little-endian bits of the integer `p - 1 + n`, which is `n - 1` in the field
+        let past_modulus = |n: u64| {
+            let mut carry = n;
+            (-Fp::ONE)
+                .to_le_bits()
+                .into_iter()
+                .take(Fp::NUM_BITS as usize)
+                .map(|bit| {
+                    let sum = bit as u64 + carry;
+                    carry = sum >> 1;
+                    Value::known(sum & 1 == 1)
+                })
+                .collect::<Vec<_>>()
+        };
+        let check = |value: Fp, le_bits: Vec<Value<bool>>| {
+            verify_gadget::<2>(11, vec![], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                main_gate.constrain_le_bits(ctx, &a, le_bits.clone())?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(-Fp::ONE, past_modulus(0)), Ok(()));
+        assert!(check(Fp::ZERO, past_modulus(1)).is_err());
+        assert!(check(Fp::from(5), past_modulus(6)).is_err());
+    }
+
+    #[test]
+    fn from_le_bits() {
+        let check = |bits: &'static [u64], expected: u64| {
+            verify_gadget::<3>(K, vec![Fp::from(expected)], move |main_gate, ctx| {
+                let bits = assign_values(main_gate, ctx, bits)?;
+                Ok(vec![main_gate.from_le_bits(ctx, &bits)?])
+            })
+        };
+
+        assert_eq!(check(&[1, 0, 1, 1, 0, 1, 1], 0b1101101), Ok(()));
+        assert!(check(&[1, 0, 1, 1, 0, 1, 1], 0b1101100).is_err());
+        // not a bit
+        assert!(check(&[2, 0], 2).is_err());
+        // no bits
+        assert_eq!(check(&[], 0), Ok(()));
+    }
+
+    #[test]
+    fn from_le_bytes_empty() {
+        assert_eq!(
+            verify_gadget::<2>(K, vec![Fp::ZERO], |main_gate, ctx| {
+                Ok(vec![main_gate.from_le_bytes(ctx, &[])?])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn le_bytes_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let check = |value: Fp, num_bytes: usize| {
+            verify_gadget::<2>(10, vec![value], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                let bytes = main_gate.to_le_bytes(ctx, &a, num_bytes)?;
+                Ok(vec![main_gate.from_le_bytes(ctx, &bytes)?])
+            })
+        };
+
+        assert_eq!(check(Fp::from(0x0102_0304), 4), Ok(()));
+        assert_eq!(check(Fp::from(0xff), 1), Ok(()));
+        for _ in 0..3 {
+            assert_eq!(check(Fp::random(&mut rng), 32), Ok(()));
+        }
+
+        // overflow of `num_bytes`
+        assert!(check(Fp::from(0x0100), 1).is_err());
+        assert!(check(Fp::from(u64::MAX), 7).is_err());
+    }
+
+    #[test]
+    fn le_bytes_non_canonical() {
+        // little-endian bytes of the integer `p - 1 + n`, which is `n - 1` in the field
+        let past_modulus = |n: u64| {
+            let mut carry = n;
+            (-Fp::ONE)
+                .to_repr()
+                .as_ref()
+                .iter()
+                .map(|byte| {
+                    let sum = *byte as u64 + carry;
+                    carry = sum >> 8;
+                    Value::known(Fp::from(sum & 0xff))
+                })
+                .collect::<Vec<_>>()
+        };
+        let check = |value: Fp, bytes: Vec<Value<Fp>>| {
+            verify_gadget::<2>(10, vec![], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                main_gate.constrain_le_bytes(ctx, &a, bytes.clone())?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(-Fp::ONE, past_modulus(0)), Ok(()));
+        assert!(check(Fp::ZERO, past_modulus(1)).is_err());
+        assert!(check(Fp::from(5), past_modulus(6)).is_err());
+    }
+
+    #[test]
+    fn le_bytes_flipped_byte() {
+        let check = |flip: Option<usize>| {
+            verify_gadget::<2>(7, vec![], move |main_gate, ctx| {
+                let mut bytes = [0x04, 0x03, 0x02, 0x01].map(Fp::from);
+                if let Some(i) = flip {
+                    bytes[i] = Fp::from(0xff) - bytes[i];
+                }
+
+                let a = main_gate.assign_value(ctx, Value::known(Fp::from(0x0102_0304)))?;
+                main_gate.constrain_le_bytes(
+                    ctx,
+                    &a,
+                    bytes.into_iter().map(Value::known).collect(),
+                )?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        for i in 0..4 {
+            assert!(check(Some(i)).is_err(), "{i}");
+        }
+    }
+
+    #[test]
+    fn assert_in_range() {
+        let check = |value: Fp, num_bits: usize| {
+            verify_gadget::<2>(8, vec![], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                main_gate.assert_in_range(ctx, &a, num_bits)?;
+                Ok(vec![])
+            })
+        };
+
+        for num_bits in [1, 7, 8, 64, 128] {
+            let bound = Fp::from_u128(1 << (num_bits - 1)).double();
+            assert_eq!(check(bound - Fp::ONE, num_bits), Ok(()), "{num_bits}");
+            assert!(check(bound, num_bits).is_err(), "{num_bits}");
+        }
+    }
+
+    #[test]
+    fn assert_in_range_full_width() {
+        assert_eq!(
+            verify_gadget::<2>(K, vec![], |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(-Fp::ONE))?;
+                let offset = ctx.offset();
+                main_gate.assert_in_range(ctx, &a, Fp::NUM_BITS as usize)?;
+                assert_eq!(ctx.offset(), offset);
+                Ok(vec![])
+            }),
+            Ok(())
+        );
+    }
+
+    const RANGE_BITS: usize = 8;
+
+    /// [`GadgetCircuit`] counterpart with the range table configured & loaded
+    #[derive(Clone)]
+    struct RangeTableCircuit<S> {
+        synthesize: S,
+    }
+
+    impl<S> Circuit<Fp> for RangeTableCircuit<S>
+    where
+        S: Clone + Fn(&MainGate<Fp, 2>, &mut RegionCtx<'_, '_, Fp>) -> Result<(), Error>,
+    {
+        type Config = MainGateConfig<2>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            MainGate::configure_with_range_table(meta, RANGE_BITS)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let main_gate = MainGate::new(config);
+            main_gate.load_range_table(&mut layouter)?;
+
+            layouter.assign_region(
+                || "range check",
+                |region| (self.synthesize)(&main_gate, &mut RegionCtx::new(region, 0)),
+            )
+        }
+    }
+
+    fn range_check_lookup(value: Fp, num_bits: usize) -> Result<(), Vec<VerifyFailure>> {
+        let circuit = RangeTableCircuit {
+            synthesize: move |main_gate: &MainGate<Fp, 2>, ctx: &mut RegionCtx<'_, '_, Fp>| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                main_gate.range_check_lookup(ctx, &a, num_bits)
+            },
+        };
+
+        MockProver::run(9, &circuit, vec![])
+            .expect("synthesize of range check circuit failed")
+            .verify()
+    }
+
+    #[test]
+    fn range_check_lookup_boundary() {
+        for num_bits in [8, 16, 24, 64] {
+            let bound = Fp::from_u128(1 << (num_bits - 1)).double();
+            assert_eq!(
+                range_check_lookup(bound - Fp::ONE, num_bits),
+                Ok(()),
+                "{num_bits}"
+            );
+            assert!(range_check_lookup(bound, num_bits).is_err(), "{num_bits}");
+        }
+
+        // limbs exactly at the table boundary
+        assert_eq!(range_check_lookup(Fp::from(0xff00ff), 24), Ok(()));
+        assert_eq!(range_check_lookup(Fp::ZERO, 8), Ok(()));
+    }
+
+    #[test]
+    fn range_check_lookup_rows() {
+        let circuit = RangeTableCircuit {
+            synthesize: |main_gate: &MainGate<Fp, 2>, ctx: &mut RegionCtx<'_, '_, Fp>| {
+                let a = main_gate.assign_value(ctx, Value::known(Fp::from(u64::MAX)))?;
+
+                let offset = ctx.offset();
+                main_gate.range_check_lookup(ctx, &a, 64)?;
+                let lookup_rows = ctx.offset() - offset;
+
+                let offset = ctx.offset();
+                main_gate.assert_in_range(ctx, &a, 64)?;
+                let bits_rows = ctx.offset() - offset;
+
+                assert_eq!(lookup_rows, 4);
+                assert_eq!(bits_rows, 96);
+                Ok(())
+            },
+        };
+
+        assert_eq!(
+            MockProver::run(9, &circuit, vec![]).unwrap().verify(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn range_check_lookup_narrow_top() {
+        for num_bits in [3, 10, 12, 63] {
+            let bound = Fp::from_u128(1 << num_bits);
+            assert_eq!(
+                range_check_lookup(bound - Fp::ONE, num_bits),
+                Ok(()),
+                "{num_bits}"
+            );
+            assert!(range_check_lookup(bound, num_bits).is_err(), "{num_bits}");
+        }
+
+        // `0xff00` as 10 bits, by a top limb of `0xff` that fits into the table but not into 2 bits
+        let circuit = RangeTableCircuit {
+            synthesize: |main_gate: &MainGate<Fp, 2>, ctx: &mut RegionCtx<'_, '_, Fp>| {
+                let a = main_gate.assign_value(ctx, Value::known(Fp::from(0xff00)))?;
+                let offset = ctx.offset();
+                main_gate.range_check_lookup(ctx, &a, 10)?;
+                assert_eq!(ctx.offset() - offset, 2);
+
+                let config = main_gate.config();
+                let [low, top] = config.state;
+                let at = |column: Column<Advice>, row: usize| Cell {
+                    column: column.into(),
+                    row_offset: offset + row,
+                    ..a.cell()
+                };
+                tamper(ctx, at(low, 0), Value::known(Fp::ZERO))?;
+                tamper(ctx, at(top, 0), Value::known(Fp::from(0xff)))?;
+                tamper(ctx, at(low, 1), Value::known(Fp::from(0xff << 6)))?;
+                tamper(ctx, at(config.out, 1), Value::known(Fp::from(0xff)))?;
+                Ok(())
+            },
+        };
+
+        let failures = MockProver::run(9, &circuit, vec![])
+            .unwrap()
+            .verify()
+            .unwrap_err();
+        assert!(failures
+            .iter()
+            .all(|failure| matches!(failure, VerifyFailure::Lookup { .. })));
+    }
+
+    #[test]
+    fn compare() {
+        const NUM_BITS: usize = 8;
+
+        let check = |a: u64, b: u64| {
+            let expected = [a < b, a <= b, a > b, a >= b]
+                .map(|flag| Fp::from(flag as u64))
+                .to_vec();
+            verify_gadget::<2>(8, expected, move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[a, b])?;
+                let (a, b) = (&cells[0], &cells[1]);
+                Ok(vec![
+                    main_gate.lt(ctx, a, b, NUM_BITS)?,
+                    main_gate.leq(ctx, a, b, NUM_BITS)?,
+                    main_gate.gt(ctx, a, b, NUM_BITS)?,
+                    main_gate.geq(ctx, a, b, NUM_BITS)?,
+                ])
+            })
+        };
+
+        for (a, b) in [(7, 7), (0, 255), (255, 0), (3, 4), (200, 100)] {
+            assert_eq!(check(a, b), Ok(()), "{a} {b}");
+        }
+
+        // out of `num_bits`
+        assert!(check(256, 0).is_err());
+    }
+
+    #[test]
+    fn lt_tampered_borrow() {
+        const NUM_BITS: usize = 4;
+
+        // honest borrow bit is `a >= b`, i.e. the top bit of `a - b + 16`
+        let check = |a: u64, b: u64, borrow: bool| {
+            verify_gadget::<2>(7, vec![], move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[a, b])?;
+                let diff = main_gate.shifted_diff(ctx, &cells[0], &cells[1], NUM_BITS)?;
+
+                let low = (a + 16 - b) & 0xf;
+                let bits = (0..NUM_BITS)
+                    .map(|i| (low >> i) & 1 == 1)
+                    .chain([borrow])
+                    .map(Value::known)
+                    .collect();
+                main_gate.constrain_le_bits(ctx, &diff, bits)?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(3, 5, false), Ok(()));
+        assert_eq!(check(5, 3, true), Ok(()));
+        assert!(check(3, 5, true).is_err());
+        assert!(check(5, 3, false).is_err());
+        assert!(check(5, 5, false).is_err());
+    }
+
+    #[test]
+    fn min_max() {
+        const NUM_BITS: usize = 8;
+
+        let mut rng = StdRng::seed_from_u64(37);
+        let pairs = [(7, 7), (0, 255), (255, 0), (3, 4), (200, 100)]
+            .into_iter()
+            .chain((0..3).map(|_| (rng.gen_range(0..256u64), rng.gen_range(0..256u64))))
+            .collect::<Vec<_>>();
+        let expected = pairs
+            .iter()
+            .flat_map(|(a, b)| [cmp::min(*a, *b), cmp::max(*a, *b)])
+            .map(Fp::from)
+            .collect();
+
+        assert_eq!(
+            verify_gadget::<2>(10, expected, move |main_gate, ctx| {
+                let mut out = vec![];
+                for (a, b) in pairs.iter() {
+                    let cells = assign_values(main_gate, ctx, &[*a, *b])?;
+                    out.push(main_gate.min(ctx, &cells[0], &cells[1], NUM_BITS)?);
+                    out.push(main_gate.max(ctx, &cells[0], &cells[1], NUM_BITS)?);
+                }
+                Ok(out)
+            }),
+            Ok(())
+        );
+    }
+
+    proptest! {
+        // 32 circuits of 64 pairs each
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn min_max_native(pairs in prop::collection::vec((0..256u64, 0..256u64), 64)) {
+            let expected = pairs
+                .iter()
+                .flat_map(|(a, b)| [cmp::min(*a, *b), cmp::max(*a, *b)])
+                .map(Fp::from)
+                .collect();
+
+            prop_assert_eq!(
+                verify_gadget::<2>(12, expected, move |main_gate, ctx| {
+                    let mut out = vec![];
+                    for (a, b) in pairs.iter() {
+                        let cells = assign_values(main_gate, ctx, &[*a, *b])?;
+                        out.push(main_gate.min(ctx, &cells[0], &cells[1], 8)?);
+                        out.push(main_gate.max(ctx, &cells[0], &cells[1], 8)?);
+                    }
+                    Ok(out)
+                }),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn assert_not_zero() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let circuit = |value: Fp| {
+            GadgetCircuit::<2, _>::new(
+                move |main_gate: &MainGate<Fp, 2>,
+                      ctx: &mut RegionCtx<'_, '_, Fp>|
+                      -> Result<Vec<AssignedValue<Fp>>, Error> {
+                    let a = main_gate.assign_value(ctx, Value::known(value))?;
+                    main_gate.assert_not_zero(ctx, &a)?;
+                    Ok(vec![])
+                },
+            )
+        };
+
+        for value in [Fp::ONE, Fp::random(&mut rng)] {
+            assert_eq!(
+                MockProver::run(K, &circuit(value), vec![vec![]])
+                    .unwrap()
+                    .verify(),
+                Ok(())
+            );
+        }
+
+        // no inverse to witness
+        assert!(MockProver::run(K, &circuit(Fp::ZERO), vec![vec![]]).is_err());
+    }
+
+    #[test]
+    fn assert_not_zero_malicious_inverse() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let check = |a: Fp, a_inv: Fp| {
+            verify_gadget::<2>(K, vec![], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(a))?;
+                main_gate.assert_inverse(ctx, &a, Value::known(a_inv))?;
+                Ok(vec![])
+            })
+        };
+
+        let a = Fp::random(&mut rng);
+        assert_eq!(check(a, a.invert().unwrap()), Ok(()));
+        assert!(check(a, a.invert().unwrap().double()).is_err());
+        for a_inv in [Fp::ZERO, Fp::ONE, Fp::random(&mut rng)] {
+            assert!(check(Fp::ZERO, a_inv).is_err());
+        }
+    }
+
+    #[test]
+    fn assert_equal_const() {
+        let check = |value: u64, c: u64| {
+            verify_gadget::<2>(K, vec![], move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[value])?;
+                main_gate.assert_equal_const(ctx, &cells[0], Fp::from(c))?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(42, 42), Ok(()));
+        assert!(check(42, 43).is_err());
+    }
+
+    #[test]
+    fn assign_constant_cached() {
+        let check = |values: [u64; 2]| {
+            verify_gadget::<2>(K, vec![], move |main_gate, ctx| {
+                let one = main_gate.assign_constant(ctx, Fp::ONE)?;
+                let offset = ctx.offset();
+                for _ in 0..10 {
+                    main_gate.assign_constant(ctx, Fp::ONE)?;
+                    main_gate.assert_equal_const(ctx, &one, Fp::ONE)?;
+                }
+                assert_eq!(ctx.offset(), offset);
+
+                // both compared against the cached `one`, without rows
+                let cells = assign_values(main_gate, ctx, &values)?;
+                let offset = ctx.offset();
+                for cell in cells.iter() {
+                    main_gate.assert_equal_const(ctx, cell, Fp::ONE)?;
+                }
+                assert_eq!(ctx.offset(), offset);
+                assert_eq!(ctx.constants_used(), 1);
+
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check([1, 1]), Ok(()));
+        assert!(check([1, 2]).is_err());
+    }
+
+    #[test]
+    fn pow_const() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let check = |base: Fp, e: u64| {
+            verify_gadget::<2>(8, vec![base.pow_vartime([e])], move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(base))?;
+                let offset = ctx.offset();
+                let pow = main_gate.pow_const(ctx, &a, e)?;
+
+                let log2 = e.checked_ilog2().unwrap_or(0) as usize;
+                assert!(ctx.offset() - offset <= (2 * log2).max(1), "{e}");
+                Ok(vec![pow])
+            })
+        };
+
+        for e in [0, 1, 2, 5, 1 << 63, u64::MAX] {
+            let base = Fp::random(&mut rng);
+            assert_eq!(check(base, e), Ok(()), "{e}");
+        }
+    }
+
+    #[test]
+    fn pow_const_special_cases() {
+        assert_eq!(
+            verify_gadget::<2>(K, vec![Fp::ONE], |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(Fp::from(7)))?;
+                let offset = ctx.offset();
+
+                let same = main_gate.pow_const(ctx, &a, 1)?;
+                assert_eq!(same.value().unwrap(), a.value().unwrap());
+                assert_eq!(ctx.offset(), offset);
+
+                Ok(vec![main_gate.pow_const(ctx, &a, 0)?])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn pow5() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let base = Fp::random(&mut rng);
+        let rc = Fp::random(&mut rng);
+
+        let check = |expected: [Fp; 2]| {
+            verify_gadget::<2>(K, expected.to_vec(), move |main_gate, ctx| {
+                let a = main_gate.assign_value(ctx, Value::known(base))?;
+                let offset = ctx.offset();
+                let outputs = vec![
+                    main_gate.pow5(ctx, &a)?,
+                    main_gate.pow5_with_constant(ctx, &a, rc)?,
+                ];
+                assert_eq!(ctx.offset(), offset + 2);
+                Ok(outputs)
+            })
+        };
+
+        let pow5 = base.pow_vartime([5]);
+        assert_eq!(check([pow5, pow5 + rc]), Ok(()));
+        assert!(check([base.pow_vartime([4]), pow5 + rc]).is_err());
+        assert!(check([pow5, pow5]).is_err());
+    }
+
+    #[test]
+    fn pow5_tampered_out() {
+        let check = |tampered, rc: Option<u64>| {
+            verify_tampered_out::<2>(tampered, move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[3])?;
+                match rc {
+                    Some(rc) => main_gate.pow5_with_constant(ctx, &cells[0], Fp::from(rc)),
+                    None => main_gate.pow5(ctx, &cells[0]),
+                }
+            })
+        };
+
+        for rc in [None, Some(7)] {
+            assert_eq!(check(false, rc), Ok(()), "{rc:?}");
+            assert!(check(true, rc).is_err(), "{rc:?}");
+        }
+    }
+
+    fn lc_native(coeffs: &[u64], values: &[u64], constant: u64) -> Fp {
+        coeffs
+            .iter()
+            .zip(values)
+            .fold(Fp::from(constant), |acc, (c, v)| {
+                acc + Fp::from(*c) * Fp::from(*v)
+            })
+    }
+
+    #[test]
+    fn lc() {
+        let check = |coeffs: &'static [u64], values: &'static [u64], constant: u64| {
+            let expected = lc_native(coeffs, values, constant);
+            verify_gadget::<3>(K, vec![expected], move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, values)?;
+                let terms = coeffs
+                    .iter()
+                    .map(|c| Fp::from(*c))
+                    .zip(cells)
+                    .collect::<Vec<_>>();
+
+                let offset = ctx.offset();
+                let lc = main_gate.lc(ctx, &terms, Fp::from(constant))?;
+                assert_eq!(ctx.offset() - offset, terms.len().div_ceil(3).max(1));
+
+                Ok(vec![lc])
+            })
+        };
+
+        assert_eq!(check(&[], &[], 7), Ok(()));
+        assert_eq!(check(&[3], &[5], 0), Ok(()));
+        assert_eq!(check(&[3], &[5], 1), Ok(()));
+        assert_eq!(check(&[0, 2, 0], &[9, 4, 9], 1), Ok(()));
+        assert_eq!(
+            check(&[1, 2, 3, 4, 5, 6, 7], &[7, 6, 5, 4, 3, 2, 1], 100),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn lc_wrong_result() {
+        let values = [1, 2, 3, 4, 5];
+        assert!(verify_gadget::<2>(
+            K,
+            vec![lc_native(&[1; 5], &values, 1) + Fp::ONE],
+            move |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &values)?;
+                let terms = cells
+                    .into_iter()
+                    .map(|cell| (Fp::ONE, cell))
+                    .collect::<Vec<_>>();
+                Ok(vec![main_gate.lc(ctx, &terms, Fp::ONE)?])
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn inner_product() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut check = |len: usize, perturb: Option<usize>| {
+            let a = (0..len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+            let b = (0..len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+            let expected = a.iter().zip(b.iter()).map(|(a, b)| *a * b).sum::<Fp>();
+
+            // the partial sum of the row of term `i`, off by one
+            let perturbed = perturb.map(|i| {
+                let partial = a.iter().zip(b.iter()).take(i + 1).map(|(a, b)| *a * b);
+                (i, partial.sum::<Fp>() + Fp::ONE)
+            });
+
+            verify_gadget::<2>(9, vec![expected], move |main_gate, ctx| {
+                let assign = |ctx: &mut RegionCtx<'_, '_, Fp>, values: &[Fp]| {
+                    values
+                        .iter()
+                        .map(|value| main_gate.assign_value(ctx, Value::known(*value)))
+                        .collect::<Result<Vec<_>, _>>()
+                };
+                let a = assign(ctx, &a)?;
+                let b = assign(ctx, &b)?;
+
+                let offset = ctx.offset();
+                let product = main_gate.inner_product(ctx, &a, &b)?;
+                assert_eq!(ctx.offset() - offset, len.max(1));
+
+                // overwrite the witness after its row was laid out
+                if let Some((i, partial)) = perturbed {
+                    let cell = Cell {
+                        column: main_gate.config().out.into(),
+                        row_offset: offset + i,
+                        ..product.cell()
+                    };
+                    tamper(ctx, cell, Value::known(partial))?;
+                }
+
+                Ok(vec![product])
+            })
+        };
+
+        for len in [0, 1, 2, 100] {
+            assert_eq!(check(len, None), Ok(()), "{len}");
+        }
+        assert!(check(2, Some(0)).is_err());
+        assert!(check(100, Some(42)).is_err());
+    }
+
+    #[test]
+    fn inner_product_length_mismatch() {
+        let circuit = GadgetCircuit::<2, _>::new(
+            |main_gate: &MainGate<Fp, 2>,
+             ctx: &mut RegionCtx<'_, '_, Fp>|
+             -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let cells = assign_values(main_gate, ctx, &[1, 2, 3])?;
+                Ok(vec![main_gate.inner_product(
+                    ctx,
+                    &cells[..2],
+                    &cells[2..],
+                )?])
+            },
+        );
+
+        assert!(MockProver::run(K, &circuit, vec![vec![]]).is_err());
+    }
+
+    #[test]
+    fn horner() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut check = |degree: Option<usize>| {
+            let coeffs = degree
+                .map(|degree| (0..=degree).map(|_| Fp::random(&mut rng)).collect())
+                .unwrap_or_else(Vec::new);
+            let x = Fp::random(&mut rng);
+            let expected = coeffs.iter().rev().fold(Fp::ZERO, |acc, c| acc * x + c);
+
+            verify_gadget::<2>(8, vec![expected], move |main_gate, ctx| {
+                let x = main_gate.assign_value(ctx, Value::known(x))?;
+                let coeffs = coeffs
+                    .iter()
+                    .map(|c| main_gate.assign_value(ctx, Value::known(*c)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let offset = ctx.offset();
+                let eval = main_gate.horner(ctx, &coeffs, &x)?;
+                assert_eq!(ctx.offset() - offset, degree.map_or(1, |degree| degree));
+
+                Ok(vec![eval])
+            })
+        };
+
+        for degree in [None, Some(0), Some(1), Some(5), Some(64)] {
+            assert_eq!(check(degree), Ok(()), "{degree:?}");
+        }
+    }
+
+    #[test]
+    fn horner_wrong_eval() {
+        assert!(verify_gadget::<2>(
+            K,
+            vec![Fp::from(1 + 2 * 3 + 3 * 9 + 1)],
+            |main_gate, ctx| {
+                let cells = assign_values(main_gate, ctx, &[1, 2, 3, 3])?;
+                Ok(vec![main_gate.horner(ctx, &cells[..3], &cells[3])?])
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn powers() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let r = Fp::random(&mut rng);
+
+        for n in [0, 1, 2, 3, 32] {
+            let expected = iter::successors(Some(Fp::ONE), |power| Some(power * r))
+                .take(n)
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                verify_gadget::<2>(6, expected, move |main_gate, ctx| {
+                    let r = main_gate.assign_value(ctx, Value::known(r))?;
+                    main_gate.powers(ctx, &r, n)
+                }),
+                Ok(()),
+                "{n}"
+            );
+        }
+    }
+
+    #[test]
+    fn powers_corrupted() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let r = Fp::random(&mut rng);
+
+        for i in 2..8 {
+            let result = verify_gadget::<2>(K, vec![], |main_gate, ctx| {
+                let r = main_gate.assign_value(ctx, Value::known(r))?;
+                let powers = main_gate.powers(ctx, &r, 8)?;
+
+                // overwrite the `out` cell of the row that computed `r^i`
+                tamper(
+                    ctx,
+                    powers[i].cell(),
+                    powers[i].value().map(|power| *power + Fp::ONE),
+                )?;
+
+                Ok(vec![])
+            });
+
+            assert!(result.is_err(), "{i}");
+        }
+    }
+
+    #[test]
+    fn fold_vectors() {
+        const LEN: usize = 5;
+        let mut rng = StdRng::seed_from_u64(0);
+        let v = (0..LEN).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+        let w = (0..LEN).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+        let r = Fp::random(&mut rng);
+        let expected = v
+            .iter()
+            .zip(w.iter())
+            .map(|(v, w)| v + r * w)
+            .collect::<Vec<_>>();
+
+        let check = |tampered: Option<usize>| {
+            verify_gadget::<2>(K, expected.clone(), |main_gate, ctx| {
+                let v = assign_values(main_gate, ctx, &v)?;
+                let w = assign_values(main_gate, ctx, &w)?;
+                let r = main_gate.assign_value(ctx, Value::known(r))?;
+                let folded = main_gate.fold_vectors(ctx, &v, &w, &r)?;
+
+                // overwrite one output cell after its `mul_add` row was laid out
+                if let Some(i) = tampered {
+                    tamper(
+                        ctx,
+                        folded[i].cell(),
+                        folded[i].value().map(|value| *value + Fp::ONE),
+                    )?;
+                }
+
+                Ok(folded)
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        for i in 0..LEN {
+            assert!(check(Some(i)).is_err(), "{i}");
+        }
+    }
+
+    #[test]
+    fn rlc_many() {
+        const LEN: usize = 4;
+        let mut rng = StdRng::seed_from_u64(0);
+        let r = Fp::random(&mut rng);
+
+        for count in [0, 1, 3] {
+            let vectors = (0..count)
+                .map(|_| (0..LEN).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+
+            let folded = vectors.iter().rev().fold(vec![Fp::ZERO; LEN], |acc, v| {
+                v.iter().zip(acc).map(|(v, acc)| v + r * acc).collect()
+            });
+            let expected = if count == 0 {
+                Fp::ZERO
+            } else {
+                folded
+                    .iter()
+                    .rev()
+                    .fold(Fp::ZERO, |acc, value| acc * r + value)
+            };
+
+            let check = |tampered: Option<(usize, usize)>| {
+                let mut vectors = vectors.clone();
+                if let Some((k, j)) = tampered {
+                    vectors[k][j] += Fp::ONE;
+                }
+
+                verify_gadget::<2>(6, vec![expected], move |main_gate, ctx| {
+                    let vectors = vectors
+                        .iter()
+                        .map(|v| assign_values(main_gate, ctx, v))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let r = main_gate.assign_value(ctx, Value::known(r))?;
+                    Ok(vec![main_gate.rlc_many(ctx, &vectors, &r)?])
+                })
+            };
+
+            assert_eq!(check(None), Ok(()), "{count}");
+            for k in 0..count {
+                for j in 0..LEN {
+                    assert!(check(Some((k, j))).is_err(), "{count}: {k} {j}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fold_vectors_length_mismatch() {
+        let circuit = GadgetCircuit::<2, _>::new(
+            |main_gate: &MainGate<Fp, 2>,
+             ctx: &mut RegionCtx<'_, '_, Fp>|
+             -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let cells = assign_values(main_gate, ctx, &[1, 2, 3, 4])?;
+                main_gate.rlc_many(ctx, &[cells[..2].to_vec(), cells[2..3].to_vec()], &cells[3])?;
+                Ok(vec![])
+            },
+        );
+
+        assert!(MockProver::run(K, &circuit, vec![vec![]]).is_err());
+    }
+
+    fn permutation_of(tampered: Option<usize>) -> (Vec<Fp>, Vec<Fp>) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let a = (0..6).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+        let mut b = a.iter().rev().copied().collect::<Vec<_>>();
+        b.rotate_left(2);
+        if let Some(i) = tampered {
+            b[i] += Fp::ONE;
+        }
+        (a, b)
+    }
+
+    #[test]
+    fn assert_permutation() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let gamma = Fp::random(&mut rng);
+
+        let check = |tampered: Option<usize>| {
+            let (a, b) = permutation_of(tampered);
+            verify_gadget::<2>(6, vec![], move |main_gate, ctx| {
+                let a = assign_values(main_gate, ctx, &a)?;
+                let b = assign_values(main_gate, ctx, &b)?;
+                let gamma = main_gate.assign_value(ctx, Value::known(gamma))?;
+                main_gate.assert_permutation(ctx, &a, &b, &gamma)?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        for i in 0..6 {
+            assert!(check(Some(i)).is_err(), "{i}");
+        }
+    }
+
+    #[test]
+    fn assert_permutation_with_ro() {
+        let check = |tampered: Option<usize>| {
+            let (a, b) = permutation_of(tampered);
+            verify_gadget::<3>(10, vec![], move |main_gate, ctx| {
+                let assign = |ctx: &mut RegionCtx<'_, '_, Fp>, values: &[Fp]| {
+                    values
+                        .iter()
+                        .map(|value| main_gate.assign_value(ctx, Value::known(*value)))
+                        .collect::<Result<Vec<_>, _>>()
+                };
+                let a = assign(ctx, &a)?;
+                let b = assign(ctx, &b)?;
+
+                let mut ro =
+                    PoseidonChip::<Fp, 3, 2>::new(main_gate.config().clone(), Spec::new(4, 3));
+                main_gate.assert_permutation_with_ro(ctx, &mut ro, &a, &b)?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        assert!(check(Some(0)).is_err());
+    }
+
+    #[test]
+    fn eval_expression() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (gates, _, _) = main_gate_expressions();
+        let expr = gates[0][0].clone();
+
+        // Z_0..=Z_12, `q_m[1]` (Z_5) isn't queried for `T = 2`
+        let vars = (0..13).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+        let challenge = Fp::random(&mut rng);
+        let expr_r = expr.clone() * Expression::Challenge(0);
+
+        let expected = expr.evaluate(
+            &|c| c,
+            &|query| vars[query.index],
+            &|_| unreachable!("no challenges in main gate"),
+            &|_| unreachable!("no challenges in main gate"),
+            &|a| -a,
+            &|a, b| a + b,
+            &|a, b| a * b,
+            &|a, k| a * k,
+        );
+
+        assert_eq!(
+            verify_gadget::<2>(
+                8,
+                vec![expected, expected * challenge],
+                move |main_gate, ctx| {
+                    let vars = assign_values(main_gate, ctx, &vars)?;
+                    let challenges = assign_values(main_gate, ctx, &[challenge])?;
+                    Ok(vec![
+                        main_gate.eval_expression(ctx, &expr, &vars, &[])?,
+                        main_gate.eval_expression(ctx, &expr_r, &vars, &challenges)?,
+                    ])
+                }
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn eval_expression_uncovered() {
+        let (gates, _, _) = main_gate_expressions();
+
+        let check = |expr: Expression<Fp>, num_vars: u64| {
+            let circuit = GadgetCircuit::<2, _>::new(
+                move |main_gate: &MainGate<Fp, 2>,
+                      ctx: &mut RegionCtx<'_, '_, Fp>|
+                      -> Result<Vec<AssignedValue<Fp>>, Error> {
+                    let vars = assign_values(main_gate, ctx, &(0..num_vars).collect::<Vec<_>>())?;
+                    main_gate.eval_expression(ctx, &expr, &vars, &[])?;
+                    Ok(vec![])
+                },
+            );
+            MockProver::run(8, &circuit, vec![vec![]]).map(|_| ())
+        };
+
+        let next = Expression::Polynomial(Query {
+            index: 0,
+            rotation: Rotation::next(),
+        });
+
+        assert!(check(gates[0][0].clone(), 13).is_ok());
+        assert!(check(gates[0][0].clone(), 12).is_err());
+        assert!(check(Expression::Challenge(0), 13).is_err());
+        assert!(check(next, 13).is_err());
+    }
+
+    fn select_from_of(
+        size: usize,
+        num_bits: usize,
+        index: usize,
+    ) -> Result<(), Vec<VerifyFailure>> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let table = (0..size).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+        // out-of-range indices aren't exposed, only the range check may fail
+        let expected = table.get(index).copied();
+
+        verify_gadget::<2>(8, expected.into_iter().collect(), move |main_gate, ctx| {
+            let table = assign_values(main_gate, ctx, &table)?;
+            let bits = (0..num_bits)
+                .map(|i| main_gate.assign_bit(ctx, Value::known((index >> i) & 1 == 1)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let selected = main_gate.select_from(ctx, &bits, &table)?;
+            Ok(expected.map(|_| selected).into_iter().collect())
+        })
+    }
+
+    #[test]
+    fn select_from() {
+        for (size, num_bits) in [(1, 0), (1, 1), (3, 2), (8, 3), (8, 4)] {
+            for index in 0..size {
+                assert_eq!(
+                    select_from_of(size, num_bits, index),
+                    Ok(()),
+                    "{size}, {num_bits}: {index}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn select_from_out_of_range() {
+        for (size, num_bits, index) in [(1, 1, 1), (3, 2, 3), (8, 4, 9)] {
+            assert!(
+                select_from_of(size, num_bits, index).is_err(),
+                "{size}, {num_bits}: {index}"
+            );
+        }
+    }
+
+    #[test]
+    fn select_from_too_few_bits() {
+        let circuit = GadgetCircuit::<2, _>::new(
+            |main_gate: &MainGate<Fp, 2>,
+             ctx: &mut RegionCtx<'_, '_, Fp>|
+             -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let table = assign_values(main_gate, ctx, &[1, 2, 3])?;
+                let bit = main_gate.assign_bit(ctx, Value::known(true))?;
+                Ok(vec![main_gate.select_from(ctx, &[bit], &table)?])
+            },
+        );
+
+        assert!(MockProver::run(K, &circuit, vec![vec![]]).is_err());
+    }
+
+    fn to_limbs_of(
+        value: Fp,
+        limb_bits: usize,
+        num_limbs: usize,
+        bounded: bool,
+    ) -> Result<(), Vec<VerifyFailure>> {
+        let num_bits = if bounded {
+            limb_bits * num_limbs
+        } else {
+            Fp::NUM_BITS as usize
+        };
+        let expected = le_limbs(&value, &limb_widths(limb_bits, num_limbs, num_bits));
+
+        verify_gadget::<2>(10, expected, move |main_gate, ctx| {
+            let a = main_gate.assign_value(ctx, Value::known(value))?;
+            if bounded {
+                main_gate.to_limbs_bounded(ctx, &a, limb_bits, num_limbs)
+            } else {
+                main_gate.to_limbs(ctx, &a, limb_bits, num_limbs)
+            }
+        })
+    }
+
+    #[test]
+    fn to_limbs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (limb_bits, num_limbs) in [(64, 4), (85, 3), (128, 2), (255, 1)] {
+            for value in [Fp::ZERO, Fp::random(&mut rng), -Fp::ONE.double(), -Fp::ONE] {
+                assert_eq!(
+                    to_limbs_of(value, limb_bits, num_limbs, false),
+                    Ok(()),
+                    "{limb_bits} x {num_limbs}: {value:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn to_limbs_bounded() {
+        let bound = Fp::from_u128(u64::MAX as u128 + 1);
+
+        assert_eq!(to_limbs_of(bound - Fp::ONE, 8, 8, true), Ok(()));
+        assert_eq!(to_limbs_of(Fp::from(0x1234), 16, 4, true), Ok(()));
+        // the limbs of `bound` don't recompose to it
+        assert!(to_limbs_of(bound, 8, 8, true).is_err());
+    }
+
+    #[test]
+    fn to_limbs_non_canonical() {
+        const LIMB_BITS: usize = 64;
+        let widths = limb_widths(LIMB_BITS, 4, Fp::NUM_BITS as usize);
+
+        // `p - 1` is divisible by `2^32`, so the lowest limb of `p` is the one of `p - 1` plus one
+        let mut modulus = le_limbs(&-Fp::ONE, &widths);
+        modulus[0] += Fp::ONE;
+
+        let check = |limbs: Vec<Fp>| {
+            let widths = widths.clone();
+            verify_gadget::<2>(10, vec![], move |main_gate, ctx| {
+                let zero = main_gate.assign_value(ctx, Value::known(Fp::ZERO))?;
+                main_gate.constrain_limbs(
+                    ctx,
+                    &zero,
+                    limbs.iter().copied().map(Value::known).collect(),
+                    &widths,
+                )?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(vec![Fp::ZERO; 4]), Ok(()));
+        assert!(check(modulus).is_err());
+    }
+
+    #[test]
+    fn to_limbs_invalid_params() {
+        let check = |limb_bits: usize, num_limbs: usize, bounded: bool| {
+            let circuit = GadgetCircuit::<2, _>::new(
+                move |main_gate: &MainGate<Fp, 2>,
+                      ctx: &mut RegionCtx<'_, '_, Fp>|
+                      -> Result<Vec<AssignedValue<Fp>>, Error> {
+                    let a = main_gate.assign_value(ctx, Value::known(Fp::ONE))?;
+                    if bounded {
+                        main_gate.to_limbs_bounded(ctx, &a, limb_bits, num_limbs)?;
+                    } else {
+                        main_gate.to_limbs(ctx, &a, limb_bits, num_limbs)?;
+                    }
+                    Ok(vec![])
+                },
+            );
+            MockProver::run(10, &circuit, vec![vec![]]).map(|_| ())
+        };
+
+        // too few bits without an explicit bound
+        assert!(check(64, 3, false).is_err());
+        // empty top limb
+        assert!(check(64, 5, false).is_err());
+        assert!(check(0, 4, false).is_err());
+        // a bound that doesn't fit below the modulus
+        assert!(check(64, 4, true).is_err());
+        assert!(check(64, 3, true).is_ok());
+    }
+
+    #[test]
+    fn to_le_bytes_with_range_table() {
+        let circuit = RangeTableCircuit {
+            synthesize: |main_gate: &MainGate<Fp, 2>, ctx: &mut RegionCtx<'_, '_, Fp>| {
+                let a = main_gate.assign_value(ctx, Value::known(Fp::from(0x0102_0304)))?;
+
+                let offset = ctx.offset();
+                let bytes = main_gate.to_le_bytes(ctx, &a, 4)?;
+                // a lookup row per byte instead of its bits
+                assert_eq!(ctx.offset() - offset, 2 * 4 + 2);
+
+                for (byte, expected) in bytes.iter().zip([4, 3, 2, 1]) {
+                    main_gate.assert_equal_const(ctx, byte, Fp::from(expected))?;
+                }
+                Ok(())
+            },
+        };
+
+        assert_eq!(
+            MockProver::run(9, &circuit, vec![]).unwrap().verify(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn to_limbs_with_range_table() {
+        let value = -Fp::ONE;
+        let expected = le_limbs(&value, &limb_widths(16, 16, Fp::NUM_BITS as usize));
+
+        let circuit = RangeTableCircuit {
+            synthesize: move |main_gate: &MainGate<Fp, 2>, ctx: &mut RegionCtx<'_, '_, Fp>| {
+                let a = main_gate.assign_value(ctx, Value::known(value))?;
+                let limbs = main_gate.to_limbs(ctx, &a, 16, 16)?;
+                for (limb, expected) in limbs.iter().zip(expected.iter()) {
+                    main_gate.assert_equal_const(ctx, limb, *expected)?;
+                }
+                Ok(())
+            },
+        };
+
+        assert_eq!(
+            MockProver::run(9, &circuit, vec![]).unwrap().verify(),
+            Ok(())
+        );
+    }
+
+    fn rlc_wide_of<const T: usize>(
+        values: Vec<Fp>,
+        r: Fp,
+        tampered: Option<usize>,
+    ) -> Result<(), Vec<VerifyFailure>> {
+        let expected = values.iter().rev().fold(Fp::ZERO, |acc, v| acc * r + v);
+
+        verify_gadget::<T>(9, vec![expected, expected], move |main_gate, ctx| {
+            let cells = values
+                .iter()
+                .map(|value| main_gate.assign_value(ctx, Value::known(*value)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let r = main_gate.assign_value(ctx, Value::known(r))?;
+
+            let narrow = main_gate.horner(ctx, &cells, &r)?;
+
+            let offset = ctx.offset();
+            let wide = main_gate.rlc_wide(ctx, &cells, &r)?;
+            let rows = ctx.offset() - offset;
+            match values.len() {
+                n if T >= 4 && n >= 3 => assert_eq!(rows, 1 + n / 2),
+                // zero is already cached by `narrow`
+                n => assert_eq!(rows, n.saturating_sub(1)),
+            }
+
+            if let Some(i) = tampered {
+                let (row, column) = rlc_wide_copy(main_gate.config(), values.len(), i);
+                let cell = Cell {
+                    column: column.into(),
+                    row_offset: offset + row,
+                    ..wide.cell()
+                };
+                tamper(ctx, cell, Value::known(values[i].double()))?;
+            }
+
+            Ok(vec![narrow, wide])
+        })
+    }
+
+    /// Row from the start of [`MainGate::rlc_wide`] of an even number `n >= 4` of terms &
+    /// column of the copy of term `i`
+    ///
+    /// `r^2` is the first row, `terms[n - 1] * r + terms[n - 2]` the second, then one row per
+    /// lower pair from the top, with the high term in the last state & the low one as input
+    fn rlc_wide_copy<const T: usize>(
+        config: &MainGateConfig<T>,
+        n: usize,
+        i: usize,
+    ) -> (usize, Column<Advice>) {
+        assert!(T >= 4 && n >= 4 && n % 2 == 0);
+        let column = match i {
+            i if i == n - 1 => config.state[0],
+            i if i % 2 == 0 => config.input,
+            _ => config.state[3],
+        };
+        (n / 2 - i / 2, column)
+    }
+
+    #[test]
+    fn rlc_wide() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let r = Fp::random(&mut rng);
+        for len in [0, 1, 2, 3, 4, 5, 10, 101] {
+            let values = (0..len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+
+            assert_eq!(rlc_wide_of::<2>(values.clone(), r, None), Ok(()), "{len}");
+            assert_eq!(rlc_wide_of::<4>(values.clone(), r, None), Ok(()), "{len}");
+            assert_eq!(rlc_wide_of::<5>(values, r, None), Ok(()), "{len}");
+        }
+    }
+
+    #[test]
+    fn rlc_wide_tampered_term() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let values = (0..6).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+        let r = Fp::random(&mut rng);
+
+        // the terms are bound to the rows by copy constraints only
+        for i in 0..values.len() {
+            let failures = rlc_wide_of::<4>(values.clone(), r, Some(i)).unwrap_err();
+            assert!(
+                failures
+                    .iter()
+                    .any(|failure| matches!(failure, VerifyFailure::Permutation { .. })),
+                "{i}: {failures:?}"
+            );
+        }
+    }
+
+    fn sum_of<const T: usize>(len: u64, expected: u64) -> Result<(), Vec<VerifyFailure>> {
+        verify_gadget::<T>(K, vec![Fp::from(expected)], move |main_gate, ctx| {
+            let cells = assign_values(main_gate, ctx, &(1..=len).collect::<Vec<_>>())?;
+
+            let offset = ctx.offset();
+            let sum = main_gate.sum(ctx, &cells)?;
+            assert_eq!(ctx.offset() - offset, cells.len().div_ceil(T).max(1));
+
+            Ok(vec![sum])
+        })
+    }
+
+    #[test]
+    fn sum() {
+        for len in [0, 1, 2, 3, 4, 5, 6, 11] {
+            let expected = len * (len + 1) / 2;
+            assert_eq!(sum_of::<2>(len, expected), Ok(()), "{len}");
+            assert_eq!(sum_of::<3>(len, expected), Ok(()), "{len}");
+            assert_eq!(sum_of::<5>(len, expected), Ok(()), "{len}");
+        }
+
+        assert!(sum_of::<3>(4, 11).is_err());
+        assert!(sum_of::<5>(11, 67).is_err());
+    }
+
+    #[test]
+    fn product() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for len in [0, 1, 2, 50] {
+            let values = (0..len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+            let expected = values.iter().product::<Fp>();
+
+            assert_eq!(
+                verify_gadget::<2>(8, vec![expected], move |main_gate, ctx| {
+                    let cells = values
+                        .iter()
+                        .map(|value| main_gate.assign_value(ctx, Value::known(*value)))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    let offset = ctx.offset();
+                    let product = main_gate.product(ctx, &cells)?;
+                    assert_eq!(ctx.offset() - offset, len.max(2) - 1);
+
+                    Ok(vec![product])
+                }),
+                Ok(()),
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn batch_invert() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let check = |values: Vec<Fp>| {
+            let expected = values
+                .iter()
+                .map(|value| value.invert().unwrap_or(Fp::ZERO))
+                .collect();
+
+            verify_gadget::<2>(8, expected, move |main_gate, ctx| {
+                let cells = values
+                    .iter()
+                    .map(|value| main_gate.assign_value(ctx, Value::known(*value)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let offset = ctx.offset();
+                let inverses = main_gate.batch_invert(ctx, &cells)?;
+                assert_eq!(ctx.offset() - offset, cells.len());
+
+                Ok(inverses)
+            })
+        };
+
+        for len in [0, 1, 2, 50] {
+            let values = (0..len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+            assert_eq!(check(values), Ok(()), "{len}");
+        }
+
+        let mut values = (1..=10).map(Fp::from).collect::<Vec<_>>();
+        values[3] = Fp::ZERO;
+        assert!(check(values).is_err());
     }
 }
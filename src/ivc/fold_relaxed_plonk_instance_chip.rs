@@ -52,6 +52,7 @@ use tracing::*;
 
 use crate::{
     constants::NUM_CHALLENGE_BITS,
+    error::AssignmentError,
     ff::{Field, FromUniformBytes, PrimeField, PrimeFieldBits},
     gadgets::{
         ecc::{AssignedPoint, EccChip},
@@ -352,6 +353,12 @@ pub enum Error {
         variable_str: String,
     },
 }
+impl From<AssignmentError> for Error {
+    fn from(err: AssignmentError) -> Self {
+        Self::Halo2(err.into())
+    }
+}
+
 impl From<Error> for halo2_proofs::plonk::Error {
     fn from(err: Error) -> halo2_proofs::plonk::Error {
         error!("downcast error: {err:?} to `Synthesis`");
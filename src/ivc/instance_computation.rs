@@ -41,7 +41,7 @@ where
 {
     pub fn generate_with_inspect(
         self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         config: MainGateConfig<T>,
         inspect: impl FnOnce(&[C::Base]),
     ) -> Result<AssignedValue<C::Base>, halo2_proofs::plonk::Error> {
@@ -58,7 +58,7 @@ where
     }
     pub fn generate(
         self,
-        ctx: &mut RegionCtx<'_, C::Base>,
+        ctx: &mut RegionCtx<'_, '_, C::Base>,
         config: MainGateConfig<T>,
     ) -> Result<AssignedValue<C::Base>, halo2_proofs::plonk::Error> {
         self.generate_with_inspect(ctx, config, |_| {})
@@ -21,6 +21,7 @@ use crate::{
     main_gate::MainGateConfig,
     nifs::{self, vanilla::VanillaFS, FoldingScheme},
     plonk::{PlonkStructure, PlonkTrace},
+    polynomial::LiftError,
     poseidon::{random_oracle::ROTrait, ROPair},
     table::CircuitRunner,
     util,
@@ -36,6 +37,8 @@ pub enum Error {
     WhileGeneratePlonkTrace(#[from] nifs::Error),
     #[error("While calculate intiail plonk relaxed trace of secondary circuit, error was occured in `process_step`: {0:?}")]
     WhileProcessStep(#[from] ivc::step_circuit::SynthesisError),
+    #[error(transparent)]
+    Lift(#[from] LiftError),
 }
 
 #[derive(Serialize)]
@@ -264,7 +267,7 @@ where
                         primary.k_table_size,
                         NUM_IO,
                         &StepParams::new(limb_width, limbs_count, primary.ro_constant.clone()),
-                    ),
+                    )?,
                 },
                 vec![C1::Scalar::ZERO; NUM_IO],
             )
@@ -283,7 +286,7 @@ where
                 secondary.k_table_size,
                 NUM_IO,
                 &secondary_initial_step_params,
-            );
+            )?;
 
             let secondary_initial_instance: [C2::Scalar; 2] = [
                 util::fe_to_fe(&secondary_initial_step_input.u.instance[0]).unwrap(),
@@ -21,6 +21,7 @@ use crate::{
     },
     main_gate::{AdviceCyclicAssignor, MainGate, MainGateConfig, RegionCtx},
     plonk::{PlonkInstance, RelaxedPlonkInstance},
+    polynomial::LiftError,
     poseidon::ROCircuitTrait,
     table::ConstraintSystemMetainfo,
 };
@@ -106,11 +107,12 @@ where
     C: CurveAffine,
     RO: ROCircuitTrait<C::Base>,
 {
+    /// Fails if the gates or lookups of `PairedCircuit` can't be lifted
     pub fn without_witness<PairedCircuit: Circuit<C::Scalar>>(
         k_table_size: u32,
         num_io: usize,
         step_pp: &'link StepParams<C::Base, RO>,
-    ) -> Self {
+    ) -> Result<Self, LiftError> {
         let mut cs = ConstraintSystem::<C::Scalar>::default();
 
         PairedCircuit::configure(&mut cs);
@@ -120,9 +122,9 @@ where
             round_sizes,
             folding_degree,
             ..
-        } = ConstraintSystemMetainfo::build(k_table_size as usize, &cs);
+        } = ConstraintSystemMetainfo::build(k_table_size as usize, &cs)?;
 
-        Self {
+        Ok(Self {
             step: C::Base::ZERO,
             step_pp,
             public_params_hash: C::identity(),
@@ -131,7 +133,7 @@ where
             U: RelaxedPlonkInstance::new(num_io, num_challenges, round_sizes.len()),
             u: PlonkInstance::new(num_io, num_challenges, round_sizes.len()),
             cross_term_commits: vec![C::identity(); folding_degree.saturating_sub(1)],
-        }
+        })
     }
 }
 
@@ -376,7 +378,7 @@ where
 
                     Ok((
                         base_case_input_check,
-                        MainGate::new(config.main_gate_config.clone()).is_equal_term(
+                        MainGate::new(config.main_gate_config.clone()).is_equal(
                             &mut ctx,
                             &expected_X0,
                             &w.input_instance[0].0,
@@ -411,8 +413,7 @@ where
                     let mut region = RegionCtx::new(region, 0);
                     let gate = MainGate::new(config.main_gate_config.clone());
 
-                    let assigned_is_zero_step =
-                        gate.is_zero_term(&mut region, assigned_step.clone())?;
+                    let assigned_is_zero_step = gate.is_zero(&mut region, &assigned_step)?;
 
                     let new_U = AssignedRelaxedPlonkInstance::<C>::conditional_select(
                         &mut region,
@@ -428,7 +429,7 @@ where
                         &non_base_case_input_check,
                         &assigned_is_zero_step,
                     )?;
-                    gate.assert_equal_const(&mut region, input_check, C::Base::ONE)?;
+                    gate.assert_equal_const(&mut region, &input_check, C::Base::ONE)?;
 
                     let assigned_input: [_; ARITY] = assigned_z_0
                         .iter()
@@ -10,6 +10,7 @@ pub mod fft;
 pub mod gadgets;
 pub mod ivc;
 pub mod main_gate;
+pub mod merkle;
 pub mod nifs;
 pub mod plonk;
 pub mod polynomial;
@@ -1,39 +1,443 @@
-use std::{array, iter, marker::PhantomData, num::NonZeroUsize};
+use std::{
+    array,
+    collections::{HashMap, HashSet},
+    iter,
+    marker::PhantomData,
+    num::NonZeroUsize,
+    ops::Range,
+};
 
 use halo2_proofs::{
-    circuit::{AssignedCell, Cell, Chip, Region, Value},
-    plonk::{Advice, Column, ConstraintSystem, Error, Expression, Fixed, Instance},
+    circuit::{
+        floor_planner::single_pass::SingleChipLayouter, AssignedCell, Cell, Chip, Layouter, Region,
+        Value,
+    },
+    plonk::{
+        Advice, Any, Column, ConstraintSystem, Error, Expression, Fixed, Instance, Selector,
+        TableColumn,
+    },
     poly::Rotation,
 };
 use itertools::Itertools;
+use tracing::*;
 
 use crate::{
+    error::AssignmentError,
     ff::{PrimeField, PrimeFieldBits},
     gadgets::ecc::AssignedPoint,
     halo2curves::{Coordinates, CurveAffine},
+    polynomial::expression::{QueryIndexContext, VarNames},
     util::{self, normalize_trailing_zeros},
 };
 
 pub type AssignedValue<F> = AssignedCell<F, F>;
 pub type AssignedBit<F> = AssignedCell<F, F>;
 
+/// Ctx of the assignments into a [`Region`] with lifetime `'a`, which is borrowed for `'b` if
+/// the ctx is created by [`RegionCtx::new_borrowed`]
 #[derive(Debug)]
-pub struct RegionCtx<'a, F: PrimeField> {
-    pub region: Region<'a, F>,
+pub struct RegionCtx<'b, 'a, F: PrimeField> {
+    region: CtxRegion<'b, 'a, F>,
+    pub offset: usize,
+    namespace: Vec<String>,
+    // high-water mark of rows touched through this ctx
+    rows_used: usize,
+    // number of copy constraints registered, to validate rollbacks
+    #[cfg(debug_assertions)]
+    constraints: usize,
+    // cells constrained to constants, by repr of the constant
+    constants: HashMap<Vec<u8>, AssignedValue<F>>,
+    // cells constrained to be boolean, see `RegionCtx::register_bit`
+    bits: HashSet<(usize, usize, Column<Any>)>,
+    // see `RegionCtx::dry_run`
+    dry_run: bool,
+    // see `RegionCtx::with_recorder`
+    records: Option<Vec<AssignmentRecord<F>>>,
+    // see `RegionCtx::with_cell_registry`
+    cells: Option<HashMap<(Column<Any>, usize), AssignedValue<F>>>,
+}
+
+/// Cell assignment captured by [`RegionCtx::with_recorder`]
+#[derive(Clone, Debug)]
+pub struct AssignmentRecord<F: PrimeField> {
+    pub column: Column<Any>,
     pub offset: usize,
+    pub annotation: String,
+    pub value: Value<F>,
+}
+
+/// Position of [`RegionCtx`] saved by [`RegionCtx::checkpoint`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionCheckpoint {
+    offset: usize,
+    // number of records, see `RegionCtx::with_recorder`
+    records: usize,
+    #[cfg(debug_assertions)]
+    constraints: usize,
+}
+
+impl RegionCheckpoint {
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// Region of a [`RegionCtx`], owned or borrowed from the caller
+#[derive(Debug)]
+enum CtxRegion<'b, 'a, F: PrimeField> {
+    Owned(Region<'a, F>),
+    Borrowed(&'b mut Region<'a, F>),
+}
+
+impl<'a, F: PrimeField> CtxRegion<'_, 'a, F> {
+    fn get(&mut self) -> &mut Region<'a, F> {
+        match self {
+            Self::Owned(region) => region,
+            Self::Borrowed(region) => region,
+        }
+    }
+}
+
+mod dry_run {
+    use halo2_proofs::plonk::{Any, Assigned, Assignment, Challenge};
+
+    use super::*;
+
+    /// [`Assignment`] that ignores everything, used by [`RegionCtx::dry_run`]
+    pub struct NoopAssignment;
+
+    impl<F: PrimeField> Assignment<F> for NoopAssignment {
+        fn enter_region<NR, N>(&mut self, _: N)
+        where
+            NR: Into<String>,
+            N: FnOnce() -> NR,
+        {
+        }
+
+        fn exit_region(&mut self) {}
+
+        fn enable_selector<A, AR>(
+            &mut self,
+            _: A,
+            _selector: &Selector,
+            _row: usize,
+        ) -> Result<(), Error>
+        where
+            A: FnOnce() -> AR,
+            AR: Into<String>,
+        {
+            Ok(())
+        }
+
+        fn annotate_column<A, AR>(&mut self, _annotation: A, _column: Column<Any>)
+        where
+            A: FnOnce() -> AR,
+            AR: Into<String>,
+        {
+        }
+
+        fn query_instance(
+            &self,
+            _column: Column<Instance>,
+            _row: usize,
+        ) -> Result<Value<F>, Error> {
+            Ok(Value::unknown())
+        }
+
+        fn assign_advice<V, VR, A, AR>(
+            &mut self,
+            _annotation: A,
+            _column: Column<Advice>,
+            _row: usize,
+            _to: V,
+        ) -> Result<(), Error>
+        where
+            V: FnOnce() -> Value<VR>,
+            VR: Into<Assigned<F>>,
+            A: FnOnce() -> AR,
+            AR: Into<String>,
+        {
+            Ok(())
+        }
+
+        fn assign_fixed<V, VR, A, AR>(
+            &mut self,
+            _annotation: A,
+            _column: Column<Fixed>,
+            _row: usize,
+            _to: V,
+        ) -> Result<(), Error>
+        where
+            V: FnOnce() -> Value<VR>,
+            VR: Into<Assigned<F>>,
+            A: FnOnce() -> AR,
+            AR: Into<String>,
+        {
+            Ok(())
+        }
+
+        fn copy(
+            &mut self,
+            _: Column<Any>,
+            _: usize,
+            _: Column<Any>,
+            _: usize,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn fill_from_row(
+            &mut self,
+            _: Column<Fixed>,
+            _: usize,
+            _: Value<Assigned<F>>,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn get_challenge(&self, _: Challenge) -> Value<F> {
+            Value::unknown()
+        }
+
+        fn push_namespace<NR, N>(&mut self, _: N)
+        where
+            NR: Into<String>,
+            N: FnOnce() -> NR,
+        {
+        }
+
+        fn pop_namespace(&mut self, _: Option<String>) {}
+
+        fn query_advice(&self, _column: Column<Advice>, _row: usize) -> Result<F, Error> {
+            Err(Error::Synthesis)
+        }
+
+        fn query_fixed(&self, _column: Column<Fixed>, _row: usize) -> Result<F, Error> {
+            Err(Error::Synthesis)
+        }
+    }
+}
+
+/// Prefix `annotation` with all `namespace` levels, separated by `/`
+fn namespaced(namespace: &[String], annotation: impl Into<String>) -> String {
+    let annotation = annotation.into();
+
+    if namespace.is_empty() {
+        annotation
+    } else {
+        format!("{}/{annotation}", namespace.join("/"))
+    }
 }
 
-impl<'a, F: PrimeField> RegionCtx<'a, F> {
+impl<'b, 'a, F: PrimeField> RegionCtx<'b, 'a, F> {
     pub fn new(region: Region<'a, F>, offset: usize) -> Self {
-        RegionCtx { region, offset }
+        Self::with_region(CtxRegion::Owned(region), offset)
+    }
+
+    /// Same as [`RegionCtx::new`], but only borrows `region`
+    ///
+    /// Allows to interleave [`MainGate`] gadgets with direct region access from other chips
+    /// inside one `assign_region` closure: create the ctx, run gadgets, drop it & continue with
+    /// `region` from [`RegionCtx::offset`]
+    pub fn new_borrowed(region: &'b mut Region<'a, F>, offset: usize) -> Self {
+        Self::with_region(CtxRegion::Borrowed(region), offset)
+    }
+
+    /// Lay out `layout` without any real region & return the number of rows it used
+    ///
+    /// Offsets advance as usual, but all assigned values are treated as unknown and constants
+    /// are not registered, so only the layout cost is measured. Same as with
+    /// [`Layouter::assign_region`], `layout` may be called more than once.
+    pub fn dry_run(
+        mut layout: impl FnMut(&mut RegionCtx<'_, '_, F>) -> Result<(), Error>,
+    ) -> Result<usize, Error> {
+        let mut assignment = dry_run::NoopAssignment;
+        let mut rows_used = 0;
+
+        SingleChipLayouter::<'_, F, _>::new(&mut assignment, vec![])?.assign_region(
+            || "dry run",
+            |region| {
+                let mut ctx = RegionCtx::new(region, 0);
+                ctx.dry_run = true;
+                layout(&mut ctx)?;
+                rows_used = rows_used.max(ctx.rows_used());
+                Ok(())
+            },
+        )?;
+
+        Ok(rows_used)
+    }
+
+    fn with_region(region: CtxRegion<'b, 'a, F>, offset: usize) -> Self {
+        RegionCtx {
+            region,
+            offset,
+            namespace: Vec::new(),
+            rows_used: offset,
+            #[cfg(debug_assertions)]
+            constraints: 0,
+            constants: HashMap::new(),
+            bits: HashSet::new(),
+            dry_run: false,
+            records: None,
+            cells: None,
+        }
+    }
+
+    /// Enter the namespace `name`, all annotations of the following assignments will be prefixed
+    /// with it until the matching [`RegionCtx::pop_namespace`]
+    ///
+    /// Namespaces nest, so annotation `s[0]` inside `rlc` & `term[3]` becomes `rlc/term[3]/s[0]`.
+    /// `name` itself is built on every push, only the prefixed annotation is built lazily, when
+    /// halo2 actually renders it.
+    pub fn push_namespace(&mut self, name: impl Into<String>) {
+        self.namespace.push(name.into());
+    }
+
+    /// Leave the innermost namespace entered by [`RegionCtx::push_namespace`]
+    pub fn pop_namespace(&mut self) {
+        self.namespace.pop();
+    }
+
+    /// Run `f` inside the namespace `name`, the namespace is left even if `f` fails
+    pub fn with_namespace<R>(
+        &mut self,
+        name: impl Into<String>,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        self.push_namespace(name);
+        let result = f(self);
+        self.pop_namespace();
+        result
+    }
+
+    /// Record every advice & fixed assignment made through this ctx, to inspect them later with
+    /// [`RegionCtx::take_records`]
+    ///
+    /// Without recorder annotations aren't even rendered, so there is no overhead
+    pub fn with_recorder(mut self) -> Self {
+        self.records = Some(Vec::new());
+        self
+    }
+
+    /// Assignments recorded so far, empty if the ctx was created without
+    /// [`RegionCtx::with_recorder`]
+    pub fn take_records(&mut self) -> Vec<AssignmentRecord<F>> {
+        self.records
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Keep every advice & fixed cell assigned through this ctx, to look them up later with
+    /// [`RegionCtx::cell_at`]
+    pub fn with_cell_registry(mut self) -> Self {
+        self.cells = Some(HashMap::new());
+        self
+    }
+
+    /// Cell assigned into `column` at `offset`, if the ctx was created
+    /// [`RegionCtx::with_cell_registry`] and the cell was assigned through it
+    pub fn cell_at(
+        &self,
+        column: impl Into<Column<Any>>,
+        offset: usize,
+    ) -> Option<&AssignedValue<F>> {
+        self.cells.as_ref()?.get(&(column.into(), offset))
+    }
+
+    fn register(&mut self, column: impl Into<Column<Any>>, cell: &AssignedValue<F>) {
+        if let Some(cells) = self.cells.as_mut() {
+            cells.insert((column.into(), self.offset), cell.clone());
+        }
+    }
+
+    fn record<AR: Into<String>>(
+        &mut self,
+        column: impl Into<Column<Any>>,
+        annotation: impl Fn() -> AR,
+        cell: &AssignedValue<F>,
+    ) {
+        if let Some(records) = self.records.as_mut() {
+            records.push(AssignmentRecord {
+                column: column.into(),
+                offset: self.offset,
+                annotation: namespaced(&self.namespace, annotation()),
+                value: cell.value().copied(),
+            });
+        }
     }
 
     pub fn offset(&self) -> usize {
         self.offset
     }
 
-    pub fn into_region(self) -> Region<'a, F> {
-        self.region
+    /// Number of rows of the region touched by this ctx so far
+    ///
+    /// This is the maximum over all offsets reached by [`RegionCtx::next`],
+    /// [`RegionCtx::next_n`] & [`RegionCtx::reset`] and all rows assigned, so rows written and
+    /// then rolled back by `reset` are still counted
+    pub fn rows_used(&self) -> usize {
+        self.rows_used.max(self.offset)
+    }
+
+    fn mark_row_used(&mut self) {
+        self.rows_used = self.rows_used.max(self.offset + 1);
+    }
+
+    fn mark_constraint(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            self.constraints += 1;
+        }
+    }
+
+    /// Save the current position, to return to it later with [`RegionCtx::rollback`]
+    pub fn checkpoint(&self) -> RegionCheckpoint {
+        RegionCheckpoint {
+            offset: self.offset,
+            records: self.records.as_ref().map_or(0, Vec::len),
+            #[cfg(debug_assertions)]
+            constraints: self.constraints,
+        }
+    }
+
+    /// Return to `checkpoint`, so the rows after it can be laid out again
+    ///
+    /// Values assigned after the checkpoint are overwritten by the next pass, but copy constraints
+    /// can't be removed from the region, so in debug builds this panics if any were registered
+    /// after the checkpoint. Rolled back rows still count in [`RegionCtx::rows_used`].
+    ///
+    /// Cached constants, registered bits & cells in rows from the checkpoint on are forgotten, as
+    /// are records made after it, since the next pass reuses those rows for other cells.
+    pub fn rollback(&mut self, checkpoint: RegionCheckpoint) {
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            self.constraints, checkpoint.constraints,
+            "copy constraints registered after checkpoint can't be rolled back"
+        );
+
+        let offset = checkpoint.offset;
+        self.constants
+            .retain(|_, cell| cell.cell().row_offset < offset);
+        self.bits.retain(|(_, row, _)| *row < offset);
+        if let Some(cells) = self.cells.as_mut() {
+            cells.retain(|(_, row), _| *row < offset);
+        }
+        if let Some(records) = self.records.as_mut() {
+            records.truncate(checkpoint.records);
+        }
+
+        self.reset(offset);
+    }
+
+    /// Region of the ctx, `None` if the ctx was created by [`RegionCtx::new_borrowed`], since
+    /// the caller holds the region then
+    pub fn into_region(self) -> Option<Region<'a, F>> {
+        match self.region {
+            CtxRegion::Owned(region) => Some(region),
+            CtxRegion::Borrowed(_) => None,
+        }
     }
 
     pub fn assign_fixed<A, AR>(
@@ -41,13 +445,51 @@ impl<'a, F: PrimeField> RegionCtx<'a, F> {
         annotation: A,
         column: Column<Fixed>,
         value: F,
-    ) -> Result<AssignedValue<F>, Error>
+    ) -> Result<AssignedValue<F>, AssignmentError>
     where
         A: Fn() -> AR,
         AR: Into<String>,
     {
-        self.region
-            .assign_fixed(annotation, column, self.offset, || Value::known(value))
+        self.assign_fixed_value(annotation, column, Value::known(value))
+    }
+
+    /// Same as [`RegionCtx::assign_fixed`], but takes [`Value`], so callers don't need to unwrap
+    /// it themselves
+    pub fn assign_fixed_value<A, AR>(
+        &mut self,
+        annotation: A,
+        column: Column<Fixed>,
+        value: Value<F>,
+    ) -> Result<AssignedValue<F>, AssignmentError>
+    where
+        A: Fn() -> AR,
+        AR: Into<String>,
+    {
+        let value = if self.dry_run {
+            Value::unknown()
+        } else {
+            value
+        };
+        self.mark_row_used();
+        let namespace = &self.namespace;
+        let cell = self
+            .region
+            .get()
+            .assign_fixed(
+                || namespaced(namespace, annotation()),
+                column,
+                self.offset,
+                || value,
+            )
+            .map_err(|err| AssignmentError {
+                err,
+                column: Some(column.into()),
+                offset: self.offset,
+                annotation: namespaced(namespace, annotation()),
+            })?;
+        self.record(column, annotation, &cell);
+        self.register(column, &cell);
+        Ok(cell)
     }
 
     pub fn assign_advice<A, AR>(
@@ -55,13 +497,53 @@ impl<'a, F: PrimeField> RegionCtx<'a, F> {
         annotation: A,
         column: Column<Advice>,
         value: Value<F>,
-    ) -> Result<AssignedValue<F>, Error>
+    ) -> Result<AssignedValue<F>, AssignmentError>
     where
         A: Fn() -> AR,
         AR: Into<String>,
     {
-        self.region
-            .assign_advice(annotation, column, self.offset, || value)
+        self.assign_advice_with(annotation, column, || value)
+    }
+
+    /// Same as [`RegionCtx::assign_advice`], but the value is computed lazily by `value`
+    ///
+    /// `value` is called only when the backend actually needs the witness, so expensive witness
+    /// computations are skipped during keygen, preprocessing & [`RegionCtx::dry_run`]
+    pub fn assign_advice_with<A, AR>(
+        &mut self,
+        annotation: A,
+        column: Column<Advice>,
+        value: impl FnOnce() -> Value<F>,
+    ) -> Result<AssignedValue<F>, AssignmentError>
+    where
+        A: Fn() -> AR,
+        AR: Into<String>,
+    {
+        self.mark_row_used();
+        let dry_run = self.dry_run;
+        let mut value = Some(value);
+        let namespace = &self.namespace;
+        let cell = self
+            .region
+            .get()
+            .assign_advice(
+                || namespaced(namespace, annotation()),
+                column,
+                self.offset,
+                || match value.take() {
+                    Some(value) if !dry_run => value(),
+                    _ => Value::unknown(),
+                },
+            )
+            .map_err(|err| AssignmentError {
+                err,
+                column: Some(column.into()),
+                offset: self.offset,
+                annotation: namespaced(namespace, annotation()),
+            })?;
+        self.record(column, annotation, &cell);
+        self.register(column, &cell);
+        Ok(cell)
     }
 
     pub fn assign_advice_from<A, AR>(
@@ -69,7 +551,7 @@ impl<'a, F: PrimeField> RegionCtx<'a, F> {
         annotation: A,
         dst: Column<Advice>,
         src: impl AssignAdviceFrom<'a, F>,
-    ) -> Result<AssignedCell<F, F>, Error>
+    ) -> Result<AssignedCell<F, F>, AssignmentError>
     where
         A: Fn() -> AR,
         AR: Into<String>,
@@ -77,35 +559,277 @@ impl<'a, F: PrimeField> RegionCtx<'a, F> {
         AssignAdviceFrom::assign_advice_from(self, annotation, dst, src)
     }
 
+    /// Assign `values` down `column`, one per row starting from the current offset, and move to
+    /// the row after the last one
+    pub fn assign_each<A, AR>(
+        &mut self,
+        annotation: A,
+        column: Column<Advice>,
+        values: impl IntoIterator<Item = Value<F>>,
+    ) -> Result<Vec<AssignedValue<F>>, AssignmentError>
+    where
+        A: Fn() -> AR,
+        AR: Into<String>,
+    {
+        values
+            .into_iter()
+            .map(|value| {
+                let cell = self.assign_advice(&annotation, column, value)?;
+                self.next();
+                Ok(cell)
+            })
+            .collect()
+    }
+
+    /// Same as [`RegionCtx::assign_each`], but also assigns `fixed_value` into `fixed` at every
+    /// row, e.g. to enable a custom gate on all of them
+    pub fn assign_each_with_fixed<A, AR>(
+        &mut self,
+        annotation: A,
+        column: Column<Advice>,
+        values: impl IntoIterator<Item = Value<F>>,
+        fixed: Column<Fixed>,
+        fixed_value: F,
+    ) -> Result<Vec<AssignedValue<F>>, AssignmentError>
+    where
+        A: Fn() -> AR,
+        AR: Into<String>,
+    {
+        values
+            .into_iter()
+            .map(|value| {
+                self.assign_fixed(&annotation, fixed, fixed_value)?;
+                let cell = self.assign_advice(&annotation, column, value)?;
+                self.next();
+                Ok(cell)
+            })
+            .collect()
+    }
+
+    /// Copy `cell` into `column` at the current offset, same as [`AssignedCell::copy_advice`]
+    ///
+    /// `cell` can be from any region, the copy is linked to it by an equality constraint
+    pub fn copy_advice<A, AR>(
+        &mut self,
+        annotation: A,
+        column: Column<Advice>,
+        cell: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, AssignmentError>
+    where
+        A: Fn() -> AR,
+        AR: Into<String>,
+    {
+        let copy = self.assign_advice(annotation, column, cell.value().copied())?;
+        self.constrain_cells_equal(&copy, cell)?;
+        Ok(copy)
+    }
+
     pub fn assign_advice_from_instance<A, AR>(
         &mut self,
         annotation: A,
         column: Column<Advice>,
         instance: Column<Instance>,
         instance_offset: usize,
-    ) -> Result<AssignedValue<F>, Error>
+    ) -> Result<AssignedValue<F>, AssignmentError>
     where
         A: Fn() -> AR,
         AR: Into<String>,
     {
-        self.region.assign_advice_from_instance(
-            annotation,
-            instance,
-            instance_offset,
-            column,
-            self.offset,
-        )
+        self.mark_row_used();
+        self.mark_constraint();
+        let namespace = &self.namespace;
+        let cell = self
+            .region
+            .get()
+            .assign_advice_from_instance(
+                || namespaced(namespace, annotation()),
+                instance,
+                instance_offset,
+                column,
+                self.offset,
+            )
+            .map_err(|err| AssignmentError {
+                err,
+                column: Some(column.into()),
+                offset: self.offset,
+                annotation: namespaced(namespace, annotation()),
+            })?;
+        self.record(column, annotation, &cell);
+        self.register(column, &cell);
+        Ok(cell)
+    }
+
+    /// Enable `selector` at the current offset, so custom gates can be mixed with [`MainGate`]
+    /// rows in the same region
+    pub fn enable_selector<A, AR>(
+        &mut self,
+        annotation: A,
+        selector: &Selector,
+    ) -> Result<(), AssignmentError>
+    where
+        A: Fn() -> AR,
+        AR: Into<String>,
+    {
+        self.mark_row_used();
+        let namespace = &self.namespace;
+        self.region
+            .get()
+            .enable_selector(
+                || namespaced(namespace, annotation()),
+                selector,
+                self.offset,
+            )
+            .map_err(|err| AssignmentError {
+                err,
+                column: None,
+                offset: self.offset,
+                annotation: namespaced(namespace, annotation()),
+            })
+    }
+
+    /// Constrain `cell_0` & `cell_1` to be equal, an error is reported at `cell_0`
+    pub fn constrain_equal(&mut self, cell_0: Cell, cell_1: Cell) -> Result<(), AssignmentError> {
+        self.mark_constraint();
+        self.region
+            .get()
+            .constrain_equal(cell_0, cell_1)
+            .map_err(|err| AssignmentError {
+                err,
+                column: Some(cell_0.column),
+                offset: cell_0.row_offset,
+                annotation: namespaced(
+                    &self.namespace,
+                    format!(
+                        "copy of {:?} at offset {}",
+                        cell_1.column, cell_1.row_offset
+                    ),
+                ),
+            })
+    }
+
+    /// Same as [`RegionCtx::constrain_equal`], but takes assigned cells directly
+    pub fn constrain_cells_equal(
+        &mut self,
+        lhs: &AssignedValue<F>,
+        rhs: &AssignedValue<F>,
+    ) -> Result<(), AssignmentError> {
+        self.constrain_equal(lhs.cell(), rhs.cell())
+    }
+
+    /// Constrain all `cells` to be equal, by chaining each one to the previous
+    pub fn constrain_all_equal(
+        &mut self,
+        cells: &[&AssignedValue<F>],
+    ) -> Result<(), AssignmentError> {
+        cells
+            .iter()
+            .tuple_windows()
+            .try_for_each(|(lhs, rhs)| self.constrain_cells_equal(lhs, rhs))
+    }
+
+    /// Constrains `cell` to be equal to `constant`
+    ///
+    /// The constant itself is placed by the layouter into one of the columns enabled by
+    /// [`ConstraintSystem::enable_constant`], so the caller does not need to allocate a fixed
+    /// cell by hand. If no such column is enabled, synthesis fails with
+    /// [`Error::NotEnoughColumnsForConstants`].
+    pub fn constrain_constant(&mut self, cell: Cell, constant: F) -> Result<(), AssignmentError> {
+        if self.dry_run {
+            // there are no constants columns in dry run
+            return Ok(());
+        }
+        self.mark_constraint();
+        self.region
+            .get()
+            .constrain_constant(cell, constant)
+            .map_err(|err| AssignmentError {
+                err,
+                column: Some(cell.column),
+                offset: cell.row_offset,
+                annotation: namespaced(&self.namespace, format!("constant {constant:?}")),
+            })
+    }
+
+    /// Assign `value` into `column` at the current offset & constrain it to the constant
+    ///
+    /// Constants are cached per ctx: if `value` was already assigned by this method or by
+    /// [`RegionCtx::assign_constant_to`], the cached cell is returned and nothing is assigned.
+    /// Requires a column enabled by [`ConstraintSystem::enable_constant`].
+    pub fn assign_constant(
+        &mut self,
+        column: Column<Advice>,
+        value: F,
+    ) -> Result<AssignedValue<F>, AssignmentError> {
+        match self.constants.get(value.to_repr().as_ref()) {
+            Some(cell) => Ok(cell.clone()),
+            None => self.assign_constant_to(column, value),
+        }
+    }
+
+    /// Same as [`RegionCtx::assign_constant`], but always assigns a fresh cell into `column`
+    ///
+    /// If `value` is already cached, the fresh cell is constrained to be equal to the cached one,
+    /// instead of registering one more constant.
+    pub fn assign_constant_to(
+        &mut self,
+        column: Column<Advice>,
+        value: F,
+    ) -> Result<AssignedValue<F>, AssignmentError> {
+        let cell = self.assign_advice(|| "constant", column, Value::known(value))?;
+
+        let key = value.to_repr().as_ref().to_vec();
+        match self.constants.get(&key).cloned() {
+            Some(cached) => self.constrain_cells_equal(&cell, &cached)?,
+            None => {
+                self.constrain_constant(cell.cell(), value)?;
+                self.constants.insert(key, cell.clone());
+            }
+        }
+
+        Ok(cell)
+    }
+
+    /// Number of distinct constants registered through [`RegionCtx::assign_constant`],
+    /// [`RegionCtx::assign_constant_to`] & [`RegionCtx::cache_constant`]
+    pub fn constants_used(&self) -> usize {
+        self.constants.len()
+    }
+
+    /// Cached cell constrained to `value`, see [`RegionCtx::assign_constant`]
+    pub fn cached_constant(&self, value: F) -> Option<&AssignedValue<F>> {
+        self.constants.get(value.to_repr().as_ref())
+    }
+
+    /// Cache `cell` as constrained to `value`, for cells constrained by gadgets rather than by
+    /// a constants column
+    ///
+    /// A cell cached for `value` before is kept.
+    pub fn cache_constant(&mut self, value: F, cell: &AssignedValue<F>) {
+        self.constants
+            .entry(value.to_repr().as_ref().to_vec())
+            .or_insert_with(|| cell.clone());
     }
 
-    pub fn constrain_equal(&mut self, cell_0: Cell, cell_1: Cell) -> Result<(), Error> {
-        self.region.constrain_equal(cell_0, cell_1)
+    /// Remember `cell` as constrained to be boolean, returns `false` if it already was
+    ///
+    /// Lets gadgets skip the booleanity row for cells checked before, like constants are cached
+    /// by [`RegionCtx::assign_constant`]
+    pub fn register_bit(&mut self, cell: Cell) -> bool {
+        self.bits
+            .insert((*cell.region_index, cell.row_offset, cell.column))
     }
 
     pub fn next(&mut self) {
-        self.offset += 1
+        self.next_n(1)
+    }
+
+    pub fn next_n(&mut self, n: usize) {
+        self.offset += n;
+        self.rows_used = self.rows_used.max(self.offset);
     }
 
     pub(crate) fn reset(&mut self, offset: usize) {
+        self.rows_used = self.rows_used.max(self.offset);
         self.offset = offset
     }
 }
@@ -115,11 +839,11 @@ mod assign_advice_from {
 
     pub trait AssignAdviceFrom<'a, F: PrimeField> {
         fn assign_advice_from<A, AR>(
-            ctx: &mut RegionCtx<'a, F>,
+            ctx: &mut RegionCtx<'_, 'a, F>,
             annotation: A,
             dst: Column<Advice>,
             src: Self,
-        ) -> Result<AssignedCell<F, F>, Error>
+        ) -> Result<AssignedCell<F, F>, AssignmentError>
         where
             A: Fn() -> AR,
             AR: Into<String>;
@@ -127,28 +851,26 @@ mod assign_advice_from {
 
     impl<'a, F: PrimeField> AssignAdviceFrom<'a, F> for &AssignedCell<F, F> {
         fn assign_advice_from<A, AR>(
-            ctx: &mut RegionCtx<'a, F>,
+            ctx: &mut RegionCtx<'_, 'a, F>,
             annotation: A,
             dst: Column<Advice>,
             src: Self,
-        ) -> Result<AssignedCell<F, F>, Error>
+        ) -> Result<AssignedCell<F, F>, AssignmentError>
         where
             A: Fn() -> AR,
             AR: Into<String>,
         {
-            let cell = ctx.assign_advice(annotation, dst, src.value().map(|f| *f))?;
-            ctx.constrain_equal(cell.cell(), src.cell())?;
-            Ok(cell)
+            ctx.copy_advice(annotation, dst, src)
         }
     }
 
     impl<'a, F: PrimeField> AssignAdviceFrom<'a, F> for AssignedCell<F, F> {
         fn assign_advice_from<A, AR>(
-            ctx: &mut RegionCtx<'a, F>,
+            ctx: &mut RegionCtx<'_, 'a, F>,
             annotation: A,
             dst: Column<Advice>,
             src: Self,
-        ) -> Result<AssignedCell<F, F>, Error>
+        ) -> Result<AssignedCell<F, F>, AssignmentError>
         where
             A: Fn() -> AR,
             AR: Into<String>,
@@ -159,11 +881,11 @@ mod assign_advice_from {
 
     impl<'a, F: PrimeField> AssignAdviceFrom<'a, F> for &F {
         fn assign_advice_from<A, AR>(
-            ctx: &mut RegionCtx<'a, F>,
+            ctx: &mut RegionCtx<'_, 'a, F>,
             annotation: A,
             dst: Column<Advice>,
             src: Self,
-        ) -> Result<AssignedCell<F, F>, Error>
+        ) -> Result<AssignedCell<F, F>, AssignmentError>
         where
             A: Fn() -> AR,
             AR: Into<String>,
@@ -174,17 +896,16 @@ mod assign_advice_from {
 
     impl<'a, F: PrimeField> AssignAdviceFrom<'a, F> for F {
         fn assign_advice_from<A, AR>(
-            ctx: &mut RegionCtx<'a, F>,
+            ctx: &mut RegionCtx<'_, 'a, F>,
             annotation: A,
             dst: Column<Advice>,
             src: Self,
-        ) -> Result<AssignedCell<F, F>, Error>
+        ) -> Result<AssignedCell<F, F>, AssignmentError>
         where
             A: Fn() -> AR,
             AR: Into<String>,
         {
-            let cell = ctx.assign_advice(annotation, dst, Value::known(src))?;
-            Ok(cell)
+            ctx.assign_advice(annotation, dst, Value::known(src))
         }
     }
 }
@@ -254,6 +975,10 @@ impl<F: PrimeField> From<&AssignedValue<F>> for WrapValue<F> {
 
 const MULTIPLICATION_COUNT: usize = 2;
 
+/// Number of terms above which [`MainGate::rlc_assigned`] switches to [`MainGate::rlc_wide`],
+/// which takes fewer rows from five terms on
+pub const RLC_WIDE_THRESHOLD: usize = 4;
+
 #[derive(Clone, Debug)]
 pub struct MainGateConfig<const T: usize> {
     pub(crate) state: [Column<Advice>; T],
@@ -267,6 +992,18 @@ pub struct MainGateConfig<const T: usize> {
     pub(crate) q_i: Column<Fixed>,
     pub(crate) q_o: Column<Fixed>,
     pub(crate) rc: Column<Fixed>,
+    // public inputs available to gadgets, if configured
+    pub(crate) instance: Option<Column<Instance>>,
+    // see `MainGate::configure_with_range_table`
+    pub(crate) range_table: Option<RangeTableConfig>,
+}
+
+/// Lookup of the state columns into the table `0..2^bits`, on rows where `q_range` is set
+#[derive(Clone, Copy, Debug)]
+pub struct RangeTableConfig {
+    pub(crate) q_range: Column<Fixed>,
+    pub(crate) table: TableColumn,
+    pub(crate) bits: usize,
 }
 
 impl<const T: usize> MainGateConfig<T> {
@@ -303,6 +1040,46 @@ impl<const T: usize> MainGateConfig<T> {
         }
 
         name_column!(rc);
+
+        if let Some(instance) = self.instance {
+            region.name_column(|| "instance", instance);
+        }
+
+        if let Some(range_table) = self.range_table {
+            region.name_column(|| "q_range", range_table.q_range);
+        }
+    }
+
+    /// Names of the columns for [`crate::polynomial::Expression::display_with`], the same as
+    /// [`MainGateConfig::name_columns`], `Z_i` are numbered by `ctx`
+    pub fn var_names(&self, ctx: &QueryIndexContext) -> VarNames {
+        let mut names = VarNames::default();
+        for (i, ((state, q_1), q_5)) in self.state.iter().zip(&self.q_1).zip(&self.q_5).enumerate()
+        {
+            names = names
+                .with_advice(ctx, *state, &format!("state[{i}]"))
+                .with_fixed(ctx, *q_1, &format!("q_1[{i}]"))
+                .with_fixed(ctx, *q_5, &format!("q_5[{i}]"));
+        }
+        for (i, q_m) in self.q_m.iter().enumerate() {
+            names = names.with_fixed(ctx, *q_m, &format!("q_m[{i}]"));
+        }
+
+        names = names
+            .with_advice(ctx, self.input, "input")
+            .with_advice(ctx, self.out, "out")
+            .with_fixed(ctx, self.q_i, "q_i")
+            .with_fixed(ctx, self.q_o, "q_o")
+            .with_fixed(ctx, self.rc, "rc");
+
+        if let Some(instance) = self.instance {
+            names = names.with_instance(ctx, instance, "instance");
+        }
+        if let Some(range_table) = self.range_table {
+            names = names.with_fixed(ctx, range_table.q_range, "q_range");
+        }
+
+        names
     }
 
     /// Converts the current `MainGateConfig` to a new configuration with a smaller size `N`.
@@ -337,7 +1114,73 @@ impl<const T: usize> MainGateConfig<T> {
             q_i: self.q_i,
             q_o: self.q_o,
             rc: self.rc,
+            instance: self.instance,
+            range_table: self.range_table,
+        })
+    }
+
+    /// Render main gate cells from `records` as one line per offset in `rows`
+    ///
+    /// Every line lists `name=value` for the columns of this config, in the order
+    /// `state`, `input`, `out`, `q_m`, `q_1`, `q_5`, `q_i`, `q_o`, `rc`. Cells that were not
+    /// assigned are rendered as `-`, unknown values as `?`.
+    pub fn format_rows<F: PrimeField>(
+        &self,
+        records: &[AssignmentRecord<F>],
+        rows: Range<usize>,
+    ) -> String {
+        let named = |name: &str, columns: &[Column<Any>]| {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| (format!("{name}[{i}]"), *column))
+                .collect::<Vec<_>>()
+        };
+        let to_any = |columns: &[Column<Fixed>]| {
+            columns
+                .iter()
+                .map(|column| Column::<Any>::from(*column))
+                .collect::<Vec<_>>()
+        };
+
+        let columns = named("state", &self.state.map(Column::<Any>::from))
+            .into_iter()
+            .chain([
+                ("input".to_owned(), self.input.into()),
+                ("out".to_owned(), self.out.into()),
+            ])
+            .chain(named("q_m", &to_any(&self.q_m)))
+            .chain(named("q_1", &to_any(&self.q_1)))
+            .chain(named("q_5", &to_any(&self.q_5)))
+            .chain([
+                ("q_i".to_owned(), self.q_i.into()),
+                ("q_o".to_owned(), self.q_o.into()),
+                ("rc".to_owned(), self.rc.into()),
+            ])
+            .collect::<Vec<(String, Column<Any>)>>();
+
+        rows.map(|offset| {
+            let cells = columns
+                .iter()
+                .map(|(name, column)| {
+                    let value = records
+                        .iter()
+                        .rev()
+                        .find(|record| record.offset == offset && record.column == *column)
+                        .map(|record| match record.value.unwrap() {
+                            Some(value) if value.is_zero_vartime() => "0x0".to_owned(),
+                            Some(value) => util::trim_leading_zeros(format!("{value:?}")),
+                            None => "?".to_owned(),
+                        })
+                        .unwrap_or_else(|| "-".to_owned());
+
+                    format!("{name}={value}")
+                })
+                .join(" ");
+
+            format!("{offset}: {cells}")
         })
+        .join("\n")
     }
 
     /// Iterated over all fixed columns in config
@@ -378,6 +1221,113 @@ impl<const T: usize> MainGateConfig<T> {
     }
 }
 
+/// Values of one complete [`MainGate`] row, see [`RegionCtx::assign_row`]
+///
+/// [`Default`] is an all-zero row, so only the used cells need to be set
+#[derive(Clone, Debug)]
+pub struct MainGateRow<F: PrimeField, const T: usize> {
+    pub state: [Value<F>; T],
+    pub input: Value<F>,
+    pub out: Value<F>,
+    pub q_m: [F; MULTIPLICATION_COUNT],
+    pub q_1: [F; T],
+    pub q_5: [F; T],
+    pub q_i: F,
+    pub q_o: F,
+    pub rc: F,
+}
+
+impl<F: PrimeField, const T: usize> Default for MainGateRow<F, T> {
+    fn default() -> Self {
+        Self {
+            state: [Value::known(F::ZERO); T],
+            input: Value::known(F::ZERO),
+            out: Value::known(F::ZERO),
+            q_m: [F::ZERO; MULTIPLICATION_COUNT],
+            q_1: [F::ZERO; T],
+            q_5: [F::ZERO; T],
+            q_i: F::ZERO,
+            q_o: F::ZERO,
+            rc: F::ZERO,
+        }
+    }
+}
+
+/// Advice cells assigned by [`RegionCtx::assign_row`]
+#[derive(Clone, Debug)]
+pub struct AssignedRow<F: PrimeField, const T: usize> {
+    pub state: [AssignedValue<F>; T],
+    pub input: AssignedValue<F>,
+    pub out: AssignedValue<F>,
+}
+
+impl<F: PrimeField> RegionCtx<'_, '_, F> {
+    /// Assign every cell of the current [`MainGate`] row and move to the next one
+    pub fn assign_row<const T: usize>(
+        &mut self,
+        config: &MainGateConfig<T>,
+        row: MainGateRow<F, T>,
+    ) -> Result<AssignedRow<F, T>, AssignmentError> {
+        let out = row.out;
+        self.assign_row_with(config, row, move || out)
+    }
+
+    /// Same as [`RegionCtx::assign_row`], but the `out` cell is computed lazily by `out`, see
+    /// [`RegionCtx::assign_advice_with`], `row.out` is not used
+    pub fn assign_row_with<const T: usize>(
+        &mut self,
+        config: &MainGateConfig<T>,
+        row: MainGateRow<F, T>,
+        out: impl FnOnce() -> Value<F>,
+    ) -> Result<AssignedRow<F, T>, AssignmentError> {
+        self.assign_row_with_copies(config, row, [None; T], out)
+    }
+
+    /// Same as [`RegionCtx::assign_row_with`], but the state cells of `copies` are copied from
+    /// assigned cells by [`RegionCtx::assign_advice_from`], their `row.state` values are not used
+    pub fn assign_row_with_copies<const T: usize>(
+        &mut self,
+        config: &MainGateConfig<T>,
+        row: MainGateRow<F, T>,
+        copies: [Option<&AssignedValue<F>>; T],
+        out: impl FnOnce() -> Value<F>,
+    ) -> Result<AssignedRow<F, T>, AssignmentError> {
+        let state = row
+            .state
+            .iter()
+            .zip(config.state.iter())
+            .zip(copies)
+            .enumerate()
+            .map(|(i, ((value, column), copy))| match copy {
+                Some(cell) => self.assign_advice_from(|| format!("s[{i}]"), *column, cell),
+                None => self.assign_advice(|| format!("s[{i}]"), *column, *value),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let input = self.assign_advice(|| "input", config.input, row.input)?;
+        let out = self.assign_advice_with(|| "out", config.out, out)?;
+
+        for (i, (q_m, column)) in row.q_m.iter().zip(config.q_m.iter()).enumerate() {
+            self.assign_fixed_value(|| format!("q_m[{i}]"), *column, Value::known(*q_m))?;
+        }
+        for i in 0..T {
+            let (q_1, q_5) = (Value::known(row.q_1[i]), Value::known(row.q_5[i]));
+            self.assign_fixed_value(|| format!("q_1[{i}]"), config.q_1[i], q_1)?;
+            self.assign_fixed_value(|| format!("q_5[{i}]"), config.q_5[i], q_5)?;
+        }
+        self.assign_fixed_value(|| "q_i", config.q_i, Value::known(row.q_i))?;
+        self.assign_fixed_value(|| "q_o", config.q_o, Value::known(row.q_o))?;
+        self.assign_fixed_value(|| "rc", config.rc, Value::known(row.rc))?;
+
+        self.next();
+
+        Ok(AssignedRow {
+            state: state.try_into().unwrap(),
+            input,
+            out,
+        })
+    }
+}
+
 // Macro to create structs and impl for both fixed and advice columns
 macro_rules! create_column_cycle {
     (
@@ -397,14 +1347,14 @@ macro_rules! create_column_cycle {
         pub trait $trait_name<F: PrimeField> {
             fn $assign_next_fn_name<AR: Into<String>>(
                 &mut self,
-                region: &mut RegionCtx<'_, F>,
+                region: &mut RegionCtx<'_, '_, F>,
                 annotation: impl Fn() -> AR,
                 value: F,
             ) -> Result<AssignedCell<F, F>, halo2_proofs::plonk::Error>;
 
             fn $assign_next_collection_fn_name<AR: Into<String>>(
                 &mut self,
-                region: &mut RegionCtx<'_, F>,
+                region: &mut RegionCtx<'_, '_, F>,
                 annotation: impl Clone + Fn() -> AR,
                 values: impl Iterator<Item = F>,
             ) -> Result<Vec<AssignedCell<F, F>>, halo2_proofs::plonk::Error>;
@@ -417,7 +1367,7 @@ macro_rules! create_column_cycle {
         {
             fn $assign_next_fn_name<AR: Into<String>>(
                 &mut self,
-                region: &mut RegionCtx<'_, F>,
+                region: &mut RegionCtx<'_, '_, F>,
                 annotation: impl Fn() -> AR,
                 value: F,
             ) -> Result<AssignedCell<F, F>, halo2_proofs::plonk::Error> {
@@ -430,12 +1380,12 @@ macro_rules! create_column_cycle {
                 self.first_pass = false;
 
                 let wrapper = $value_wrapper;
-                region.$region_assign_fn(annotation, *column, wrapper(value))
+                Ok(region.$region_assign_fn(annotation, *column, wrapper(value))?)
             }
 
             fn $assign_next_collection_fn_name<AR: Into<String>>(
                 &mut self,
-                region: &mut RegionCtx<'_, F>,
+                region: &mut RegionCtx<'_, '_, F>,
                 annotation: impl Clone + Fn() -> AR,
                 values: impl Iterator<Item = F>,
             ) -> Result<Vec<AssignedCell<F, F>>, halo2_proofs::plonk::Error> {
@@ -495,6 +1445,23 @@ impl<F: PrimeField, const T: usize> MainGate<F, T> {
     }
 
     pub fn configure(meta: &mut ConstraintSystem<F>) -> MainGateConfig<T> {
+        Self::configure_columns(meta, None)
+    }
+
+    /// Same as [`MainGate::configure`], but also attaches the `instance` column to the config,
+    /// so gadgets can pull public inputs into gate rows with [`MainGate::assign_from_instance`]
+    pub fn configure_with_instance(
+        meta: &mut ConstraintSystem<F>,
+        instance: Column<Instance>,
+    ) -> MainGateConfig<T> {
+        meta.enable_equality(instance);
+        Self::configure_columns(meta, Some(instance))
+    }
+
+    fn configure_columns(
+        meta: &mut ConstraintSystem<F>,
+        instance: Option<Column<Instance>>,
+    ) -> MainGateConfig<T> {
         assert!(T >= 2);
         let state = array::from_fn(|_| meta.advice_column());
         let input = meta.advice_column();
@@ -554,14 +1521,170 @@ impl<F: PrimeField, const T: usize> MainGate<F, T> {
             q_i,
             q_o,
             rc,
+            instance,
+            range_table: None,
+        }
+    }
+
+    /// Same as [`MainGate::configure`], but also looks up the state columns of rows marked by
+    /// `q_range` into the table `0..2^bits`, see [`MainGate::range_check_lookup`]
+    ///
+    /// The table itself is filled by [`MainGate::load_range_table`], once per circuit.
+    pub fn configure_with_range_table(
+        meta: &mut ConstraintSystem<F>,
+        bits: usize,
+    ) -> MainGateConfig<T> {
+        let mut config = Self::configure_columns(meta, None);
+
+        let q_range = meta.fixed_column();
+        let table = meta.lookup_table_column();
+        for column in config.state {
+            meta.lookup("range", |meta| {
+                let q_range = meta.query_fixed(q_range, Rotation::cur());
+                let value = meta.query_advice(column, Rotation::cur());
+                vec![(q_range * value, table)]
+            });
+        }
+
+        config.range_table = Some(RangeTableConfig {
+            q_range,
+            table,
+            bits,
+        });
+        config
+    }
+
+    /// Fill the table of [`MainGate::configure_with_range_table`] with `0..2^bits`
+    ///
+    /// Fails with [`Error::Synthesis`] if the config was created without a range table
+    pub fn load_range_table(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+        let Some(range_table) = self.config.range_table else {
+            error!("range table isn't configured");
+            return Err(Error::Synthesis);
+        };
+
+        layouter.assign_table(
+            || "range",
+            |mut table| {
+                for value in 0..1u64 << range_table.bits {
+                    table.assign_cell(
+                        || "range",
+                        range_table.table,
+                        value as usize,
+                        || Value::known(F::from(value)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Load `values` into the state columns, filling them column by column, and move to the row
+    /// after the loaded ones
+    ///
+    /// Takes `values.len().div_ceil(T)` rows, the returned cells are in the order of `values`
+    pub fn load_vector(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        values: &[Value<F>],
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        if values.is_empty() {
+            return Ok(vec![]);
         }
+
+        let start = ctx.offset();
+        let rows = values.len().div_ceil(T);
+
+        let cells = ctx.with_namespace("load_vector", |ctx| {
+            values
+                .chunks(rows)
+                .zip(self.config.state.iter())
+                .enumerate()
+                .map(|(i, (chunk, column))| {
+                    ctx.reset(start);
+                    ctx.assign_each(|| format!("s[{i}]"), *column, chunk.iter().copied())
+                })
+                .flatten_ok()
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        ctx.reset(start + rows);
+
+        Ok(cells)
+    }
+
+    /// Copy `instance[row]` into the `out` column of the current row and move to the next one
+    ///
+    /// Fails with [`Error::Synthesis`] if the config was created without an instance column,
+    /// see [`MainGate::configure_with_instance`]
+    pub fn assign_from_instance(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        row: usize,
+    ) -> Result<AssignedValue<F>, Error> {
+        let Some(instance) = self.config.instance else {
+            error!("main gate configured without instance column");
+            return Err(Error::Synthesis);
+        };
+
+        let out = ctx.assign_advice_from_instance(
+            || "out from instance",
+            self.config.out,
+            instance,
+            row,
+        )?;
+        ctx.next();
+        Ok(out)
+    }
+
+    /// Constrain `cell` to be equal to `instance[row]`, i.e. make it a public input
+    ///
+    /// Exposing the same cell to the same row again is a no-op, since the cells are already in
+    /// one permutation cycle. Fails with [`Error::Synthesis`] if the config was created without
+    /// an instance column, see [`MainGate::configure_with_instance`]
+    pub fn expose_public(
+        &self,
+        mut layouter: impl Layouter<F>,
+        cell: &AssignedValue<F>,
+        row: usize,
+    ) -> Result<(), Error> {
+        let Some(instance) = self.config.instance else {
+            error!("main gate configured without instance column");
+            return Err(Error::Synthesis);
+        };
+
+        layouter.constrain_instance(cell.cell(), instance, row)
+    }
+
+    /// Constrain every state cell at `lhs_offset` to be equal to the state cell of the same
+    /// column at `rhs_offset`
+    ///
+    /// Cells are taken from the ctx registry, so `ctx` must be created
+    /// [`RegionCtx::with_cell_registry`], otherwise fails with [`Error::Synthesis`]
+    pub fn link_rows(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        lhs_offset: usize,
+        rhs_offset: usize,
+    ) -> Result<(), Error> {
+        self.config.state.iter().try_for_each(|column| {
+            let (Some(lhs), Some(rhs)) = (
+                ctx.cell_at(*column, lhs_offset).cloned(),
+                ctx.cell_at(*column, rhs_offset).cloned(),
+            ) else {
+                error!("no state cell in {column:?} at {lhs_offset} or {rhs_offset} to link");
+                return Err(Error::Synthesis);
+            };
+
+            Ok(ctx.constrain_cells_equal(&lhs, &rhs)?)
+        })
     }
 
     // helper function for some usecases: no copy constraints, only return out cell
     // state: (q_1, q_m, state), out: (q_o, out)
     pub fn apply(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         state: (Option<Vec<F>>, Option<Vec<F>>, Option<Vec<WrapValue<F>>>),
         rc: Option<F>,
         out: (F, WrapValue<F>),
@@ -623,7 +1746,7 @@ impl<F: PrimeField, const T: usize> MainGate<F, T> {
 
     pub fn apply_with_input(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         state: (Option<Vec<F>>, Option<F>, Option<Vec<WrapValue<F>>>),
         input: (Option<F>, Option<WrapValue<F>>),
         out: (F, WrapValue<F>),
@@ -689,58 +1812,162 @@ impl<F: PrimeField, const T: usize> MainGate<F, T> {
         Ok(res)
     }
 
+    /// Number of rows consumed by [`MainGate::random_linear_combination`] for `terms_len` terms
+    ///
+    /// Zero terms cost at most one row, none if the zero constant is already cached in the region
+    pub const fn rlc_row_cost(terms_len: usize) -> usize {
+        if terms_len < 2 {
+            1
+        } else {
+            terms_len - 1
+        }
+    }
+
     // calculate sum_{i=0}^d r^i terms[i]
+    //
+    // no terms give an assigned zero, a single term is assigned as is into `out`
     pub fn random_linear_combination(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         terms: Vec<F>,
         r: F,
     ) -> Result<AssignedValue<F>, Error> {
+        self.rlc_returning_terms(ctx, terms, r)
+            .map(|(_terms, out)| out)
+    }
+
+    /// [`MainGate::random_linear_combination`] that also returns the cells holding `terms`, in
+    /// order, so callers can copy-constrain them to the data being combined
+    pub fn rlc_returning_terms(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: Vec<F>,
+        r: F,
+    ) -> Result<(Vec<AssignedValue<F>>, AssignedValue<F>), Error> {
+        match terms.as_slice() {
+            [] => {
+                return ctx.with_namespace("rlc", |ctx| {
+                    Ok((vec![], self.assign_constant(ctx, F::ZERO)?))
+                })
+            }
+            [term] => {
+                let term = Value::known(*term);
+                return ctx.with_namespace("rlc", |ctx| {
+                    // out = input
+                    let row = ctx.assign_row(
+                        &self.config,
+                        MainGateRow {
+                            input: term,
+                            out: term,
+                            q_i: F::ONE,
+                            q_o: -F::ONE,
+                            ..Default::default()
+                        },
+                    )?;
+                    Ok((vec![row.input], row.out))
+                });
+            }
+            _ => {}
+        }
+
         let d = terms.len();
         let mut out: Option<AssignedValue<F>> = None;
-        for i in 1..d {
-            let lhs_val = Value::known(terms[d - 1 - i]);
-            let rhs_val = if i == 1 {
-                Value::known(terms[d - i])
+        let mut r_cell: Option<AssignedValue<F>> = None;
+        // filled from the last term, which is only assigned into `s[1]` of the first row
+        let mut term_cells = Vec::with_capacity(d);
+        ctx.with_namespace("rlc", |ctx| {
+            for i in 1..d {
+                ctx.with_namespace(format!("term[{}]", d - 1 - i), |ctx| {
+                    let lhs_val = Value::known(terms[d - 1 - i]);
+                    let r_val = Value::known(r);
+                    let rhs_val = match &out {
+                        Some(prev_out) => prev_out.value().copied(),
+                        None => Value::known(terms[d - i]),
+                    };
+
+                    let mut state = [Value::known(F::ZERO); T];
+                    state[0] = r_val;
+                    state[1] = rhs_val;
+
+                    // `r` is a witness (it differs between folded instances), so it's copied
+                    // from the first row instead of being constrained to a constant
+                    let mut copies = [None; T];
+                    copies[0] = r_cell.as_ref();
+                    copies[1] = out.as_ref();
+
+                    let row = ctx.assign_row_with_copies(
+                        &self.config,
+                        MainGateRow {
+                            state,
+                            input: lhs_val,
+                            q_m: [F::ONE, F::ZERO],
+                            q_i: F::ONE,
+                            q_o: -F::ONE,
+                            ..Default::default()
+                        },
+                        copies,
+                        // out = s[0]*s[1] + input
+                        || {
+                            lhs_val
+                                .zip(r_val)
+                                .zip(rhs_val)
+                                .map(|((lhs, r), rhs)| lhs + r * rhs)
+                        },
+                    )?;
+
+                    if out.replace(row.out).is_none() {
+                        term_cells.push(row.state[1].clone());
+                    }
+                    term_cells.push(row.input);
+                    r_cell.get_or_insert(row.state[0].clone());
+
+                    Result::<_, Error>::Ok(())
+                })?;
+            }
+
+            Result::<_, Error>::Ok(())
+        })?;
+
+        term_cells.reverse();
+        Ok((term_cells, out.expect("at least two terms")))
+    }
+
+    /// `sum_i r^i * terms[i]` over assigned `terms` & challenge `r`
+    ///
+    /// Unlike [`MainGate::random_linear_combination`], every term & `r` are copy-constrained into
+    /// the rows, so the result is bound to cells from the rest of the circuit. Above
+    /// [`RLC_WIDE_THRESHOLD`] terms it's laid out by [`MainGate::rlc_wide`], otherwise by
+    /// [`MainGate::horner`] in `terms.len() - 1` rows.
+    pub fn rlc_assigned(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        terms: &[AssignedValue<F>],
+        r: &AssignedValue<F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        ctx.with_namespace("rlc", |ctx| {
+            if terms.len() > RLC_WIDE_THRESHOLD {
+                self.rlc_wide(ctx, terms, r)
             } else {
-                out.as_ref().unwrap().value().copied()
-            };
-            let r_val = Value::known(r);
-            ctx.assign_advice(|| "input", self.config.input, lhs_val)?;
-            let rhs = ctx.assign_advice(|| "s[1]", self.config.state[1], rhs_val)?;
-            if out.is_some() {
-                ctx.constrain_equal(rhs.cell(), out.unwrap().cell())?;
-            }
-            ctx.assign_advice(|| "s[0]", self.config.state[0], r_val)?;
-            out = Some(ctx.assign_advice(
-                || "out=s[0]*s[1]+input",
-                self.config.out,
-                lhs_val + r_val * rhs_val,
-            )?);
-
-            ctx.assign_fixed(|| "q_i", self.config.q_i, F::ONE)?;
-            ctx.assign_fixed(|| "q_m", self.config.q_m[0], F::ONE)?;
-            ctx.assign_fixed(|| "q_o", self.config.q_o, -F::ONE)?;
-            ctx.next();
-        }
-        Ok(out.unwrap())
+                self.horner(ctx, terms, r)
+            }
+        })
     }
 }
 
 impl<F: PrimeFieldBits, const T: usize> MainGate<F, T> {
     pub fn assign_bits(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         bits: &[bool],
     ) -> Result<Vec<AssignedValue<F>>, Error> {
         bits.iter()
-            .map(|bit| self.assign_bit(ctx, Value::known(if *bit { F::ONE } else { F::ZERO })))
+            .map(|bit| self.assign_bit(ctx, Value::known(*bit)))
             .collect()
     }
 
     pub fn le_bits_to_num(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         bits: &[AssignedValue<F>],
     ) -> Result<AssignedValue<F>, Error> {
         bits.iter()
@@ -771,7 +1998,7 @@ impl<F: PrimeFieldBits, const T: usize> MainGate<F, T> {
 
     pub fn le_num_to_bits(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         input: AssignedValue<F>,
         bit_len: NonZeroUsize,
     ) -> Result<Vec<AssignedValue<F>>, Error> {
@@ -797,75 +2024,1622 @@ impl<F: PrimeFieldBits, const T: usize> MainGate<F, T> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        dev::{MockProver, VerifyFailure},
+        plonk::Circuit,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
     use tracing_test::traced_test;
 
     use super::*;
     use crate::{
+        ff::Field,
         halo2curves::pasta::Fp,
         plonk::CompressedGates,
-        polynomial::{expression::QueryIndexContext, Expression},
+        polynomial::{expression::QueryIndexContext, testing::assert_identities, Expression},
+        table::CircuitRunner,
     };
 
-    #[traced_test]
-    #[test]
-    fn main_gate_size_change() {
-        const T: usize = 10;
-        const RATE: usize = 2;
-        let mut cs = ConstraintSystem::<Fp>::default();
-        let config: MainGateConfig<T> = MainGate::configure(&mut cs);
+    #[derive(Clone, Debug)]
+    pub(crate) struct GadgetCircuitConfig<const T: usize> {
+        pub(crate) main_gate: MainGateConfig<T>,
+        pub(crate) instance: Column<Instance>,
+    }
 
-        let _ = config.into_smaller_size::<{ T - 1 }>().unwrap();
-        assert!(config.into_smaller_size::<{ T + 1 }>().is_none());
+    impl<const T: usize> GadgetCircuitConfig<T> {
+        pub(crate) fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let constants = meta.fixed_column();
+            meta.enable_constant(constants);
+
+            Self {
+                main_gate: MainGate::configure_with_instance(meta, instance),
+                instance,
+            }
+        }
     }
 
-    fn main_gate_expressions() -> (Vec<Vec<Expression<Fp>>>, usize, QueryIndexContext) {
-        const T: usize = 2;
-        const RATE: usize = 2;
-        let mut cs = ConstraintSystem::<Fp>::default();
-        let _: MainGateConfig<T> = MainGate::configure(&mut cs);
-        let num_selector = cs.num_selectors; // is zero for current main_gate design
-        let num_fixed = cs.num_fixed_columns();
-        let num_instance = cs.num_instance_columns();
-        let num_advice = cs.num_advice_columns();
-        let gates: Vec<Vec<Expression<Fp>>> = cs
-            .gates()
+    /// Test circuit that lays out [`MainGate`] gadgets in a single region and exposes the returned
+    /// cells as public inputs, in order
+    #[derive(Clone)]
+    pub(crate) struct GadgetCircuit<const T: usize, S> {
+        synthesize: S,
+    }
+
+    impl<const T: usize, S> GadgetCircuit<T, S>
+    where
+        S: Clone
+            + Fn(
+                &MainGate<Fp, T>,
+                &mut RegionCtx<'_, '_, Fp>,
+            ) -> Result<Vec<AssignedValue<Fp>>, Error>,
+    {
+        pub(crate) fn new(synthesize: S) -> Self {
+            Self { synthesize }
+        }
+    }
+
+    impl<const T: usize, S> Circuit<Fp> for GadgetCircuit<T, S>
+    where
+        S: Clone
+            + Fn(
+                &MainGate<Fp, T>,
+                &mut RegionCtx<'_, '_, Fp>,
+            ) -> Result<Vec<AssignedValue<Fp>>, Error>,
+    {
+        type Config = GadgetCircuitConfig<T>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            GadgetCircuitConfig::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let main_gate = MainGate::new(config.main_gate);
+
+            let outputs = layouter.assign_region(
+                || "gadget",
+                |region| (self.synthesize)(&main_gate, &mut RegionCtx::new(region, 0)),
+            )?;
+
+            for (row, output) in outputs.iter().enumerate() {
+                layouter.constrain_instance(output.cell(), config.instance, row)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Assign each of `values` into a cell of its own
+    pub(crate) fn assign_values<const T: usize>(
+        main_gate: &MainGate<Fp, T>,
+        ctx: &mut RegionCtx<'_, '_, Fp>,
+        values: &[impl Copy + Into<Fp>],
+    ) -> Result<Vec<AssignedValue<Fp>>, Error> {
+        values
             .iter()
-            .map(|gate| {
-                gate.polynomials()
-                    .iter()
-                    .map(|expr| Expression::from_halo2_expr(expr, num_selector, num_fixed))
-                    .collect()
-            })
-            .collect();
-        (
-            gates,
-            num_instance,
-            QueryIndexContext {
-                num_fixed,
-                num_advice,
-                num_selectors: cs.num_selectors,
-                num_challenges: cs.num_challenges(),
-                num_lookups: 0,
-            },
+            .map(|value| main_gate.assign_value(ctx, Value::known((*value).into())))
+            .collect()
+    }
+
+    /// Run [`MockProver`] over [`GadgetCircuit`] built from `synthesize`
+    pub(crate) fn verify_gadget<const T: usize>(
+        k: u32,
+        public_inputs: Vec<Fp>,
+        synthesize: impl Clone
+            + Fn(
+                &MainGate<Fp, T>,
+                &mut RegionCtx<'_, '_, Fp>,
+            ) -> Result<Vec<AssignedValue<Fp>>, Error>,
+    ) -> Result<(), Vec<VerifyFailure>> {
+        MockProver::run(
+            k,
+            &GadgetCircuit::<T, _>::new(synthesize),
+            vec![public_inputs],
         )
+        .expect("synthesize of gadget circuit failed")
+        .verify()
+    }
+
+    /// Overwrites the advice or fixed `cell`, laid out earlier, by `value`
+    ///
+    /// For malicious witness tests: the value goes straight into the region, so the offset &
+    /// the caches of `ctx` are left as they are
+    pub(crate) fn tamper(
+        ctx: &mut RegionCtx<'_, '_, Fp>,
+        cell: Cell,
+        value: Value<Fp>,
+    ) -> Result<(), Error> {
+        let region = ctx.region.get();
+        match cell.column.column_type() {
+            Any::Advice(_) => region
+                .assign_advice(
+                    || "tampered",
+                    cell.column.try_into().unwrap(),
+                    cell.row_offset,
+                    || value,
+                )
+                .map(|_| ()),
+            Any::Fixed => region
+                .assign_fixed(
+                    || "tampered",
+                    cell.column.try_into().unwrap(),
+                    cell.row_offset,
+                    || value,
+                )
+                .map(|_| ()),
+            Any::Instance => unreachable!("instance cells aren't assigned in a region"),
+        }
     }
 
     #[test]
-    fn test_main_gate_expr() {
-        let (gates, _, _) = main_gate_expressions();
-        for (i, gate) in gates.iter().enumerate() {
-            for (j, poly) in gate.iter().enumerate() {
-                if i == 0 && j == 0 {
-                    // i.e. qm * s1_0 * s1_1 + qi * in1 + rc + qo * out1 + q1_0 * s1_0 + q5_0 * s1_0^5
-                    // + q1_1 * s1_1 + q5_1 * s1_1^5
-                    assert_eq!(
-                         poly.to_string(),
-                        "Z_4 * Z_9 * Z_10 + Z_6 * Z_11 + Z_8 + Z_7 * Z_12 + Z_0 * Z_9 + Z_2 * Z_9 * Z_9 * Z_9 * Z_9 * Z_9 + Z_1 * Z_10 + Z_3 * Z_10 * Z_10 * Z_10 * Z_10 * Z_10"
-                    );
-                }
+    fn constrain_constant() {
+        const K: u32 = 4;
+        let value = Fp::from(42);
+
+        let pin = |constant: Fp| {
+            move |main_gate: &MainGate<Fp, 2>,
+                  ctx: &mut RegionCtx<'_, '_, Fp>|
+                  -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let cell = main_gate.assign_value(ctx, Value::known(value))?;
+                ctx.constrain_constant(cell.cell(), constant)?;
+                Ok(vec![cell])
+            }
+        };
+
+        assert_eq!(verify_gadget(K, vec![value], pin(value)), Ok(()));
+        assert!(verify_gadget(K, vec![value], pin(value + Fp::ONE)).is_err());
+    }
+
+    #[test]
+    fn constrain_constant_without_constants_column() {
+        struct NoConstantsCircuit;
+
+        impl Circuit<Fp> for NoConstantsCircuit {
+            type Config = MainGateConfig<2>;
+            type FloorPlanner = SimpleFloorPlanner;
+
+            fn without_witnesses(&self) -> Self {
+                Self
+            }
+
+            fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+                MainGate::configure(meta)
+            }
+
+            fn synthesize(
+                &self,
+                config: Self::Config,
+                mut layouter: impl Layouter<Fp>,
+            ) -> Result<(), Error> {
+                let main_gate = MainGate::new(config);
+                layouter.assign_region(
+                    || "constant",
+                    |region| {
+                        let ctx = &mut RegionCtx::new(region, 0);
+                        let cell = main_gate.assign_value(ctx, Value::known(Fp::ONE))?;
+                        Ok(ctx.constrain_constant(cell.cell(), Fp::ONE)?)
+                    },
+                )
+            }
+        }
+
+        assert!(matches!(
+            MockProver::run(4, &NoConstantsCircuit, vec![]),
+            Err(Error::NotEnoughColumnsForConstants)
+        ));
+    }
+
+    #[test]
+    fn assign_advice_from_other_region() {
+        struct CopyCircuit {
+            value: Fp,
+        }
+
+        impl Circuit<Fp> for CopyCircuit {
+            type Config = GadgetCircuitConfig<2>;
+            type FloorPlanner = SimpleFloorPlanner;
+
+            fn without_witnesses(&self) -> Self {
+                Self { value: Fp::ZERO }
+            }
+
+            fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+                GadgetCircuitConfig::configure(meta)
             }
+
+            fn synthesize(
+                &self,
+                config: Self::Config,
+                mut layouter: impl Layouter<Fp>,
+            ) -> Result<(), Error> {
+                let main_gate = MainGate::new(config.main_gate.clone());
+
+                let src = layouter.assign_region(
+                    || "src",
+                    |region| {
+                        main_gate
+                            .assign_value(&mut RegionCtx::new(region, 0), Value::known(self.value))
+                    },
+                )?;
+                let dst = layouter.assign_region(
+                    || "dst",
+                    |region| {
+                        Ok(RegionCtx::new(region, 0).assign_advice_from(
+                            || "copy",
+                            config.main_gate.state[0],
+                            &src,
+                        )?)
+                    },
+                )?;
+
+                layouter.constrain_instance(dst.cell(), config.instance, 0)
+            }
+        }
+
+        let value = Fp::from(7);
+        let prover = MockProver::run(4, &CopyCircuit { value }, vec![vec![value]]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn fixed_columns_do_not_depend_on_witness() {
+        const K: u32 = 4;
+
+        struct TripleCircuit {
+            witness: Value<Fp>,
         }
+
+        impl Circuit<Fp> for TripleCircuit {
+            type Config = GadgetCircuitConfig<2>;
+            type FloorPlanner = SimpleFloorPlanner;
+
+            fn without_witnesses(&self) -> Self {
+                Self {
+                    witness: Value::unknown(),
+                }
+            }
+
+            fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+                GadgetCircuitConfig::configure(meta)
+            }
+
+            fn synthesize(
+                &self,
+                config: Self::Config,
+                mut layouter: impl Layouter<Fp>,
+            ) -> Result<(), Error> {
+                let main_gate = MainGate::new(config.main_gate);
+
+                layouter.assign_region(
+                    || "triple",
+                    |region| {
+                        let ctx = &mut RegionCtx::new(region, 0);
+                        let config = main_gate.config();
+                        let a = main_gate.assign_value(ctx, self.witness)?;
+
+                        // 3 * a - out = 0
+                        let s = ctx.assign_advice_from(|| "a", config.state[0], &a)?;
+                        ctx.assign_fixed_value(|| "q_1", config.q_1[0], Value::known(Fp::from(3)))?;
+                        ctx.assign_fixed(|| "q_o", config.q_o, -Fp::ONE)?;
+                        ctx.assign_advice(
+                            || "out",
+                            config.out,
+                            s.value().map(|s| *s * Fp::from(3)),
+                        )?;
+                        ctx.next();
+
+                        Ok(())
+                    },
+                )
+            }
+        }
+
+        let collect_fixed = |circuit: TripleCircuit| {
+            CircuitRunner::new(K, circuit, vec![])
+                .try_collect_plonk_structure()
+                .unwrap()
+                .fixed_columns
+        };
+
+        let circuit = TripleCircuit {
+            witness: Value::known(Fp::from(5)),
+        };
+        assert_eq!(
+            collect_fixed(circuit.without_witnesses()),
+            collect_fixed(circuit)
+        );
+    }
+
+    #[test]
+    fn random_linear_combination_as_public_input() {
+        const K: u32 = 5;
+        let terms = (1..10).map(Fp::from).collect::<Vec<_>>();
+        let r = Fp::from(5);
+
+        let expected = terms
+            .iter()
+            .rev()
+            .fold(Fp::ZERO, |acc, term| acc * r + term);
+
+        let check = |public_input| {
+            verify_gadget::<2>(K, vec![public_input], |main_gate, ctx| {
+                let rlc = main_gate.random_linear_combination(ctx, terms.clone(), r)?;
+                let public = main_gate.assign_from_instance(ctx, 0)?;
+                ctx.constrain_equal(rlc.cell(), public.cell())?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(expected), Ok(()));
+        assert!(check(expected + Fp::ONE).is_err());
+    }
+
+    #[test]
+    fn namespaced_annotation() {
+        assert_eq!(namespaced(&[], "s[0]"), "s[0]");
+        assert_eq!(
+            namespaced(&["rlc".to_owned(), "term[3]".to_owned()], "s[0]"),
+            "rlc/term[3]/s[0]"
+        );
+    }
+
+    #[test]
+    fn random_linear_combination_leaves_namespace() {
+        let terms = (1..4).map(Fp::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            verify_gadget::<2>(4, vec![], |main_gate, ctx| {
+                main_gate.random_linear_combination(ctx, terms.clone(), Fp::ONE)?;
+                assert!(ctx.namespace.is_empty());
+                Ok(vec![])
+            }),
+            Ok(())
+        );
+    }
+
+    #[traced_test]
+    #[test]
+    fn random_linear_combination_failure_names_term() {
+        let terms = (1..4).map(Fp::from).collect::<Vec<_>>();
+
+        let circuit = GadgetCircuit::<2, _>::new(
+            move |main_gate: &MainGate<Fp, 2>,
+                  ctx: &mut RegionCtx<'_, '_, Fp>|
+                  -> Result<Vec<AssignedValue<Fp>>, Error> {
+                ctx.next_n(100);
+                let rlc = main_gate.random_linear_combination(ctx, terms.clone(), Fp::ONE)?;
+                Ok(vec![rlc])
+            },
+        );
+
+        assert!(MockProver::run(4, &circuit, vec![vec![]]).is_err());
+        // the first row of the rlc holds the second to last term
+        assert!(logs_contain("rlc/term[1]/s[0]"));
+        assert!(logs_contain("offset 100"));
+    }
+
+    #[test]
+    fn enable_selector_with_main_gate() {
+        #[derive(Clone, Debug)]
+        struct DoubleConfig {
+            main_gate: MainGateConfig<2>,
+            double: Column<Advice>,
+            selector: Selector,
+        }
+
+        struct DoubleCircuit {
+            values: Vec<(Fp, Fp)>,
+        }
+
+        impl Circuit<Fp> for DoubleCircuit {
+            type Config = DoubleConfig;
+            type FloorPlanner = SimpleFloorPlanner;
+
+            fn without_witnesses(&self) -> Self {
+                Self { values: vec![] }
+            }
+
+            fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+                let main_gate = MainGate::configure(meta);
+                let double = meta.advice_column();
+                let selector = meta.selector();
+
+                meta.create_gate("double = 2 * out", |meta| {
+                    let s = meta.query_selector(selector);
+                    let double = meta.query_advice(double, Rotation::cur());
+                    let out = meta.query_advice(main_gate.out, Rotation::cur());
+                    vec![s * (double - out * Fp::from(2))]
+                });
+
+                DoubleConfig {
+                    main_gate,
+                    double,
+                    selector,
+                }
+            }
+
+            fn synthesize(
+                &self,
+                config: Self::Config,
+                mut layouter: impl Layouter<Fp>,
+            ) -> Result<(), Error> {
+                let main_gate = MainGate::new(config.main_gate.clone());
+
+                layouter.assign_region(
+                    || "double",
+                    |region| {
+                        let ctx = &mut RegionCtx::new(region, 0);
+                        for (value, double) in self.values.iter() {
+                            ctx.enable_selector(|| "double", &config.selector)?;
+                            ctx.assign_advice(|| "double", config.double, Value::known(*double))?;
+                            main_gate.assign_value(ctx, Value::known(*value))?;
+                        }
+                        // regular main gate row after the selector-gated ones
+                        let one = main_gate.assign_value(ctx, Value::known(Fp::ONE))?;
+                        main_gate.add(ctx, &one, &one)?;
+                        Ok(())
+                    },
+                )
+            }
+        }
+
+        let verify = |values: Vec<(Fp, Fp)>| {
+            MockProver::run(5, &DoubleCircuit { values }, vec![])
+                .unwrap()
+                .verify()
+        };
+
+        assert_eq!(
+            verify(vec![
+                (Fp::from(3), Fp::from(6)),
+                (Fp::from(5), Fp::from(10))
+            ]),
+            Ok(())
+        );
+        assert!(verify(vec![
+            (Fp::from(3), Fp::from(6)),
+            (Fp::from(5), Fp::from(11))
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn borrowed_region_shared_with_other_chip() {
+        #[derive(Clone, Debug)]
+        struct SharedConfig {
+            main_gate: MainGateConfig<2>,
+            custom: Column<Advice>,
+            instance: Column<Instance>,
+        }
+
+        struct SharedRegionCircuit {
+            value: Fp,
+        }
+
+        impl Circuit<Fp> for SharedRegionCircuit {
+            type Config = SharedConfig;
+            type FloorPlanner = SimpleFloorPlanner;
+
+            fn without_witnesses(&self) -> Self {
+                Self { value: Fp::ZERO }
+            }
+
+            fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+                let custom = meta.advice_column();
+                meta.enable_equality(custom);
+                let instance = meta.instance_column();
+                meta.enable_equality(instance);
+
+                SharedConfig {
+                    main_gate: MainGate::configure(meta),
+                    custom,
+                    instance,
+                }
+            }
+
+            fn synthesize(
+                &self,
+                config: Self::Config,
+                mut layouter: impl Layouter<Fp>,
+            ) -> Result<(), Error> {
+                let main_gate = MainGate::new(config.main_gate.clone());
+
+                let sum = layouter.assign_region(
+                    || "shared",
+                    |mut region| {
+                        let value = region.assign_advice(
+                            || "custom value",
+                            config.custom,
+                            0,
+                            || Value::known(self.value),
+                        )?;
+
+                        let (sum, offset) = {
+                            let ctx = &mut RegionCtx::new_borrowed(&mut region, 1);
+                            let sum = main_gate.add(ctx, &value, &value)?;
+                            (sum, ctx.offset())
+                        };
+
+                        sum.copy_advice(|| "custom sum", &mut region, config.custom, offset)
+                    },
+                )?;
+
+                layouter.constrain_instance(sum.cell(), config.instance, 0)
+            }
+        }
+
+        let verify = |value: Fp, expected: Fp| {
+            MockProver::run(4, &SharedRegionCircuit { value }, vec![vec![expected]])
+                .unwrap()
+                .verify()
+        };
+
+        assert_eq!(verify(Fp::from(21), Fp::from(42)), Ok(()));
+        assert!(verify(Fp::from(21), Fp::from(43)).is_err());
+    }
+
+    #[test]
+    fn assign_row() {
+        let check = |sum: Fp| {
+            verify_gadget::<4>(4, vec![sum], move |main_gate, ctx| {
+                let row = ctx.assign_row(
+                    main_gate.config(),
+                    MainGateRow {
+                        state: [1, 2, 3, 4].map(|v| Value::known(Fp::from(v))),
+                        out: Value::known(sum),
+                        q_1: [Fp::ONE; 4],
+                        q_o: -Fp::ONE,
+                        ..Default::default()
+                    },
+                )?;
+                assert_eq!(ctx.offset(), 1);
+                Ok(vec![row.out])
+            })
+        };
+
+        assert_eq!(check(Fp::from(10)), Ok(()));
+        assert!(check(Fp::from(11)).is_err());
+    }
+
+    #[test]
+    fn constrain_all_equal() {
+        let check = |values: [u64; 3]| {
+            verify_gadget::<2>(4, vec![], move |main_gate, ctx| {
+                let cells = values
+                    .iter()
+                    .map(|v| main_gate.assign_value(ctx, Value::known(Fp::from(*v))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                ctx.constrain_all_equal(&cells.iter().collect::<Vec<_>>())?;
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check([7, 7, 7]), Ok(()));
+        assert!(check([7, 7, 8]).is_err());
+        assert!(check([8, 7, 7]).is_err());
+    }
+
+    #[test]
+    fn assign_constant_cache() {
+        assert_eq!(
+            verify_gadget::<2>(4, vec![Fp::ONE], |main_gate, ctx| {
+                let config = main_gate.config();
+
+                ctx.assign_constant(config.state[0], Fp::ONE)?;
+                ctx.assign_constant(config.state[1], Fp::ZERO)?;
+                ctx.next();
+
+                // cached, nothing assigned at this row
+                ctx.assign_constant(config.state[0], Fp::ONE)?;
+                assert_eq!(ctx.rows_used(), 1);
+
+                let fresh_one = ctx.assign_constant_to(config.out, Fp::ONE)?;
+                ctx.assign_constant_to(config.state[0], Fp::ZERO)?;
+                ctx.next();
+
+                assert_eq!(ctx.constants_used(), 2);
+
+                Ok(vec![fresh_one])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn random_linear_combination_constants_cache() {
+        assert_eq!(
+            verify_gadget::<2>(4, vec![], |main_gate, ctx| {
+                assert_eq!(ctx.constants_used(), 0);
+                let zero = main_gate.random_linear_combination(ctx, vec![], Fp::from(7))?;
+                assert_eq!(ctx.constants_used(), 1);
+
+                // the zeros of the following empty rlcs are the cached one
+                let rows = ctx.rows_used();
+                let r = main_gate.assign_value(ctx, Value::known(Fp::from(7)))?;
+                for rlc in [
+                    main_gate.random_linear_combination(ctx, vec![], Fp::from(11))?,
+                    main_gate.rlc_assigned(ctx, &[], &r)?,
+                ] {
+                    assert_eq!(rlc.cell(), zero.cell());
+                }
+                assert_eq!(ctx.constants_used(), 1);
+                assert_eq!(ctx.rows_used(), rows + 1);
+
+                Ok(vec![])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn recorder() {
+        let config = MainGate::<Fp, 2>::configure(&mut ConstraintSystem::default());
+
+        let mut records = vec![];
+        SingleChipLayouter::<'_, Fp, _>::new(&mut dry_run::NoopAssignment, vec![])
+            .unwrap()
+            .assign_region(
+                || "recorded",
+                |region| {
+                    let mut ctx = RegionCtx::new(region, 0).with_recorder();
+                    ctx.assign_row(
+                        &config,
+                        MainGateRow {
+                            state: [1, 2].map(|v| Value::known(Fp::from(v))),
+                            out: Value::known(Fp::from(3)),
+                            q_1: [Fp::ONE; 2],
+                            q_o: -Fp::ONE,
+                            ..Default::default()
+                        },
+                    )?;
+                    records = ctx.take_records();
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        assert_eq!(records.len(), 13);
+        assert_eq!(records[0].annotation, "s[0]");
+
+        let minus_one = util::trim_leading_zeros(format!("{:?}", -Fp::ONE));
+        assert_eq!(
+            config.format_rows(&records, 0..2),
+            format!(
+                "0: state[0]=0x1 state[1]=0x2 input=0x0 out=0x3 q_m[0]=0x0 q_m[1]=0x0 q_1[0]=0x1 \
+                 q_1[1]=0x1 q_5[0]=0x0 q_5[1]=0x0 q_i=0x0 q_o={minus_one} rc=0x0\n\
+                 1: state[0]=- state[1]=- input=- out=- q_m[0]=- q_m[1]=- q_1[0]=- q_1[1]=- \
+                 q_5[0]=- q_5[1]=- q_i=- q_o=- rc=-"
+            )
+        );
+    }
+
+    #[test]
+    fn recorder_disabled() {
+        assert_eq!(
+            verify_gadget::<2>(4, vec![], |main_gate, ctx| {
+                main_gate.assign_value(ctx, Value::known(Fp::ONE))?;
+                assert!(ctx.take_records().is_empty());
+                Ok(vec![])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn assign_advice_with_is_lazy() {
+        use std::{cell::Cell, rc::Rc};
+
+        let calls = Rc::new(Cell::new(0));
+
+        let circuit = GadgetCircuit::<2, _>::new({
+            let calls = calls.clone();
+            move |main_gate: &MainGate<Fp, 2>,
+                  ctx: &mut RegionCtx<'_, '_, Fp>|
+                  -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let calls = calls.clone();
+                let cell = ctx.assign_advice_with(
+                    || "lazy",
+                    main_gate.config().out,
+                    move || {
+                        calls.set(calls.get() + 1);
+                        Value::known(Fp::ONE)
+                    },
+                )?;
+                ctx.next();
+                Ok(vec![cell])
+            }
+        });
+
+        CircuitRunner::new(4, circuit.clone(), vec![Fp::ONE])
+            .try_collect_plonk_structure()
+            .unwrap();
+        assert_eq!(calls.get(), 0);
+
+        let prover = MockProver::run(4, &circuit, vec![vec![Fp::ONE]]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+        assert_ne!(calls.get(), 0);
+    }
+
+    #[test]
+    fn assignment_error_context() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let message = Rc::new(RefCell::new(None));
+
+        let circuit = GadgetCircuit::<2, _>::new({
+            let message = message.clone();
+            move |main_gate: &MainGate<Fp, 2>,
+                  ctx: &mut RegionCtx<'_, '_, Fp>|
+                  -> Result<Vec<AssignedValue<Fp>>, Error> {
+                ctx.next_n(100);
+                ctx.with_namespace("gadget", |ctx| {
+                    if let Err(err) =
+                        ctx.assign_advice(|| "far", main_gate.config().out, Value::known(Fp::ONE))
+                    {
+                        *message.borrow_mut() = Some(err.to_string());
+                    }
+                });
+                Ok(vec![])
+            }
+        });
+
+        let _ = MockProver::run(4, &circuit, vec![vec![]]);
+
+        let message = message.borrow().clone().expect("assignment must fail");
+        assert!(message.contains("gadget/far"), "{message}");
+        assert!(message.contains("offset 100"), "{message}");
+        assert!(message.contains("Advice"), "{message}");
+    }
+
+    #[test]
+    fn constraint_error_context() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let message = Rc::new(RefCell::new(None));
+
+        let circuit = GadgetCircuit::<2, _>::new({
+            let message = message.clone();
+            move |main_gate: &MainGate<Fp, 2>,
+                  ctx: &mut RegionCtx<'_, '_, Fp>|
+                  -> Result<Vec<AssignedValue<Fp>>, Error> {
+                let config = main_gate.config();
+                let value = ctx.assign_advice(|| "value", config.out, Value::known(Fp::ONE))?;
+                ctx.next();
+                // fixed columns of the main gate aren't in the permutation
+                let fixed = ctx.assign_fixed(|| "fixed", config.rc, Fp::ONE)?;
+                ctx.with_namespace("gadget", |ctx| {
+                    if let Err(err) = ctx.constrain_cells_equal(&fixed, &value) {
+                        *message.borrow_mut() = Some(err.to_string());
+                    }
+                });
+                Ok(vec![])
+            }
+        });
+
+        let _ = MockProver::run(4, &circuit, vec![vec![]]);
+
+        let message = message.borrow().clone().expect("constraint must fail");
+        assert!(message.contains("gadget/copy of"), "{message}");
+        assert!(message.contains("Fixed"), "{message}");
+        assert!(message.contains("offset 1"), "{message}");
+        assert!(message.contains("Advice"), "{message}");
+    }
+
+    #[test]
+    fn copy_advice_permutation() {
+        const K: u32 = 5;
+        let terms = [3, 1, 4, 1].map(Fp::from);
+        let r = Fp::from(7);
+        let expected = terms
+            .iter()
+            .rev()
+            .fold(Fp::ZERO, |acc, term| acc * r + term);
+
+        // from the second row on, `r` & the previous out are copied into `s[0]` & `s[1]`
+        let check = |broken: Option<(usize, usize)>| {
+            verify_gadget::<2>(K, vec![expected], move |main_gate, ctx| {
+                let start = ctx.offset();
+                let rlc = main_gate.random_linear_combination(ctx, terms.to_vec(), r)?;
+
+                if let Some((row, state)) = broken {
+                    let cell = Cell {
+                        column: main_gate.config().state[state].into(),
+                        row_offset: start + row,
+                        ..rlc.cell()
+                    };
+                    // no cell of the rows holds `-1`
+                    tamper(ctx, cell, Value::known(-Fp::ONE))?;
+                }
+
+                Ok(vec![rlc])
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        for (row, state) in [(1, 0), (1, 1), (2, 0), (2, 1)] {
+            let failures = check(Some((row, state))).unwrap_err();
+            assert!(
+                failures
+                    .iter()
+                    .any(|failure| matches!(failure, VerifyFailure::Permutation { .. })),
+                "{row} {state}: {failures:?}"
+            );
+        }
+
+        // the same rows with the chaining constrained after the assignment, as before
+        // `RegionCtx::copy_advice`, give the same permutation
+        let permutation = |copy: bool| {
+            let circuit = GadgetCircuit::<2, _>::new(
+                move |main_gate: &MainGate<Fp, 2>,
+                      ctx: &mut RegionCtx<'_, '_, Fp>|
+                      -> Result<Vec<AssignedValue<Fp>>, Error> {
+                    if copy {
+                        return Ok(vec![main_gate.random_linear_combination(
+                            ctx,
+                            terms.to_vec(),
+                            r,
+                        )?]);
+                    }
+
+                    let d = terms.len();
+                    let mut out: Option<AssignedValue<Fp>> = None;
+                    let mut r_cell: Option<AssignedValue<Fp>> = None;
+                    for i in 1..d {
+                        let rhs = match &out {
+                            Some(prev_out) => prev_out.value().copied(),
+                            None => Value::known(terms[d - i]),
+                        };
+                        let row = ctx.assign_row_with(
+                            main_gate.config(),
+                            MainGateRow {
+                                state: [Value::known(r), rhs],
+                                input: Value::known(terms[d - 1 - i]),
+                                q_m: [Fp::ONE, Fp::ZERO],
+                                q_i: Fp::ONE,
+                                q_o: -Fp::ONE,
+                                ..Default::default()
+                            },
+                            || rhs.map(|rhs| terms[d - 1 - i] + r * rhs),
+                        )?;
+
+                        if let Some(prev_out) = out.replace(row.out) {
+                            ctx.constrain_cells_equal(&row.state[1], &prev_out)?;
+                        }
+                        match &r_cell {
+                            Some(r_cell) => ctx.constrain_cells_equal(&row.state[0], r_cell)?,
+                            None => r_cell = Some(row.state[0].clone()),
+                        }
+                    }
+
+                    Ok(vec![out.unwrap()])
+                },
+            );
+
+            CircuitRunner::new(K, circuit, vec![expected])
+                .try_collect_plonk_structure()
+                .unwrap()
+                .permutation_matrix
+        };
+
+        let copied = permutation(true);
+        assert!(!copied.is_empty());
+        assert_eq!(copied, permutation(false));
+    }
+
+    #[test]
+    fn assign_each() {
+        let values = (1..=5).map(Fp::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            verify_gadget::<2>(4, values.clone(), |main_gate, ctx| {
+                let config = main_gate.config();
+                let cells = ctx.assign_each_with_fixed(
+                    || "value",
+                    config.state[0],
+                    values.iter().copied().map(Value::known),
+                    config.q_1[0],
+                    Fp::ZERO,
+                )?;
+                assert_eq!(ctx.offset(), values.len());
+                Ok(cells)
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn load_vector() {
+        let values = (1..=7).map(Fp::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            verify_gadget::<3>(4, values.clone(), |main_gate, ctx| {
+                let cells = main_gate.load_vector(
+                    ctx,
+                    &values.iter().copied().map(Value::known).collect::<Vec<_>>(),
+                )?;
+                assert_eq!(ctx.offset(), 3);
+                Ok(cells)
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn expose_public() {
+        const K: u32 = 4;
+
+        #[derive(Clone)]
+        struct ExposeCircuit {
+            value: Fp,
+            times: usize,
+        }
+
+        impl Circuit<Fp> for ExposeCircuit {
+            type Config = GadgetCircuitConfig<2>;
+            type FloorPlanner = SimpleFloorPlanner;
+
+            fn without_witnesses(&self) -> Self {
+                self.clone()
+            }
+
+            fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+                GadgetCircuitConfig::configure(meta)
+            }
+
+            fn synthesize(
+                &self,
+                config: Self::Config,
+                mut layouter: impl Layouter<Fp>,
+            ) -> Result<(), Error> {
+                let main_gate = MainGate::new(config.main_gate);
+
+                let cell = layouter.assign_region(
+                    || "value",
+                    |region| {
+                        main_gate
+                            .assign_value(&mut RegionCtx::new(region, 0), Value::known(self.value))
+                    },
+                )?;
+
+                for _ in 0..self.times {
+                    main_gate.expose_public(layouter.namespace(|| "expose"), &cell, 0)?;
+                }
+
+                Ok(())
+            }
+        }
+
+        let value = Fp::from(11);
+        let verify = |times, public_input| {
+            MockProver::run(K, &ExposeCircuit { value, times }, vec![vec![public_input]])
+                .unwrap()
+                .verify()
+        };
+
+        assert_eq!(verify(1, value), Ok(()));
+        assert!(verify(1, value + Fp::ONE).is_err());
+        assert_eq!(verify(2, value), Ok(()));
+        assert!(verify(2, value + Fp::ONE).is_err());
+
+        let permutation = |times| {
+            CircuitRunner::new(K, ExposeCircuit { value, times }, vec![value])
+                .try_collect_plonk_structure()
+                .unwrap()
+                .permutation_matrix
+        };
+        assert_eq!(permutation(1), permutation(2));
+    }
+
+    #[test]
+    fn link_rows() {
+        const K: u32 = 5;
+        let r = Fp::from(3);
+
+        #[derive(Clone)]
+        struct ChainedRlcCircuit {
+            first: Vec<Fp>,
+            second: Vec<Fp>,
+            r: Fp,
+        }
+
+        impl Circuit<Fp> for ChainedRlcCircuit {
+            type Config = GadgetCircuitConfig<2>;
+            type FloorPlanner = SimpleFloorPlanner;
+
+            fn without_witnesses(&self) -> Self {
+                self.clone()
+            }
+
+            fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+                GadgetCircuitConfig::configure(meta)
+            }
+
+            fn synthesize(
+                &self,
+                config: Self::Config,
+                mut layouter: impl Layouter<Fp>,
+            ) -> Result<(), Error> {
+                let main_gate = MainGate::new(config.main_gate);
+
+                layouter.assign_region(
+                    || "chained rlc",
+                    |region| {
+                        let ctx = &mut RegionCtx::new(region, 0).with_cell_registry();
+
+                        let first_row = ctx.offset();
+                        main_gate.random_linear_combination(ctx, self.first.clone(), self.r)?;
+
+                        let second_row = ctx.offset();
+                        main_gate.random_linear_combination(ctx, self.second.clone(), self.r)?;
+
+                        // the second row of the first rlc is `[r, intermediate accumulator]`, the
+                        // first row of the second one is `[r, last term]`, so the accumulator
+                        // is carried over as the last term of the second rlc
+                        main_gate.link_rows(ctx, first_row + 1, second_row)
+                    },
+                )
+            }
+        }
+
+        let verify = |second: Vec<Fp>| {
+            let circuit = ChainedRlcCircuit {
+                first: vec![Fp::from(5), Fp::from(7), Fp::from(9)],
+                second,
+                r,
+            };
+            MockProver::run(K, &circuit, vec![vec![]]).unwrap().verify()
+        };
+
+        // accumulator after the first step: 7 + 9 * r
+        let intermediate = Fp::from(7) + Fp::from(9) * r;
+        assert_eq!(verify(vec![Fp::from(1), intermediate]), Ok(()));
+        assert!(verify(vec![Fp::from(1), intermediate + Fp::ONE]).is_err());
+        // last term of the first rlc, not the accumulator
+        assert!(verify(vec![Fp::from(1), Fp::from(9)]).is_err());
+    }
+
+    #[test]
+    fn rows_used() {
+        assert_eq!(
+            verify_gadget::<2>(4, vec![], |main_gate, ctx| {
+                assert_eq!(ctx.rows_used(), 0);
+
+                main_gate.assign_value(ctx, Value::known(Fp::ONE))?;
+                assert_eq!(ctx.rows_used(), 1);
+
+                ctx.next_n(3);
+                assert_eq!(ctx.rows_used(), 4);
+
+                ctx.reset(1);
+                assert_eq!(ctx.offset(), 1);
+                assert_eq!(ctx.rows_used(), 4);
+
+                Ok(vec![])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn checkpoint_rollback() {
+        assert_eq!(
+            verify_gadget::<2>(4, vec![Fp::from(2)], |main_gate, ctx| {
+                let checkpoint = ctx.checkpoint();
+
+                // speculative layout, overwritten after rollback
+                main_gate.assign_value(ctx, Value::known(Fp::from(100)))?;
+                main_gate.assign_value(ctx, Value::known(Fp::from(100)))?;
+                ctx.rollback(checkpoint);
+
+                assert_eq!(ctx.offset(), checkpoint.offset());
+                assert_eq!(ctx.rows_used(), 2);
+
+                let one = main_gate.assign_value(ctx, Value::known(Fp::ONE))?;
+                Ok(vec![main_gate.add(ctx, &one, &one)?])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rollback_forgets_cached_cells() {
+        // a constant cached & a bit registered in the rows rolled back, then cells that aren't
+        // either laid out in those rows
+        let check = |constant: bool| {
+            verify_gadget::<2>(5, vec![], move |main_gate, ctx| {
+                let checkpoint = ctx.checkpoint();
+                main_gate.assign_constant(ctx, Fp::from(5))?;
+                let bit = ctx.assign_row(
+                    main_gate.config(),
+                    MainGateRow {
+                        state: [Value::known(Fp::ONE); 2],
+                        q_m: [Fp::ONE, Fp::ZERO],
+                        q_1: [-Fp::ONE, Fp::ZERO],
+                        ..Default::default()
+                    },
+                )?;
+                ctx.register_bit(bit.state[0].cell());
+                ctx.rollback(checkpoint);
+
+                let seven = ctx.assign_row(
+                    main_gate.config(),
+                    MainGateRow {
+                        state: [Value::known(Fp::from(7)), Value::known(Fp::ZERO)],
+                        ..Default::default()
+                    },
+                )?;
+                let two = ctx.assign_row(
+                    main_gate.config(),
+                    MainGateRow {
+                        state: [Value::known(Fp::from(2)), Value::known(Fp::ZERO)],
+                        ..Default::default()
+                    },
+                )?;
+
+                if constant {
+                    main_gate.assert_equal_const(ctx, &seven.state[0], Fp::from(5))?;
+                } else {
+                    main_gate.assert_bit(ctx, &two.state[0])?;
+                }
+                Ok(vec![])
+            })
+        };
+
+        assert!(check(true).is_err());
+        assert!(check(false).is_err());
+    }
+
+    #[test]
+    fn rollback_forgets_records() {
+        let config = MainGate::<Fp, 2>::configure(&mut ConstraintSystem::default());
+
+        SingleChipLayouter::<'_, Fp, _>::new(&mut dry_run::NoopAssignment, vec![])
+            .unwrap()
+            .assign_region(
+                || "rolled back",
+                |region| {
+                    let mut ctx = RegionCtx::new(region, 0)
+                        .with_recorder()
+                        .with_cell_registry();
+                    let checkpoint = ctx.checkpoint();
+                    ctx.assign_row(&config, MainGateRow::default())?;
+                    assert!(ctx.cell_at(config.state[0], 0).is_some());
+
+                    ctx.rollback(checkpoint);
+                    assert!(ctx.take_records().is_empty());
+                    assert!(ctx.cell_at(config.state[0], 0).is_none());
+                    Ok(())
+                },
+            )
+            .unwrap();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "copy constraints registered after checkpoint")]
+    fn rollback_past_constraint() {
+        let _ = verify_gadget::<2>(4, vec![], |main_gate, ctx| {
+            let checkpoint = ctx.checkpoint();
+            let one = main_gate.assign_value(ctx, Value::known(Fp::ONE))?;
+            main_gate.add(ctx, &one, &one)?;
+            ctx.rollback(checkpoint);
+            Ok(vec![])
+        });
+    }
+
+    #[test]
+    fn random_linear_combination_row_cost() {
+        for d in [0, 1, 2, 3, 10] {
+            let terms = (0..d as u64).map(Fp::from).collect::<Vec<_>>();
+
+            assert_eq!(
+                verify_gadget::<2>(5, vec![], |main_gate, ctx| {
+                    main_gate.random_linear_combination(ctx, terms.clone(), Fp::from(2))?;
+                    assert_eq!(ctx.rows_used(), MainGate::<Fp, 2>::rlc_row_cost(d));
+                    Ok(vec![])
+                }),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn rlc_assigned() {
+        const K: u32 = 5;
+        let terms = [3, 1, 4, 1, 5].map(Fp::from);
+        let r = Fp::from(7);
+        let expected = terms
+            .iter()
+            .rev()
+            .fold(Fp::ZERO, |acc, term| acc * r + term);
+
+        // the terms are private, only the copy constraints bind them to the rows
+        let check = |tampered: Option<usize>| {
+            verify_gadget::<2>(K, vec![expected], move |main_gate, ctx| {
+                let cells = terms
+                    .iter()
+                    .map(|value| main_gate.assign_value(ctx, Value::known(*value)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let r = main_gate.assign_value(ctx, Value::known(r))?;
+
+                let start = ctx.offset();
+                let rlc = main_gate.rlc_assigned(ctx, &cells, &r)?;
+
+                // `horner` copies the highest term into the state of its first row & every
+                // other one into the input of the row adding it
+                if let Some(i) = tampered {
+                    let last = terms.len() - 1;
+                    let config = main_gate.config();
+                    let (offset, column) = if i == last {
+                        (start, config.state[0])
+                    } else {
+                        (start + last - 1 - i, config.input)
+                    };
+
+                    let cell = Cell {
+                        column: column.into(),
+                        row_offset: offset,
+                        ..rlc.cell()
+                    };
+                    tamper(ctx, cell, Value::known(terms[i].double()))?;
+                }
+
+                Ok(vec![rlc])
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        for i in 0..terms.len() {
+            let failures = check(Some(i)).unwrap_err();
+            assert!(
+                failures
+                    .iter()
+                    .any(|failure| matches!(failure, VerifyFailure::Permutation { .. })),
+                "{i}: {failures:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn random_linear_combination_short() {
+        const K: u32 = 4;
+        let r = Fp::from(5);
+
+        for d in 0..=3 {
+            let terms = (1..=d as u64).map(Fp::from).collect::<Vec<_>>();
+            let expected = terms
+                .iter()
+                .rev()
+                .fold(Fp::ZERO, |acc, term| acc * r + term);
+
+            let check = |expected| {
+                verify_gadget::<2>(K, vec![expected], |main_gate, ctx| {
+                    let rlc = main_gate.random_linear_combination(ctx, terms.clone(), r)?;
+                    if d > 0 {
+                        assert_eq!(
+                            rlc.cell().column,
+                            Column::<Any>::from(main_gate.config().out)
+                        );
+                    }
+                    Ok(vec![rlc])
+                })
+            };
+
+            assert_eq!(check(expected), Ok(()), "{d}");
+            assert!(check(expected + Fp::ONE).is_err(), "{d}");
+        }
+    }
+
+    #[test]
+    fn rlc_returning_terms() {
+        const K: u32 = 5;
+        let terms = [3, 1, 4, 1, 5].map(Fp::from);
+        let r = Fp::from(7);
+
+        for d in [0, 1, 2, terms.len()] {
+            let terms = &terms[..d];
+            let expected = terms
+                .iter()
+                .rev()
+                .fold(Fp::ZERO, |acc, term| acc * r + term);
+
+            // the term cells are wired to independently assigned values
+            let check = |tampered: Option<usize>| {
+                verify_gadget::<2>(K, vec![expected], move |main_gate, ctx| {
+                    let (term_cells, rlc) =
+                        main_gate.rlc_returning_terms(ctx, terms.to_vec(), r)?;
+                    assert_eq!(term_cells.len(), terms.len());
+
+                    for (i, (cell, term)) in term_cells.iter().zip(terms).enumerate() {
+                        let mut value = *term;
+                        if tampered == Some(i) {
+                            value += Fp::ONE;
+                        }
+                        let source = main_gate.assign_value(ctx, Value::known(value))?;
+                        ctx.constrain_cells_equal(cell, &source)?;
+                    }
+
+                    Ok(vec![rlc])
+                })
+            };
+
+            assert_eq!(check(None), Ok(()), "{d}");
+            for i in 0..d {
+                assert!(check(Some(i)).is_err(), "{d}: {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_linear_combination_output_in_out_column() {
+        const K: u32 = 5;
+        let terms = (1..10).map(Fp::from).collect::<Vec<_>>();
+        let r = Fp::from(3);
+
+        let check = |corrupt: bool| {
+            verify_gadget::<2>(K, vec![], |main_gate, ctx| {
+                let rlc = main_gate.random_linear_combination(ctx, terms.clone(), r)?;
+
+                let cell = rlc.cell();
+                assert_eq!(cell.column, Column::<Any>::from(main_gate.config().out));
+                assert_eq!(cell.row_offset + 1, ctx.offset());
+
+                if corrupt {
+                    // overwrite the claimed output, the gate of the last row must catch it
+                    tamper(ctx, cell, rlc.value().map(|value| *value + Fp::ONE))?;
+                }
+
+                Ok(vec![])
+            })
+        };
+
+        assert_eq!(check(false), Ok(()));
+        assert!(check(true).is_err());
+    }
+
+    #[test]
+    fn rlc_assigned_wide() {
+        const K: u32 = 6;
+        let terms = (1..=10).map(Fp::from).collect::<Vec<_>>();
+        let r = Fp::from(7);
+        let expected = terms
+            .iter()
+            .rev()
+            .fold(Fp::ZERO, |acc, term| acc * r + term);
+
+        assert_eq!(
+            verify_gadget::<4>(K, vec![expected], |main_gate, ctx| {
+                let cells = terms
+                    .iter()
+                    .map(|term| main_gate.assign_value(ctx, Value::known(*term)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let r = main_gate.assign_value(ctx, Value::known(r))?;
+
+                let offset = ctx.offset();
+                let rlc = main_gate.rlc_assigned(ctx, &cells, &r)?;
+                assert_eq!(ctx.offset() - offset, 1 + terms.len() / 2);
+
+                Ok(vec![rlc])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn random_linear_combination_dry_run() {
+        let config = MainGate::<Fp, 2>::configure(&mut ConstraintSystem::default());
+        let main_gate = MainGate::new(config);
+
+        for d in [2, 3, 10, 100] {
+            let terms = (0..d as u64).map(Fp::from).collect::<Vec<_>>();
+
+            let rows = RegionCtx::dry_run(|ctx| {
+                main_gate.random_linear_combination(ctx, terms.clone(), Fp::from(2))?;
+                Ok(())
+            })
+            .unwrap();
+
+            assert_eq!(rows, d - 1);
+        }
+    }
+
+    #[test]
+    fn random_linear_combination() {
+        const K: u32 = 5;
+        let terms = (1..10).map(Fp::from).collect::<Vec<_>>();
+        let r = Fp::from(3);
+
+        let expected = terms
+            .iter()
+            .rev()
+            .fold(Fp::ZERO, |acc, term| acc * r + term);
+
+        assert_eq!(
+            verify_gadget::<2>(K, vec![expected], |main_gate, ctx| {
+                Ok(vec![main_gate.random_linear_combination(
+                    ctx,
+                    terms.clone(),
+                    r,
+                )?])
+            }),
+            Ok(())
+        );
+    }
+
+    #[traced_test]
+    #[test]
+    fn main_gate_size_change() {
+        const T: usize = 10;
+        const RATE: usize = 2;
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let config: MainGateConfig<T> = MainGate::configure(&mut cs);
+
+        let _ = config.into_smaller_size::<{ T - 1 }>().unwrap();
+        assert!(config.into_smaller_size::<{ T + 1 }>().is_none());
+    }
+
+    pub(crate) fn main_gate_expressions() -> (Vec<Vec<Expression<Fp>>>, usize, QueryIndexContext) {
+        const T: usize = 2;
+        const RATE: usize = 2;
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let _: MainGateConfig<T> = MainGate::configure(&mut cs);
+        let num_selector = cs.num_selectors; // is zero for current main_gate design
+        let num_fixed = cs.num_fixed_columns();
+        let num_instance = cs.num_instance_columns();
+        let num_advice = cs.num_advice_columns();
+        let gates: Vec<Vec<Expression<Fp>>> = cs
+            .gates()
+            .iter()
+            .map(|gate| {
+                gate.polynomials()
+                    .iter()
+                    .map(|expr| Expression::from_halo2_expr(expr, num_selector, num_fixed).unwrap())
+                    .collect()
+            })
+            .collect();
+        (
+            gates,
+            num_instance,
+            QueryIndexContext {
+                num_fixed,
+                num_advice,
+                num_selectors: cs.num_selectors,
+                num_challenges: cs.num_challenges(),
+                num_lookups: 0,
+                num_instance: 0,
+            },
+        )
+    }
+
+    #[test]
+    fn test_main_gate_expr() {
+        let (gates, _, _) = main_gate_expressions();
+        for (i, gate) in gates.iter().enumerate() {
+            for (j, poly) in gate.iter().enumerate() {
+                if i == 0 && j == 0 {
+                    // i.e. qm * s1_0 * s1_1 + qi * in1 + rc + qo * out1 + q1_0 * s1_0 + q5_0 * s1_0^5
+                    // + q1_1 * s1_1 + q5_1 * s1_1^5
+                    assert_eq!(
+                         poly.to_string(),
+                        "Z_4 * Z_9 * Z_10 + Z_6 * Z_11 + Z_8 + Z_7 * Z_12 + Z_0 * Z_9 + Z_2 * Z_9 * Z_9 * Z_9 * Z_9 * Z_9 + Z_1 * Z_10 + Z_3 * Z_10 * Z_10 * Z_10 * Z_10 * Z_10"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn main_gate_identities() {
+        let (gates, _, ctx) = main_gate_expressions();
+        let mut rng = StdRng::seed_from_u64(0);
+        for gate in gates.iter().flatten() {
+            assert_identities(gate, &ctx, 4, &mut rng);
+        }
+    }
+
+    #[test]
+    fn main_gate_var_names() {
+        let (gates, _num_instance, mut ctx) = main_gate_expressions();
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let config: MainGateConfig<2> = MainGate::configure(&mut cs);
+        let names = config.var_names(&ctx);
+
+        // the gate of `test_main_gate_expr`
+        assert_eq!(
+            gates[0][0].display_with(&names),
+            "q_m[0] * state[0] * state[1] + q_i * input + rc + q_o * out + q_1[0] * state[0] + q_5[0] * state[0] * state[0] * state[0] * state[0] * state[0] + q_1[1] * state[1] + q_5[1] * state[1] * state[1] * state[1] * state[1] * state[1]"
+        );
+        assert_eq!(
+            gates[0][0].display_with(&VarNames::default()),
+            gates[0][0].to_string()
+        );
+
+        // the last cross term of `test_main_gate_cross_term`, only second instance variables
+        let compressed = CompressedGates::new(&[gates[0][0].clone()], &mut ctx);
+        let names = names.with_challenge(0, "u").with_fold_copies(&ctx);
+        assert_eq!(
+            compressed.grouped().get(5).unwrap().display_with(&names),
+            "u' * u' * u' * (state[1]' * state[0]' * q_m[0] + u' * input' * q_i + u' * u' * rc + u' * out' * q_o) + u' * u' * u' * u' * state[0]' * q_1[0] + state[0]' * state[0]' * state[0]' * state[0]' * state[0]' * q_5[0] + u' * u' * u' * u' * state[1]' * q_1[1] + state[1]' * state[1]' * state[1]' * state[1]' * state[1]' * q_5[1]"
+        );
+    }
+
+    #[test]
+    fn main_gate_tree_latex() {
+        let (gates, _num_instance, ctx) = main_gate_expressions();
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let config: MainGateConfig<2> = MainGate::configure(&mut cs);
+        let names = config.var_names(&ctx);
+
+        assert_eq!(
+            gates[0][0].to_tree_string(&names),
+            [
+                "+",
+                "  +",
+                "    +",
+                "      +",
+                "        +",
+                "          *",
+                "            *",
+                "              q_m[0]",
+                "              state[0]",
+                "            state[1]",
+                "          *",
+                "            q_i",
+                "            input",
+                "        rc",
+                "      *",
+                "        q_o",
+                "        out",
+                "    +",
+                "      *",
+                "        q_1[0]",
+                "        state[0]",
+                "      *",
+                "        q_5[0]",
+                "        *",
+                "          *",
+                "            *",
+                "              state[0]",
+                "              state[0]",
+                "            *",
+                "              state[0]",
+                "              state[0]",
+                "          state[0]",
+                "  +",
+                "    *",
+                "      q_1[1]",
+                "      state[1]",
+                "    *",
+                "      q_5[1]",
+                "      *",
+                "        *",
+                "          *",
+                "            state[1]",
+                "            state[1]",
+                "          *",
+                "            state[1]",
+                "            state[1]",
+                "        state[1]",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            gates[0][0].to_latex(&names),
+            r"\mathrm{q\_m[0]} \cdot \mathrm{state[0]} \cdot \mathrm{state[1]} + \mathrm{q\_i} \cdot \mathrm{input} + \mathrm{rc} + \mathrm{q\_o} \cdot \mathrm{out} + \mathrm{q\_1[0]} \cdot \mathrm{state[0]} + \mathrm{q\_5[0]} \cdot \mathrm{state[0]} \cdot \mathrm{state[0]} \cdot \mathrm{state[0]} \cdot \mathrm{state[0]} \cdot \mathrm{state[0]} + \mathrm{q\_1[1]} \cdot \mathrm{state[1]} + \mathrm{q\_5[1]} \cdot \mathrm{state[1]} \cdot \mathrm{state[1]} \cdot \mathrm{state[1]} \cdot \mathrm{state[1]} \cdot \mathrm{state[1]}"
+        );
     }
 
     #[test]
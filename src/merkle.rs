@@ -0,0 +1,321 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ff::{FromUniformBytes, PrimeFieldBits},
+    poseidon::{poseidon_hash_n, Domain, Spec},
+};
+
+/// Poseidon state width of a node hash
+pub const T: usize = 3;
+/// Poseidon rate of a node hash, filled by the two children
+pub const RATE: usize = 2;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error("Can't build a merkle tree without leaves")]
+    NoLeaves,
+    #[error("Leaf index {index} is out of a tree of {len} leaves")]
+    IndexOutOfRange { index: usize, len: usize },
+}
+
+/// Domain of the leaf hashes, so a leaf never passes for an inner node
+pub const LEAF_DOMAIN: Domain = Domain::Custom(u64::from_be_bytes(*b"mrk-leaf"));
+/// Domain of the hash binding the number of leaves to the top node
+pub const ROOT_DOMAIN: Domain = Domain::Custom(u64::from_be_bytes(*b"mrk-root"));
+
+/// Binary merkle tree over field elements, nodes hashed by [`poseidon_hash_n`] in the
+/// [`Domain::MerkleNode`] domain
+///
+/// Each leaf is hashed in [`LEAF_DOMAIN`] first & the hashes are padded by zeros up to a power
+/// of two, so every path has [`MerkleTree::depth`] siblings. The root is the hash of the top
+/// node & the number of leaves in [`ROOT_DOMAIN`]: leaves `[a, b, c]` & `[a, b, c, 0]` give
+/// different ones, as does a single leaf & the tree of its children.
+#[derive(Clone, Debug)]
+pub struct MerkleTree<F: PrimeFieldBits + FromUniformBytes<64>> {
+    spec: Spec<F, T, RATE>,
+    leaves: Vec<F>,
+    /// Layers from the padded leaf hashes up to the top node
+    layers: Vec<Vec<F>>,
+    root: F,
+}
+
+impl<F: PrimeFieldBits + FromUniformBytes<64>> MerkleTree<F> {
+    pub const ARITY: usize = 2;
+
+    pub fn new(spec: Spec<F, T, RATE>, leaves: Vec<F>) -> Result<Self, Error> {
+        if leaves.is_empty() {
+            return Err(Error::NoLeaves);
+        }
+
+        let mut hashes = leaves
+            .par_iter()
+            .map(|leaf| hash_leaf(&spec, *leaf))
+            .collect::<Vec<_>>();
+        hashes.resize(leaves.len().next_power_of_two(), F::ZERO);
+
+        let mut layers = vec![hashes];
+        while layers.last().unwrap().len() > 1 {
+            let next = layers
+                .last()
+                .unwrap()
+                .par_chunks(Self::ARITY)
+                .map(|children| hash_node(&spec, children[0], children[1]))
+                .collect();
+            layers.push(next);
+        }
+        let root = hash_root(&spec, layers.last().unwrap()[0], leaves.len());
+
+        Ok(Self {
+            spec,
+            leaves,
+            layers,
+            root,
+        })
+    }
+
+    pub fn root(&self) -> F {
+        self.root
+    }
+
+    /// Number of siblings of each path, `log2` of the padded leaves count
+    pub fn depth(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    /// Leaves the tree was built from, without padding
+    pub fn leaves(&self) -> &[F] {
+        &self.leaves
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn path(&self, index: usize) -> Result<MerklePath<F>, Error> {
+        if index >= self.num_leaves() {
+            return Err(Error::IndexOutOfRange {
+                index,
+                len: self.num_leaves(),
+            });
+        }
+
+        let siblings = self.layers[..self.depth()]
+            .iter()
+            .enumerate()
+            .map(|(height, layer)| layer[(index >> height) ^ 1])
+            .collect();
+
+        Ok(MerklePath {
+            index,
+            num_leaves: self.num_leaves(),
+            siblings,
+        })
+    }
+
+    pub fn spec(&self) -> &Spec<F, T, RATE> {
+        &self.spec
+    }
+}
+
+/// Poseidon hash of a leaf, the bottom layer of a [`MerkleTree`]
+pub fn hash_leaf<F: PrimeFieldBits + FromUniformBytes<64>>(spec: &Spec<F, T, RATE>, leaf: F) -> F {
+    poseidon_hash_n(spec.clone(), LEAF_DOMAIN, [leaf])
+}
+
+/// Poseidon hash of the `left` & `right` children of a node
+pub fn hash_node<F: PrimeFieldBits + FromUniformBytes<64>>(
+    spec: &Spec<F, T, RATE>,
+    left: F,
+    right: F,
+) -> F {
+    poseidon_hash_n(
+        spec.clone(),
+        Domain::MerkleNode {
+            arity: MerkleTree::<F>::ARITY,
+        },
+        [left, right],
+    )
+}
+
+/// Root of a [`MerkleTree`] of `num_leaves` leaves with the `top` node
+pub fn hash_root<F: PrimeFieldBits + FromUniformBytes<64>>(
+    spec: &Spec<F, T, RATE>,
+    top: F,
+    num_leaves: usize,
+) -> F {
+    poseidon_hash_n(spec.clone(), ROOT_DOMAIN, [top, F::from(num_leaves as u64)])
+}
+
+/// Siblings from a leaf up to the top node, the bits of `index` tell the side at each height
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerklePath<F> {
+    index: usize,
+    num_leaves: usize,
+    siblings: Vec<F>,
+}
+
+impl<F: PrimeFieldBits + FromUniformBytes<64>> MerklePath<F> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Number of leaves of the tree, bound into its root
+    pub fn num_leaves(&self) -> usize {
+        self.num_leaves
+    }
+
+    pub fn siblings(&self) -> &[F] {
+        &self.siblings
+    }
+
+    /// Root of the tree with `leaf` at [`MerklePath::index`]
+    pub fn root(&self, spec: &Spec<F, T, RATE>, leaf: F) -> F {
+        let top = self.siblings.iter().enumerate().fold(
+            hash_leaf(spec, leaf),
+            |node, (height, sibling)| {
+                if (self.index >> height) & 1 == 0 {
+                    hash_node(spec, node, *sibling)
+                } else {
+                    hash_node(spec, *sibling, node)
+                }
+            },
+        );
+        hash_root(spec, top, self.num_leaves)
+    }
+
+    /// Also fails if the index or the number of siblings don't fit [`MerklePath::num_leaves`]
+    pub fn verify(&self, spec: &Spec<F, T, RATE>, leaf: F, root: F) -> bool {
+        let depth = self.num_leaves.next_power_of_two().trailing_zeros() as usize;
+        self.index < self.num_leaves
+            && self.siblings.len() == depth
+            && self.root(spec, leaf) == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ff::Field, halo2curves::pasta::Fp};
+
+    fn spec() -> Spec<Fp, T, RATE> {
+        Spec::new(4, 3)
+    }
+
+    fn leaves(len: u64) -> Vec<Fp> {
+        (0..len).map(|i| Fp::from(i + 1)).collect()
+    }
+
+    #[test]
+    fn paths() {
+        for len in [1, 2, 3, 1024] {
+            let tree = MerkleTree::new(spec(), leaves(len)).unwrap();
+            assert_eq!(tree.num_leaves(), len as usize);
+            assert_eq!(1 << tree.depth(), (len as usize).next_power_of_two());
+
+            for (index, leaf) in tree.leaves().iter().enumerate() {
+                let path = tree.path(index).unwrap();
+                assert_eq!(path.siblings().len(), tree.depth());
+                assert!(
+                    path.verify(tree.spec(), *leaf, tree.root()),
+                    "{len} {index}"
+                );
+                assert!(!path.verify(tree.spec(), *leaf + Fp::ONE, tree.root()));
+            }
+        }
+    }
+
+    #[test]
+    fn small_roots() {
+        let spec = spec();
+        let [a, b, c] = [1, 2, 3].map(Fp::from);
+        let leaf = |leaf| hash_leaf(&spec, leaf);
+
+        assert_eq!(
+            MerkleTree::new(spec.clone(), vec![a]).unwrap().root(),
+            hash_root(&spec, leaf(a), 1)
+        );
+        assert_eq!(
+            MerkleTree::new(spec.clone(), vec![a, b]).unwrap().root(),
+            hash_root(&spec, hash_node(&spec, leaf(a), leaf(b)), 2)
+        );
+        // odd leaves are padded by zeros
+        assert_eq!(
+            MerkleTree::new(spec.clone(), vec![a, b, c]).unwrap().root(),
+            hash_root(
+                &spec,
+                hash_node(
+                    &spec,
+                    hash_node(&spec, leaf(a), leaf(b)),
+                    hash_node(&spec, leaf(c), Fp::ZERO)
+                ),
+                3
+            )
+        );
+    }
+
+    #[test]
+    fn bound_shape() {
+        let spec = spec();
+        let [a, b, c] = [1, 2, 3].map(Fp::from);
+        let root = |leaves: Vec<Fp>| MerkleTree::new(spec.clone(), leaves).unwrap().root();
+
+        // a one leaf tree isn't its leaf, nor the tree of the children of a node
+        assert_ne!(root(vec![a]), a);
+        assert_ne!(root(vec![hash_node(&spec, a, b)]), root(vec![a, b]));
+        // padding isn't a leaf
+        assert_ne!(root(vec![a, b, c]), root(vec![a, b, c, Fp::ZERO]));
+
+        // a path can't claim another number of leaves
+        let tree = MerkleTree::new(spec.clone(), vec![a, b, c]).unwrap();
+        let mut path = tree.path(2).unwrap();
+        assert!(path.verify(&spec, c, tree.root()));
+        path.num_leaves = 4;
+        assert!(!path.verify(&spec, c, tree.root()));
+        path.num_leaves = 2;
+        assert!(!path.verify(&spec, c, tree.root()));
+    }
+
+    #[test]
+    fn rebuild() {
+        let root = MerkleTree::new(spec(), leaves(1024)).unwrap().root();
+        assert_eq!(MerkleTree::new(spec(), leaves(1024)).unwrap().root(), root);
+
+        let mut changed = leaves(1024);
+        changed[700] += Fp::ONE;
+        assert_ne!(MerkleTree::new(spec(), changed).unwrap().root(), root);
+
+        // a node isn't a plain sponge hash of its children
+        let tree = MerkleTree::new(spec(), leaves(2)).unwrap();
+        assert_ne!(
+            tree.root(),
+            poseidon_hash_n(spec(), Domain::Transcript, [Fp::from(1), Fp::from(2)])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            MerkleTree::<Fp>::new(spec(), vec![]).unwrap_err(),
+            Error::NoLeaves
+        );
+        assert_eq!(
+            MerkleTree::new(spec(), leaves(3))
+                .unwrap()
+                .path(3)
+                .unwrap_err(),
+            Error::IndexOutOfRange { index: 3, len: 3 }
+        );
+    }
+
+    #[test]
+    fn serialize_path() {
+        let tree = MerkleTree::new(spec(), leaves(3)).unwrap();
+        let path = tree.path(2).unwrap();
+
+        let bytes = bincode::serialize(&path).unwrap();
+        let decoded: MerklePath<Fp> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, path);
+        assert!(decoded.verify(tree.spec(), Fp::from(3), tree.root()));
+    }
+}
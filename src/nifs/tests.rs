@@ -341,6 +341,112 @@ mod zero_round_test {
     }
 }
 
+// test a custom gate querying the instance column
+mod instance_query_test {
+    use tracing_test::traced_test;
+
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct TestCircuitConfig {
+        s: Selector,
+        a: Column<Advice>,
+        instance: Column<Instance>,
+    }
+
+    /// Copies every public input into `a`, checked by the gate `s * (a - instance) = 0`
+    struct TestCircuit<F: PrimeField> {
+        values: Vec<Value<F>>,
+    }
+
+    impl<F: PrimeField> TestCircuit<F> {
+        fn new(values: &[F]) -> Self {
+            Self {
+                values: values.iter().copied().map(Value::known).collect(),
+            }
+        }
+    }
+
+    impl<F: PrimeField> Circuit<F> for TestCircuit<F> {
+        type Config = TestCircuitConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                values: vec![Value::unknown(); self.values.len()],
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+            let s = meta.selector();
+            let a = meta.advice_column();
+            let instance = meta.instance_column();
+
+            meta.create_gate("a = instance", |meta| {
+                let s = meta.query_selector(s);
+                let a = meta.query_advice(a, Rotation::cur());
+                let instance = meta.query_instance(instance, Rotation::cur());
+                vec![s * (a - instance)]
+            });
+
+            Self::Config { s, a, instance }
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<F>,
+        ) -> Result<(), plonk::Error> {
+            layouter.assign_region(
+                || "public inputs",
+                |mut region| {
+                    for (row, value) in self.values.iter().enumerate() {
+                        config.s.enable(&mut region, row)?;
+                        region.assign_advice(|| "a", config.a, row, || *value)?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[traced_test]
+    #[test]
+    fn test_nifs() -> Result<(), Error<G1Affine>> {
+        const K: u32 = 4;
+        let public_inputs1 = vec![Fr::from(3), Fr::from(5)];
+        let public_inputs2 = vec![Fr::from(7), Fr::from(11)];
+
+        // the witness of the second circuit doesn't match other public inputs
+        let wrong_inputs = vec![Fr::from(7), Fr::from(12)];
+        assert!(matches!(
+            prepare_trace(
+                K,
+                TestCircuit::new(&public_inputs1),
+                TestCircuit::new(&public_inputs2),
+                public_inputs1.clone(),
+                wrong_inputs,
+                G1Affine::default(),
+            ),
+            Err(Error::Verify { .. })
+        ));
+
+        let (ck, S, pair1, pair2) = prepare_trace(
+            K,
+            TestCircuit::new(&public_inputs1),
+            TestCircuit::new(&public_inputs2),
+            public_inputs1,
+            public_inputs2,
+            G1Affine::default(),
+        )?;
+        // the instance column is folded like an advice column
+        assert_eq!(S.num_instance, 1);
+        assert_eq!(S.num_fold_vars(), 2);
+
+        fold_instances(&ck, &S, pair1, pair2, G1Affine::default())
+    }
+}
+
 // test multiple gates without lookup
 // test example adapted from https://github.com/icemelon/halo2-tutorial
 mod one_round_test {
@@ -86,11 +86,14 @@ impl<C: CurveAffine> VanillaFS<C> {
         let data = PlonkEvalDomain {
             num_advice: S.num_advice_columns,
             num_lookup: S.num_lookups(),
+            num_instance: S.num_instance,
             challenges: &concat_vec!(&U1.challenges, &[U1.u], &U2.challenges, &[U2.to_relax().u]),
             selectors: &S.selectors,
             fixed: &S.fixed_columns,
             W1s: &W1.W,
             W2s: &W2.W,
+            instance1: Some(&U1.instance[..]),
+            instance2: Some(&U2.instance[..]),
         };
 
         let row_size = data.row_size();
@@ -93,6 +93,8 @@ pub struct LookupEvalDomain<'a, F: PrimeField> {
 pub struct PlonkEvalDomain<'a, F: PrimeField> {
     pub(crate) num_advice: usize,
     pub(crate) num_lookup: usize,
+    /// see [`crate::polynomial::expression::QueryIndexContext::num_instance`]
+    pub(crate) num_instance: usize,
     // concatenation of challenges from two RelaxedPlonkInstance
     pub(crate) challenges: &'a [F],
     pub(crate) selectors: &'a [Vec<bool>],
@@ -101,6 +103,10 @@ pub struct PlonkEvalDomain<'a, F: PrimeField> {
     pub(crate) W1s: &'a [Vec<F>],
     // [`RelaxedPlonkWitness::W`] for second instance
     pub(crate) W2s: &'a [Vec<F>],
+    // public inputs of the first instance, the values of its instance column, if known
+    pub(crate) instance1: Option<&'a [F]>,
+    // public inputs of the second instance
+    pub(crate) instance2: Option<&'a [F]>,
 }
 
 impl<'a, F: PrimeField> GetDataForEval<F> for LookupEvalDomain<'a, F> {
@@ -155,12 +161,32 @@ impl<'a, F: PrimeField> GetDataForEval<F> for PlonkEvalDomain<'a, F> {
         let num_advice = self.num_advice;
         let num_lookup = self.num_lookup();
         // maximum index for one instance
-        let max_width = num_advice + num_lookup * 5;
+        let max_width = num_advice + num_lookup * 5 + self.num_instance;
         let (is_first_instance, index) = if index < max_width {
             (true, index)
         } else {
             (false, index - max_width)
         };
+
+        // instance columns follow the lookups, only a single one is supported, same as
+        // `WitnessCollector`
+        let instance_index = index.checked_sub(num_advice + num_lookup * 5);
+        if let Some(instance_index) = instance_index.filter(|i| *i < self.num_instance) {
+            let instance = if is_first_instance {
+                self.instance1
+            } else {
+                self.instance2
+            };
+            return match instance {
+                // the column is zero after the public inputs
+                Some(instance) if instance_index == 0 => {
+                    Ok(instance.get(row).copied().unwrap_or(F::ZERO))
+                }
+                _ => Err(Error::ColumnVariableIndexOutOfBoundary {
+                    column_index: index,
+                }),
+            };
+        }
         let num_witness = if is_first_instance {
             self.W1s.len()
         } else {
@@ -53,7 +53,7 @@ use crate::{
         util::compress_halo2_expression,
         PlonkStructure,
     },
-    polynomial::{graph_evaluator::GraphEvaluator, Expression, Query},
+    polynomial::{graph_evaluator::GraphEvaluator, Expression, LiftError, Query},
 };
 
 /// Lookup Argument
@@ -83,14 +83,19 @@ pub struct Arguments<F: PrimeField> {
 
 impl<F: PrimeField> Arguments<F> {
     /// Compresses a potentially vector Lookup Argument from a constraint system into non-vector expression.
+    ///
+    /// `None` if `cs` has no lookups, fails if a lookup queries an instance column
     #[instrument(name = "lookup", skip_all)]
-    pub fn compress_from(cs: &ConstraintSystem<F>) -> Option<Self> {
-        let max_lookup_len = cs
+    pub fn compress_from(cs: &ConstraintSystem<F>) -> Result<Option<Self>, LiftError> {
+        let Some(max_lookup_len) = cs
             .lookups()
             .iter()
             .map(|arg| arg.input_expressions().len())
             .max()
-            .filter(|l| *l != 0)?;
+            .filter(|l| *l != 0)
+        else {
+            return Ok(None);
+        };
 
         let has_vector_lookup = max_lookup_len > 1;
 
@@ -98,7 +103,7 @@ impl<F: PrimeField> Arguments<F> {
             .lookups()
             .iter()
             .map(|arg| {
-                (
+                Ok((
                     compress_halo2_expression(
                         arg.input_expressions()
                             .iter()
@@ -110,23 +115,25 @@ impl<F: PrimeField> Arguments<F> {
                         cs.num_fixed_columns(),
                         // compress vector table items with r1 (challenge_index = 0)
                         0,
-                    ),
+                    )?,
                     compress_halo2_expression(
                         arg.table_expressions(),
                         cs.num_selectors,
                         cs.num_fixed_columns(),
                         // compress vector lookups with r1 (challenge_index = 0)
                         0,
-                    ),
-                )
+                    )?,
+                ))
             })
+            .collect::<Result<Vec<_>, LiftError>>()?
+            .into_iter()
             .unzip();
 
-        Some(Self {
+        Ok(Some(Self {
             lookup_polys,
             table_polys,
             has_vector_lookup,
-        })
+        }))
     }
 
     /// TODO #159
@@ -142,6 +142,12 @@ pub struct PlonkStructure<F: PrimeField> {
     pub(crate) fixed_columns: Vec<Vec<F>>,
 
     pub(crate) num_advice_columns: usize,
+    /// Instance columns queried by gates, see [`QueryIndexContext::num_instance`]
+    ///
+    /// Not serialized, the variables of [`PlonkStructure::custom_gates_lookup_compressed`]
+    /// already depend on it
+    #[serde(skip_serializing)]
+    pub(crate) num_instance: usize,
 
     /// We follow the special soundness protocol(SPS), section 3.1 in [Protostar](https://eprint.iacr.org/2023/620)
     /// let k = num_challenges; when k > 0, we add extra verifier round, this is slightly different
@@ -340,9 +346,10 @@ impl<F: PrimeField> PlonkStructure<F> {
     }
 
     /// return the number of variables to be folded
-    /// each lookup argument will add 5 variables (l,t,m,h,g)
+    /// each lookup argument will add 5 variables (l,t,m,h,g), each instance column queried by
+    /// gates one
     pub fn num_fold_vars(&self) -> usize {
-        self.num_advice_columns + 5 * self.num_lookups()
+        self.num_advice_columns + 5 * self.num_lookups() + self.num_instance
     }
 
     pub fn num_lookups(&self) -> usize {
@@ -376,11 +383,14 @@ impl<F: PrimeField> PlonkStructure<F> {
         let data = PlonkEvalDomain {
             num_advice: self.num_advice_columns,
             num_lookup: self.num_lookups(),
+            num_instance: self.num_instance,
             challenges: &U.challenges,
             selectors: &self.selectors,
             fixed: &self.fixed_columns,
             W1s: &W.W,
             W2s: &[],
+            instance1: Some(&U.instance[..]),
+            instance2: None,
         };
 
         let total_row = 1 << self.k;
@@ -434,11 +444,14 @@ impl<F: PrimeField> PlonkStructure<F> {
         let data = PlonkEvalDomain {
             num_advice: self.num_advice_columns,
             num_lookup: self.num_lookups(),
+            num_instance: self.num_instance,
             challenges: &concat_vec!(&U.challenges, &[U.u]),
             selectors: &self.selectors,
             fixed: &self.fixed_columns,
             W1s: &W.W,
             W2s: &[],
+            instance1: Some(&U.instance[..]),
+            instance2: None,
         };
 
         let evaluator = GraphEvaluator::new(self.custom_gates_lookup_compressed.homogeneous());
@@ -962,14 +975,18 @@ pub(crate) fn iter_evaluate_witness<'link, F: PrimeField>(
     trace: &'link (impl Sync + GetChallenges<F> + GetWitness<F>),
 ) -> impl 'link + Send + Iterator<Item = Result<F, eval::Error>> {
     S.gates.iter().flat_map(|gate| {
+        // traces carry no public inputs, so gates querying instance columns fail to evaluate
         let eval_domain = PlonkEvalDomain {
             num_advice: S.num_advice_columns,
             num_lookup: S.num_lookups(),
+            num_instance: S.num_instance,
             selectors: &S.selectors,
             fixed: &S.fixed_columns,
             challenges: trace.get_challenges(),
             W1s: trace.get_witness(),
             W2s: &[],
+            instance1: None,
+            instance2: None,
         };
 
         let evaluator = GraphEvaluator::new(gate);
@@ -6,7 +6,7 @@ use tracing::*;
 use crate::{
     ff::PrimeField,
     plonk::permutation::Assembly,
-    polynomial::{sparse::SparseMatrix, Expression},
+    polynomial::{sparse::SparseMatrix, Expression, LiftError},
 };
 
 // Helper function to convert cell indices (column, row) to index in Z vector
@@ -76,17 +76,17 @@ pub(crate) fn compress_halo2_expression<F: PrimeField>(
     num_selectors: usize,
     num_fixed: usize,
     challenge_index: usize,
-) -> Expression<F> {
+) -> Result<Expression<F>, LiftError> {
     let y = Expression::Challenge(challenge_index);
     if exprs.len() > 1 {
         exprs
             .iter()
             .map(|expr| Expression::from_halo2_expr(expr, num_selectors, num_fixed))
-            .fold(Expression::Constant(F::ZERO), |acc, expr| {
-                Expression::Sum(
-                    Box::new(expr),
+            .try_fold(Expression::Constant(F::ZERO), |acc, expr| {
+                Ok(Expression::Sum(
+                    Box::new(expr?),
                     Box::new(Expression::Product(Box::new(acc), Box::new(y.clone()))),
-                )
+                ))
             })
     } else {
         Expression::from_halo2_expr(&exprs[0], num_selectors, num_fixed)
@@ -0,0 +1,379 @@
+//! Straight-line programs evaluating an [`Expression`] on one row or a row of a table
+
+use super::{
+    interned::{ExpressionArena, Node, NodeId},
+    Expression,
+};
+use crate::ff::PrimeField;
+
+/// Register of an [`EvalPlan`], the value of the op of the same index
+type Register = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op<F> {
+    Constant(F),
+    /// `z[index]` at a rotation, on one row only the current one
+    Load(usize, i32),
+    /// `challenges[index]`
+    Challenge(usize),
+    Negate(Register),
+    Add(Register, Register),
+    Mul(Register, Register),
+    Scale(Register, F),
+    /// `x^5`, of a product of five equal factors however they are grouped
+    Pow5(Register),
+}
+
+/// [`Expression::eval_at`] compiled into a sequence of register ops
+///
+/// Identical subtrees are computed once, by interning the tree in an [`ExpressionArena`], &
+/// only the nodes the root needs are kept. Each op writes its own register, the last one is the
+/// value of the expression.
+#[derive(Clone, Debug)]
+pub struct EvalPlan<F> {
+    ops: Vec<Op<F>>,
+    /// Minimal lengths of `z` & `challenges`
+    num_vars: usize,
+    num_challenges: usize,
+    /// Some load is rotated, so the plan is only evaluated over a table
+    rotated: bool,
+}
+
+impl<F: PrimeField> EvalPlan<F> {
+    /// Panics on a halo2 challenge, like [`Expression::eval_at`]
+    pub fn new(expr: &Expression<F>) -> Self {
+        let mut arena = ExpressionArena::default();
+        let root = arena.insert(expr);
+
+        // `(x, n)` for a product of `n` factors `x`
+        let mut powers = Vec::<(NodeId, usize)>::with_capacity(arena.len());
+        let ops = arena
+            .iter()
+            .map(|(id, node)| {
+                let mut power = (id, 1);
+                let op = match node {
+                    Node::Constant(c) => Op::Constant(*c),
+                    Node::Polynomial(query) => Op::Load(query.index, query.rotation.0),
+                    Node::Challenge(index) => Op::Challenge(*index),
+                    Node::Negated(a) => Op::Negate(a.index()),
+                    Node::Sum(a, b) => Op::Add(a.index(), b.index()),
+                    Node::Product(a, b) => {
+                        let ((a_base, a_exp), (b_base, b_exp)) =
+                            (powers[a.index()], powers[b.index()]);
+                        if a_base == b_base {
+                            power = (a_base, a_exp + b_exp);
+                        }
+                        match power {
+                            (base, 5) => Op::Pow5(base.index()),
+                            _ => Op::Mul(a.index(), b.index()),
+                        }
+                    }
+                    Node::Scaled(a, k) => Op::Scale(a.index(), *k),
+                    other => panic!("{other:?} has no value on a single row"),
+                };
+                powers.push(power);
+                op
+            })
+            .collect::<Vec<_>>();
+
+        // ops the root depends on, operands always come before their op
+        let mut needed = vec![false; ops.len()];
+        needed[root.index()] = true;
+        for index in (0..ops.len()).rev() {
+            if !needed[index] {
+                continue;
+            }
+            match ops[index] {
+                Op::Negate(a) | Op::Scale(a, _) | Op::Pow5(a) => needed[a] = true,
+                Op::Add(a, b) | Op::Mul(a, b) => {
+                    needed[a] = true;
+                    needed[b] = true;
+                }
+                Op::Constant(_) | Op::Load(_, _) | Op::Challenge(_) => (),
+            }
+        }
+
+        let mut registers = vec![usize::MAX; ops.len()];
+        let mut plan = Self {
+            ops: vec![],
+            num_vars: 0,
+            num_challenges: 0,
+            rotated: false,
+        };
+        for (index, op) in ops.into_iter().enumerate() {
+            if !needed[index] {
+                continue;
+            }
+            let op = match op {
+                Op::Load(var, rotation) => {
+                    plan.num_vars = plan.num_vars.max(var + 1);
+                    plan.rotated |= rotation != 0;
+                    op
+                }
+                Op::Challenge(challenge) => {
+                    plan.num_challenges = plan.num_challenges.max(challenge + 1);
+                    op
+                }
+                Op::Constant(_) => op,
+                Op::Negate(a) => Op::Negate(registers[a]),
+                Op::Add(a, b) => Op::Add(registers[a], registers[b]),
+                Op::Mul(a, b) => Op::Mul(registers[a], registers[b]),
+                Op::Scale(a, k) => Op::Scale(registers[a], k),
+                Op::Pow5(a) => Op::Pow5(registers[a]),
+            };
+            registers[index] = plan.ops.len();
+            plan.ops.push(op);
+        }
+
+        plan
+    }
+
+    /// Number of ops, one per distinct subtree the root needs
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Value of the compiled expression at `z` & `challenges`, the same as
+    /// [`Expression::eval_at`]
+    ///
+    /// Panics if `z` or `challenges` misses a variable of the expression, or on a rotated
+    /// query, see [`EvalPlan::eval_table`]
+    pub fn eval_row(&self, z: &[F], challenges: &[F]) -> F {
+        assert!(!self.rotated, "rotated query on a single row");
+        self.check_len(z.len(), challenges.len());
+
+        self.run(|index, _| z[index], challenges)
+    }
+
+    /// Value of the compiled expression at `row` of `columns`, `Z_i` at rotation `n` is
+    /// `columns[i][row + n]` with rows wrapping around, like [`super::graph_evaluator::GraphEvaluator`] does
+    ///
+    /// Panics if `columns` or `challenges` misses a variable of the expression, or if the
+    /// columns aren't all of `row + 1` rows at least
+    pub fn eval_table(&self, columns: &[Vec<F>], row: usize, challenges: &[F]) -> F {
+        self.check_len(columns.len(), challenges.len());
+        let num_rows = columns.first().map_or(0, Vec::len);
+        assert!(
+            row < num_rows && columns.iter().all(|column| column.len() == num_rows),
+            "row {row} of columns of {:?} rows",
+            columns.iter().map(Vec::len).collect::<Vec<_>>()
+        );
+
+        self.run(
+            |index, rotation| {
+                let rotated = (row as i64 + rotation as i64).rem_euclid(num_rows as i64);
+                columns[index][rotated as usize]
+            },
+            challenges,
+        )
+    }
+
+    fn check_len(&self, num_vars: usize, num_challenges: usize) {
+        assert!(
+            num_vars >= self.num_vars && num_challenges >= self.num_challenges,
+            "plan of {} values of `z` & {} challenges, got {num_vars} & {num_challenges}",
+            self.num_vars,
+            self.num_challenges,
+        );
+    }
+
+    /// The ops in order, `load(index, rotation)` is the value of a [`Op::Load`]
+    fn run(&self, load: impl Fn(usize, i32) -> F, challenges: &[F]) -> F {
+        let mut registers = Vec::<F>::with_capacity(self.ops.len());
+        for op in &self.ops {
+            let value = match *op {
+                Op::Constant(c) => c,
+                Op::Load(index, rotation) => load(index, rotation),
+                Op::Challenge(index) => challenges[index],
+                Op::Negate(a) => -registers[a],
+                Op::Add(a, b) => registers[a] + registers[b],
+                Op::Mul(a, b) => registers[a] * registers[b],
+                Op::Scale(a, k) => registers[a] * k,
+                Op::Pow5(a) => registers[a].square().square() * registers[a],
+            };
+            registers.push(value);
+        }
+
+        registers
+            .pop()
+            .expect("a plan has at least the op of its root")
+    }
+}
+
+impl<F: PrimeField> Expression<F> {
+    /// [`EvalPlan::new`] of `self`
+    pub fn compile(&self) -> EvalPlan<F> {
+        EvalPlan::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::poly::Rotation;
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        ff::Field,
+        halo2curves::pasta::Fp,
+        main_gate::tests::main_gate_expressions,
+        polynomial::{expression::QueryIndexContext, Query},
+    };
+
+    fn z(index: usize) -> Expression<Fp> {
+        Expression::Polynomial(Query {
+            index,
+            rotation: Rotation::cur(),
+        })
+    }
+
+    fn random(len: usize, rng: &mut StdRng) -> Vec<Fp> {
+        (0..len).map(|_| Fp::random(&mut *rng)).collect()
+    }
+
+    #[test]
+    fn shared_subtrees() {
+        // `Z_0 * Z_1` once, the square of the fifth power of `Z_2` is `Pow5` & a `Mul`
+        let product = z(0) * z(1);
+        let square = z(2) * z(2);
+        let pow5 = square.clone() * square * z(2);
+        let expr = product.clone() + product * Fp::from(3) - pow5.clone() * pow5;
+
+        let plan = expr.compile();
+        assert_eq!(
+            plan.ops,
+            [
+                Op::Load(0, 0),
+                Op::Load(1, 0),
+                Op::Mul(0, 1),
+                Op::Scale(2, Fp::from(3)),
+                Op::Add(2, 3),
+                Op::Load(2, 0),
+                Op::Pow5(5),
+                Op::Mul(6, 6),
+                Op::Negate(7),
+                Op::Add(4, 8),
+            ]
+        );
+
+        let values = [2, 3, 5].map(Fp::from);
+        assert_eq!(plan.eval_row(&values, &[]), expr.eval_at(&values, &[]));
+        assert_eq!(
+            plan.eval_row(&values, &[]),
+            Fp::from(24) - Fp::from(5u64.pow(10))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "plan of 3 values of `z` & 0 challenges, got 2 & 0")]
+    fn short_row() {
+        (z(0) * z(2)).compile().eval_row(&[Fp::ONE, Fp::ONE], &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "rotated query on a single row")]
+    fn rotated_row() {
+        let next = Expression::Polynomial(Query {
+            index: 0,
+            rotation: Rotation::next(),
+        });
+        (next - z(0)).compile().eval_row(&[Fp::ONE], &[]);
+    }
+
+    #[test]
+    fn rotated_table() {
+        // `Z_0[+1] - Z_0 - Z_1[-1]`, loads of the same column at other rotations are apart
+        let rotated = |index, rotation| {
+            Expression::Polynomial(Query {
+                index,
+                rotation: Rotation(rotation),
+            })
+        };
+        let expr = rotated(0, 1) - z(0) - rotated(1, -1);
+        let plan = expr.compile();
+        assert_eq!(plan.ops[..2], [Op::Load(0, 1), Op::Load(0, 0)]);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let columns = vec![random(4, &mut rng), random(4, &mut rng)];
+        for row in 0..4 {
+            let value = |column: usize, rotation: i32| {
+                columns[column][(row as i32 + rotation).rem_euclid(4) as usize]
+            };
+            assert_eq!(
+                plan.eval_table(&columns, row, &[]),
+                value(0, 1) - value(0, 0) - value(1, -1),
+                "{row}"
+            );
+        }
+        // the first & last rows wrap around
+        assert_eq!(
+            plan.eval_table(&columns, 3, &[]),
+            columns[0][0] - columns[0][3] - columns[1][2]
+        );
+        assert_eq!(
+            plan.eval_table(&columns, 0, &[]),
+            columns[0][1] - columns[0][0] - columns[1][3]
+        );
+    }
+
+    #[test]
+    fn main_gate() {
+        let (gates, _, ctx) = main_gate_expressions();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let plan = gates[0][0].compile();
+        // `q_5[i] * state[i]^5` for both states
+        assert_eq!(
+            plan.ops
+                .iter()
+                .filter(|op| matches!(op, Op::Pow5(_)))
+                .count(),
+            2
+        );
+
+        for _ in 0..16 {
+            let values = random(ctx.num_selectors + ctx.num_fixed + ctx.num_advice, &mut rng);
+            for gate in gates.iter().flatten() {
+                assert_eq!(
+                    gate.compile().eval_row(&values, &[]),
+                    gate.eval_at(&values, &[])
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        /// The folded main gate, with every cross term sharing the gate
+        #[test]
+        fn folded_main_gate(seed in any::<u64>()) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (gates, _, ctx) = main_gate_expressions();
+
+            for gate in gates.iter().flatten() {
+                let homogeneous = gate.homogeneous(&ctx).expr;
+                let ctx = QueryIndexContext {
+                    num_challenges: homogeneous.num_challenges(),
+                    ..ctx
+                };
+                let folded = homogeneous
+                    .fold_transform(ctx.num_selectors + ctx.num_fixed, ctx.num_fold_vars());
+
+                let values =
+                    random(ctx.num_selectors + ctx.num_fixed + 2 * ctx.num_fold_vars(), &mut rng);
+                let challenges = random(2 * ctx.num_challenges + 1, &mut rng);
+                let plan = folded.compile();
+                prop_assert_eq!(
+                    plan.eval_row(&values, &challenges),
+                    folded.eval_at(&values, &challenges)
+                );
+            }
+        }
+    }
+}
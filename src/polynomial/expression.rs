@@ -1,19 +1,32 @@
 use std::{
     cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd},
-    collections::{BTreeSet, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt,
     fmt::{Debug, Display},
     ops::{self, Add, Mul, Neg, Sub},
 };
 
-use halo2_proofs::{plonk::Expression as PE, poly::Rotation};
-use serde::Serialize;
+use halo2_proofs::{
+    plonk::{
+        Advice, Challenge, Column, ConstraintSystem, Expression as PE, Fixed, Instance, Selector,
+        VirtualCells,
+    },
+    poly::Rotation,
+};
+use rand_core::RngCore;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::{ff::PrimeField, plonk::PlonkStructure, util::trim_leading_zeros};
+use super::interned::ExpressionArena;
+use crate::{
+    ff::PrimeField,
+    plonk::{eval::Error as EvalError, PlonkStructure},
+    util::trim_leading_zeros,
+};
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub enum ColumnIndex {
     Challenge { column_index: usize },
     Polynominal { rotation: i32, column_index: usize },
+    Halo2Challenge { column_index: usize },
 }
 
 impl PartialOrd for ColumnIndex {
@@ -29,6 +42,7 @@ impl Ord for ColumnIndex {
                 column_index,
             } => (*rotation, *column_index, 0),
             Self::Challenge { column_index } => (0, *column_index, 1),
+            Self::Halo2Challenge { column_index } => (0, *column_index, 2),
         };
 
         to_tuple(self).cmp(&to_tuple(other))
@@ -42,6 +56,8 @@ pub struct QueryIndexContext {
     pub num_advice: usize,
     pub num_challenges: usize,
     pub num_lookups: usize,
+    /// Instance columns queried by gates, indexed after the lookups & folded like advice
+    pub num_instance: usize,
 }
 impl<F: PrimeField> From<&PlonkStructure<F>> for QueryIndexContext {
     fn from(S: &PlonkStructure<F>) -> Self {
@@ -51,13 +67,14 @@ impl<F: PrimeField> From<&PlonkStructure<F>> for QueryIndexContext {
             num_selectors: S.selectors.len(),
             num_challenges: S.num_challenges,
             num_lookups: S.num_lookups(),
+            num_instance: S.num_instance,
         }
     }
 }
 
 impl QueryIndexContext {
     pub fn num_fold_vars(self) -> usize {
-        self.num_advice + self.num_lookups * 5
+        self.num_advice + self.num_lookups * 5 + self.num_instance
     }
 
     pub fn shift_advice_index(self, advice_poly_index: usize) -> usize {
@@ -67,12 +84,156 @@ impl QueryIndexContext {
     pub fn shift_lookup_index(self, lookup_poly_index: usize) -> usize {
         lookup_poly_index + self.num_fold_vars()
     }
+
+    pub fn shift_instance_index(self, instance_poly_index: usize) -> usize {
+        instance_poly_index + self.num_fold_vars()
+    }
+
+    /// Index of the first instance column, right after the lookup polynomials
+    pub fn instance_offset(self) -> usize {
+        self.num_selectors + self.num_fixed + self.num_advice + 5 * self.num_lookups
+    }
+}
+
+/// halo2 columns behind the `Z_i` of [`Expression::from_halo2_expr_with_instance`], each kind
+/// in index order, see [`Expression::to_halo2_expr`]
+#[derive(Clone, Debug, Default)]
+pub struct ColumnMapping {
+    pub selectors: Vec<Selector>,
+    pub fixed: Vec<Column<Fixed>>,
+    pub advice: Vec<Column<Advice>>,
+    /// Lookup polynomials sit between advice & instance, they have no column
+    pub num_lookups: usize,
+    pub instance: Vec<Column<Instance>>,
+    /// By [`Challenge::index`]
+    pub challenges: Vec<Challenge>,
+}
+
+impl ColumnMapping {
+    /// Context numbering the `Z_i` of these columns
+    pub fn query_index_context(&self) -> QueryIndexContext {
+        QueryIndexContext {
+            num_selectors: self.selectors.len(),
+            num_fixed: self.fixed.len(),
+            num_advice: self.advice.len(),
+            num_lookups: self.num_lookups,
+            num_instance: self.instance.len(),
+            ..Default::default()
+        }
+    }
+
+    fn query<F: PrimeField>(
+        &self,
+        query: &Query,
+        meta: &mut VirtualCells<'_, F>,
+    ) -> Result<PE<F>, EvalError> {
+        let ctx = self.query_index_context();
+        if query.index >= ctx.instance_offset() + ctx.num_instance {
+            return Err(EvalError::ColumnVariableIndexOutOfBoundary {
+                column_index: query.index,
+            });
+        }
+
+        let rotation = query.rotation;
+        Ok(match query.subtype(&ctx) {
+            QueryType::Selector if rotation.0 == 0 => {
+                meta.query_selector(self.selectors[query.index])
+            }
+            QueryType::Fixed => {
+                meta.query_fixed(self.fixed[query.index - ctx.num_selectors], rotation)
+            }
+            QueryType::Advice => meta.query_advice(
+                self.advice[query.index - ctx.num_selectors - ctx.num_fixed],
+                rotation,
+            ),
+            QueryType::Instance => {
+                meta.query_instance(self.instance[query.index - ctx.instance_offset()], rotation)
+            }
+            QueryType::Selector | QueryType::Lookup => {
+                return Err(EvalError::UnsupportedVariableType {
+                    var_type: ColumnIndex::Polynominal {
+                        rotation: rotation.0,
+                        column_index: query.index,
+                    },
+                })
+            }
+        })
+    }
+}
+
+/// Names of the variables of an [`Expression`], see [`Expression::display_with`]
+///
+/// A variable without a name keeps its raw form, `Z_i`, `r_i` or `c_i`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VarNames {
+    polys: BTreeMap<usize, String>,
+    challenges: BTreeMap<usize, String>,
+}
+
+impl VarNames {
+    /// Name `Z_index` at any rotation, a rotation is still printed after the name
+    pub fn with_poly(mut self, index: usize, name: impl Into<String>) -> Self {
+        self.polys.insert(index, name.into());
+        self
+    }
+
+    pub fn with_fixed(self, ctx: &QueryIndexContext, column: Column<Fixed>, name: &str) -> Self {
+        self.with_poly(ctx.num_selectors + column.index(), name)
+    }
+
+    pub fn with_advice(self, ctx: &QueryIndexContext, column: Column<Advice>, name: &str) -> Self {
+        self.with_poly(ctx.num_selectors + ctx.num_fixed + column.index(), name)
+    }
+
+    pub fn with_instance(
+        self,
+        ctx: &QueryIndexContext,
+        column: Column<Instance>,
+        name: &str,
+    ) -> Self {
+        self.with_poly(ctx.instance_offset() + column.index(), name)
+    }
+
+    /// Name `r_index`, e.g. `u` for the one added by [`Expression::homogeneous`]
+    pub fn with_challenge(mut self, index: usize, name: impl Into<String>) -> Self {
+        self.challenges.insert(index, name.into());
+        self
+    }
+
+    /// Name the second instance copies of [`GroupedPoly`] after the named originals, `a` gives
+    /// `a'`
+    ///
+    /// The folded variables of `ctx` are copied, selectors & fixed columns are shared
+    ///
+    /// [`GroupedPoly`]: super::grouped_poly::GroupedPoly
+    pub fn with_fold_copies(mut self, ctx: &QueryIndexContext) -> Self {
+        let folded = ctx.num_selectors + ctx.num_fixed..ctx.instance_offset() + ctx.num_instance;
+        let polys = self
+            .polys
+            .iter()
+            .filter(|(index, _)| folded.contains(index))
+            .map(|(index, name)| (index + ctx.num_fold_vars(), format!("{name}'")))
+            .collect::<Vec<_>>();
+        let challenges = self
+            .challenges
+            .iter()
+            .filter(|(index, _)| **index < ctx.num_challenges)
+            .map(|(index, name)| (index + ctx.num_challenges, format!("{name}'")))
+            .collect::<Vec<_>>();
+
+        self.polys.extend(polys);
+        self.challenges.extend(challenges);
+        self
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Query {
     pub index: usize,
-    #[serde(serialize_with = "serialize_rotation")]
+    #[serde(
+        serialize_with = "serialize_rotation",
+        deserialize_with = "deserialize_rotation"
+    )]
     pub rotation: Rotation,
 }
 
@@ -81,6 +242,7 @@ pub enum QueryType {
     Fixed,
     Advice,
     Lookup,
+    Instance,
 }
 
 impl Query {
@@ -95,36 +257,215 @@ impl Query {
             < ctx.num_selectors + ctx.num_fixed + ctx.num_advice + (5 * ctx.num_lookups)
         {
             QueryType::Lookup
+        } else if self.index < ctx.instance_offset() + ctx.num_instance {
+            QueryType::Instance
         } else {
             unreachable!("unknown index {} in {ctx:?}", self.index)
         }
     }
 }
 
-fn serialize_rotation<S: serde::ser::Serializer>(
-    v: &Rotation,
-    serializer: S,
-) -> Result<S::Ok, S::Error> {
+/// `[+1]`-like rotation after a variable, nothing for the current row
+pub(super) fn rotation_suffix(rotation: Rotation) -> String {
+    match rotation.0.cmp(&0) {
+        Ordering::Equal => "".to_owned(),
+        Ordering::Less => format!("[{}]", rotation.0),
+        Ordering::Greater => format!("[+{}]", rotation.0),
+    }
+}
+
+fn serialize_rotation<S: Serializer>(v: &Rotation, serializer: S) -> Result<S::Ok, S::Error> {
     v.0.serialize(serializer)
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize)]
+fn deserialize_rotation<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rotation, D::Error> {
+    i32::deserialize(deserializer).map(Rotation)
+}
+
+/// Field elements are their canonical [`PrimeField::Repr`] bytes
+fn serialize_field<F: PrimeField, S: Serializer>(v: &F, serializer: S) -> Result<S::Ok, S::Error> {
+    v.to_repr().as_ref().serialize(serializer)
+}
+
+fn deserialize_field<'de, F: PrimeField, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<F, D::Error> {
+    let bytes = Vec::<u8>::deserialize(deserializer)?;
+
+    let mut repr = F::Repr::default();
+    if bytes.len() != repr.as_ref().len() {
+        return Err(de::Error::invalid_length(
+            bytes.len(),
+            &"field element repr",
+        ));
+    }
+    repr.as_mut().copy_from_slice(&bytes);
+
+    Option::from(F::from_repr(repr))
+        .ok_or_else(|| de::Error::custom("non-canonical field element repr"))
+}
+
+/// Serialized with the encoding of `F`, which the digest of public params depends on, an
+/// [`ExpressionCache`] encodes field elements by their repr instead
+/// Halo2 queries that [`Expression::from_halo2_expr`] & friends can't lift
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LiftError {
+    #[error("query of instance column {column_index}, only {num_instance} can be lifted")]
+    UnsupportedQuery {
+        column_index: usize,
+        num_instance: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Expression<F> {
     Constant(F),
     Polynomial(Query),
     Challenge(usize),
+    /// Challenge of a multi-phase halo2 circuit, shared by both folded instances
+    ///
+    /// Unlike [`Expression::Challenge`] it isn't folded: it has zero degree & no second copy
+    Halo2Challenge(usize),
     Negated(Box<Expression<F>>),
     Sum(Box<Expression<F>>, Box<Expression<F>>),
     Product(Box<Expression<F>>, Box<Expression<F>>),
     Scaled(Box<Expression<F>>, F),
 }
 
+/// [`Expression`] with field elements as their repr, the encoding of an [`ExpressionCache`]
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "F: PrimeField", deserialize = "F: PrimeField"))]
+enum ReprExpression<F> {
+    Constant(
+        #[serde(
+            serialize_with = "serialize_field",
+            deserialize_with = "deserialize_field"
+        )]
+        F,
+    ),
+    Polynomial(Query),
+    Challenge(usize),
+    Halo2Challenge(usize),
+    Negated(Box<ReprExpression<F>>),
+    Sum(Box<ReprExpression<F>>, Box<ReprExpression<F>>),
+    Product(Box<ReprExpression<F>>, Box<ReprExpression<F>>),
+    Scaled(
+        Box<ReprExpression<F>>,
+        #[serde(
+            serialize_with = "serialize_field",
+            deserialize_with = "deserialize_field"
+        )]
+        F,
+    ),
+}
+
+impl<F: Copy> From<&Expression<F>> for ReprExpression<F> {
+    fn from(expr: &Expression<F>) -> Self {
+        let lift = |expr: &Expression<F>| Box::new(ReprExpression::from(expr));
+        match expr {
+            Expression::Constant(v) => ReprExpression::Constant(*v),
+            Expression::Polynomial(query) => ReprExpression::Polynomial(*query),
+            Expression::Challenge(index) => ReprExpression::Challenge(*index),
+            Expression::Halo2Challenge(index) => ReprExpression::Halo2Challenge(*index),
+            Expression::Negated(a) => ReprExpression::Negated(lift(a)),
+            Expression::Sum(a, b) => ReprExpression::Sum(lift(a), lift(b)),
+            Expression::Product(a, b) => ReprExpression::Product(lift(a), lift(b)),
+            Expression::Scaled(a, k) => ReprExpression::Scaled(lift(a), *k),
+        }
+    }
+}
+
+impl<F> From<ReprExpression<F>> for Expression<F> {
+    fn from(expr: ReprExpression<F>) -> Self {
+        let lower = |expr: Box<ReprExpression<F>>| Box::new(Expression::from(*expr));
+        match expr {
+            ReprExpression::Constant(v) => Expression::Constant(v),
+            ReprExpression::Polynomial(query) => Expression::Polynomial(query),
+            ReprExpression::Challenge(index) => Expression::Challenge(index),
+            ReprExpression::Halo2Challenge(index) => Expression::Halo2Challenge(index),
+            ReprExpression::Negated(a) => Expression::Negated(lower(a)),
+            ReprExpression::Sum(a, b) => Expression::Sum(lower(a), lower(b)),
+            ReprExpression::Product(a, b) => Expression::Product(lower(a), lower(b)),
+            ReprExpression::Scaled(a, k) => Expression::Scaled(lower(a), k),
+        }
+    }
+}
+
+/// Version of [`ExpressionCache`], bumped with any change of the encoding of [`Expression`]
+pub const EXPRESSION_CACHE_VERSION: u32 = 1;
+
+/// [`Expression`]s to keep on disk, tagged by [`EXPRESSION_CACHE_VERSION`]
+///
+/// A cache of another version fails to deserialize instead of being read as other expressions.
+/// Field elements are their canonical [`PrimeField::Repr`] bytes, whatever the encoding of `F`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    bound(serialize = "F: PrimeField", deserialize = "F: PrimeField"),
+    into = "ReprCache<F>",
+    from = "ReprCache<F>"
+)]
+pub struct ExpressionCache<F> {
+    version: u32,
+    pub expressions: Vec<Expression<F>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "F: PrimeField", deserialize = "F: PrimeField"))]
+struct ReprCache<F> {
+    #[serde(deserialize_with = "deserialize_cache_version")]
+    version: u32,
+    expressions: Vec<ReprExpression<F>>,
+}
+
+impl<F: Copy> From<ExpressionCache<F>> for ReprCache<F> {
+    fn from(cache: ExpressionCache<F>) -> Self {
+        Self {
+            version: cache.version,
+            expressions: cache.expressions.iter().map(ReprExpression::from).collect(),
+        }
+    }
+}
+
+impl<F> From<ReprCache<F>> for ExpressionCache<F> {
+    fn from(cache: ReprCache<F>) -> Self {
+        Self {
+            version: cache.version,
+            expressions: cache
+                .expressions
+                .into_iter()
+                .map(Expression::from)
+                .collect(),
+        }
+    }
+}
+
+impl<F> ExpressionCache<F> {
+    pub fn new(expressions: Vec<Expression<F>>) -> Self {
+        Self {
+            version: EXPRESSION_CACHE_VERSION,
+            expressions,
+        }
+    }
+}
+
+fn deserialize_cache_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    if version != EXPRESSION_CACHE_VERSION {
+        return Err(de::Error::custom(format!(
+            "expression cache of version {version}, expected {EXPRESSION_CACHE_VERSION}"
+        )));
+    }
+    Ok(version)
+}
+
 impl<F: Default> Default for Expression<F> {
     fn default() -> Self {
         Expression::Constant(F::default())
     }
 }
 
+/// A sum under a negation, a scale or a product is parenthesized, so the output reads back by
+/// [`Expression::parse`]
 impl<F: PrimeField> Display for Expression<F> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.visualize())
@@ -146,6 +487,11 @@ impl<F: PrimeField> Expression<F> {
                     column_index: *index,
                 });
             }
+            Expression::Halo2Challenge(index) => {
+                set.insert(ColumnIndex::Halo2Challenge {
+                    column_index: *index,
+                });
+            }
             Expression::Negated(a) => a.poly_set(set),
             Expression::Sum(a, b) => {
                 a.poly_set(set);
@@ -159,6 +505,33 @@ impl<F: PrimeField> Expression<F> {
         }
     }
 
+    /// Indices `i` of every `Z_i` of `self`, at any rotation
+    pub fn used_vars(&self) -> BTreeSet<usize> {
+        let mut set = BTreeSet::new();
+        self.poly_set(&mut set);
+        set.into_iter()
+            .filter_map(|index| match index {
+                ColumnIndex::Polynominal { column_index, .. } => Some(column_index),
+                _other => None,
+            })
+            .collect()
+    }
+
+    /// Biggest index of [`Expression::used_vars`], `None` if `self` has no `Z_i`
+    pub fn max_var(&self) -> Option<usize> {
+        self.used_vars().last().copied()
+    }
+
+    /// Check an assignment of `len` values of `Z_i` covers every index of `self`
+    pub fn validate_assignment(&self, len: usize) -> Result<(), EvalError> {
+        match self.max_var() {
+            Some(column_index) if column_index >= len => {
+                Err(EvalError::ColumnVariableIndexOutOfBoundary { column_index })
+            }
+            _ => Ok(()),
+        }
+    }
+
     // return the number of challenges in expression
     pub fn num_challenges(&self) -> usize {
         let mut set = HashSet::new();
@@ -169,7 +542,7 @@ impl<F: PrimeField> Expression<F> {
     fn collect_challenges(&self, set: &mut HashSet<ColumnIndex>) {
         match self {
             Expression::Constant(_) => (),
-            Expression::Polynomial(_) => (),
+            Expression::Polynomial(_) | Expression::Halo2Challenge(_) => (),
             Expression::Challenge(index) => {
                 set.insert(ColumnIndex::Challenge {
                     column_index: *index,
@@ -194,18 +567,29 @@ impl<F: PrimeField> Expression<F> {
         constant: &impl Fn(F) -> T,
         poly: &impl Fn(Query) -> T,
         challenge: &impl Fn(usize) -> T,
+        halo2_challenge: &impl Fn(usize) -> T,
         negated: &impl Fn(T) -> T,
         sum: &impl Fn(T, T) -> T,
         product: &impl Fn(T, T) -> T,
         scaled: &impl Fn(T, F) -> T,
     ) -> T {
         let evaluate = |expr: &Expression<F>| {
-            expr.evaluate(constant, poly, challenge, negated, sum, product, scaled)
+            expr.evaluate(
+                constant,
+                poly,
+                challenge,
+                halo2_challenge,
+                negated,
+                sum,
+                product,
+                scaled,
+            )
         };
         match self {
             Expression::Constant(scalar) => constant(*scalar),
             Expression::Polynomial(query) => poly(*query),
             Expression::Challenge(usize) => challenge(*usize),
+            Expression::Halo2Challenge(index) => halo2_challenge(*index),
             Expression::Negated(a) => {
                 let a = evaluate(a);
                 negated(a)
@@ -227,8 +611,220 @@ impl<F: PrimeField> Expression<F> {
         }
     }
 
+    /// Value of `self` with every variable of [`Expression::poly_set`] taking `assign(variable)`
+    ///
+    /// One walk of the tree, nothing is expanded
+    pub fn eval(&self, assign: &impl Fn(ColumnIndex) -> F) -> F {
+        self.evaluate(
+            &|constant| constant,
+            &|query| {
+                assign(ColumnIndex::Polynominal {
+                    rotation: query.rotation.0,
+                    column_index: query.index,
+                })
+            },
+            &|index| {
+                assign(ColumnIndex::Challenge {
+                    column_index: index,
+                })
+            },
+            &|index| {
+                assign(ColumnIndex::Halo2Challenge {
+                    column_index: index,
+                })
+            },
+            &|a| -a,
+            &|a, b| a + b,
+            &|a, b| a * b,
+            &|a, k| a * k,
+        )
+    }
+
+    /// [`Expression::eval`] on one row: `Z_i` is `z[i]` & `r_i` is `challenges[i]`, in the
+    /// numbering of [`Expression::from_halo2_expr`]
+    ///
+    /// Panics on a rotated query, a halo2 challenge or an index out of `z` or `challenges`, see
+    /// [`Expression::validate_assignment`]
+    pub fn eval_at(&self, z: &[F], challenges: &[F]) -> F {
+        if let Err(err) = self.validate_assignment(z.len()) {
+            panic!("{err}, {} values of `z`", z.len());
+        }
+
+        self.eval(&|variable| match variable {
+            ColumnIndex::Polynominal {
+                rotation: 0,
+                column_index,
+            } => z[column_index],
+            ColumnIndex::Challenge { column_index } => challenges[column_index],
+            other => panic!("{other:?} has no value on a single row"),
+        })
+    }
+
+    /// `self` with constants folded, `0 + a`, `1 * a` & `--a` reduced to `a` & every product by
+    /// the zero constant turned into it, [`Expression::eval`] doesn't change
+    pub fn simplify(&self) -> Self {
+        use Expression::*;
+        let is = |expr: &Self, value: F| matches!(expr, Constant(c) if *c == value);
+
+        self.evaluate(
+            &Constant,
+            &Polynomial,
+            &Challenge,
+            &Halo2Challenge,
+            &|a| match a {
+                Constant(c) => Constant(-c),
+                Negated(a) => *a,
+                a => Negated(Box::new(a)),
+            },
+            &|a, b| match (a, b) {
+                (Constant(a), Constant(b)) => Constant(a + b),
+                (a, b) if is(&a, F::ZERO) => b,
+                (a, b) if is(&b, F::ZERO) => a,
+                (a, b) => Sum(Box::new(a), Box::new(b)),
+            },
+            &|a, b| match (a, b) {
+                (Constant(a), Constant(b)) => Constant(a * b),
+                (a, b) if is(&a, F::ZERO) || is(&b, F::ZERO) => Constant(F::ZERO),
+                (a, b) if is(&a, F::ONE) => b,
+                (a, b) if is(&b, F::ONE) => a,
+                (a, b) => Product(Box::new(a), Box::new(b)),
+            },
+            &|a, k| match a {
+                Constant(c) => Constant(c * k),
+                _ if k == F::ZERO => Constant(F::ZERO),
+                a if k == F::ONE => a,
+                a => Scaled(Box::new(a), k),
+            },
+        )
+    }
+
+    /// Canonical form of `self`: nested sums & products are flattened & their operands sorted,
+    /// constants of a sum are added up & the constant factors of a product, negations included,
+    /// are hoisted into one [`Expression::Scaled`]
+    ///
+    /// Nothing is distributed, so `a * (b + c)` & `a * b + a * c` stay apart, see
+    /// [`Expression::semantic_eq_probabilistic`]
+    pub fn normalize(&self) -> Self {
+        use Expression::*;
+
+        fn scale<F: PrimeField>(expr: Expression<F>, k: F) -> Expression<F> {
+            match expr {
+                Constant(c) => Constant(c * k),
+                Scaled(expr, k_expr) => scale(*expr, k * k_expr),
+                expr if k == F::ONE => expr,
+                expr => Scaled(Box::new(expr), k),
+            }
+        }
+
+        self.evaluate(
+            &Constant,
+            &Polynomial,
+            &Challenge,
+            &Halo2Challenge,
+            &|a| scale(a, -F::ONE),
+            &|a, b| {
+                let (mut constant, mut terms) = (F::ZERO, vec![]);
+                let mut stack = vec![a, b];
+                while let Some(term) = stack.pop() {
+                    match term {
+                        Sum(a, b) => stack.extend([*a, *b]),
+                        Constant(c) => constant += c,
+                        term => terms.push(term),
+                    }
+                }
+                if constant != F::ZERO || terms.is_empty() {
+                    terms.push(Constant(constant));
+                }
+
+                terms.sort_by(Self::canonical_cmp);
+                terms
+                    .into_iter()
+                    .reduce(|acc, term| Sum(Box::new(acc), Box::new(term)))
+                    .expect("at least one term")
+            },
+            &|a, b| {
+                let (mut coefficient, mut factors) = (F::ONE, vec![]);
+                let mut stack = vec![a, b];
+                while let Some(factor) = stack.pop() {
+                    match factor {
+                        Product(a, b) => stack.extend([*a, *b]),
+                        Scaled(a, k) => {
+                            coefficient *= k;
+                            stack.push(*a);
+                        }
+                        Constant(c) => coefficient *= c,
+                        factor => factors.push(factor),
+                    }
+                }
+
+                factors.sort_by(Self::canonical_cmp);
+                let product = factors
+                    .into_iter()
+                    .reduce(|acc, factor| Product(Box::new(acc), Box::new(factor)))
+                    .unwrap_or(Constant(F::ONE));
+                scale(product, coefficient)
+            },
+            &scale,
+        )
+    }
+
+    /// Total order of trees by variant, then by operands, constants by their repr
+    fn canonical_cmp(&self, other: &Self) -> Ordering {
+        use Expression::*;
+        let rank = |expr: &Self| match expr {
+            Constant(_) => 0,
+            Polynomial(_) => 1,
+            Challenge(_) => 2,
+            Halo2Challenge(_) => 3,
+            Negated(_) => 4,
+            Sum(_, _) => 5,
+            Product(_, _) => 6,
+            Scaled(_, _) => 7,
+        };
+        let cmp_field = |a: &F, b: &F| a.to_repr().as_ref().cmp(b.to_repr().as_ref());
+
+        match (self, other) {
+            (Constant(a), Constant(b)) => cmp_field(a, b),
+            (Polynomial(a), Polynomial(b)) => (a.index, a.rotation.0).cmp(&(b.index, b.rotation.0)),
+            (Challenge(a), Challenge(b)) | (Halo2Challenge(a), Halo2Challenge(b)) => a.cmp(b),
+            (Negated(a), Negated(b)) => a.canonical_cmp(b),
+            (Sum(a_lhs, a_rhs), Sum(b_lhs, b_rhs))
+            | (Product(a_lhs, a_rhs), Product(b_lhs, b_rhs)) => a_lhs
+                .canonical_cmp(b_lhs)
+                .then_with(|| a_rhs.canonical_cmp(b_rhs)),
+            (Scaled(a, a_k), Scaled(b, b_k)) => {
+                a.canonical_cmp(b).then_with(|| cmp_field(a_k, b_k))
+            }
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+
+    /// Equality of the [`Expression::normalize`]-d forms, `==` compares the trees as they are
+    pub fn normalized_eq(&self, other: &Self) -> bool {
+        self.normalize() == other.normalize()
+    }
+
+    /// `self` & `other` take the same value on a random assignment of their variables
+    ///
+    /// Equal polynomials always pass, different ones pass with probability at most their degree
+    /// over the field size. Rotated queries are variables of their own.
+    pub fn semantic_eq_probabilistic(&self, other: &Self, mut rng: impl RngCore) -> bool {
+        let mut variables = BTreeSet::new();
+        self.poly_set(&mut variables);
+        other.poly_set(&mut variables);
+
+        let values = variables
+            .into_iter()
+            .map(|variable| (variable, F::random(&mut rng)))
+            .collect::<BTreeMap<_, _>>();
+        let assign = |variable: ColumnIndex| values[&variable];
+
+        self.eval(&assign) == other.eval(&assign)
+    }
+
     // fold_transform will fold a polynomial expression P(f_1,...f_m, x_1,...,x_n)
     // and output P(f_1,...,f_m, x_1+r*y_1,...,x_n+r*y_n)
+    // halo2 challenges are shared, like f_i
     // here mm = num_fixed+num_selectors
     // nn = num_advice
     pub(super) fn fold_transform(&self, mm: usize, nn: usize) -> Self {
@@ -252,6 +848,7 @@ impl<F: PrimeField> Expression<F> {
                 // fold variable x_i -> x_i + r * y_i
                 Expression::Challenge(index) + r.clone() * y
             },
+            &|index| Expression::Halo2Challenge(index),
             &|a| -a,
             &|a, b| a + b,
             &|a, b| a * b,
@@ -260,79 +857,309 @@ impl<F: PrimeField> Expression<F> {
     }
 
     fn visualize(&self) -> String {
+        self.display_with(&VarNames::default())
+    }
+
+    /// [`Display`] of `self` with the variables of `names` printed by name
+    pub fn display_with(&self, names: &VarNames) -> String {
         match self {
             Expression::Constant(c) => trim_leading_zeros(format!("{:?}", c)),
             Expression::Polynomial(poly) => {
-                let rotation = match poly.rotation.0.cmp(&0) {
-                    Ordering::Equal => "".to_owned(),
-                    Ordering::Less => format!("[{}]", poly.rotation.0),
-                    Ordering::Greater => format!("[+{}]", poly.rotation.0),
-                };
-                format!("Z_{}{}", poly.index, rotation)
+                let rotation = rotation_suffix(poly.rotation);
+                match names.polys.get(&poly.index) {
+                    Some(name) => format!("{}{}", name, rotation),
+                    None => format!("Z_{}{}", poly.index, rotation),
+                }
             }
-            Expression::Challenge(index) => format!("r_{}", index),
-            Expression::Negated(a) => format!("-{}", a),
+            Expression::Challenge(index) => match names.challenges.get(index) {
+                Some(name) => name.clone(),
+                None => format!("r_{}", index),
+            },
+            Expression::Halo2Challenge(index) => format!("c_{}", index),
+            Expression::Negated(a) => format!("-{}", a.factor_with(names)),
             Expression::Sum(lhs, rhs) => {
                 if let Expression::Negated(b) = &**rhs {
-                    format!("{} - {}", lhs, b)
+                    format!("{} - {}", lhs.display_with(names), b.factor_with(names))
                 } else {
-                    format!("{} + {}", lhs, rhs)
+                    format!("{} + {}", lhs.display_with(names), rhs.display_with(names))
                 }
             }
             Expression::Product(lhs, rhs) => {
-                let left = if let Expression::Sum(_, _) = &**lhs {
-                    format!("({})", lhs.visualize())
-                } else {
-                    lhs.visualize()
-                };
-                let right = if let Expression::Sum(_, _) = &**rhs {
-                    format!("({})", rhs.visualize())
-                } else {
-                    rhs.visualize()
-                };
-                format!("{} * {}", left, right)
+                format!("{} * {}", lhs.factor_with(names), rhs.factor_with(names))
             }
             Expression::Scaled(a, k) => {
-                format!("{:?} * {}", trim_leading_zeros(format!("{:?}", k)), a)
+                format!(
+                    "{:?} * {}",
+                    trim_leading_zeros(format!("{:?}", k)),
+                    a.factor_with(names)
+                )
             }
         }
     }
 
-    pub fn from_halo2_expr(expr: &PE<F>, num_selector: usize, num_fixed: usize) -> Self {
-        match expr {
-            PE::Constant(c) => Expression::Constant(*c),
-            PE::Selector(sel) => Expression::Polynomial(Query {
-                index: sel.index(),
-                rotation: Rotation(0),
-            }),
-            PE::Fixed(query) => Expression::Polynomial(Query {
-                index: num_selector + query.column_index(),
-                rotation: query.rotation(),
-            }),
-            PE::Advice(query) => Expression::Polynomial(Query {
-                index: num_selector + num_fixed + query.column_index(),
-                rotation: query.rotation(),
-            }),
-            PE::Negated(a) => {
-                let a = Self::from_halo2_expr(a, num_selector, num_fixed);
-                -a
+    /// [`Expression::display_with`] in parentheses if `self` is a sum
+    fn factor_with(&self, names: &VarNames) -> String {
+        match self {
+            Expression::Sum(_, _) => format!("({})", self.display_with(names)),
+            other => other.display_with(names),
+        }
+    }
+
+    /// Operands of `self`, in order
+    fn children(&self) -> Vec<&Self> {
+        match self {
+            Expression::Constant(_)
+            | Expression::Polynomial(_)
+            | Expression::Challenge(_)
+            | Expression::Halo2Challenge(_) => vec![],
+            Expression::Negated(a) | Expression::Scaled(a, _) => vec![a],
+            Expression::Sum(a, b) | Expression::Product(a, b) => vec![a, b],
+        }
+    }
+
+    /// Post-order fold of the tree by an explicit stack, `visit` takes a node & the values of
+    /// its [`Expression::children`]
+    ///
+    /// Lifted gates of big circuits are too deep for a recursive walk
+    pub(super) fn fold_iter<T>(&self, mut visit: impl FnMut(&Self, Vec<T>) -> T) -> T {
+        let mut stack = vec![(self, false)];
+        let mut values = vec![];
+        while let Some((expr, visited)) = stack.pop() {
+            let children = expr.children();
+            if visited || children.is_empty() {
+                let args = values.split_off(values.len() - children.len());
+                values.push(visit(expr, args));
+            } else {
+                stack.push((expr, true));
+                stack.extend(children.into_iter().rev().map(|child| (child, false)));
+            }
+        }
+        values.pop().expect("the root is visited last")
+    }
+
+    /// Operator tree of `self`, one node per line indented by its depth
+    ///
+    /// Operators are `+`, `*`, `-` for a negation & `* k` for a scaling, leaves are printed by
+    /// [`Expression::display_with`]
+    pub fn to_tree_string(&self, names: &VarNames) -> String {
+        let mut lines = vec![];
+        let mut stack = vec![(self, 0)];
+        while let Some((expr, depth)) = stack.pop() {
+            let label = match expr {
+                Expression::Sum(_, _) => "+".to_owned(),
+                Expression::Product(_, _) => "*".to_owned(),
+                Expression::Negated(_) => "-".to_owned(),
+                Expression::Scaled(_, k) => format!("* {}", trim_leading_zeros(format!("{:?}", k))),
+                leaf => leaf.display_with(names),
+            };
+            lines.push(format!("{}{}", "  ".repeat(depth), label));
+            stack.extend(
+                expr.children()
+                    .into_iter()
+                    .rev()
+                    .map(|child| (child, depth + 1)),
+            );
+        }
+        lines.join("\n")
+    }
+
+    /// LaTeX of `self` for math mode, named variables are in `\mathrm`
+    pub fn to_latex(&self, names: &VarNames) -> String {
+        struct Latex {
+            latex: String,
+            is_sum: bool,
+            /// Operand of a negation, so a sum prints it as a difference
+            negated: Option<String>,
+        }
+        let name = |name: &str| format!("\\mathrm{{{}}}", name.replace('_', "\\_"));
+        let operand = |operand: &Latex| {
+            if operand.is_sum {
+                format!("\\left({}\\right)", operand.latex)
+            } else {
+                operand.latex.clone()
             }
-            PE::Sum(a, b) => {
-                let a = Self::from_halo2_expr(a, num_selector, num_fixed);
-                let b = Self::from_halo2_expr(b, num_selector, num_fixed);
-                a + b
+        };
+
+        let Latex { latex, .. } = self.fold_iter(|expr, args: Vec<Latex>| {
+            let (latex, negated) = match (expr, args.as_slice()) {
+                (Expression::Constant(c), []) => (trim_leading_zeros(format!("{:?}", c)), None),
+                (Expression::Polynomial(poly), []) => {
+                    let variable = match names.polys.get(&poly.index) {
+                        Some(poly_name) => name(poly_name),
+                        None => format!("Z_{{{}}}", poly.index),
+                    };
+                    (
+                        format!("{}{}", variable, rotation_suffix(poly.rotation)),
+                        None,
+                    )
+                }
+                (Expression::Challenge(index), []) => match names.challenges.get(index) {
+                    Some(challenge_name) => (name(challenge_name), None),
+                    None => (format!("r_{{{}}}", index), None),
+                },
+                (Expression::Halo2Challenge(index), []) => (format!("c_{{{}}}", index), None),
+                (Expression::Negated(_), [a]) => {
+                    let a = operand(a);
+                    (format!("-{}", a), Some(a))
+                }
+                (Expression::Sum(_, _), [a, b]) => match &b.negated {
+                    Some(b) => (format!("{} - {}", a.latex, b), None),
+                    None => (format!("{} + {}", a.latex, b.latex), None),
+                },
+                (Expression::Product(_, _), [a, b]) => {
+                    (format!("{} \\cdot {}", operand(a), operand(b)), None)
+                }
+                (Expression::Scaled(_, k), [a]) => (
+                    format!(
+                        "{} \\cdot {}",
+                        trim_leading_zeros(format!("{:?}", k)),
+                        operand(a)
+                    ),
+                    None,
+                ),
+                _ => unreachable!("arguments are the children"),
+            };
+
+            Latex {
+                latex,
+                is_sum: matches!(expr, Expression::Sum(_, _)),
+                negated,
             }
-            PE::Product(a, b) => {
-                let a = Self::from_halo2_expr(a, num_selector, num_fixed);
-                let b = Self::from_halo2_expr(b, num_selector, num_fixed);
-                a * b
+        });
+
+        latex
+    }
+
+    /// Lift a halo2 gate polynomial, selectors, fixed & advice columns are indexed in this order
+    ///
+    /// The rotation of a query is kept in [`Query`], so a column queried at several rotations
+    /// gives a distinct variable per `(index, rotation)`, see [`Expression::poly_set`]. Challenges
+    /// become [`Expression::Halo2Challenge`] of the same index. Instance queries fail with
+    /// [`LiftError::UnsupportedQuery`], see [`Expression::from_halo2_expr_with_instance`].
+    pub fn from_halo2_expr(
+        expr: &PE<F>,
+        num_selector: usize,
+        num_fixed: usize,
+    ) -> Result<Self, LiftError> {
+        Self::lift_halo2_expr(expr, num_selector, num_fixed, None)
+    }
+
+    /// [`Expression::from_halo2_expr`] mapping instance queries after the lookups of `ctx`
+    ///
+    /// Instance column `i` becomes [`QueryIndexContext::instance_offset`] `+ i`, so it is a
+    /// [`QueryType::Instance`] folded like an advice column
+    pub fn from_halo2_expr_with_instance(
+        expr: &PE<F>,
+        ctx: &QueryIndexContext,
+    ) -> Result<Self, LiftError> {
+        Self::lift_halo2_expr(
+            expr,
+            ctx.num_selectors,
+            ctx.num_fixed,
+            Some((ctx.instance_offset(), ctx.num_instance)),
+        )
+    }
+
+    /// Reverse of [`Expression::from_halo2_expr_with_instance`] for the columns of `mapping`
+    ///
+    /// The queries are made by `meta`, so it's called inside a gate of the constraint system of
+    /// `mapping`. Fails on an [`Expression::Challenge`], a lookup polynomial, a rotated selector
+    /// or an index out of `mapping`.
+    pub fn to_halo2_expr(
+        &self,
+        mapping: &ColumnMapping,
+        meta: &mut VirtualCells<'_, F>,
+    ) -> Result<PE<F>, EvalError> {
+        // variants are built as is, halo2 operators panic on a product of two selectors
+        Ok(match self {
+            Expression::Constant(c) => PE::Constant(*c),
+            Expression::Polynomial(query) => mapping.query(query, meta)?,
+            Expression::Challenge(index) => {
+                return Err(EvalError::UnsupportedVariableType {
+                    var_type: ColumnIndex::Challenge {
+                        column_index: *index,
+                    },
+                })
             }
-            PE::Scaled(a, k) => {
-                let a = Self::from_halo2_expr(a, num_selector, num_fixed);
-                a * *k
+            Expression::Halo2Challenge(index) => {
+                let challenge = mapping.challenges.get(*index).ok_or(
+                    EvalError::ChallengeIndexOutOfBoundary {
+                        challenge_index: *index,
+                        challeges_len: mapping.challenges.len(),
+                    },
+                )?;
+                meta.query_challenge(*challenge)
             }
-            _ => unimplemented!("not supported"),
-        }
+            Expression::Negated(a) => PE::Negated(Box::new(a.to_halo2_expr(mapping, meta)?)),
+            Expression::Sum(a, b) => PE::Sum(
+                Box::new(a.to_halo2_expr(mapping, meta)?),
+                Box::new(b.to_halo2_expr(mapping, meta)?),
+            ),
+            Expression::Product(a, b) => PE::Product(
+                Box::new(a.to_halo2_expr(mapping, meta)?),
+                Box::new(b.to_halo2_expr(mapping, meta)?),
+            ),
+            Expression::Scaled(a, k) => PE::Scaled(Box::new(a.to_halo2_expr(mapping, meta)?), *k),
+        })
+    }
+
+    /// [`Expression::from_halo2_expr`] of every polynomial of every gate of `cs`, in order
+    ///
+    /// Gates are not [`Expression::simplify`]-ed: the compressed gates, the folding degree & the
+    /// digest of the public params are built from them, and a term like `0 * a^5` still counts
+    /// in the degree. Simplify the result explicitly where only its value matters.
+    pub fn lift_all_gates(cs: &ConstraintSystem<F>) -> Result<Vec<Self>, LiftError> {
+        Self::lift_gates(cs, None)
+    }
+
+    /// [`Expression::lift_all_gates`] through [`Expression::from_halo2_expr_with_instance`], so
+    /// gates may query the instance columns of `ctx`
+    pub fn lift_all_gates_with_instance(
+        cs: &ConstraintSystem<F>,
+        ctx: &QueryIndexContext,
+    ) -> Result<Vec<Self>, LiftError> {
+        Self::lift_gates(cs, Some((ctx.instance_offset(), ctx.num_instance)))
+    }
+
+    /// All gates go through one [`ExpressionArena`], so subtrees shared by several gates are
+    /// lowered once
+    fn lift_gates(
+        cs: &ConstraintSystem<F>,
+        instance: Option<(usize, usize)>,
+    ) -> Result<Vec<Self>, LiftError> {
+        let (arena, roots) = ExpressionArena::intern_gates(cs, instance)?;
+        Ok(roots
+            .into_iter()
+            .map(|root| arena.to_expression(root))
+            .collect())
+    }
+
+    /// [`Expression::lift_all_gates`] with the selectors turned into fixed columns first
+    ///
+    /// `selectors` are the values of every selector of `cs` by row. Each one becomes a fixed
+    /// column after those of `cs`, by [`ConstraintSystem::directly_convert_selectors_to_fixed`],
+    /// so the gates have no [`QueryType::Selector`] left. Returns the gates & the values of these
+    /// columns.
+    pub fn lift_all_gates_with_fixed_selectors(
+        cs: &ConstraintSystem<F>,
+        selectors: Vec<Vec<bool>>,
+    ) -> Result<(Vec<Self>, Vec<Vec<F>>), LiftError> {
+        let (cs, selector_columns) = cs.clone().directly_convert_selectors_to_fixed(selectors);
+        Ok((Self::lift_all_gates(&cs)?, selector_columns))
+    }
+
+    /// `instance` is the offset & number of instance columns, if they can be queried
+    ///
+    /// Lifted through an [`ExpressionArena`], so each distinct subtree is lowered once
+    fn lift_halo2_expr(
+        expr: &PE<F>,
+        num_selector: usize,
+        num_fixed: usize,
+        instance: Option<(usize, usize)>,
+    ) -> Result<Self, LiftError> {
+        let mut arena = ExpressionArena::default();
+        let root = arena.lift_halo2_expr_with(expr, num_selector, num_fixed, instance)?;
+        Ok(arena.to_expression(root))
     }
 
     /// Transforms the current expression into a homogeneous expression with a potentially
@@ -365,11 +1192,12 @@ impl<F: PrimeField> Expression<F> {
             Polynomial(polynomial) => HomogeneousExpression {
                 expr: Polynomial(*polynomial),
                 degree: match polynomial.subtype(ctx) {
-                    QueryType::Advice | QueryType::Lookup => 1,
+                    QueryType::Advice | QueryType::Lookup | QueryType::Instance => 1,
                     _other => 0,
                 },
             },
             Expression::Challenge(challenge) => (Challenge(*challenge), 1).into(),
+            Expression::Halo2Challenge(challenge) => (Halo2Challenge(*challenge), 0).into(),
             Expression::Negated(expr) => {
                 let HomogeneousExpression { expr, degree } = expr.homogeneous(ctx);
                 (Expression::Negated(Box::new(expr)), degree).into()
@@ -432,10 +1260,11 @@ impl<F: PrimeField> Expression<F> {
         self.evaluate(
             &|_| 0,
             &|poly| match poly.subtype(ctx) {
-                QueryType::Advice | QueryType::Lookup => 1,
+                QueryType::Advice | QueryType::Lookup | QueryType::Instance => 1,
                 _other => 0,
             },
             &|_| 1,
+            &|_| 0,
             &|a| a,
             &|a, b| match a.cmp(&b) {
                 Ordering::Equal | Ordering::Greater => a,
@@ -445,23 +1274,63 @@ impl<F: PrimeField> Expression<F> {
             &|a, _| a,
         )
     }
-}
 
-#[derive(Clone, Debug, PartialEq, Serialize, Default)]
-pub struct HomogeneousExpression<F: PrimeField> {
-    pub expr: Expression<F>,
-    pub degree: usize,
-}
+    /// Degree of `self` in all of its variables, selectors & fixed columns included, unlike
+    /// [`Expression::degree`]
+    ///
+    /// Read from the tree without expanding it, so cancelled terms still count
+    pub fn total_degree(&self) -> usize {
+        self.degree_by(&|_| 1)
+    }
 
-impl<F: PrimeField> ops::Deref for HomogeneousExpression<F> {
-    type Target = Expression<F>;
-    fn deref(&self) -> &Self::Target {
-        &self.expr
+    /// Degree of `self` in `variable` alone, the others are constants, see
+    /// [`Expression::total_degree`]
+    pub fn degree_of(&self, variable: &ColumnIndex) -> usize {
+        self.degree_by(&|other| usize::from(&other == variable))
     }
-}
 
-impl<F: PrimeField> From<(Expression<F>, usize)> for HomogeneousExpression<F> {
-    fn from(value: (Expression<F>, usize)) -> Self {
+    fn degree_by(&self, variable_degree: &impl Fn(ColumnIndex) -> usize) -> usize {
+        self.evaluate(
+            &|_| 0,
+            &|query| {
+                variable_degree(ColumnIndex::Polynominal {
+                    rotation: query.rotation.0,
+                    column_index: query.index,
+                })
+            },
+            &|index| {
+                variable_degree(ColumnIndex::Challenge {
+                    column_index: index,
+                })
+            },
+            &|index| {
+                variable_degree(ColumnIndex::Halo2Challenge {
+                    column_index: index,
+                })
+            },
+            &|a| a,
+            &|a, b| a.max(b),
+            &|a, b| a + b,
+            &|a, _| a,
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Default)]
+pub struct HomogeneousExpression<F: PrimeField> {
+    pub expr: Expression<F>,
+    pub degree: usize,
+}
+
+impl<F: PrimeField> ops::Deref for HomogeneousExpression<F> {
+    type Target = Expression<F>;
+    fn deref(&self) -> &Self::Target {
+        &self.expr
+    }
+}
+
+impl<F: PrimeField> From<(Expression<F>, usize)> for HomogeneousExpression<F> {
+    fn from(value: (Expression<F>, usize)) -> Self {
         let (expr, degree) = value;
         Self { expr, degree }
     }
@@ -497,6 +1366,22 @@ impl_expression_ops!(Add, add, Sum, Expression<F>, std::convert::identity);
 impl_expression_ops!(Sub, sub, Sum, Expression<F>, Neg::neg);
 impl_expression_ops!(Mul, mul, Product, Expression<F>, std::convert::identity);
 
+// constants take the node shapes of `a + b` & `a - b`, so `Sum(a, Negated(b))` stays the only
+// subtraction
+impl<F: PrimeField> Add<F> for Expression<F> {
+    type Output = Expression<F>;
+    fn add(self, rhs: F) -> Expression<F> {
+        self + Expression::Constant(rhs)
+    }
+}
+
+impl<F: PrimeField> Sub<F> for Expression<F> {
+    type Output = Expression<F>;
+    fn sub(self, rhs: F) -> Expression<F> {
+        self - Expression::Constant(rhs)
+    }
+}
+
 /// Multiply `Expression::Challenge(new_challenge_index)` by the `degree` time
 pub fn challenge_in_degree<F: PrimeField>(
     new_challenge_index: usize,
@@ -514,17 +1399,30 @@ pub fn challenge_in_degree<F: PrimeField>(
 
 #[cfg(test)]
 mod tests {
-    use std::array;
+    use std::{
+        array,
+        collections::{BTreeMap, BTreeSet},
+        iter,
+    };
 
     // use pasta_curves::{Fp, pallas};
-    use halo2_proofs::poly::Rotation;
+    use halo2_proofs::{
+        plonk::{
+            AdviceQuery, ConstraintSystem, FirstPhase, FixedQuery, InstanceQuery, SecondPhase,
+        },
+        poly::Rotation,
+    };
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
     use tracing::*;
     use tracing_test::traced_test;
 
     use super::super::expression::*;
     use crate::{
-        ff::PrimeField,
+        ff::{Field, PrimeField},
         halo2curves::pasta::{pallas, Fp},
+        main_gate::{tests::main_gate_expressions, MainGate, MainGateConfig},
+        polynomial::grouped_poly::GroupedPoly,
     };
 
     #[traced_test]
@@ -604,4 +1502,942 @@ mod tests {
             "((Z_0 * r_0 + Z_0 * Z_1) * r_0 + Z_0 * Z_1 * Z_2) * r_0 * r_0 + Z_0 * Z_1 * Z_2 * Z_3 * Z_4"
         );
     }
+
+    /// `s * (a[+1] - a - b)`, a running sum of `b` in `a`
+    fn running_sum_gate() -> (Expression<Fp>, QueryIndexContext) {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let s = cs.selector();
+        let [a, b] = [cs.advice_column(), cs.advice_column()];
+        cs.create_gate("running sum", |meta| {
+            let s = meta.query_selector(s);
+            let a_next = meta.query_advice(a, Rotation::next());
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            vec![s * (a_next - a - b)]
+        });
+
+        let ctx = QueryIndexContext {
+            num_selectors: cs.num_selectors,
+            num_fixed: cs.num_fixed_columns(),
+            num_advice: cs.num_advice_columns(),
+            ..Default::default()
+        };
+        let expr = Expression::from_halo2_expr(
+            &cs.gates()[0].polynomials()[0],
+            ctx.num_selectors,
+            ctx.num_fixed,
+        )
+        .unwrap();
+
+        (expr, ctx)
+    }
+
+    #[test]
+    fn from_halo2_expr_rotation() {
+        let (expr, _ctx) = running_sum_gate();
+        assert_eq!(expr.to_string(), "Z_0 * (Z_1[+1] - Z_1 - Z_2)");
+
+        let mut set = BTreeSet::new();
+        expr.poly_set(&mut set);
+        assert_eq!(
+            set.into_iter().collect::<Vec<_>>(),
+            [(0, 0), (0, 1), (0, 2), (1, 1)]
+                .map(|(rotation, column_index)| ColumnIndex::Polynominal {
+                    rotation,
+                    column_index
+                })
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn rotation_cross_terms() {
+        let (expr, ctx) = running_sum_gate();
+
+        // the rotated query of the second instance keeps its rotation
+        let actual = GroupedPoly::new(&expr, &ctx)
+            .iter()
+            .map(|term| term.map(ToString::to_string))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actual,
+            ["(Z_1[+1] - Z_1 - Z_2) * Z_0", "(Z_3[+1] - Z_3 - Z_4) * Z_0"]
+                .map(|term| Some(term.to_owned()))
+                .to_vec()
+        );
+
+        let mut set = BTreeSet::new();
+        expr.fold_transform(ctx.num_selectors + ctx.num_fixed, ctx.num_advice)
+            .poly_set(&mut set);
+        assert!(set.contains(&ColumnIndex::Polynominal {
+            rotation: 1,
+            column_index: 3
+        }));
+        assert!(!set.contains(&ColumnIndex::Polynominal {
+            rotation: 1,
+            column_index: 4
+        }));
+    }
+
+    #[test]
+    fn from_halo2_expr_challenge() {
+        // `s * (b - a * c)`, with `b` assigned after the challenge `c` of the first phase
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let s = cs.selector();
+        let a = cs.advice_column_in(FirstPhase);
+        let c = cs.challenge_usable_after(FirstPhase);
+        let b = cs.advice_column_in(SecondPhase);
+        cs.create_gate("two phases", |meta| {
+            let s = meta.query_selector(s);
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let c = meta.query_challenge(c);
+            vec![s * (b - a * c)]
+        });
+
+        let ctx = QueryIndexContext {
+            num_selectors: cs.num_selectors,
+            num_fixed: cs.num_fixed_columns(),
+            num_advice: cs.num_advice_columns(),
+            ..Default::default()
+        };
+        let expr = Expression::from_halo2_expr(
+            &cs.gates()[0].polynomials()[0],
+            ctx.num_selectors,
+            ctx.num_fixed,
+        )
+        .unwrap();
+        assert_eq!(expr.to_string(), "Z_0 * (Z_2 - Z_1 * c_0)");
+        assert_eq!(expr.num_challenges(), 0);
+        assert_eq!(expr.degree(&ctx), 1);
+
+        let halo2_challenges = |expr: &Expression<Fp>| {
+            let mut set = BTreeSet::new();
+            expr.poly_set(&mut set);
+            set.into_iter()
+                .filter(|index| matches!(index, ColumnIndex::Halo2Challenge { .. }))
+                .collect::<Vec<_>>()
+        };
+        let shared = vec![ColumnIndex::Halo2Challenge { column_index: 0 }];
+
+        // the challenge isn't relabelled for the second instance, unlike the advice columns
+        let grouped = GroupedPoly::new(&expr, &ctx);
+        assert_eq!(
+            grouped
+                .iter()
+                .map(|term| term.map(ToString::to_string))
+                .collect::<Vec<_>>(),
+            ["(Z_2 - Z_1 * c_0) * Z_0", "(Z_4 - Z_3 * c_0) * Z_0"]
+                .map(|term| Some(term.to_owned()))
+                .to_vec()
+        );
+        for term in grouped.iter().flatten() {
+            assert_eq!(halo2_challenges(term), shared);
+        }
+
+        let folded = expr.fold_transform(ctx.num_selectors + ctx.num_fixed, ctx.num_advice);
+        assert_eq!(halo2_challenges(&folded), shared);
+    }
+
+    #[test]
+    fn from_halo2_expr_instance() {
+        // `a - x` for an advice `a` & an instance `x`
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let a = cs.advice_column();
+        let x = cs.instance_column();
+        cs.create_gate("public", |meta| {
+            vec![meta.query_advice(a, Rotation::cur()) - meta.query_instance(x, Rotation::cur())]
+        });
+        let gate = &cs.gates()[0].polynomials()[0];
+
+        let ctx = QueryIndexContext {
+            num_selectors: cs.num_selectors,
+            num_fixed: cs.num_fixed_columns(),
+            num_advice: cs.num_advice_columns(),
+            num_instance: cs.num_instance_columns(),
+            ..Default::default()
+        };
+        let expr = Expression::from_halo2_expr_with_instance(gate, &ctx).unwrap();
+        assert_eq!(expr.to_string(), "Z_0 - Z_1");
+        assert_eq!(expr.degree(&ctx), 1);
+
+        // without the instance columns of a context the query can't be lifted
+        assert_eq!(
+            Expression::from_halo2_expr(gate, ctx.num_selectors, ctx.num_fixed),
+            Err(LiftError::UnsupportedQuery {
+                column_index: 0,
+                num_instance: 0
+            })
+        );
+        assert_eq!(
+            Expression::from_halo2_expr_with_instance(
+                gate,
+                &QueryIndexContext {
+                    num_instance: 0,
+                    ..ctx
+                }
+            ),
+            Err(LiftError::UnsupportedQuery {
+                column_index: 0,
+                num_instance: 0
+            })
+        );
+
+        // `Z_2` & `Z_3` are the advice & instance of the second instance
+        let grouped = GroupedPoly::new(&expr, &ctx);
+        assert_eq!(
+            grouped
+                .iter()
+                .map(|term| term.map(ToString::to_string))
+                .collect::<Vec<_>>(),
+            ["Z_0 - Z_1", "Z_2 - Z_3"]
+                .map(|term| Some(term.to_owned()))
+                .to_vec()
+        );
+
+        let mut set = BTreeSet::new();
+        expr.fold_transform(ctx.num_selectors + ctx.num_fixed, ctx.num_fold_vars())
+            .poly_set(&mut set);
+        assert_eq!(
+            set,
+            iter::once(ColumnIndex::Challenge { column_index: 0 })
+                .chain((0..4).map(|column_index| ColumnIndex::Polynominal {
+                    rotation: 0,
+                    column_index
+                }))
+                .collect::<BTreeSet<_>>()
+        );
+
+        // instance columns follow the lookup polynomials
+        let ctx = QueryIndexContext {
+            num_lookups: 1,
+            ..ctx
+        };
+        let expr = Expression::from_halo2_expr_with_instance(gate, &ctx).unwrap();
+        assert_eq!(expr.to_string(), "Z_0 - Z_6");
+        assert!(matches!(
+            Query {
+                index: 6,
+                rotation: Rotation::cur()
+            }
+            .subtype(&ctx),
+            QueryType::Instance
+        ));
+    }
+
+    #[test]
+    fn lift_all_gates_not_simplified() {
+        // `0 * a^5 + a`
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let a = cs.advice_column();
+        cs.create_gate("zero term", |meta| {
+            let a = meta.query_advice(a, Rotation::cur());
+            let a_5 = a.clone() * a.clone() * a.clone() * a.clone() * a.clone();
+            vec![PE::Constant(Fp::ZERO) * a_5 + a]
+        });
+
+        let gates = Expression::lift_all_gates(&cs).unwrap();
+        assert_eq!(gates.len(), 1);
+
+        // the zero term keeps the folding degree of the gate
+        let ctx = QueryIndexContext {
+            num_advice: 1,
+            ..Default::default()
+        };
+        assert_eq!(gates[0].degree(&ctx), 5);
+        assert_eq!(GroupedPoly::new(&gates[0], &ctx).len(), 6);
+        assert_eq!(gates[0].simplify().degree(&ctx), 1);
+    }
+
+    #[test]
+    fn lift_all_gates_with_fixed_selectors() {
+        // `s_1 * (a - f)` & `s_2 * a * a`
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let [s_1, s_2] = [cs.selector(), cs.selector()];
+        let f = cs.fixed_column();
+        let a = cs.advice_column();
+        cs.create_gate("copy fixed", |meta| {
+            let s_1 = meta.query_selector(s_1);
+            let a = meta.query_advice(a, Rotation::cur());
+            let f = meta.query_fixed(f, Rotation::cur());
+            vec![s_1 * (a - f)]
+        });
+        cs.create_gate("square", |meta| {
+            let s_2 = meta.query_selector(s_2);
+            let a = meta.query_advice(a, Rotation::cur());
+            vec![s_2 * a.clone() * a]
+        });
+
+        let to_strings =
+            |gates: Vec<Expression<Fp>>| gates.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+        assert_eq!(
+            to_strings(Expression::lift_all_gates(&cs).unwrap()),
+            ["Z_0 * (Z_3 - Z_2)", "Z_1 * Z_3 * Z_3"]
+        );
+
+        let selectors = vec![vec![true, false], vec![false, true]];
+        let (gates, selector_columns) =
+            Expression::lift_all_gates_with_fixed_selectors(&cs, selectors).unwrap();
+
+        // `f` keeps `Z_0`, `s_1` & `s_2` are the next fixed columns
+        assert_eq!(
+            to_strings(gates.clone()),
+            ["Z_1 * (Z_3 - Z_0)", "Z_2 * Z_3 * Z_3"]
+        );
+        assert_eq!(
+            selector_columns,
+            [[1, 0], [0, 1]].map(|column| column.map(Fp::from).to_vec())
+        );
+
+        let ctx = QueryIndexContext {
+            num_fixed: 3,
+            num_advice: 1,
+            ..Default::default()
+        };
+        for gate in gates {
+            let mut set = BTreeSet::new();
+            gate.poly_set(&mut set);
+            for index in set {
+                let ColumnIndex::Polynominal { column_index, .. } = index else {
+                    unreachable!("no challenges in gates")
+                };
+                let query = Query {
+                    index: column_index,
+                    rotation: Rotation::cur(),
+                };
+                assert!(!matches!(query.subtype(&ctx), QueryType::Selector));
+            }
+        }
+    }
+
+    /// Lift the first gate of `cs`, lower it back as a new gate, check both halo2 gates lift to
+    /// the same & agree on a random assignment
+    fn halo2_round_trip(mut cs: ConstraintSystem<Fp>, mapping: &ColumnMapping) -> Expression<Fp> {
+        let ctx = mapping.query_index_context();
+        let original = cs.gates()[0].polynomials()[0].clone();
+        let lifted = Expression::from_halo2_expr_with_instance(&original, &ctx).unwrap();
+        cs.create_gate("round trip", |meta| {
+            vec![lifted.to_halo2_expr(mapping, meta).unwrap()]
+        });
+
+        let lowered = cs.gates().last().unwrap().polynomials()[0].clone();
+        assert_eq!(
+            Expression::from_halo2_expr_with_instance(&lowered, &ctx).unwrap(),
+            lifted
+        );
+
+        // values by kind, column & rotation from `-1` to `1`
+        let mut rng = StdRng::seed_from_u64(0);
+        let values: [[[Fp; 3]; 16]; 5] =
+            array::from_fn(|_| array::from_fn(|_| array::from_fn(|_| Fp::random(&mut rng))));
+        let eval = |expr: &PE<Fp>| {
+            expr.evaluate(
+                &|c| c,
+                &|selector| values[0][selector.index()][1],
+                &|query: FixedQuery| {
+                    values[1][query.column_index()][(query.rotation().0 + 1) as usize]
+                },
+                &|query: AdviceQuery| {
+                    values[2][query.column_index()][(query.rotation().0 + 1) as usize]
+                },
+                &|query: InstanceQuery| {
+                    values[3][query.column_index()][(query.rotation().0 + 1) as usize]
+                },
+                &|challenge| values[4][challenge.index()][1],
+                &|a| -a,
+                &|a, b| a + b,
+                &|a, b| a * b,
+                &|a, k| a * k,
+            )
+        };
+        assert_eq!(eval(&lowered), eval(&original));
+
+        lifted
+    }
+
+    #[test]
+    fn to_halo2_expr() {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let s = cs.selector();
+        let f = cs.fixed_column();
+        let a = cs.advice_column_in(FirstPhase);
+        let c = cs.challenge_usable_after(FirstPhase);
+        let b = cs.advice_column_in(SecondPhase);
+        let instance = cs.instance_column();
+        cs.create_gate("every kind", |meta| {
+            let s = meta.query_selector(s);
+            let f = meta.query_fixed(f, Rotation::cur());
+            let a_next = meta.query_advice(a, Rotation::next());
+            let a = meta.query_advice(a, Rotation::cur());
+            let b_prev = meta.query_advice(b, Rotation::prev());
+            let instance = meta.query_instance(instance, Rotation::cur());
+            let c = meta.query_challenge(c);
+            vec![s * (a_next - a * f * Fp::from(3) - b_prev * c) + instance]
+        });
+
+        let mapping = ColumnMapping {
+            selectors: vec![s],
+            fixed: vec![f],
+            advice: vec![a, b],
+            instance: vec![instance],
+            challenges: vec![c],
+            ..Default::default()
+        };
+        // `s`, `f`, `a`, `b` & `instance` in this order
+        assert_eq!(
+            halo2_round_trip(cs, &mapping).used_vars(),
+            (0..5).collect::<BTreeSet<_>>()
+        );
+
+        // no halo2 counterpart
+        let mut cs = ConstraintSystem::<Fp>::default();
+        cs.create_gate("unsupported", |meta| {
+            let folding = Expression::<Fp>::Challenge(0);
+            assert_eq!(
+                folding.to_halo2_expr(&mapping, meta),
+                Err(EvalError::UnsupportedVariableType {
+                    var_type: ColumnIndex::Challenge { column_index: 0 }
+                })
+            );
+            let out_of_mapping = Expression::<Fp>::Polynomial(Query {
+                index: 5,
+                rotation: Rotation(0),
+            });
+            assert_eq!(
+                out_of_mapping.to_halo2_expr(&mapping, meta),
+                Err(EvalError::ColumnVariableIndexOutOfBoundary { column_index: 5 })
+            );
+            vec![PE::Constant(Fp::ZERO)]
+        });
+    }
+
+    #[test]
+    fn to_halo2_expr_main_gate() {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let config: MainGateConfig<2> = MainGate::configure(&mut cs);
+
+        // in the order `MainGate::configure` makes them
+        let mut advice = config.state.to_vec();
+        advice.extend([config.input, config.out]);
+        let fixed = config
+            .q_1
+            .into_iter()
+            .chain(config.q_5)
+            .chain(config.q_m)
+            .chain([config.q_i, config.q_o, config.rc])
+            .collect();
+        let mapping = ColumnMapping {
+            fixed,
+            advice,
+            ..Default::default()
+        };
+
+        let (gates, _, _) = main_gate_expressions();
+        assert_eq!(halo2_round_trip(cs, &mapping), gates[0][0]);
+    }
+
+    #[test]
+    fn tree_latex() {
+        let [a_next, b, c] = [(0, 1), (1, 0), (2, 0)].map(|(index, rotation)| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation(rotation),
+            })
+        });
+        let expr = a_next * (b - (c + Expression::Challenge(0)))
+            + Expression::Halo2Challenge(0) * Fp::from(2);
+        let names = VarNames::default().with_poly(0, "a").with_challenge(0, "u");
+
+        assert_eq!(
+            expr.to_tree_string(&names),
+            [
+                "+",
+                "  *",
+                "    a[+1]",
+                "    +",
+                "      Z_1",
+                "      -",
+                "        +",
+                "          Z_2",
+                "          u",
+                "  * 0x2",
+                "    c_0",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            expr.to_latex(&names),
+            r"\mathrm{a}[+1] \cdot \left(Z_{1} - \left(Z_{2} + \mathrm{u}\right)\right) + 0x2 \cdot c_{0}"
+        );
+    }
+
+    #[test]
+    fn tree_latex_deep() {
+        const DEPTH: usize = 5_000;
+        let z = Expression::<Fp>::Polynomial(Query {
+            index: 0,
+            rotation: Rotation(0),
+        });
+        let deep = (0..DEPTH).fold(z.clone(), |acc, _| acc + z.clone());
+
+        let tree = deep.to_tree_string(&VarNames::default());
+        assert_eq!(tree.lines().count(), 2 * DEPTH + 1);
+        assert_eq!(
+            tree.lines().nth(DEPTH),
+            Some(format!("{}Z_0", "  ".repeat(DEPTH)).as_str())
+        );
+        assert_eq!(
+            deep.to_latex(&VarNames::default()),
+            vec!["Z_{0}"; DEPTH + 1].join(" + ")
+        );
+    }
+
+    #[test]
+    fn normalize() {
+        let [a, b, c] = array::from_fn(|index| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation(0),
+            })
+        });
+        let two = Fp::from(2);
+
+        // commuted & reassociated
+        assert!((a.clone() * b.clone()).normalized_eq(&(b.clone() * a.clone())));
+        assert!(((a.clone() + b.clone()) + c.clone())
+            .normalized_eq(&(c.clone() + (b.clone() + a.clone()))));
+        // constants hoisted
+        assert!((a.clone() * b.clone() * two)
+            .normalized_eq(&(Expression::Constant(two) * b.clone() * a.clone())));
+        assert!((-(a.clone() * b.clone())).normalized_eq(&(b.clone() * -a.clone())));
+        assert!((a.clone() + Fp::ONE + b.clone() + two)
+            .normalized_eq(&(b.clone() + a.clone() + Fp::from(3))));
+        assert!(!(a.clone() * b.clone()).normalized_eq(&(a.clone() * c.clone())));
+
+        // nothing is distributed
+        let factored = a.clone() * (b.clone() + c.clone());
+        let distributed = a.clone() * b.clone() + a.clone() * c.clone();
+        assert!(!factored.normalized_eq(&distributed));
+        assert_ne!(factored, distributed);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(factored.semantic_eq_probabilistic(&distributed, &mut rng));
+        assert!(!factored.semantic_eq_probabilistic(&(a * b + c), &mut rng));
+    }
+
+    #[test]
+    fn ops_shapes() {
+        let [a, b] = array::from_fn(|index| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation(0),
+            })
+        });
+        let k = Fp::from(3);
+        let ctx = QueryIndexContext {
+            num_advice: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(a.clone() - b.clone(), a.clone() + (-b.clone()));
+        assert_eq!(
+            GroupedPoly::new(&(a.clone() - b.clone()), &ctx),
+            GroupedPoly::new(&(a.clone() + (-b.clone())), &ctx)
+        );
+
+        assert_eq!(a.clone() + k, a.clone() + Expression::Constant(k));
+        assert_eq!(a.clone() - k, a.clone() - Expression::Constant(k));
+        assert_eq!((a.clone() - k).to_string(), "Z_0 - 0x3");
+
+        // the scalar multiplies every degree of the grouped polynomial
+        let grouped = |expr: Expression<Fp>| {
+            GroupedPoly::new(&expr, &ctx)
+                .iter()
+                .map(|term| term.map(ToString::to_string))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            grouped((a + b) * k),
+            ["0x3 * (Z_0 + Z_1)", "0x3 * (Z_2 + Z_3)"]
+                .map(|term| Some(term.to_owned()))
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn eval_at() {
+        let [a, b] = array::from_fn(|index| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation(0),
+            })
+        });
+        let expr = (a - Fp::ONE) * b + Expression::Challenge(0) * Fp::from(2);
+
+        assert_eq!(
+            expr.eval_at(&[Fp::from(4), Fp::from(5)], &[Fp::from(7)]),
+            Fp::from(3 * 5 + 2 * 7)
+        );
+    }
+
+    #[test]
+    fn used_vars() {
+        let (gates, _, ctx) = main_gate_expressions();
+        let (mm, nn) = (ctx.num_selectors + ctx.num_fixed, ctx.num_advice);
+
+        for gate in gates.iter().flatten() {
+            let used = gate.used_vars();
+            assert!(used.iter().any(|index| *index < mm));
+            assert_eq!(gate.max_var(), used.last().copied());
+            assert!(gate.max_var().unwrap() < mm + nn);
+
+            // fixed stay, advice get their copies after all advice
+            let folded = gate.fold_transform(mm, nn);
+            let expected = used
+                .iter()
+                .flat_map(|index| {
+                    if *index < mm {
+                        vec![*index]
+                    } else {
+                        vec![*index, index + nn]
+                    }
+                })
+                .collect::<BTreeSet<_>>();
+            assert_eq!(folded.used_vars(), expected);
+
+            let max = folded.max_var().unwrap();
+            assert_eq!(folded.validate_assignment(max + 1), Ok(()));
+            assert_eq!(
+                folded.validate_assignment(max),
+                Err(EvalError::ColumnVariableIndexOutOfBoundary { column_index: max })
+            );
+        }
+
+        let constant = Expression::Constant(Fp::ONE) * Expression::Challenge(0);
+        assert_eq!(constant.used_vars(), BTreeSet::new());
+        assert_eq!(constant.max_var(), None);
+        assert_eq!(constant.validate_assignment(0), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "column variable index out of boundary: 1")]
+    fn eval_at_short_assignment() {
+        let expr = Expression::<Fp>::Polynomial(Query {
+            index: 1,
+            rotation: Rotation(0),
+        });
+        expr.eval_at(&[Fp::ONE], &[]);
+    }
+
+    #[test]
+    fn simplify() {
+        let [z_0, z_1, z_2, z_3] = array::from_fn(|index| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation(0),
+            })
+        });
+        let constant = |c: u64| Expression::Constant(Fp::from(c));
+
+        let gate = z_0.clone() * (z_1.clone() * z_2.clone() - z_3.clone())
+            + constant(0) * z_1.clone() * z_2.clone() * z_3.clone()
+            + constant(1) * z_1.clone()
+            + z_1.clone() * z_2.clone() * z_3.clone() * z_3.clone() * Fp::ZERO
+            + (constant(2) * constant(3) - constant(6)) * z_3.clone() * z_3.clone() * z_3.clone();
+        let simplified = gate.simplify();
+        assert_eq!(simplified.to_string(), "Z_0 * (Z_1 * Z_2 - Z_3) + Z_1");
+
+        let num_nodes = |expr: &Expression<Fp>| {
+            expr.evaluate(
+                &|_| 1,
+                &|_| 1,
+                &|_| 1,
+                &|_| 1,
+                &|a| a + 1,
+                &|a, b| a + b + 1,
+                &|a, b| a + b + 1,
+                &|a, _| a + 1,
+            )
+        };
+        assert_eq!((num_nodes(&gate), num_nodes(&simplified)), (42, 10));
+
+        let ctx = QueryIndexContext {
+            num_fixed: 1,
+            num_advice: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            (
+                GroupedPoly::new(&gate, &ctx).len(),
+                GroupedPoly::new(&simplified, &ctx).len()
+            ),
+            (5, 3)
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let (gates, _, ctx) = main_gate_expressions();
+        let gate = gates[0][0].clone();
+        let folded = gate
+            .homogeneous(&ctx)
+            .expr
+            .fold_transform(ctx.num_selectors + ctx.num_fixed, ctx.num_advice);
+        let (rotated, _) = running_sum_gate();
+        let cache = ExpressionCache::new(vec![
+            gate,
+            folded,
+            rotated * -Fp::ONE,
+            Expression::Halo2Challenge(0),
+        ]);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ExpressionCache<Fp>>(&json).unwrap(),
+            cache
+        );
+        let bytes = bincode::serialize(&cache).unwrap();
+        assert_eq!(
+            bincode::deserialize::<ExpressionCache<Fp>>(&bytes).unwrap(),
+            cache
+        );
+
+        // the version leads the encoding
+        let mut stale = bytes.clone();
+        stale[0] += 1;
+        let err = bincode::deserialize::<ExpressionCache<Fp>>(&stale).unwrap_err();
+        assert!(
+            err.to_string().contains("expression cache of version 2"),
+            "{err}"
+        );
+
+        // a repr past the modulus isn't read as another element
+        let json = format!(
+            "{{\"version\":{EXPRESSION_CACHE_VERSION},\"expressions\":[{{\"Constant\":{:?}}}]}}",
+            [u8::MAX; 32]
+        );
+        let err = serde_json::from_str::<ExpressionCache<Fp>>(&json).unwrap_err();
+        assert!(err.to_string().contains("non-canonical"), "{err}");
+    }
+
+    /// The digest of public params serializes expressions, they keep the encoding of `F`
+    #[test]
+    fn serialize_field_encoding() {
+        let k = Fp::from(7);
+        let variant = |index: u32| index.to_le_bytes().to_vec();
+        let field = bincode::serialize(&k).unwrap();
+
+        assert_eq!(
+            bincode::serialize(&Expression::Constant(k)).unwrap(),
+            [variant(0), field.clone()].concat()
+        );
+        assert_eq!(
+            bincode::serialize(&Expression::Scaled(Box::new(Expression::Challenge(1)), k)).unwrap(),
+            [variant(7), variant(2), 1u64.to_le_bytes().to_vec(), field].concat()
+        );
+    }
+
+    fn arb_expr() -> impl Strategy<Value = Expression<Fp>> {
+        let field = prop_oneof![
+            Just(Fp::ZERO),
+            Just(Fp::ONE),
+            any::<u64>().prop_map(Fp::from)
+        ];
+        let leaf = prop_oneof![
+            field.clone().prop_map(Expression::Constant),
+            (0..3usize, -1..2i32).prop_map(|(index, rotation)| Expression::Polynomial(Query {
+                index,
+                rotation: Rotation(rotation),
+            })),
+            (0..2usize).prop_map(Expression::Challenge),
+            Just(Expression::Halo2Challenge(0)),
+        ];
+        leaf.prop_recursive(5, 32, 2, move |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|a| -a),
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| a + b),
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| a * b),
+                (inner, field.clone()).prop_map(|(a, k)| a * k),
+            ]
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn simplify_keeps_eval(expr in arb_expr(), seed in any::<u64>()) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut variables = BTreeSet::new();
+            expr.poly_set(&mut variables);
+            let values = variables
+                .into_iter()
+                .map(|variable| (variable, Fp::random(&mut rng)))
+                .collect::<BTreeMap<_, _>>();
+
+            let simplified = expr.simplify();
+            let assign = |variable: ColumnIndex| values[&variable];
+            prop_assert_eq!(simplified.eval(&assign), expr.eval(&assign));
+            prop_assert_eq!(simplified.simplify(), simplified);
+        }
+
+        #[test]
+        fn normalize_keeps_eval(expr in arb_expr(), seed in any::<u64>()) {
+            let normalized = expr.normalize();
+            prop_assert!(normalized.semantic_eq_probabilistic(&expr, StdRng::seed_from_u64(seed)));
+            prop_assert_eq!(normalized.normalize(), normalized);
+
+            // operands of every sum & product swapped
+            let mirrored = expr.evaluate(
+                &Expression::Constant,
+                &Expression::Polynomial,
+                &Expression::Challenge,
+                &Expression::Halo2Challenge,
+                &|a| -a,
+                &|a, b| b + a,
+                &|a, b| b * a,
+                &|a, k| a * k,
+            );
+            prop_assert!(mirrored.normalized_eq(&expr));
+        }
+
+        /// [`Expression::parse`] reads back the [`Display`] of any expression
+        #[test]
+        fn parse_display_keeps_eval(expr in arb_expr(), seed in any::<u64>()) {
+            let parsed = Expression::<Fp>::parse(&expr.to_string()).unwrap();
+            prop_assert!(parsed.semantic_eq_probabilistic(&expr, StdRng::seed_from_u64(seed)));
+        }
+
+        #[test]
+        fn serde_round_trip_random(expr in arb_expr()) {
+            let bytes = bincode::serialize(&expr).unwrap();
+            prop_assert_eq!(bincode::deserialize::<Expression<Fp>>(&bytes).unwrap(), expr);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        /// The folded main gate is the sum of its grouped terms by powers of `r`
+        #[test]
+        fn eval_folded_main_gate(seed in any::<u64>()) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (gates, _, ctx) = main_gate_expressions();
+
+            for gate in gates.iter().flatten() {
+                let homogeneous = gate.homogeneous(&ctx).expr;
+                let ctx = QueryIndexContext {
+                    num_challenges: homogeneous.num_challenges(),
+                    ..ctx
+                };
+                let grouped = GroupedPoly::new(&homogeneous, &ctx);
+                let folded = homogeneous.fold_transform(
+                    ctx.num_selectors + ctx.num_fixed,
+                    ctx.num_fold_vars(),
+                );
+
+                let z = (0..ctx.num_selectors + ctx.num_fixed + 2 * ctx.num_fold_vars())
+                    .map(|_| Fp::random(&mut rng))
+                    .collect::<Vec<_>>();
+                // both instances' challenges, then `r`
+                let challenges = (0..2 * ctx.num_challenges + 1)
+                    .map(|_| Fp::random(&mut rng))
+                    .collect::<Vec<_>>();
+                let r = challenges[2 * ctx.num_challenges];
+
+                let expected = grouped
+                    .iter()
+                    .zip(iter::successors(Some(Fp::ONE), |power| Some(*power * r)))
+                    .map(|(term, power)| {
+                        term.map_or(Fp::ZERO, |term| term.eval_at(&z, &challenges)) * power
+                    })
+                    .sum::<Fp>();
+                prop_assert_eq!(folded.eval_at(&z, &challenges), expected);
+            }
+        }
+    }
+
+    /// Trees over `Z_0..Z_2` & `r_0` with positive constants only, so no term of the expanded
+    /// polynomial cancels & the degrees of the tree are exact
+    fn arb_positive_expr() -> impl Strategy<Value = Expression<Fp>> {
+        let leaf = prop_oneof![
+            (1..100u64).prop_map(|c| Expression::Constant(Fp::from(c))),
+            (0..3usize).prop_map(|index| Expression::Polynomial(Query {
+                index,
+                rotation: Rotation(0),
+            })),
+            Just(Expression::Challenge(0)),
+        ];
+        leaf.prop_recursive(4, 16, 2, |inner| {
+            prop_oneof![
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| a + b),
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| a * b),
+                (inner, 1..100u64).prop_map(|(a, k)| a * Fp::from(k)),
+            ]
+        })
+    }
+
+    /// Degree of `f` as a polynomial of its single argument, up to `bound`, by the last non-zero
+    /// forward difference at zero
+    fn degree_in_t(f: impl Fn(Fp) -> Fp, bound: usize) -> usize {
+        let mut differences = (0..=bound as u64 + 1)
+            .map(|t| f(Fp::from(t)))
+            .collect::<Vec<_>>();
+        let mut degree = 0;
+        for order in 0..=bound + 1 {
+            if differences[0] != Fp::ZERO {
+                degree = order;
+            }
+            differences = differences.windows(2).map(|w| w[1] - w[0]).collect();
+        }
+        degree
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn degrees(expr in arb_positive_expr(), seed in any::<u64>()) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let variables = (0..3)
+                .map(|column_index| ColumnIndex::Polynominal {
+                    rotation: 0,
+                    column_index,
+                })
+                .chain(iter::once(ColumnIndex::Challenge { column_index: 0 }))
+                .collect::<Vec<_>>();
+            let values = variables
+                .iter()
+                .map(|_| Fp::random(&mut rng))
+                .collect::<Vec<_>>();
+            let value_of = |variable: &ColumnIndex| {
+                values[variables.iter().position(|v| v == variable).unwrap()]
+            };
+
+            // every variable moves along `t`
+            let total = expr.total_degree();
+            prop_assert_eq!(
+                degree_in_t(|t| expr.eval(&|variable| value_of(&variable) * t), total),
+                total
+            );
+
+            // only `variable` moves along `t`
+            for variable in &variables {
+                let degree = expr.degree_of(variable);
+                let along = |t: Fp| {
+                    expr.eval(&|other| if &other == variable { t } else { value_of(&other) })
+                };
+                prop_assert_eq!(degree_in_t(along, degree), degree, "{:?}", variable);
+            }
+
+            // the fold degree is the number of cross terms of the grouped polynomial
+            let ctx = QueryIndexContext {
+                num_advice: 3,
+                num_challenges: 1,
+                ..Default::default()
+            };
+            prop_assert_eq!(GroupedPoly::new(&expr, &ctx).len(), expr.degree(&ctx) + 1);
+        }
+    }
 }
@@ -0,0 +1,311 @@
+use std::fmt::{self, Display};
+
+use halo2_proofs::plonk::Expression as PE;
+
+use super::{
+    expression::{rotation_suffix, LiftError},
+    ColumnIndex, Expression, Query,
+};
+use crate::{ff::PrimeField, util::trim_leading_zeros};
+
+/// [`Expression`] with n-ary sums & products, by [`Expression::flatten`]
+///
+/// Lifted gates are long left-leaning chains of binary nodes, here a chain of `+` or `*` is one
+/// node whatever its grouping, so it's as deep as the nesting of different operators.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlatExpression<F> {
+    Constant(F),
+    Polynomial(Query),
+    Challenge(usize),
+    Halo2Challenge(usize),
+    Negated(Box<FlatExpression<F>>),
+    /// Terms, none of them a sum
+    Sum(Vec<FlatExpression<F>>),
+    /// Factors, none of them a product
+    Product(Vec<FlatExpression<F>>),
+    Scaled(Box<FlatExpression<F>>, F),
+}
+
+/// `args` in order, with the ones `split` opens replaced by their operands
+fn concat<F>(
+    args: Vec<FlatExpression<F>>,
+    split: impl Fn(FlatExpression<F>) -> Result<Vec<FlatExpression<F>>, FlatExpression<F>>,
+) -> Vec<FlatExpression<F>> {
+    let mut operands = vec![];
+    for arg in args {
+        match split(arg) {
+            // the left operand of a chain is the longest, take it as is
+            Ok(inner) if operands.is_empty() => operands = inner,
+            Ok(inner) => operands.extend(inner),
+            Err(arg) => operands.push(arg),
+        }
+    }
+    operands
+}
+
+impl<F: PrimeField> Expression<F> {
+    /// [`FlatExpression`] of `self`, nested sums & products are merged into one node, it
+    /// evaluates the same
+    pub fn flatten(&self) -> FlatExpression<F> {
+        use FlatExpression as Flat;
+
+        self.fold_iter(|expr, mut args: Vec<Flat<F>>| match expr {
+            Expression::Constant(c) => Flat::Constant(*c),
+            Expression::Polynomial(query) => Flat::Polynomial(*query),
+            Expression::Challenge(index) => Flat::Challenge(*index),
+            Expression::Halo2Challenge(index) => Flat::Halo2Challenge(*index),
+            Expression::Negated(_) => Flat::Negated(Box::new(args.pop().unwrap())),
+            Expression::Scaled(_, k) => Flat::Scaled(Box::new(args.pop().unwrap()), *k),
+            Expression::Sum(_, _) => Flat::Sum(concat(args, |arg| match arg {
+                Flat::Sum(terms) => Ok(terms),
+                arg => Err(arg),
+            })),
+            Expression::Product(_, _) => Flat::Product(concat(args, |arg| match arg {
+                Flat::Product(factors) => Ok(factors),
+                arg => Err(arg),
+            })),
+        })
+    }
+}
+
+impl<F: PrimeField> FlatExpression<F> {
+    /// [`Expression::from_halo2_expr`] flattened
+    pub fn from_halo2_expr(
+        expr: &PE<F>,
+        num_selector: usize,
+        num_fixed: usize,
+    ) -> Result<Self, LiftError> {
+        Ok(Expression::from_halo2_expr(expr, num_selector, num_fixed)?.flatten())
+    }
+
+    /// Binary tree of `self`, each sum & product as a left-leaning chain
+    pub fn to_expression(&self) -> Expression<F> {
+        let chain = |operands: &[Self], op: fn(Expression<F>, Expression<F>) -> Expression<F>| {
+            operands
+                .iter()
+                .map(Self::to_expression)
+                .reduce(op)
+                .expect("n-ary nodes have operands")
+        };
+
+        match self {
+            Self::Constant(c) => Expression::Constant(*c),
+            Self::Polynomial(query) => Expression::Polynomial(*query),
+            Self::Challenge(index) => Expression::Challenge(*index),
+            Self::Halo2Challenge(index) => Expression::Halo2Challenge(*index),
+            Self::Negated(a) => -a.to_expression(),
+            Self::Sum(terms) => chain(terms, |a, b| a + b),
+            Self::Product(factors) => chain(factors, |a, b| a * b),
+            Self::Scaled(a, k) => a.to_expression() * *k,
+        }
+    }
+
+    /// Same as [`Expression::eval`] of [`FlatExpression::to_expression`]
+    pub fn eval(&self, assign: &impl Fn(ColumnIndex) -> F) -> F {
+        match self {
+            Self::Constant(c) => *c,
+            Self::Polynomial(query) => assign(ColumnIndex::Polynominal {
+                rotation: query.rotation.0,
+                column_index: query.index,
+            }),
+            Self::Challenge(index) => assign(ColumnIndex::Challenge {
+                column_index: *index,
+            }),
+            Self::Halo2Challenge(index) => assign(ColumnIndex::Halo2Challenge {
+                column_index: *index,
+            }),
+            Self::Negated(a) => -a.eval(assign),
+            Self::Sum(terms) => terms.iter().map(|term| term.eval(assign)).sum(),
+            Self::Product(factors) => factors.iter().map(|factor| factor.eval(assign)).product(),
+            Self::Scaled(a, k) => a.eval(assign) * k,
+        }
+    }
+
+    /// Nodes on the longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        1 + match self {
+            Self::Negated(a) | Self::Scaled(a, _) => a.depth(),
+            Self::Sum(operands) | Self::Product(operands) => {
+                operands.iter().map(Self::depth).max().unwrap_or(0)
+            }
+            _leaf => 0,
+        }
+    }
+
+    /// `self` in parentheses if it is a sum
+    fn factor(&self) -> String {
+        match self {
+            Self::Sum(_) => format!("({})", self),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// The same as the [`Display`] of [`Expression`], except that a scale is printed like a constant
+/// factor, [`Expression::parse`] reads both back
+impl<F: PrimeField> Display for FlatExpression<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Constant(c) => write!(f, "{}", trim_leading_zeros(format!("{:?}", c))),
+            Self::Polynomial(query) => {
+                write!(f, "Z_{}{}", query.index, rotation_suffix(query.rotation))
+            }
+            Self::Challenge(index) => write!(f, "r_{}", index),
+            Self::Halo2Challenge(index) => write!(f, "c_{}", index),
+            Self::Negated(a) => write!(f, "-{}", a.factor()),
+            Self::Sum(terms) => {
+                for (i, term) in terms.iter().enumerate() {
+                    match (i, term) {
+                        (0, term) => write!(f, "{}", term)?,
+                        (_, Self::Negated(a)) => write!(f, " - {}", a.factor())?,
+                        (_, term) => write!(f, " + {}", term)?,
+                    }
+                }
+                Ok(())
+            }
+            Self::Product(factors) => {
+                let factors = factors.iter().map(Self::factor).collect::<Vec<_>>();
+                write!(f, "{}", factors.join(" * "))
+            }
+            Self::Scaled(a, k) => write!(
+                f,
+                "{} * {}",
+                trim_leading_zeros(format!("{:?}", k)),
+                a.factor()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use halo2_proofs::poly::Rotation;
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        ff::Field,
+        halo2curves::pasta::Fp,
+        main_gate::tests::main_gate_expressions,
+        polynomial::{expression::QueryIndexContext, grouped_poly::GroupedPoly},
+    };
+
+    /// Depth of the binary tree, to compare with [`FlatExpression::depth`]
+    fn binary_depth(expr: &Expression<Fp>) -> usize {
+        expr.fold_iter(|_, args: Vec<usize>| 1 + args.into_iter().max().unwrap_or(0))
+    }
+
+    /// Random values of every variable of `expr`
+    fn assignment(expr: &Expression<Fp>, seed: u64) -> BTreeMap<ColumnIndex, Fp> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut variables = Default::default();
+        expr.poly_set(&mut variables);
+        variables
+            .into_iter()
+            .map(|variable| (variable, Fp::random(&mut rng)))
+            .collect()
+    }
+
+    #[test]
+    fn main_gate() {
+        let (gates, _, ctx) = main_gate_expressions();
+
+        // the chain of the gate is one sum of products
+        let flat = gates[0][0].flatten();
+        assert_eq!(
+            flat.to_string(),
+            "Z_4 * Z_9 * Z_10 + Z_6 * Z_11 + Z_8 + Z_7 * Z_12 + Z_0 * Z_9 + Z_2 * Z_9 * Z_9 * Z_9 * Z_9 * Z_9 + Z_1 * Z_10 + Z_3 * Z_10 * Z_10 * Z_10 * Z_10 * Z_10"
+        );
+        assert_eq!(flat.depth(), 3);
+        assert!(binary_depth(&gates[0][0]) > flat.depth());
+
+        // cross terms of the folded gate
+        for gate in gates.iter().flatten() {
+            let homogeneous = gate.homogeneous(&ctx).expr;
+            let ctx = QueryIndexContext {
+                num_challenges: homogeneous.num_challenges(),
+                ..ctx
+            };
+            for term in GroupedPoly::new(&homogeneous, &ctx).iter().flatten() {
+                let flat = term.flatten();
+                let values = assignment(term, 0);
+                let assign = |variable: ColumnIndex| values[&variable];
+
+                assert_eq!(flat.eval(&assign), term.eval(&assign));
+                assert!(flat.depth() <= binary_depth(term));
+                assert_eq!(flat.to_expression().flatten(), flat);
+            }
+        }
+    }
+
+    #[test]
+    fn display() {
+        let z = |index| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation::cur(),
+            })
+        };
+        let sum = z(0) + z(1);
+
+        // both forms parenthesize a negated or scaled sum, only the scale is printed apart
+        let expr = -sum.clone() * Fp::from(2) + (z(2) - sum.clone()) * -sum.clone();
+        assert_eq!(
+            expr.to_string(),
+            "\"0x2\" * -(Z_0 + Z_1) + (Z_2 - (Z_0 + Z_1)) * -(Z_0 + Z_1)"
+        );
+        assert_eq!(
+            expr.flatten().to_string(),
+            "0x2 * -(Z_0 + Z_1) + (Z_2 - (Z_0 + Z_1)) * -(Z_0 + Z_1)"
+        );
+    }
+
+    /// Trees over `Z_0..Z_2`, a rotated `Z_0`, `r_0` & `c_0`, with every node kind
+    fn arb_expr() -> impl Strategy<Value = Expression<Fp>> {
+        let leaf = prop_oneof![
+            (0..4u64).prop_map(|c| Expression::Constant(Fp::from(c))),
+            (0..3usize).prop_map(|index| Expression::Polynomial(Query {
+                index,
+                rotation: Rotation::cur(),
+            })),
+            Just(Expression::Polynomial(Query {
+                index: 0,
+                rotation: Rotation::next(),
+            })),
+            Just(Expression::Challenge(0)),
+            Just(Expression::Halo2Challenge(0)),
+        ];
+        leaf.prop_recursive(6, 64, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|a| -a),
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| a + b),
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| a - b),
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| a * b),
+                (inner, 1..100u64).prop_map(|(a, k)| a * Fp::from(k)),
+            ]
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        /// The binary form stays the reference of eval
+        #[test]
+        fn flatten_keeps_eval(expr in arb_expr(), seed in any::<u64>()) {
+            let values = assignment(&expr, seed);
+            let assign = |variable: ColumnIndex| values[&variable];
+            let flat = expr.flatten();
+
+            prop_assert_eq!(flat.eval(&assign), expr.eval(&assign));
+            prop_assert_eq!(flat.to_expression().eval(&assign), expr.eval(&assign));
+            prop_assert_eq!(flat.to_expression().flatten(), flat.clone());
+            prop_assert!(flat.depth() <= binary_depth(&expr));
+
+            let parsed = Expression::parse(&flat.to_string()).unwrap();
+            prop_assert_eq!(parsed.eval(&assign), expr.eval(&assign));
+        }
+    }
+}
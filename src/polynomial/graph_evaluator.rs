@@ -1,7 +1,7 @@
 use halo2_proofs::poly::Rotation;
 use tracing::*;
 
-use super::Expression;
+use super::{ColumnIndex, Expression};
 /// This module provides an efficient and flexible way to evaluate expressions that represent
 /// can be represented as a graph of calculations.
 ///
@@ -65,6 +65,8 @@ enum ValueSource {
     Poly { index: usize, rotation: usize },
     /// This is a challenge
     Challenge { index: usize },
+    /// This is a challenge of a multi-phase halo2 circuit, not provided by [`GetDataForEval`]
+    Halo2Challenge { index: usize },
 }
 
 /// Calculation
@@ -126,6 +128,11 @@ impl Calculation {
                             challeges_len: challenges.len(),
                         })
                 }
+                ValueSource::Halo2Challenge { index } => Err(EvalError::UnsupportedVariableType {
+                    var_type: ColumnIndex::Halo2Challenge {
+                        column_index: *index,
+                    },
+                }),
             }
         };
 
@@ -273,6 +280,11 @@ impl<F: PrimeField> GraphEvaluator<F> {
                     index: *challenge_index,
                 }))
             }
+            Expression::Halo2Challenge(challenge_index) => {
+                self.add_calculation(Calculation::Store(ValueSource::Halo2Challenge {
+                    index: *challenge_index,
+                }))
+            }
             Expression::Negated(a) => match **a {
                 Expression::Constant(scalar) => self.add_constant(&-scalar),
                 _ => {
@@ -107,6 +107,10 @@ impl<F: PrimeField> GroupedPoly<F> {
                         index: ctx.shift_lookup_index(poly.index),
                         rotation: poly.rotation,
                     }))),
+                    QueryType::Instance => terms.push(Some(Expression::Polynomial(Query {
+                        index: ctx.shift_instance_index(poly.index),
+                        rotation: poly.rotation,
+                    }))),
                     _other => (),
                 }
 
@@ -118,6 +122,10 @@ impl<F: PrimeField> GroupedPoly<F> {
                     Some(Expression::Challenge(challenge_index + ctx.num_challenges)),
                 ],
             },
+            // shared by both instances, so it has no second copy
+            Halo2Challenge(challenge_index) => GroupedPoly {
+                terms: vec![Some(Expression::Halo2Challenge(*challenge_index))],
+            },
             Negated(a) => GroupedPoly::new(a, ctx).neg(),
             Sum(a, b) => {
                 let (a, b) = (GroupedPoly::new(a, ctx), GroupedPoly::new(b, ctx));
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use halo2_proofs::{
+    plonk::{ConstraintSystem, Expression as PE},
+    poly::Rotation,
+};
+
+use super::{expression::LiftError, ColumnIndex, Expression, Query};
+use crate::ff::PrimeField;
+
+/// Index of a node of an [`ExpressionArena`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// Position of the node in its arena
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Node of an [`ExpressionArena`], the operands are ids of earlier nodes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node<F> {
+    Constant(F),
+    Polynomial(Query),
+    Challenge(usize),
+    Halo2Challenge(usize),
+    Negated(NodeId),
+    Sum(NodeId, NodeId),
+    Product(NodeId, NodeId),
+    Scaled(NodeId, F),
+}
+
+/// [`Node`] with field elements as their repr, to find a node already in the arena
+#[derive(PartialEq, Eq, Hash)]
+enum NodeKey {
+    Constant(Vec<u8>),
+    Polynomial(usize, i32),
+    Challenge(usize),
+    Halo2Challenge(usize),
+    Negated(NodeId),
+    Sum(NodeId, NodeId),
+    Product(NodeId, NodeId),
+    Scaled(NodeId, Vec<u8>),
+}
+
+impl<F: PrimeField> Node<F> {
+    fn key(&self) -> NodeKey {
+        let repr = |v: &F| v.to_repr().as_ref().to_vec();
+        match self {
+            Node::Constant(c) => NodeKey::Constant(repr(c)),
+            Node::Polynomial(query) => NodeKey::Polynomial(query.index, query.rotation.0),
+            Node::Challenge(index) => NodeKey::Challenge(*index),
+            Node::Halo2Challenge(index) => NodeKey::Halo2Challenge(*index),
+            Node::Negated(a) => NodeKey::Negated(*a),
+            Node::Sum(a, b) => NodeKey::Sum(*a, *b),
+            Node::Product(a, b) => NodeKey::Product(*a, *b),
+            Node::Scaled(a, k) => NodeKey::Scaled(*a, repr(k)),
+        }
+    }
+}
+
+/// Hash-consed [`Expression`]s: structurally identical subtrees are stored once
+///
+/// Lifted gates share a lot, e.g. a selector & the body it multiplies in several gates, each
+/// distinct subtree is one [`Node`] here. Nodes are in insertion order, so the operands of a
+/// node always come before it.
+#[derive(Clone, Debug)]
+pub struct ExpressionArena<F: PrimeField> {
+    nodes: Vec<Node<F>>,
+    ids: HashMap<NodeKey, NodeId>,
+}
+
+impl<F: PrimeField> Default for ExpressionArena<F> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            ids: HashMap::new(),
+        }
+    }
+}
+
+impl<F: PrimeField> ExpressionArena<F> {
+    /// Number of distinct nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node<F> {
+        &self.nodes[id.0]
+    }
+
+    /// Nodes with their ids, operands before the nodes using them
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node<F>)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (NodeId(index), node))
+    }
+
+    /// Id of `node`, added only if the arena has no identical one
+    ///
+    /// Panics if an operand of `node` isn't in the arena
+    pub fn intern(&mut self, node: Node<F>) -> NodeId {
+        let operands = match &node {
+            Node::Negated(a) | Node::Scaled(a, _) => vec![*a],
+            Node::Sum(a, b) | Node::Product(a, b) => vec![*a, *b],
+            _leaf => vec![],
+        };
+        assert!(
+            operands.iter().all(|operand| operand.0 < self.nodes.len()),
+            "operands of {node:?} aren't in the arena"
+        );
+
+        let nodes = &mut self.nodes;
+        *self.ids.entry(node.key()).or_insert_with(|| {
+            nodes.push(node);
+            NodeId(nodes.len() - 1)
+        })
+    }
+
+    /// Id of the root of `expr`, interning every subtree
+    pub fn insert(&mut self, expr: &Expression<F>) -> NodeId {
+        let node = match expr {
+            Expression::Constant(c) => Node::Constant(*c),
+            Expression::Polynomial(query) => Node::Polynomial(*query),
+            Expression::Challenge(index) => Node::Challenge(*index),
+            Expression::Halo2Challenge(index) => Node::Halo2Challenge(*index),
+            Expression::Negated(a) => Node::Negated(self.insert(a)),
+            Expression::Sum(a, b) => Node::Sum(self.insert(a), self.insert(b)),
+            Expression::Product(a, b) => Node::Product(self.insert(a), self.insert(b)),
+            Expression::Scaled(a, k) => Node::Scaled(self.insert(a), *k),
+        };
+        self.intern(node)
+    }
+
+    /// [`Expression::from_halo2_expr`] straight into the arena, without building the tree
+    pub fn lift_halo2_expr(
+        &mut self,
+        expr: &PE<F>,
+        num_selector: usize,
+        num_fixed: usize,
+    ) -> Result<NodeId, LiftError> {
+        self.lift_halo2_expr_with(expr, num_selector, num_fixed, None)
+    }
+
+    /// `instance` is the offset & number of instance columns, if they can be queried
+    ///
+    /// Fails with [`LiftError::UnsupportedQuery`] on a query of any other instance column
+    pub(super) fn lift_halo2_expr_with(
+        &mut self,
+        expr: &PE<F>,
+        num_selector: usize,
+        num_fixed: usize,
+        instance: Option<(usize, usize)>,
+    ) -> Result<NodeId, LiftError> {
+        let mut lift =
+            |expr: &PE<F>| self.lift_halo2_expr_with(expr, num_selector, num_fixed, instance);
+        let node = match expr {
+            PE::Constant(c) => Node::Constant(*c),
+            PE::Selector(sel) => Node::Polynomial(Query {
+                index: sel.index(),
+                rotation: Rotation(0),
+            }),
+            PE::Fixed(query) => Node::Polynomial(Query {
+                index: num_selector + query.column_index(),
+                rotation: query.rotation(),
+            }),
+            PE::Advice(query) => Node::Polynomial(Query {
+                index: num_selector + num_fixed + query.column_index(),
+                rotation: query.rotation(),
+            }),
+            PE::Instance(query) => match instance {
+                Some((offset, num_instance)) if query.column_index() < num_instance => {
+                    Node::Polynomial(Query {
+                        index: offset + query.column_index(),
+                        rotation: query.rotation(),
+                    })
+                }
+                _ => {
+                    return Err(LiftError::UnsupportedQuery {
+                        column_index: query.column_index(),
+                        num_instance: instance.map_or(0, |(_, num_instance)| num_instance),
+                    })
+                }
+            },
+            PE::Challenge(challenge) => Node::Halo2Challenge(challenge.index()),
+            PE::Negated(a) => Node::Negated(lift(a)?),
+            PE::Sum(a, b) => {
+                let a = lift(a)?;
+                Node::Sum(a, lift(b)?)
+            }
+            PE::Product(a, b) => {
+                let a = lift(a)?;
+                Node::Product(a, lift(b)?)
+            }
+            PE::Scaled(a, k) => Node::Scaled(lift(a)?, *k),
+        };
+        Ok(self.intern(node))
+    }
+
+    /// Roots of [`Expression::lift_all_gates`] in a new arena, the gates aren't simplified
+    pub fn intern_all_gates(cs: &ConstraintSystem<F>) -> Result<(Self, Vec<NodeId>), LiftError> {
+        Self::intern_gates(cs, None)
+    }
+
+    /// `instance` is as in [`ExpressionArena::lift_halo2_expr_with`]
+    pub(super) fn intern_gates(
+        cs: &ConstraintSystem<F>,
+        instance: Option<(usize, usize)>,
+    ) -> Result<(Self, Vec<NodeId>), LiftError> {
+        let mut arena = Self::default();
+        let roots = cs
+            .gates()
+            .iter()
+            .flat_map(|gate| gate.polynomials())
+            .map(|expr| {
+                arena.lift_halo2_expr_with(expr, cs.num_selectors, cs.num_fixed_columns(), instance)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((arena, roots))
+    }
+
+    /// Tree of the node `id`, shared subtrees are cloned
+    pub fn to_expression(&self, id: NodeId) -> Expression<F> {
+        match self.node(id) {
+            Node::Constant(c) => Expression::Constant(*c),
+            Node::Polynomial(query) => Expression::Polynomial(*query),
+            Node::Challenge(index) => Expression::Challenge(*index),
+            Node::Halo2Challenge(index) => Expression::Halo2Challenge(*index),
+            Node::Negated(a) => -self.to_expression(*a),
+            Node::Sum(a, b) => self.to_expression(*a) + self.to_expression(*b),
+            Node::Product(a, b) => self.to_expression(*a) * self.to_expression(*b),
+            Node::Scaled(a, k) => self.to_expression(*a) * *k,
+        }
+    }
+
+    /// [`Expression::eval`] of the node `id`, each shared node it reaches is evaluated once
+    pub fn eval(&self, id: NodeId, assign: &impl Fn(ColumnIndex) -> F) -> F {
+        self.eval_reachable(id, assign, &mut HashMap::new())
+    }
+
+    /// `values` are the nodes already evaluated, the nodes not reached from `id` are never
+    /// visited
+    fn eval_reachable(
+        &self,
+        id: NodeId,
+        assign: &impl Fn(ColumnIndex) -> F,
+        values: &mut HashMap<NodeId, F>,
+    ) -> F {
+        if let Some(value) = values.get(&id) {
+            return *value;
+        }
+
+        let mut eval = |id: NodeId| self.eval_reachable(id, assign, values);
+        let value = match self.node(id) {
+            Node::Constant(c) => *c,
+            Node::Polynomial(query) => assign(ColumnIndex::Polynominal {
+                rotation: query.rotation.0,
+                column_index: query.index,
+            }),
+            Node::Challenge(index) => assign(ColumnIndex::Challenge {
+                column_index: *index,
+            }),
+            Node::Halo2Challenge(index) => assign(ColumnIndex::Halo2Challenge {
+                column_index: *index,
+            }),
+            Node::Negated(a) => -eval(*a),
+            Node::Sum(a, b) => eval(*a) + eval(*b),
+            Node::Product(a, b) => eval(*a) * eval(*b),
+            Node::Scaled(a, k) => eval(*a) * k,
+        };
+        values.insert(id, value);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{ff::Field, halo2curves::pasta::Fp};
+
+    fn num_nodes(expr: &Expression<Fp>) -> usize {
+        expr.evaluate(
+            &|_| 1,
+            &|_| 1,
+            &|_| 1,
+            &|_| 1,
+            &|a| a + 1,
+            &|a, b| a + b + 1,
+            &|a, b| a + b + 1,
+            &|a, _| a + 1,
+        )
+    }
+
+    #[test]
+    fn shared_selector() {
+        const NUM_GATES: usize = 50;
+
+        // `s * (a_i - b * c)` for every `i`
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let s = cs.selector();
+        let [b, c] = [cs.advice_column(), cs.advice_column()];
+        for _ in 0..NUM_GATES {
+            let a = cs.advice_column();
+            cs.create_gate("shared selector", |meta| {
+                let s = meta.query_selector(s);
+                let [a, b, c] = [a, b, c].map(|column| meta.query_advice(column, Rotation::cur()));
+                vec![s * (a - b * c)]
+            });
+        }
+
+        let (arena, roots) = ExpressionArena::intern_all_gates(&cs).unwrap();
+        let gates = Expression::lift_all_gates(&cs).unwrap();
+        assert_eq!(roots.len(), NUM_GATES);
+
+        // `s`, `b`, `c`, `b * c` & its negation once, `a_i`, the sum & the product per gate
+        let naive = gates.iter().map(num_nodes).sum::<usize>();
+        assert_eq!((arena.len(), naive), (5 + 3 * NUM_GATES, 8 * NUM_GATES));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let values = (0..cs.num_selectors + cs.num_advice_columns())
+            .map(|column_index| {
+                (
+                    ColumnIndex::Polynominal {
+                        rotation: 0,
+                        column_index,
+                    },
+                    Fp::random(&mut rng),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let assign = |variable: ColumnIndex| values[&variable];
+
+        for (root, gate) in roots.iter().zip(&gates) {
+            let expr = arena.to_expression(*root);
+            assert_eq!(&expr, gate);
+            assert_eq!(expr.to_string(), gate.to_string());
+            assert_eq!(arena.eval(*root, &assign), gate.eval(&assign));
+        }
+    }
+
+    #[test]
+    fn insert() {
+        let z = |index| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation(0),
+            })
+        };
+        let square = z(0) * z(0);
+        let expr = square.clone() + square * Fp::from(2) - Expression::Constant(Fp::ONE);
+
+        let mut arena = ExpressionArena::default();
+        let root = arena.insert(&expr);
+        // `Z_0`, the square, its scale, the sum, `1`, its negation & the difference
+        assert_eq!(arena.len(), 7);
+        assert_eq!(arena.to_expression(root), expr);
+
+        // inserting again adds nothing
+        assert_eq!(arena.insert(&expr), root);
+        assert_eq!(
+            arena.intern(Node::Polynomial(Query {
+                index: 0,
+                rotation: Rotation(0),
+            })),
+            NodeId(0)
+        );
+        assert_eq!(arena.len(), 7);
+    }
+
+    #[test]
+    fn eval_reachable() {
+        let z = |index| {
+            Expression::<Fp>::Polynomial(Query {
+                index,
+                rotation: Rotation(0),
+            })
+        };
+
+        let mut arena = ExpressionArena::default();
+        let first = arena.insert(&(z(0) * z(0)));
+        let second = arena.insert(&(z(1) + z(1) * z(1)));
+        assert!(first < second);
+
+        // `Z_0` isn't reached from the second root
+        let assign = |variable: ColumnIndex| match variable {
+            ColumnIndex::Polynominal {
+                column_index: 1, ..
+            } => Fp::from(3),
+            _ => panic!("{variable:?} evaluated"),
+        };
+        assert_eq!(arena.eval(second, &assign), Fp::from(12));
+    }
+}
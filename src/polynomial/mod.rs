@@ -1,9 +1,15 @@
+pub mod eval_plan;
 pub mod expression;
+pub mod flat;
 pub mod graph_evaluator;
 pub mod grouped_poly;
+pub mod interned;
 pub mod lagrange;
+pub mod parse;
 pub mod sparse;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod univariate;
 
-pub use expression::{ColumnIndex, Expression, Query, QueryType};
+pub use expression::{ColumnIndex, Expression, LiftError, Query, QueryType};
 pub use lagrange::iter_eval_lagrange_poly_for_cyclic_group;
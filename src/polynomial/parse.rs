@@ -0,0 +1,382 @@
+//! Parser of the [`Display`](std::fmt::Display) of an [`Expression`], for test fixtures
+
+use halo2_proofs::poly::Rotation;
+
+use super::{Expression, Query};
+use crate::ff::PrimeField;
+
+/// Largest `^` exponent, each power is expanded to that many factors
+pub const MAX_EXPONENT: usize = 64;
+/// Deepest nesting of parentheses, negations & scales, the parser recurses on each
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    #[error("Unexpected end of input, expected {expected}")]
+    UnexpectedEnd { expected: &'static str },
+    #[error("Unexpected `{found}` at {position}, expected {expected}")]
+    Unexpected {
+        found: char,
+        position: usize,
+        expected: &'static str,
+    },
+    #[error("Number at {position} is too big")]
+    TooBig { position: usize },
+    #[error("Exponent at {position} is above {}", MAX_EXPONENT)]
+    ExponentTooBig { position: usize },
+    #[error("Nesting at {position} is deeper than {}", MAX_DEPTH)]
+    TooDeep { position: usize },
+}
+
+/// Recursive descent over `input`, `position` is a byte offset & `depth` the number of the
+/// enclosing parentheses, negations & scales
+struct Parser<'s> {
+    input: &'s str,
+    position: usize,
+    depth: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn rest(&self) -> &'s str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, token: char) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.position += token.len_utf8();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: char, expected: &'static str) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn unexpected(&mut self, expected: &'static str) -> ParseError {
+        match self.peek() {
+            Some(found) => ParseError::Unexpected {
+                found,
+                position: self.position,
+                expected,
+            },
+            None => ParseError::UnexpectedEnd { expected },
+        }
+    }
+
+    /// Digits of `radix` right at the position, maybe none
+    fn digits(&mut self, radix: u32) -> &'s str {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(rest.len());
+        self.position += len;
+        &rest[..len]
+    }
+
+    /// `parse` one level deeper, up to [`MAX_DEPTH`]
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError::TooDeep {
+                position: self.position,
+            });
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    /// Decimal `usize`, of an index or an exponent
+    fn index(&mut self, expected: &'static str) -> Result<usize, ParseError> {
+        let position = self.position;
+        match self.digits(10) {
+            "" => Err(self.unexpected(expected)),
+            digits => digits.parse().map_err(|_| ParseError::TooBig { position }),
+        }
+    }
+
+    /// `0x` & hex digits as [`Expression::Constant`] prints it, or decimal digits, reduced by
+    /// the modulus
+    fn constant<F: PrimeField>(&mut self) -> Result<F, ParseError> {
+        let (radix, digits) = if self.rest().starts_with("0x") {
+            self.position += 2;
+            (16, self.digits(16))
+        } else {
+            match self.digits(10) {
+                "" => return Err(self.unexpected("a constant")),
+                digits => (10, digits),
+            }
+        };
+
+        Ok(digits.chars().fold(F::ZERO, |acc, digit| {
+            acc * F::from(radix as u64) + F::from(digit.to_digit(radix).unwrap() as u64)
+        }))
+    }
+
+    /// Left-associative `+` & `-` of products, `a - b` is `a + (-b)`
+    fn sum<F: PrimeField>(&mut self) -> Result<Expression<F>, ParseError> {
+        let mut sum = self.product()?;
+        loop {
+            if self.eat('+') {
+                sum = sum + self.product()?;
+            } else if self.eat('-') {
+                sum = sum - self.product()?;
+            } else {
+                return Ok(sum);
+            }
+        }
+    }
+
+    fn product<F: PrimeField>(&mut self) -> Result<Expression<F>, ParseError> {
+        let mut product = self.unary()?;
+        while self.eat('*') {
+            product = product * self.unary()?;
+        }
+        Ok(product)
+    }
+
+    /// Negation, a power or a scale `"k" * a`, which takes the rest of the product like
+    /// [`Expression::Scaled`] prints it
+    fn unary<F: PrimeField>(&mut self) -> Result<Expression<F>, ParseError> {
+        if self.eat('-') {
+            return self.nested(|parser| Ok(-parser.unary()?));
+        }
+        if self.eat('"') {
+            let k = self.constant()?;
+            self.expect('"', "a closing quote")?;
+            self.expect('*', "`*` after a scale")?;
+            return self.nested(|parser| Ok(parser.product()? * k));
+        }
+
+        let base = self.primary()?;
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        self.skip_whitespace();
+        let position = self.position;
+        Ok(match self.index("an exponent")? {
+            0 => Expression::Constant(F::ONE),
+            exponent if exponent > MAX_EXPONENT => {
+                return Err(ParseError::ExponentTooBig { position })
+            }
+            exponent => (1..exponent).fold(base.clone(), |power, _| power * base.clone()),
+        })
+    }
+
+    fn primary<F: PrimeField>(&mut self) -> Result<Expression<F>, ParseError> {
+        if self.eat('(') {
+            let expr = self.nested(Self::sum)?;
+            self.expect(')', "`)`")?;
+            return Ok(expr);
+        }
+
+        let Some(found) = self.peek() else {
+            return Err(self.unexpected("an operand"));
+        };
+        if found.is_ascii_digit() {
+            return Ok(Expression::Constant(self.constant()?));
+        }
+
+        let variable = ["Z_", "r_", "c_"]
+            .into_iter()
+            .find(|prefix| self.rest().starts_with(prefix))
+            .ok_or_else(|| self.unexpected("an operand"))?;
+        self.position += variable.len();
+        let index = self.index("an index")?;
+
+        Ok(match variable {
+            "r_" => Expression::Challenge(index),
+            "c_" => Expression::Halo2Challenge(index),
+            _ => Expression::Polynomial(Query {
+                index,
+                rotation: self.rotation()?,
+            }),
+        })
+    }
+
+    /// `[+n]` or `[-n]` right after a `Z_i`, zero without one
+    fn rotation(&mut self) -> Result<Rotation, ParseError> {
+        if !self.rest().starts_with('[') {
+            return Ok(Rotation::cur());
+        }
+        self.position += 1;
+
+        let negative = self.eat('-');
+        if !negative {
+            self.eat('+');
+        }
+        self.skip_whitespace();
+        let position = self.position;
+        let rotation = i32::try_from(self.index("a rotation")?)
+            .map_err(|_| ParseError::TooBig { position })?;
+        self.expect(']', "`]`")?;
+
+        Ok(Rotation(if negative { -rotation } else { rotation }))
+    }
+}
+
+impl<F: PrimeField> Expression<F> {
+    /// Reverse of [`Display`](std::fmt::Display): `Z_i` with an optional `[+n]` or `[-n]`
+    /// rotation, `r_i`, `c_i`, hex & decimal constants, `+`, `-`, `*`, parentheses & `^` by a
+    /// decimal exponent, which is expanded to a product
+    ///
+    /// The exponent is capped by [`MAX_EXPONENT`] & the nesting by [`MAX_DEPTH`], so neither a
+    /// big power nor deep parentheses blow up on untrusted input
+    ///
+    /// `+`, `-` & `*` are left-associative & `*` binds tighter, so a parsed expression displays
+    /// as the parsed string, up to whitespace. The other way about, the display of any
+    /// expression parses back to one of the same value, maybe associated differently
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            input,
+            position: 0,
+            depth: 0,
+        };
+        let expr = parser.sum()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(_) => Err(parser.unexpected("an operator")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{halo2curves::pasta::Fp, main_gate::tests::main_gate_expressions};
+
+    fn z(index: usize) -> Expression<Fp> {
+        Expression::Polynomial(Query {
+            index,
+            rotation: Rotation::cur(),
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        for input in [
+            "Z_0 * (Z_1[+1] - Z_1 - Z_2)",
+            "Z_0 * (Z_2 - Z_1 * c_0)",
+            "Z_0 * (Z_1 * Z_2 - Z_3) + Z_1",
+            "(Z_0 + 0x1 * r_0) * r_0 + Z_0 * Z_1",
+            "((Z_0 * r_0 + Z_0 * Z_1) * r_0 + Z_0 * Z_1 * Z_2) * r_0 * r_0 + Z_0 * Z_1 * Z_2 * Z_3 * Z_4",
+            "(Z_3 + Z_4 + 0x) * (Z_0 + Z_1 + Z_2 + 0x)",
+            "0x3 * (Z_0 + Z_1)",
+            "Z_0 - 0x3",
+            "-Z_5[-2]",
+            "Z_2 * Z_3 * (Z_0 + Z_1[+1])",
+            "\"0x2\" * Z_0 * Z_1 - -Z_2",
+            "Z_4 * Z_9 * Z_10 + Z_6 * Z_11 + Z_8 + Z_7 * Z_12 + Z_0 * Z_9 + Z_2 * Z_9 * Z_9 * Z_9 * Z_9 * Z_9 + Z_1 * Z_10 + Z_3 * Z_10 * Z_10 * Z_10 * Z_10 * Z_10",
+            "r_0 * r_0 * r_0 * (Z_10 * Z_9 * Z_4 + r_0 * Z_11 * Z_6 + r_0 * r_0 * Z_8 + r_0 * Z_12 * Z_7) + r_0 * r_0 * r_0 * r_0 * Z_9 * Z_0 + Z_9 * Z_9 * Z_9 * Z_9 * Z_9 * Z_2",
+        ] {
+            let expr = Expression::<Fp>::parse(input).unwrap();
+            assert_eq!(expr.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn grammar() {
+        assert_eq!(
+            Expression::parse("Z_0+Z_1 *Z_2^2  -  3").unwrap(),
+            z(0) + z(1) * (z(2) * z(2)) - Expression::Constant(Fp::from(3))
+        );
+        assert_eq!(
+            Expression::parse("-(Z_0 - Z_1) * Z_2^0").unwrap(),
+            -(z(0) - z(1)) * Expression::Constant(Fp::ONE)
+        );
+        assert_eq!(
+            Expression::parse("Z_1[+1] * \"0xff\" * Z_0 + 255").unwrap(),
+            Expression::Polynomial(Query {
+                index: 1,
+                rotation: Rotation::next(),
+            }) * (z(0) * Fp::from(255))
+                + Expression::Constant(Fp::from(0xff))
+        );
+
+        // the lifted main gate, compared by structure & by value instead of its string
+        let (gates, _, _) = main_gate_expressions();
+        let parsed = Expression::parse(
+            "Z_4 * Z_9 * Z_10 + Z_6 * Z_11 + Z_8 + Z_7 * Z_12 + Z_0 * Z_9 + Z_2 * Z_9^5 \
+             + Z_1 * Z_10 + Z_3 * Z_10^5",
+        )
+        .unwrap();
+        assert!(parsed.normalized_eq(&gates[0][0]));
+        assert!(parsed.semantic_eq_probabilistic(&gates[0][0], StdRng::seed_from_u64(0)));
+    }
+
+    #[test]
+    fn errors() {
+        let parse = Expression::<Fp>::parse;
+
+        assert_eq!(
+            parse("Z_0 +"),
+            Err(ParseError::UnexpectedEnd {
+                expected: "an operand"
+            })
+        );
+        assert_eq!(
+            parse("Z_0 ) "),
+            Err(ParseError::Unexpected {
+                found: ')',
+                position: 4,
+                expected: "an operator"
+            })
+        );
+        assert_eq!(
+            parse("(Z_0 * x_1)"),
+            Err(ParseError::Unexpected {
+                found: 'x',
+                position: 7,
+                expected: "an operand"
+            })
+        );
+        assert_eq!(
+            parse("Z_0[+1"),
+            Err(ParseError::UnexpectedEnd { expected: "`]`" })
+        );
+        assert_eq!(
+            parse("Z_99999999999999999999999"),
+            Err(ParseError::TooBig { position: 2 })
+        );
+        assert!(parse(&format!("Z_0^{MAX_EXPONENT}")).is_ok());
+        assert_eq!(
+            parse(&format!("Z_0 * Z_1^{}", MAX_EXPONENT + 1)),
+            Err(ParseError::ExponentTooBig { position: 10 })
+        );
+
+        let nested = |depth: usize| format!("{}Z_0{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(MAX_DEPTH + 1)),
+            Err(ParseError::TooDeep {
+                position: MAX_DEPTH + 1
+            })
+        );
+        assert_eq!(
+            parse(&"-".repeat(100_000)),
+            Err(ParseError::TooDeep {
+                position: MAX_DEPTH + 1
+            })
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Randomized identities every lifted gate must satisfy
+//!
+//! [`assert_identities`] checks an [`Expression`] against its rewrites & its folding on random
+//! assignments, so a circuit can sanity-check its gates. Compiled for tests & with the `testing`
+//! feature.
+
+use std::collections::BTreeMap;
+
+use rand_core::RngCore;
+
+use super::{
+    expression::QueryIndexContext, grouped_poly::GroupedPoly, interned::ExpressionArena,
+    ColumnIndex, Expression,
+};
+use crate::ff::PrimeField;
+
+/// Panics, naming the gate & the sample, if one of these fails on `n_samples` random assignments
+///
+/// * the [`Expression::simplify`]-ed, [`Expression::normalize`]-d & interned forms of `expr`
+///   evaluate like it
+/// * the fold of the [`Expression::homogeneous`] form at the first instance with `u = 1` & the
+///   zero second instance is `expr` at the first instance, for any `r`
+/// * swapping the instances maps the cross term of `r^k` to the one of `r^(d - k)`, `d` is the
+///   degree of the homogeneous form
+///
+/// `ctx` numbers the variables of `expr`, its `num_challenges` are the challenges of `expr`, so
+/// `u` comes right after them
+pub fn assert_identities<F: PrimeField>(
+    expr: &Expression<F>,
+    ctx: &QueryIndexContext,
+    n_samples: usize,
+    mut rng: impl RngCore,
+) {
+    let homogeneous = expr.homogeneous(ctx);
+    let u = ColumnIndex::Challenge {
+        column_index: ctx.num_challenges,
+    };
+    let ctx = QueryIndexContext {
+        num_challenges: homogeneous.num_challenges(),
+        ..*ctx
+    };
+    let (mm, nn) = (ctx.num_selectors + ctx.num_fixed, ctx.num_fold_vars());
+    let folded = homogeneous.fold_transform(mm, nn);
+    let grouped = GroupedPoly::new(&homogeneous, &ctx);
+
+    let rewrites = [
+        ("simplify", expr.simplify()),
+        ("normalize", expr.normalize()),
+    ];
+    let mut arena = ExpressionArena::default();
+    let root = arena.insert(expr);
+
+    // the copy of the second instance & back, `None` for shared variables & `r`
+    let swap = |variable: &ColumnIndex| match *variable {
+        ColumnIndex::Polynominal {
+            rotation,
+            column_index,
+        } if column_index >= mm => Some(ColumnIndex::Polynominal {
+            rotation,
+            column_index: if column_index < mm + nn {
+                column_index + nn
+            } else {
+                column_index - nn
+            },
+        }),
+        ColumnIndex::Challenge { column_index } if column_index < ctx.num_challenges => {
+            Some(ColumnIndex::Challenge {
+                column_index: column_index + ctx.num_challenges,
+            })
+        }
+        ColumnIndex::Challenge { column_index } if column_index < 2 * ctx.num_challenges => {
+            Some(ColumnIndex::Challenge {
+                column_index: column_index - ctx.num_challenges,
+            })
+        }
+        _ => None,
+    };
+    let is_second = |variable: &ColumnIndex| match *variable {
+        ColumnIndex::Polynominal { column_index, .. } => column_index >= mm + nn,
+        ColumnIndex::Challenge { column_index } => {
+            (ctx.num_challenges..2 * ctx.num_challenges).contains(&column_index)
+        }
+        ColumnIndex::Halo2Challenge { .. } => false,
+    };
+
+    for sample in 0..n_samples {
+        let mut variables = Default::default();
+        folded.poly_set(&mut variables);
+        let values = variables
+            .into_iter()
+            .map(|variable| (variable, F::random(&mut rng)))
+            .collect::<BTreeMap<_, _>>();
+        let assign = |variable: ColumnIndex| values[&variable];
+
+        let expected = expr.eval(&assign);
+        for (name, rewrite) in &rewrites {
+            assert_eq!(
+                rewrite.eval(&assign),
+                expected,
+                "{name} of {expr}, sample {sample}"
+            );
+        }
+        assert_eq!(
+            arena.eval(root, &assign),
+            expected,
+            "interned {expr}, sample {sample}"
+        );
+
+        let first_instance = |variable: ColumnIndex| {
+            if is_second(&variable) {
+                F::ZERO
+            } else if variable == u {
+                F::ONE
+            } else {
+                values[&variable]
+            }
+        };
+        assert_eq!(
+            folded.eval(&first_instance),
+            expected,
+            "fold of {expr} at the first instance, sample {sample}"
+        );
+
+        let swapped = |variable: ColumnIndex| values[&swap(&variable).unwrap_or(variable)];
+        let term = |degree: usize, assign: &dyn Fn(ColumnIndex) -> F| {
+            grouped
+                .get(degree)
+                .map_or(F::ZERO, |term| term.eval(&assign))
+        };
+        for degree in 0..=homogeneous.degree {
+            assert_eq!(
+                term(degree, &swapped),
+                term(homogeneous.degree - degree, &assign),
+                "cross term {degree} of {expr} with swapped instances, sample {sample}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::{
+        plonk::{ConstraintSystem, FirstPhase},
+        poly::Rotation,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{halo2curves::pasta::Fp, polynomial::Query};
+
+    #[test]
+    fn rotation_challenges() {
+        // `s * (b[+1] - a * c) + r_0 * a * a`, with a halo2 challenge `c`
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let s = cs.selector();
+        let [a, b] = [cs.advice_column(), cs.advice_column()];
+        let c = cs.challenge_usable_after(FirstPhase);
+        cs.create_gate("rotation", |meta| {
+            let s = meta.query_selector(s);
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::next());
+            let c = meta.query_challenge(c);
+            vec![s * (b - a * c)]
+        });
+
+        let ctx = QueryIndexContext {
+            num_selectors: cs.num_selectors,
+            num_fixed: cs.num_fixed_columns(),
+            num_advice: cs.num_advice_columns(),
+            num_challenges: 1,
+            ..Default::default()
+        };
+        let a = Expression::Polynomial(Query {
+            index: 1,
+            rotation: Rotation(0),
+        });
+        for gate in Expression::lift_all_gates(&cs).unwrap() {
+            let expr = gate + Expression::Challenge(0) * a.clone() * a.clone();
+            assert_identities(&expr, &ctx, 8, StdRng::seed_from_u64(0));
+        }
+    }
+}
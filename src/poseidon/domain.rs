@@ -0,0 +1,131 @@
+use crate::ff::PrimeField;
+
+/// Domain separation tag of a Poseidon sponge, encoded into its capacity element
+///
+/// The capacity element is `kind * 2^192 + param * 2^128 + length`, where the low 128 bits
+/// encode the input length of [`super::poseidon_hash_n`] & [`super::poseidon_hash_var`], or
+/// are `2^64` for the sponge of [`super::PoseidonHash`]. [`Domain::Transcript`] has zero kind &
+/// param, so it keeps the capacity of [`poseidon::State::default`]. Kind 3 is reserved for the
+/// keyed hashes of [`Domain::keyed_capacity`], no variant reaches it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Domain {
+    /// Fiat-Shamir transcript, the default one
+    #[default]
+    Transcript,
+    /// Node of a merkle tree with `arity` children
+    MerkleNode { arity: usize },
+    /// Any other use, told apart by the tag
+    Custom(u64),
+}
+
+impl Domain {
+    fn tag<F: PrimeField>(&self) -> F {
+        let (kind, param) = match self {
+            Self::Transcript => (0, 0),
+            Self::MerkleNode { arity } => (1, *arity as u64),
+            Self::Custom(tag) => (2, *tag),
+        };
+
+        Self::encode_tag(kind, param)
+    }
+
+    fn encode_tag<F: PrimeField>(kind: u64, param: u64) -> F {
+        let shift = F::from_u128(1 << 64);
+        (F::from(kind) * shift + F::from(param)) * shift.square()
+    }
+
+    /// Capacity element of the sponge of [`super::PoseidonHash`] in this domain
+    pub(crate) fn sponge_capacity<F: PrimeField>(&self) -> F {
+        self.tag::<F>() + F::from_u128(1 << 64)
+    }
+
+    /// Capacity element of a fixed-length hash of `len` elements, `len * 2^64 + 2` in the low
+    /// bits
+    ///
+    /// The low word tells it apart from [`Domain::sponge_capacity`] & from
+    /// [`Domain::var_length_capacity`] of the same length
+    pub(crate) fn const_length_capacity<F: PrimeField>(&self, len: usize) -> F {
+        self.tag::<F>() + F::from_u128(((len as u128) << 64) + 2)
+    }
+
+    /// Capacity element of a variable-length hash of `len` elements, `len * 2^64 + 1` in the
+    /// low bits
+    ///
+    /// The low word tells it apart from [`Domain::const_length_capacity`] of the same length
+    pub(crate) fn var_length_capacity<F: PrimeField>(&self, len: usize) -> F {
+        self.tag::<F>() + F::from_u128(((len as u128) << 64) + 1)
+    }
+
+    /// Capacity element of a keyed hash of a key & `len` elements, the low bits of
+    /// [`Domain::var_length_capacity`] under the reserved kind 3
+    ///
+    /// No [`Domain`] has this kind, so a keyed hash never shares the capacity of an unkeyed one
+    pub(crate) fn keyed_capacity<F: PrimeField>(len: usize) -> F {
+        Self::encode_tag::<F>(3, 0) + F::from_u128(((len as u128) << 64) + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo2curves::pasta::Fp;
+
+    #[test]
+    fn transcript_capacity() {
+        let default: [Fp; 3] = poseidon::State::default().words();
+        assert_eq!(Domain::default().sponge_capacity::<Fp>(), default[0]);
+    }
+
+    #[test]
+    fn tags_differ() {
+        let domains = [
+            Domain::Transcript,
+            Domain::MerkleNode { arity: 2 },
+            Domain::MerkleNode { arity: 4 },
+            Domain::Custom(0),
+            Domain::Custom(2),
+        ];
+
+        for (i, a) in domains.iter().enumerate() {
+            for b in &domains[i + 1..] {
+                assert_ne!(a.tag::<Fp>(), b.tag::<Fp>(), "{a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn length_capacities_differ() {
+        let domain = Domain::MerkleNode { arity: 2 };
+        for len in 0..4 {
+            let capacity = domain.const_length_capacity::<Fp>(len);
+            assert_ne!(capacity, domain.sponge_capacity::<Fp>(), "{len}");
+            assert_ne!(capacity, domain.var_length_capacity::<Fp>(len), "{len}");
+        }
+    }
+
+    #[test]
+    fn keyed_capacity_reserved() {
+        let domains = [
+            Domain::Transcript,
+            Domain::MerkleNode { arity: 2 },
+            Domain::Custom(0),
+            Domain::Custom(u64::MAX),
+        ];
+
+        for len in 0..4 {
+            let keyed = Domain::keyed_capacity::<Fp>(len);
+            for domain in &domains {
+                // the key takes one more element than the message
+                for other in [len, len + 1] {
+                    assert_ne!(keyed, domain.var_length_capacity::<Fp>(other), "{domain:?}");
+                    assert_ne!(
+                        keyed,
+                        domain.const_length_capacity::<Fp>(other),
+                        "{domain:?}"
+                    );
+                }
+                assert_ne!(keyed, domain.sponge_capacity::<Fp>(), "{domain:?}");
+            }
+        }
+    }
+}
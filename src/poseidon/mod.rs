@@ -1,11 +1,19 @@
+mod domain;
+mod permutation;
+pub mod poseidon2;
 pub mod poseidon_circuit;
 pub mod poseidon_hash;
 pub mod random_oracle;
 mod spec;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
-pub use poseidon_hash::PoseidonHash;
+pub use domain::Domain;
+pub use permutation::PermutationSpec;
+pub use poseidon2::{Poseidon2Spec, Poseidon2SpecError};
+pub use poseidon_hash::{poseidon_hash_keyed, poseidon_hash_n, poseidon_hash_var, PoseidonHash};
 pub use random_oracle::*;
-pub use spec::Spec;
+pub use spec::{Spec, SpecError, MIN_FULL_ROUNDS, SECURITY_BITS};
 
 use crate::ff::{FromUniformBytes, PrimeField, PrimeFieldBits};
 
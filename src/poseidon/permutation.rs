@@ -0,0 +1,92 @@
+use halo2_proofs::plonk::Error;
+
+use super::{poseidon_circuit::PoseidonChip, poseidon_hash, Domain, Spec};
+use crate::{
+    ff::{FromUniformBytes, PrimeFieldBits},
+    main_gate::{AssignedValue, MainGate, RegionCtx},
+};
+
+/// A permutation of a `T` elements state, both off-circuit & laid out by [`MainGate`]
+///
+/// The gate is the same for every implementation, only the round schedule & matrices differ.
+/// [`PoseidonChip`] & [`super::PoseidonHash`] are sponges over any of them.
+pub trait PermutationSpec<F: PrimeFieldBits, const T: usize> {
+    /// Permutation of `state` off-circuit
+    fn permute(&self, state: [F; T]) -> [F; T];
+
+    /// Permutation of `state` in-circuit, `state` is copied into the first rows
+    fn assign_permutation(
+        &self,
+        main_gate: &MainGate<F, T>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+    ) -> Result<[AssignedValue<F>; T], Error>;
+
+    /// Number of rows laid out by [`PermutationSpec::assign_permutation`]
+    fn permutation_row_cost(&self) -> usize;
+
+    /// Constants the permutation starts by adding to the state, zeros if it doesn't
+    ///
+    /// A sponge adds them in the same rows as the absorbed inputs & continues by
+    /// [`PermutationSpec::assign_rounds`]
+    fn initial_constants(&self) -> [F; T] {
+        [F::ZERO; T]
+    }
+
+    /// [`PermutationSpec::assign_permutation`] of a `state` the
+    /// [`PermutationSpec::initial_constants`] are already added to
+    fn assign_rounds(
+        &self,
+        main_gate: &MainGate<F, T>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        self.assign_permutation(main_gate, ctx, state)
+    }
+}
+
+impl<F, const T: usize, const RATE: usize> PermutationSpec<F, T> for Spec<F, T, RATE>
+where
+    F: PrimeFieldBits + FromUniformBytes<64>,
+{
+    fn permute(&self, state: [F; T]) -> [F; T] {
+        poseidon_hash::permute(self, state)
+    }
+
+    fn assign_permutation(
+        &self,
+        main_gate: &MainGate<F, T>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let state = state
+            .iter()
+            .zip(self.initial_constants())
+            .map(|(s, c)| main_gate.add_constant(ctx, s, c))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.assign_rounds(main_gate, ctx, &state.try_into().unwrap())
+    }
+
+    fn permutation_row_cost(&self) -> usize {
+        T * (1 + self.r_f() + self.constants().partial().len())
+    }
+
+    fn initial_constants(&self) -> [F; T] {
+        self.constants().start()[0]
+    }
+
+    fn assign_rounds(
+        &self,
+        main_gate: &MainGate<F, T>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        PoseidonChip::<F, T, RATE, &Self>::with_spec(
+            main_gate.config().clone(),
+            self,
+            Domain::Transcript,
+        )
+        .rounds(ctx, state.to_vec())
+    }
+}
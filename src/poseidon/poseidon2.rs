@@ -0,0 +1,592 @@
+use std::collections::VecDeque;
+
+use halo2_proofs::{circuit::Value, plonk::Error};
+
+use super::{PermutationSpec, ROConstantsTrait};
+use crate::{
+    ff::{PrimeField, PrimeFieldBits},
+    main_gate::{AssignedValue, MainGate, MainGateRow, RegionCtx},
+};
+
+/// Grain LFSR of the Poseidon reference implementation, used to derive round constants
+///
+/// Same stream as the one behind [`super::Spec`] for the same parameters
+struct Grain {
+    state: VecDeque<bool>,
+}
+
+impl Grain {
+    fn new(num_bits: u32, t: usize, r_f: usize, r_p: usize) -> Self {
+        let mut state = VecDeque::with_capacity(80);
+        let mut push = |value: u64, len: usize| {
+            for i in (0..len).rev() {
+                state.push_back((value >> i) & 1 == 1);
+            }
+        };
+        push(1, 2); // prime field
+        push(0, 4); // `x^alpha` S-box
+        push(num_bits as u64, 12);
+        push(t as u64, 12);
+        push(r_f as u64, 10);
+        push(r_p as u64, 10);
+        push((1 << 30) - 1, 30);
+
+        let mut grain = Self { state };
+        for _ in 0..160 {
+            grain.next_raw_bit();
+        }
+        grain
+    }
+
+    fn next_raw_bit(&mut self) -> bool {
+        let s = &self.state;
+        let bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+        self.state.pop_front();
+        self.state.push_back(bit);
+        bit
+    }
+
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let (keep, bit) = (self.next_raw_bit(), self.next_raw_bit());
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    /// `F::NUM_BITS` bits in big-endian order, rejected until they are below the modulus
+    ///
+    /// Assumes a little-endian `F::Repr`
+    fn next_field_element<F: PrimeField>(&mut self) -> F {
+        loop {
+            let mut repr = F::Repr::default();
+            for i in (0..F::NUM_BITS as usize).rev() {
+                if self.next_bit() {
+                    repr.as_mut()[i / 8] |= 1 << (i % 8);
+                }
+            }
+
+            if let Some(element) = Option::from(F::from_repr(repr)) {
+                return element;
+            }
+        }
+    }
+}
+
+/// Poseidon2 permutation of a `T` elements state, `T` is 2 or 3
+///
+/// An initial external linear layer, then `r_f / 2` external (full) rounds, `r_p` internal
+/// (partial) rounds & `r_f / 2` external rounds. Every round adds its constants, applies `x^5`
+/// to the whole state (external) or to the first element (internal) & mixes by the external
+/// matrix `circ(2, 1, ..)` or the internal one `1 + diag(1, .., 2)`.
+///
+/// The matrices & round constants are the ones of the HorizenLabs reference implementation:
+/// the constants are drawn from the Grain stream of [`super::Spec`], `T` per external round &
+/// one per internal round.
+///
+/// In-circuit a round costs `T` rows of [`MainGate`], like a round of [`super::Spec`], but two
+/// internal rounds share `T + 1` rows: only the first element goes through an S-box, so the
+/// second round is affine in the two S-box inputs & the other elements before the first.
+#[derive(Clone, Debug)]
+pub struct Poseidon2Spec<F: PrimeField, const T: usize> {
+    r_f: usize,
+    r_p: usize,
+    /// Constants of every round in order, internal rounds have zeros after the first one
+    constants: Vec<[F; T]>,
+    external_mds: [[F; T]; T],
+    internal_mds: [[F; T]; T],
+}
+
+/// Parameters rejected by [`Poseidon2Spec::new`]
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Poseidon2SpecError {
+    #[error("Poseidon2 matrices are defined for T = 2, 3 only, got {t}")]
+    UnsupportedWidth { t: usize },
+    #[error("number of full rounds must be even, got {r_f}")]
+    OddFullRounds { r_f: usize },
+}
+
+impl<F: PrimeField, const T: usize> Poseidon2Spec<F, T> {
+    pub fn new(r_f: usize, r_p: usize) -> Result<Self, Poseidon2SpecError> {
+        if T != 2 && T != 3 {
+            return Err(Poseidon2SpecError::UnsupportedWidth { t: T });
+        }
+        if r_f % 2 != 0 {
+            return Err(Poseidon2SpecError::OddFullRounds { r_f });
+        }
+
+        let mut grain = Grain::new(F::NUM_BITS, T, r_f, r_p);
+        let constants = (0..r_f + r_p)
+            .map(|round| {
+                if (r_f / 2..r_f / 2 + r_p).contains(&round) {
+                    let mut internal = [F::ZERO; T];
+                    internal[0] = grain.next_field_element();
+                    internal
+                } else {
+                    [(); T].map(|_| grain.next_field_element())
+                }
+            })
+            .collect();
+
+        let external_mds = Self::matrix(|i, j| if i == j { 2 } else { 1 });
+        let internal_mds = Self::matrix(|i, j| match (i == j, i == T - 1) {
+            (true, true) => 3,
+            (true, false) => 2,
+            (false, _) => 1,
+        });
+
+        Ok(Self {
+            r_f,
+            r_p,
+            constants,
+            external_mds,
+            internal_mds,
+        })
+    }
+
+    fn matrix(entry: impl Fn(usize, usize) -> u64) -> [[F; T]; T] {
+        let mut matrix = [[F::ZERO; T]; T];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, m) in row.iter_mut().enumerate() {
+                *m = F::from(entry(i, j));
+            }
+        }
+        matrix
+    }
+
+    pub fn r_f(&self) -> usize {
+        self.r_f
+    }
+
+    pub fn r_p(&self) -> usize {
+        self.r_p
+    }
+
+    fn is_external(&self, round: usize) -> bool {
+        !(self.r_f / 2..self.r_f / 2 + self.r_p).contains(&round)
+    }
+
+    /// Constants added right after `round`, zeros after the last one
+    fn next_constants(&self, round: usize) -> [F; T] {
+        self.constants
+            .get(round + 1)
+            .copied()
+            .unwrap_or([F::ZERO; T])
+    }
+}
+
+fn mix<F: PrimeField, const T: usize>(matrix: &[[F; T]; T], state: &[F; T]) -> [F; T] {
+    matrix.map(|row| row.iter().zip(state).map(|(m, s)| *m * s).sum())
+}
+
+/// `row` with `state` & `input` copied in, the `out` cell is the next state element
+///
+/// `out = rc + q_i * input + sum_j q_1[j] * s[j] + q_5[j] * s[j]^5`, the `q_o` of `row` is
+/// replaced by `-1`
+fn assign_linked_row<F: PrimeField, const T: usize>(
+    main_gate: &MainGate<F, T>,
+    ctx: &mut RegionCtx<'_, '_, F>,
+    state: &[AssignedValue<F>; T],
+    input: Option<&AssignedValue<F>>,
+    row: MainGateRow<F, T>,
+) -> Result<AssignedValue<F>, Error> {
+    let state_vals = state.clone().map(|s| s.value().copied());
+    let input_val = input.map_or(Value::known(F::ZERO), |input| input.value().copied());
+
+    let out = state_vals.iter().zip(row.q_1.iter().zip(&row.q_5)).fold(
+        input_val.map(|input| row.rc + input * row.q_i),
+        |out, (s, (q_1, q_5))| out + s.map(|s| s * q_1 + s.square().square() * s * q_5),
+    );
+
+    let assigned = ctx.assign_row(
+        main_gate.config(),
+        MainGateRow {
+            state: state_vals,
+            input: input_val,
+            q_o: -F::ONE,
+            out,
+            ..row
+        },
+    )?;
+    for (copy, cell) in assigned.state.iter().zip(state.iter()) {
+        ctx.constrain_cells_equal(copy, cell)?;
+    }
+    if let Some(input) = input {
+        ctx.constrain_cells_equal(&assigned.input, input)?;
+    }
+
+    Ok(assigned.out)
+}
+
+impl<F: PrimeFieldBits, const T: usize> Poseidon2Spec<F, T> {
+    /// One row per element: `matrix * state` with the S-box applied to the elements of `sbox`,
+    /// plus `constants`
+    fn assign_layer(
+        main_gate: &MainGate<F, T>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+        sbox: &[bool; T],
+        matrix: &[[F; T]; T],
+        constants: [F; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let next = matrix
+            .iter()
+            .zip(constants)
+            .map(|(matrix_row, rc)| {
+                let (mut q_1, mut q_5) = ([F::ZERO; T], [F::ZERO; T]);
+                for ((sbox, m), (linear, pow5)) in sbox
+                    .iter()
+                    .zip(matrix_row)
+                    .zip(q_1.iter_mut().zip(q_5.iter_mut()))
+                {
+                    if *sbox {
+                        *pow5 = *m;
+                    } else {
+                        *linear = *m;
+                    }
+                }
+
+                assign_linked_row(
+                    main_gate,
+                    ctx,
+                    state,
+                    None,
+                    MainGateRow {
+                        q_1,
+                        q_5,
+                        rc,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(next
+            .try_into()
+            .expect("Unreachable, because matrix has T rows"))
+    }
+
+    /// Internal rounds `round` & `round + 1` in `T + 1` rows
+    ///
+    /// The first row is the S-box input `y[0]` of the second round. With the internal matrix
+    /// `M`, the constants `c` & `d` added after each round & `p(v) = v^5`, every output is
+    /// ```markdown
+    /// z[i] = M[i][0] * p(y[0]) + sum_{j > 0} M[i][j] * (M[j][0] * p(x[0]) + sum_{l > 0} M[j][l] * x[l] + c[j]) + d[i]
+    /// ```
+    /// so its row holds `x[0]` & `y[0]` with an S-box each, `x[1..T - 1]` in the other state
+    /// columns & `x[T - 1]` in the input column.
+    fn assign_internal_pair(
+        &self,
+        main_gate: &MainGate<F, T>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        x: &[AssignedValue<F>; T],
+        round: usize,
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let m = &self.internal_mds;
+        let (c, d) = (self.next_constants(round), self.next_constants(round + 1));
+
+        let (mut q_1, mut q_5) = ([F::ZERO; T], [F::ZERO; T]);
+        q_5[0] = m[0][0];
+        q_1[1..].copy_from_slice(&m[0][1..]);
+        let y_0 = assign_linked_row(
+            main_gate,
+            ctx,
+            x,
+            None,
+            MainGateRow {
+                q_1,
+                q_5,
+                rc: c[0],
+                ..Default::default()
+            },
+        )?;
+
+        let mut cells = x.clone();
+        cells[1] = y_0;
+        cells[2..].clone_from_slice(&x[1..T - 1]);
+
+        let next = (0..T)
+            .map(|i| {
+                let through = |column: usize| (1..T).map(|j| m[i][j] * m[j][column]).sum::<F>();
+
+                let (mut q_1, mut q_5) = ([F::ZERO; T], [F::ZERO; T]);
+                q_5[0] = through(0);
+                q_5[1] = m[i][0];
+                for (l, q) in (1..T - 1).zip(q_1[2..].iter_mut()) {
+                    *q = through(l);
+                }
+                let rc = (1..T).map(|j| m[i][j] * c[j]).sum::<F>() + d[i];
+
+                assign_linked_row(
+                    main_gate,
+                    ctx,
+                    &cells,
+                    Some(&x[T - 1]),
+                    MainGateRow {
+                        q_1,
+                        q_5,
+                        q_i: through(T - 1),
+                        rc,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(next
+            .try_into()
+            .expect("Unreachable, because collected T elements"))
+    }
+}
+
+impl<F: PrimeFieldBits, const T: usize> PermutationSpec<F, T> for Poseidon2Spec<F, T> {
+    fn permute(&self, state: [F; T]) -> [F; T] {
+        let pow5 = |v: F| v.square().square() * v;
+
+        let mut state = mix(&self.external_mds, &state);
+        for (s, c) in state.iter_mut().zip(&self.constants[0]) {
+            *s += c;
+        }
+
+        for round in 0..self.r_f + self.r_p {
+            let matrix = if self.is_external(round) {
+                state = state.map(pow5);
+                &self.external_mds
+            } else {
+                state[0] = pow5(state[0]);
+                &self.internal_mds
+            };
+
+            state = mix(matrix, &state);
+            for (s, c) in state.iter_mut().zip(self.next_constants(round)) {
+                *s += c;
+            }
+        }
+
+        state
+    }
+
+    /// One row per element of the initial layer & of every external round: the S-box is in
+    /// `q_5`, the linear part in `q_1` & the next round constants in `rc`. Internal rounds go
+    /// by pairs, see [`Poseidon2Spec::assign_internal_pair`], the last one alone if `r_p` is
+    /// odd.
+    fn assign_permutation(
+        &self,
+        main_gate: &MainGate<F, T>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let mut state = Self::assign_layer(
+            main_gate,
+            ctx,
+            state,
+            &[false; T],
+            &self.external_mds,
+            self.constants[0],
+        )?;
+
+        let mut round = 0;
+        while round < self.r_f + self.r_p {
+            if self.is_external(round) {
+                state = Self::assign_layer(
+                    main_gate,
+                    ctx,
+                    &state,
+                    &[true; T],
+                    &self.external_mds,
+                    self.next_constants(round),
+                )?;
+                round += 1;
+            } else if self.is_external(round + 1) {
+                let mut sbox = [false; T];
+                sbox[0] = true;
+                state = Self::assign_layer(
+                    main_gate,
+                    ctx,
+                    &state,
+                    &sbox,
+                    &self.internal_mds,
+                    self.next_constants(round),
+                )?;
+                round += 1;
+            } else {
+                state = self.assign_internal_pair(main_gate, ctx, &state, round)?;
+                round += 2;
+            }
+        }
+
+        Ok(state)
+    }
+
+    fn permutation_row_cost(&self) -> usize {
+        T * (1 + self.r_f + self.r_p % 2) + (T + 1) * (self.r_p / 2)
+    }
+}
+
+impl<F: PrimeField, const T: usize> ROConstantsTrait for Poseidon2Spec<F, T> {
+    fn new(r_f: usize, r_p: usize) -> Self {
+        Poseidon2Spec::new(r_f, r_p).unwrap_or_else(|err| panic!("{err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        halo2curves::{bn256::Fr, pasta::Fp},
+        main_gate::tests::verify_gadget,
+        poseidon::{poseidon_circuit::PoseidonChip, Domain, PoseidonHash, ROTrait, Spec},
+    };
+
+    /// Poseidon2 of `[0, 1, 2]` for `T = 3`, by `(r_f, r_p)`
+    ///
+    /// The reference implementation has no pasta instances, these are regression values of an
+    /// independent Python model which reproduces [`BN256_KNOWN_ANSWER`]
+    const KNOWN_ANSWERS: [((usize, usize), [&str; 3]); 2] = [
+        (
+            (4, 3),
+            [
+                "18686641050488691875850707597440390589869864037281215382101467830614810800277",
+                "392614694097866883793483310470963767143450961416049832905177421785618351675",
+                "22155169067255883990296963848828533229195202644759524002000682689609622545477",
+            ],
+        ),
+        (
+            (8, 56),
+            [
+                "12034580478475899756768852307737011850845987783813919900518943507286591966586",
+                "12793588015935436972406883162492490643371701127091833715009315828048670032380",
+                "3445110498342580915003896963218627245598739827334005084754590007820223034369",
+            ],
+        ),
+    ];
+
+    /// Poseidon2 of `[0, 1, 2]` over the BN256 scalar field for `T = 3, R_F = 8, R_P = 56`, the
+    /// test vector of the HorizenLabs reference implementation
+    const BN256_KNOWN_ANSWER: [&str; 3] = [
+        "5297208644449048816064511434384511824916970985131888684874823260532015509555",
+        "21816030159894113985964609355246484851575571273661473159848781012394295965040",
+        "13940986381491601233448981668101586453321811870310341844570924906201623195336",
+    ];
+
+    /// [`PoseidonHash`] of `0..5` over [`Poseidon2Spec`] for `T = 3, RATE = 2, R_F = 4, R_P = 3`,
+    /// a regression value of the same Python model as [`KNOWN_ANSWERS`]
+    const SPONGE_KNOWN_ANSWER: &str =
+        "3594542717907728736301462255114685124910656621109920647477517521798545804017";
+
+    #[test]
+    fn permute() {
+        for ((r_f, r_p), expected) in KNOWN_ANSWERS {
+            let spec = Poseidon2Spec::<Fp, 3>::new(r_f, r_p).unwrap();
+            assert_eq!(
+                spec.permute([0, 1, 2].map(Fp::from)),
+                expected.map(|v| Fp::from_str_vartime(v).unwrap()),
+                "{r_f} {r_p}"
+            );
+        }
+    }
+
+    #[test]
+    fn permute_reference() {
+        let spec = Poseidon2Spec::<Fr, 3>::new(8, 56).unwrap();
+        assert_eq!(
+            spec.permute([0, 1, 2].map(Fr::from)),
+            BN256_KNOWN_ANSWER.map(|v| Fr::from_str_vartime(v).unwrap())
+        );
+    }
+
+    #[test]
+    fn new_rejects() {
+        assert_eq!(
+            Poseidon2Spec::<Fp, 4>::new(8, 56).unwrap_err(),
+            Poseidon2SpecError::UnsupportedWidth { t: 4 }
+        );
+        assert_eq!(
+            Poseidon2Spec::<Fp, 3>::new(7, 56).unwrap_err(),
+            Poseidon2SpecError::OddFullRounds { r_f: 7 }
+        );
+    }
+
+    fn check_assign_permutation<const T: usize>(r_f: usize, r_p: usize) {
+        let spec = Poseidon2Spec::<Fp, T>::new(r_f, r_p).unwrap();
+        let state = std::array::from_fn(|i| Fp::from(i as u64));
+        let expected = spec.permute(state);
+
+        assert_eq!(
+            verify_gadget::<T>(8, expected.to_vec(), |main_gate, ctx| {
+                let state = state
+                    .map(|s| main_gate.assign_value(ctx, Value::known(s)))
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let offset = ctx.offset();
+                let next = spec.assign_permutation(main_gate, ctx, &state.try_into().unwrap())?;
+                assert_eq!(ctx.offset() - offset, spec.permutation_row_cost());
+
+                Ok(next.to_vec())
+            }),
+            Ok(()),
+            "{T} {r_f} {r_p}"
+        );
+    }
+
+    #[test]
+    fn assign_permutation() {
+        // internal rounds by pairs, with one left alone if `r_p` is odd
+        for (r_f, r_p) in [(4, 3), (4, 4), (8, 56)] {
+            check_assign_permutation::<3>(r_f, r_p);
+        }
+        check_assign_permutation::<2>(4, 3);
+    }
+
+    #[test]
+    fn sponge() {
+        let spec = Poseidon2Spec::<Fp, 3>::new(4, 3).unwrap();
+        let inputs = (0..5).map(Fp::from).collect::<Vec<_>>();
+
+        let expected: Fp = PoseidonHash::<Fp, 3, 2, _>::with_spec(spec.clone(), Domain::Transcript)
+            .absorb_field_iter(inputs.iter().copied())
+            .output(NonZeroUsize::new(Fp::NUM_BITS as usize).unwrap());
+        assert_eq!(expected, Fp::from_str_vartime(SPONGE_KNOWN_ANSWER).unwrap());
+
+        assert_eq!(
+            verify_gadget::<3>(8, vec![expected], |main_gate, ctx| {
+                let mut chip = PoseidonChip::<Fp, 3, 2, _>::with_spec(
+                    main_gate.config().clone(),
+                    spec.clone(),
+                    Domain::Transcript,
+                );
+                for input in &inputs {
+                    let input = main_gate.assign_value(ctx, Value::known(*input))?;
+                    chip.absorb(ctx, &input)?;
+                }
+
+                // the inputs take `T` rows before the permutation
+                let offset = ctx.offset();
+                let output = chip.squeeze(ctx)?;
+                assert_eq!(ctx.offset() - offset, 3 + spec.permutation_row_cost());
+
+                Ok(vec![output])
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn row_cost() {
+        // the initial layer & 8 external rounds of `T` rows, then 28 pairs of internal rounds
+        // of `T + 1` rows, against 65 rounds of `T` rows for Poseidon
+        assert_eq!(
+            (
+                Poseidon2Spec::<Fp, 3>::new(8, 56)
+                    .unwrap()
+                    .permutation_row_cost(),
+                Spec::<Fp, 3, 2>::new(8, 56).permutation_row_cost(),
+            ),
+            (3 * 9 + 4 * 28, 3 * 65)
+        );
+    }
+}
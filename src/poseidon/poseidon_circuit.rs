@@ -1,38 +1,54 @@
-use std::{convert::TryInto, num::NonZeroUsize};
+use std::{borrow::Borrow, convert::TryInto, fmt, iter, mem, num::NonZeroUsize};
 
 use halo2_proofs::{
+    arithmetic::CurveAffine,
     circuit::{AssignedCell, Chip, Value},
     plonk::Error,
 };
-use poseidon::{self};
+use poseidon::SparseMDSMatrix;
 use tracing::*;
 
-use super::{ROCircuitTrait, Spec};
+use super::{Domain, PermutationSpec, ROCircuitTrait, Spec, SpecError};
 use crate::{
-    constants::MAX_BITS,
+    constants::{MAX_BITS, NUM_CHALLENGE_BITS},
     ff::{FromUniformBytes, PrimeField, PrimeFieldBits},
-    main_gate::{AssignedBit, AssignedValue, MainGate, MainGateConfig, RegionCtx, WrapValue},
+    gadgets::ecc::AssignedPoint,
+    main_gate::{
+        AssignedBit, AssignedValue, MainGate, MainGateConfig, MainGateRow, RegionCtx, WrapValue,
+    },
+    merkle,
 };
 
-pub struct PoseidonChip<F: PrimeFieldBits, const T: usize, const RATE: usize> {
+/// State entering a permutation of [`PoseidonChip`]
+#[derive(Clone, Debug)]
+enum SpongeState<F: PrimeField, const T: usize> {
+    /// Zero rate & this capacity element, not assigned but folded into `rc` of the pre-round
+    /// rows, so the initial state is fixed by the circuit rather than chosen by the prover
+    Initial(F),
+    Assigned([AssignedValue<F>; T]),
+}
+
+/// In-circuit sponge over the permutation of `S`, [`Spec`] by default
+pub struct PoseidonChip<F: PrimeFieldBits, const T: usize, const RATE: usize, S = Spec<F, T, RATE>>
+{
     main_gate: MainGate<F, T>,
-    spec: Spec<F, T, RATE>,
+    spec: S,
     buf: Vec<WrapValue<F>>,
+    /// Sponge state after the last permutation, `None` before the first one
+    state: Option<[AssignedValue<F>; T]>,
+    domain: Domain,
 }
 
-impl<F: PrimeFieldBits + FromUniformBytes<64>, const T: usize, const RATE: usize> ROCircuitTrait<F>
-    for PoseidonChip<F, T, RATE>
+impl<F: PrimeFieldBits + FromUniformBytes<64>, const T: usize, const RATE: usize, S>
+    ROCircuitTrait<F> for PoseidonChip<F, T, RATE, S>
+where
+    S: PermutationSpec<F, T> + fmt::Debug + Clone,
 {
-    type Args = Spec<F, T, RATE>;
+    type Args = S;
     type Config = MainGateConfig<T>;
 
     fn new(config: Self::Config, spec: Self::Args) -> Self {
-        let main_gate: MainGate<F, T> = MainGate::new(config);
-        Self {
-            main_gate,
-            spec,
-            buf: Vec::new(),
-        }
+        Self::with_spec(config, spec, Domain::Transcript)
     }
 
     fn absorb_base(&mut self, base: WrapValue<F>) -> &mut Self {
@@ -60,7 +76,7 @@ impl<F: PrimeFieldBits + FromUniformBytes<64>, const T: usize, const RATE: usize
 
     fn squeeze_n_bits(
         &mut self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         num_bits: NonZeroUsize,
     ) -> Result<Vec<AssignedBit<F>>, Error> {
         let val = self.squeeze(ctx)?;
@@ -73,13 +89,19 @@ impl<F: PrimeFieldBits + FromUniformBytes<64>, const T: usize, const RATE: usize
     }
 }
 
-impl<F: PrimeField + PrimeFieldBits, const T: usize, const RATE: usize> PoseidonChip<F, T, RATE> {
-    pub fn new(config: MainGateConfig<T>, spec: Spec<F, T, RATE>) -> Self {
+impl<F: PrimeField + PrimeFieldBits, const T: usize, const RATE: usize, S>
+    PoseidonChip<F, T, RATE, S>
+{
+    /// Sponge over the permutation of `spec` initialized in `domain`, like
+    /// [`super::PoseidonHash::with_spec`]
+    pub fn with_spec(config: MainGateConfig<T>, spec: S, domain: Domain) -> Self {
         let main_gate: MainGate<F, T> = MainGate::new(config);
         Self {
             main_gate,
             spec,
             buf: Vec::new(),
+            state: None,
+            domain,
         }
     }
 
@@ -101,286 +123,427 @@ impl<F: PrimeField + PrimeFieldBits, const T: usize, const RATE: usize> Poseidon
         out * Value::known((-q_o).invert().unwrap())
     }
 
-    pub fn pre_round(
+    /// Full round of the optimized schedule: `mds * (state^5 + round_constants)`
+    ///
+    /// One row per element `i`, with `state` copied in, `q_5` set to row `i` of `mds` and
+    /// `rc = sum_j mds[i][j] * round_constants[j]`, so the S-box, the constants & the mix share
+    /// it. Pure layout: the constants & the matrix of the round are chosen by the caller.
+    pub fn full_round(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        inputs: Vec<WrapValue<F>>,
-        state_idx: usize,
+        ctx: &mut RegionCtx<'_, '_, F>,
         state: &[AssignedValue<F>; T],
-    ) -> Result<AssignedValue<F>, Error> {
-        assert!(inputs.len() <= RATE);
-        let s_val = state[state_idx].value().copied();
-
-        // TODO: add copy constraint
-        let inputs = std::iter::once(Value::known(F::ZERO))
-            .chain(inputs.into_iter().map(|v| v.value()))
-            .chain(std::iter::once(Value::known(F::ONE)))
-            .chain(std::iter::repeat(Value::known(F::ZERO)))
-            .take(T)
-            .collect::<Vec<_>>();
-        let input_val = inputs[state_idx];
+        round_constants: &[F; T],
+        mds: &[[F; T]; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let state_vals = state.clone().map(|s| s.value().copied());
 
-        let constants = self.spec.constants().start();
-        let pre_constants = constants[0];
-        let rc_val = pre_constants[state_idx];
+        let next = mds
+            .iter()
+            .map(|mds_row| {
+                let rc = mds_row
+                    .iter()
+                    .zip(round_constants)
+                    .fold(F::ZERO, |rc, (m, c)| rc + *m * c);
 
-        let out_val = s_val + input_val + Value::known(rc_val);
+                let row = ctx.assign_row(
+                    self.main_gate.config(),
+                    MainGateRow {
+                        state: state_vals,
+                        q_5: *mds_row,
+                        q_o: -F::ONE,
+                        rc,
+                        out: Self::next_state_val(state_vals, [F::ZERO; T], *mds_row, -F::ONE, rc),
+                        ..Default::default()
+                    },
+                )?;
+                for (copy, cell) in row.state.iter().zip(state.iter()) {
+                    ctx.constrain_cells_equal(copy, cell)?;
+                }
 
-        let si = ctx.assign_advice(
-            || "first round: state",
-            self.main_gate.config().state[state_idx],
-            s_val,
-        )?;
-        ctx.constrain_equal(state[state_idx].cell(), si.cell())?;
+                Ok(row.out)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
 
-        ctx.assign_advice(
-            || "pre_round: input",
-            self.main_gate.config().input,
-            input_val,
-        )?;
-        ctx.assign_fixed(
-            || "pre_round: q_1",
-            self.main_gate.config().q_1[state_idx],
-            F::ONE,
-        )?;
-        ctx.assign_fixed(|| "pre_round: q_i", self.main_gate.config().q_i, F::ONE)?;
-        ctx.assign_fixed(|| "pre_round: q_o", self.main_gate.config().q_o, -F::ONE)?;
-        ctx.assign_fixed(|| "pre_round: rc", self.main_gate.config().rc, rc_val)?;
-        let out = ctx.assign_advice(|| "pre_round: out", self.main_gate.config().out, out_val)?;
+        Ok(next
+            .try_into()
+            .expect("Unreachable, because mds has T rows"))
+    }
 
-        ctx.next();
-        Ok(out)
+    /// Partial round of the optimized schedule, in the sparse MDS form of the spec: only
+    /// `state[0]` goes through the S-box
+    /// ```markdown
+    /// out[0] = row[0] * (s[0]^5 + c) + sum_{j > 0} row[j] * s[j]
+    /// out[i] = col_hat[i - 1] * (s[0]^5 + c) + s[i], i > 0
+    /// ```
+    /// One row per element, with `state` copied in: the S-box is `q_5[0]`, the rest are `q_1`
+    /// terms & the constant is folded into `rc`. The gate constrains a single `out` per row, so
+    /// `T` rows is the minimum for `T` fresh cells.
+    pub fn partial_round(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+        round_constant: F,
+        sparse_mds: &SparseMDSMatrix<F, T, RATE>,
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let state_vals = state.clone().map(|s| s.value().copied());
+
+        let row = sparse_mds.row();
+        let col_hat = sparse_mds.col_hat();
+
+        let next = (0..T)
+            .map(|i| {
+                let mut q_1 = [F::ZERO; T];
+                let mut q_5 = [F::ZERO; T];
+                if i == 0 {
+                    q_5[0] = row[0];
+                    q_1[1..].copy_from_slice(&row[1..]);
+                } else {
+                    q_5[0] = col_hat[i - 1];
+                    q_1[i] = F::ONE;
+                }
+                let rc = q_5[0] * round_constant;
+
+                let assigned = ctx.assign_row(
+                    self.main_gate.config(),
+                    MainGateRow {
+                        state: state_vals,
+                        q_1,
+                        q_5,
+                        q_o: -F::ONE,
+                        rc,
+                        out: Self::next_state_val(state_vals, q_1, q_5, -F::ONE, rc),
+                        ..Default::default()
+                    },
+                )?;
+                for (copy, cell) in assigned.state.iter().zip(state.iter()) {
+                    ctx.constrain_cells_equal(copy, cell)?;
+                }
+
+                Ok(assigned.out)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(next
+            .try_into()
+            .expect("Unreachable, because collected T elements"))
     }
+}
 
-    // round_idx \in [0; r_f - 1] indicates the round index of either first half full or second half full
-    pub fn full_round(
+impl<F: PrimeField + PrimeFieldBits, const T: usize, const RATE: usize> PoseidonChip<F, T, RATE>
+where
+    F: FromUniformBytes<64>,
+{
+    pub fn new(config: MainGateConfig<T>, spec: Spec<F, T, RATE>) -> Self {
+        Self::with_spec(config, spec, Domain::Transcript)
+    }
+
+    /// Same as [`PoseidonChip::new`], but the sponge is initialized in `domain`, like
+    /// [`super::PoseidonHash::with_domain`]
+    pub fn with_domain(config: MainGateConfig<T>, spec: Spec<F, T, RATE>, domain: Domain) -> Self {
+        Self::with_spec(config, spec, domain)
+    }
+
+    /// [`PoseidonChip::new`] with a [`Spec`] built by [`Spec::with_rounds`]
+    pub fn new_with_rounds(
+        config: MainGateConfig<T>,
+        r_f: usize,
+        r_p: usize,
+    ) -> Result<Self, SpecError> {
+        Spec::with_rounds(r_f, r_p).map(|spec| Self::new(config, spec))
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, const T: usize, const RATE: usize, S>
+    PoseidonChip<F, T, RATE, S>
+where
+    S: Borrow<Spec<F, T, RATE>>,
+{
+    /// `mds * state` for the MDS matrix of `self.spec`, one [`MainGate::lc`] row per element
+    ///
+    /// The matrix entries are the `q_1` selectors of each row, `state` is copied into it
+    pub fn apply_mds(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        is_first_half_full: bool,
-        round_idx: usize,
-        state_idx: usize,
-        state: &[AssignedCell<F, F>; T],
-    ) -> Result<AssignedCell<F, F>, Error> {
-        let mut state_vals = [Value::known(F::ZERO); T];
-        let q_1_vals = [F::ZERO; T];
-        let mut q_5_vals = [F::ZERO; T];
-        let q_o_val = -F::ONE;
-
-        let r_f = self.spec.r_f() / 2;
-        let constants = if is_first_half_full {
-            self.spec.constants().start()
-        } else {
-            self.spec.constants().end()
-        };
-        let rcs = if is_first_half_full {
-            constants[round_idx + 1]
-        } else if round_idx < r_f - 1 {
-            constants[round_idx]
-        } else {
-            [F::ZERO; T]
-        };
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let mds = self.spec.borrow().mds_matrices().mds().rows();
 
-        let mds = if is_first_half_full && round_idx == r_f - 1 {
-            self.spec.mds_matrices().pre_sparse_mds().rows()
-        } else {
-            self.spec.mds_matrices().mds().rows()
-        };
-        let mds_row = mds[state_idx];
-
-        let mut rc_val = F::ZERO;
-        for (j, (mij, cj)) in mds_row.iter().zip(rcs).enumerate() {
-            rc_val += *mij * cj;
-            q_5_vals[j] = *mij;
-            ctx.assign_fixed(
-                || format!("full_round {}: q_5", round_idx),
-                self.main_gate.config().q_5[j],
-                q_5_vals[j],
-            )?;
+        let next = mds
+            .iter()
+            .map(|row| {
+                let terms = row
+                    .iter()
+                    .zip(state.iter())
+                    .map(|(m, s)| (*m, s.clone()))
+                    .collect::<Vec<_>>();
+                self.main_gate.lc(ctx, &terms, F::ZERO)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(next
+            .try_into()
+            .expect("Unreachable, because mds has T rows"))
+    }
+
+    /// Every round after the first constants, [`PermutationSpec::assign_rounds`] of [`Spec`]
+    pub(super) fn rounds(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        mut state: Vec<AssignedValue<F>>,
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let r_f = self.spec.borrow().r_f() / 2;
+
+        let mds = self.spec.borrow().mds_matrices().mds().rows();
+        let pre_sparse_mds = self.spec.borrow().mds_matrices().pre_sparse_mds().rows();
+
+        let constants = self.spec.borrow().constants().start();
+        for round_idx in 0..r_f {
+            let mds = if round_idx == r_f - 1 {
+                &pre_sparse_mds
+            } else {
+                &mds
+            };
+            state = self
+                .full_round(
+                    ctx,
+                    state[..].try_into().unwrap(),
+                    &constants[round_idx + 1],
+                    mds,
+                )?
+                .to_vec();
         }
 
-        for (i, s) in state.iter().enumerate() {
-            state_vals[i] = s.value().copied();
-            let si = ctx.assign_advice(
-                || format!("full_round {}: state", round_idx),
-                self.main_gate.config().state[i],
-                s.value().copied(),
-            )?;
-            ctx.constrain_equal(s.cell(), si.cell())?;
+        let constants = self.spec.borrow().constants().partial();
+        let sparse_matrices = self.spec.borrow().mds_matrices().sparse_matrices();
+        for (constant, sparse_mds) in constants.iter().zip(sparse_matrices.iter()) {
+            state = self
+                .partial_round(ctx, state[..].try_into().unwrap(), *constant, sparse_mds)?
+                .to_vec();
         }
 
-        ctx.assign_fixed(
-            || format!("full_round {}: rc", round_idx),
-            self.main_gate.config().rc,
-            rc_val,
-        )?;
-        ctx.assign_fixed(
-            || format!("full_round {}: q_o", round_idx),
-            self.main_gate.config().q_o,
-            q_o_val,
-        )?;
-        let out_val = Self::next_state_val(state_vals, q_1_vals, q_5_vals, q_o_val, rc_val);
-        let out = ctx.assign_advice(
-            || format!("full_round {}: out", round_idx),
-            self.main_gate.config().out,
-            out_val,
-        )?;
-        ctx.next();
-        Ok(out)
+        let constants = self.spec.borrow().constants().end();
+        for round_idx in 0..r_f {
+            // the last round has no constants left to add
+            let rcs = constants.get(round_idx).copied().unwrap_or([F::ZERO; T]);
+            state = self
+                .full_round(ctx, state[..].try_into().unwrap(), &rcs, &mds)?
+                .to_vec();
+        }
+        let res: [AssignedValue<F>; T] = state.try_into().unwrap();
+        Ok(res)
     }
+}
 
-    pub fn partial_round(
+impl<F: PrimeField + PrimeFieldBits, const T: usize, const RATE: usize, S>
+    PoseidonChip<F, T, RATE, S>
+where
+    S: PermutationSpec<F, T>,
+{
+    pub fn pre_round(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
-        round_idx: usize,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        inputs: Vec<WrapValue<F>>,
         state_idx: usize,
         state: &[AssignedValue<F>; T],
     ) -> Result<AssignedValue<F>, Error> {
-        let mut state_vals = [Value::known(F::ZERO); T];
-        let mut q_1_vals = [F::ZERO; T];
-        let mut q_5_vals = [F::ZERO; T];
-        let q_o_val = -F::ONE;
-
-        let constants = self.spec.constants().partial();
-        let rc = constants[round_idx];
-
-        let sparse_mds = self.spec.mds_matrices().sparse_matrices();
-        let row = sparse_mds[round_idx].row();
-        let col_hat = sparse_mds[round_idx].col_hat();
-
-        for (i, s) in state.iter().enumerate() {
-            state_vals[i] = s.value().copied();
-            let si = ctx.assign_advice(
-                || format!("partial_round {}: state", round_idx),
-                self.main_gate.config().state[i],
-                s.value().copied(),
-            )?;
-            ctx.constrain_equal(s.cell(), si.cell())?;
-        }
+        self.pre_round_from(
+            ctx,
+            inputs,
+            state_idx,
+            &SpongeState::Assigned(state.clone()),
+        )
+    }
 
-        let rc_val;
-        if state_idx == 0 {
-            q_5_vals[0] = row[0];
-            ctx.assign_fixed(
-                || format!("partial_round {}: q_5", round_idx),
-                self.main_gate.config().q_5[0],
-                q_5_vals[0],
-            )?;
-            rc_val = row[0] * rc;
-            ctx.assign_fixed(
-                || format!("partial_round {}: rc", round_idx),
-                self.main_gate.config().rc,
-                rc_val,
-            )?;
-            for j in 1..T {
-                q_1_vals[j] = row[j];
+    /// [`PoseidonChip::pre_round`] from a [`SpongeState`], the elements of
+    /// [`SpongeState::Initial`] go into `rc` instead of a copied state cell
+    fn pre_round_from(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        inputs: Vec<WrapValue<F>>,
+        state_idx: usize,
+        state: &SpongeState<F, T>,
+    ) -> Result<AssignedValue<F>, Error> {
+        assert!(inputs.len() <= RATE);
+
+        let input = std::iter::once(WrapValue::Zero)
+            .chain(inputs)
+            .chain(std::iter::once(WrapValue::Unassigned(Value::known(F::ONE))))
+            .chain(std::iter::repeat(WrapValue::Zero))
+            .nth(state_idx)
+            .expect("Unreachable, because of infinite iterator");
+        let input_val = input.value();
+
+        let pre_constants = self.spec.initial_constants();
+        let (s_val, rc_val) = match state {
+            SpongeState::Initial(capacity) => {
+                let s = if state_idx == 0 { *capacity } else { F::ZERO };
+                (Value::known(F::ZERO), pre_constants[state_idx] + s)
+            }
+            SpongeState::Assigned(state) => {
+                let si = ctx.assign_advice(
+                    || "first round: state",
+                    self.main_gate.config().state[state_idx],
+                    state[state_idx].value().copied(),
+                )?;
+                ctx.constrain_equal(state[state_idx].cell(), si.cell())?;
                 ctx.assign_fixed(
-                    || format!("partial_round {}: q_1", round_idx),
-                    self.main_gate.config().q_1[j],
-                    q_1_vals[j],
+                    || "pre_round: q_1",
+                    self.main_gate.config().q_1[state_idx],
+                    F::ONE,
                 )?;
+                (si.value().copied(), pre_constants[state_idx])
             }
-        } else {
-            q_5_vals[0] = col_hat[state_idx - 1];
-            q_1_vals[state_idx] = F::ONE;
-            ctx.assign_fixed(
-                || format!("partial_round {}: q_5", round_idx),
-                self.main_gate.config().q_5[0],
-                q_5_vals[0],
-            )?;
-            ctx.assign_fixed(
-                || format!("partial_round {}: q_1", round_idx),
-                self.main_gate.config().q_1[state_idx],
-                q_1_vals[state_idx],
-            )?;
-            rc_val = col_hat[state_idx - 1] * rc;
-            ctx.assign_fixed(
-                || format!("partial_round {}, rc", round_idx),
-                self.main_gate.config().rc,
-                rc_val,
-            )?;
-        }
+        };
 
-        let out_val = Self::next_state_val(state_vals, q_1_vals, q_5_vals, -F::ONE, rc_val);
-        ctx.assign_fixed(
-            || format!("full_round {}: q_o", round_idx),
-            self.main_gate.config().q_o,
-            q_o_val,
-        )?;
-        let out = ctx.assign_advice(
-            || format!("full_round {}: out", round_idx),
-            self.main_gate.config().out,
-            out_val,
+        let out_val = s_val + input_val + Value::known(rc_val);
+
+        let input_cell = ctx.assign_advice(
+            || "pre_round: input",
+            self.main_gate.config().input,
+            input_val,
         )?;
+        if let WrapValue::Assigned(input) = &input {
+            ctx.constrain_equal(input.cell(), input_cell.cell())?;
+        }
+        ctx.assign_fixed(|| "pre_round: q_i", self.main_gate.config().q_i, F::ONE)?;
+        ctx.assign_fixed(|| "pre_round: q_o", self.main_gate.config().q_o, -F::ONE)?;
+        ctx.assign_fixed(|| "pre_round: rc", self.main_gate.config().rc, rc_val)?;
+        let out = ctx.assign_advice(|| "pre_round: out", self.main_gate.config().out, out_val)?;
+
         ctx.next();
         Ok(out)
     }
 
+    /// Permutation of `state` by `self.spec`, see [`PermutationSpec::assign_permutation`]
+    ///
+    /// For [`Spec`] it matches [`PoseidonHash::permute`](super::PoseidonHash::permute): the
+    /// first round constants are added by one [`MainGate::add_constant`] row per element, then
+    /// the full, partial & full rounds follow.
     pub fn permutation(
         &self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        state: &[AssignedValue<F>; T],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        self.spec.assign_permutation(&self.main_gate, ctx, state)
+    }
+
+    /// Number of rows laid out by [`PoseidonChip::permutation`]
+    ///
+    /// A permutation with absorbed inputs lays out `T` rows for them, then
+    /// [`PermutationSpec::assign_rounds`]. The same number of rows for [`Spec`], whose first
+    /// constants go into the rows of the inputs.
+    pub fn permutation_row_cost(&self) -> usize {
+        self.spec.permutation_row_cost()
+    }
+
+    /// [`PoseidonChip::permutation`] with `inputs` & padding added to the state, together with
+    /// the [`PermutationSpec::initial_constants`], like the off-circuit sponge does
+    fn permutation_with_inputs(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
         inputs: Vec<WrapValue<F>>,
-        init_state: &[AssignedValue<F>; T],
+        init_state: &SpongeState<F, T>,
     ) -> Result<[AssignedValue<F>; T], Error> {
         let mut state = Vec::new();
         for i in 0..T {
-            let si = self.pre_round(ctx, inputs.clone(), i, init_state)?;
+            let si = self.pre_round_from(ctx, inputs.clone(), i, init_state)?;
             state.push(si);
         }
 
-        let r_f = self.spec.r_f() / 2;
-        let r_p = self.spec.constants().partial().len();
+        self.spec
+            .assign_rounds(&self.main_gate, ctx, &state.try_into().unwrap())
+    }
 
-        for round_idx in 0..r_f {
-            let mut next_state = Vec::new();
-            for state_idx in 0..T {
-                let si = self.full_round(
-                    ctx,
-                    true,
-                    round_idx,
-                    state_idx,
-                    state[..].try_into().unwrap(),
-                )?;
-                next_state.push(si);
-            }
-            state = next_state;
+    pub fn update(&mut self, inputs: &[WrapValue<F>]) -> &mut Self {
+        self.buf.extend_from_slice(inputs);
+        self
+    }
+
+    /// Absorb an already assigned `value` into the sponge
+    ///
+    /// Values are buffered until `RATE` of them are collected, then they are added to the rate
+    /// part of the state by one [`PoseidonChip::permutation`]. The input cells are copied into
+    /// it, so the squeezed output is bound to `value`.
+    pub fn absorb(
+        &mut self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        value: &AssignedValue<F>,
+    ) -> Result<&mut Self, Error> {
+        self.buf.push(WrapValue::Assigned(value.clone()));
+
+        while self.buf.len() >= RATE {
+            let chunk = self.buf.drain(..RATE).collect::<Vec<_>>();
+            let state = self.sponge_state();
+            self.state = Some(self.permutation_with_inputs(ctx, chunk, &state)?);
         }
 
-        for round_idx in 0..r_p {
-            let mut next_state = Vec::new();
-            for state_idx in 0..T {
-                let si =
-                    self.partial_round(ctx, round_idx, state_idx, state[..].try_into().unwrap())?;
-                next_state.push(si);
-            }
-            state = next_state;
+        Ok(self)
+    }
+
+    /// [`PoseidonChip::absorb`] of each of `values`, in order
+    pub fn absorb_many(
+        &mut self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        values: &[AssignedValue<F>],
+    ) -> Result<&mut Self, Error> {
+        for value in values {
+            self.absorb(ctx, value)?;
         }
+        Ok(self)
+    }
 
-        for round_idx in 0..r_f {
-            let mut next_state = Vec::new();
-            for state_idx in 0..T {
-                let si = self.full_round(
-                    ctx,
-                    false,
-                    round_idx,
-                    state_idx,
-                    state[..].try_into().unwrap(),
-                )?;
-                next_state.push(si);
-            }
-            state = next_state;
+    /// Absorb the `x` & `y` cells of `point`, the identity is assigned as `(0, 0)`
+    ///
+    /// Same injective encoding as [`super::ROTrait::absorb_point`] off-circuit
+    pub fn absorb_assigned_point<C: CurveAffine<Base = F>>(
+        &mut self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        point: &AssignedPoint<C>,
+    ) -> Result<&mut Self, Error> {
+        let (x, y) = point.coordinates();
+        self.absorb(ctx, x)?.absorb(ctx, y)
+    }
+
+    /// Current sponge state, taken out of `self` until the next permutation stores it back
+    ///
+    /// The initial state is zero rate & the capacity element of [`Domain::sponge_capacity`],
+    /// the same as the off-circuit sponge in this domain.
+    fn sponge_state(&mut self) -> SpongeState<F, T> {
+        match self.state.take() {
+            Some(state) => SpongeState::Assigned(state),
+            None => SpongeState::Initial(self.domain.sponge_capacity()),
         }
-        let res: [AssignedValue<F>; T] = state.try_into().unwrap();
-        Ok(res)
     }
 
-    pub fn update(&mut self, inputs: &[WrapValue<F>]) -> &mut Self {
-        self.buf.extend_from_slice(inputs);
-        self
+    /// Absorb `buf` by chunks of `RATE` with padding, plus one more permutation if the chunks
+    /// are exact, like [`super::PoseidonHash::output`]
+    fn absorb_padded(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        mut state: SpongeState<F, T>,
+        buf: &[WrapValue<F>],
+    ) -> Result<[AssignedValue<F>; T], Error> {
+        let (full, padded) = buf.split_at(buf.len() - buf.len() % RATE);
+        for chunk in full.chunks(RATE) {
+            state =
+                SpongeState::Assigned(self.permutation_with_inputs(ctx, chunk.to_vec(), &state)?);
+        }
+
+        // the padded chunk is empty if the chunks are exact
+        self.permutation_with_inputs(ctx, padded.to_vec(), &state)
     }
 
-    pub fn squeeze(&mut self, ctx: &mut RegionCtx<'_, F>) -> Result<AssignedValue<F>, Error> {
-        //let buf = mem::take(&mut self.buf);
-        let buf = self.buf.clone();
+    /// Squeeze one challenge out of the sponge
+    ///
+    /// The buffered inputs are absorbed with padding, one more permutation is laid out if
+    /// they fill the rate exactly (or nothing is buffered), and `state[1]` is returned. Same as
+    /// [`super::PoseidonHash::output`], so after a squeeze the next absorbed values start a new
+    /// padded chunk, exactly like off-circuit.
+    ///
+    /// The buffer & state move into the chip, so a floor planner running the region closure
+    /// twice (like `SimpleFloorPlanner`) needs the chip to be created & fed inside of it.
+    pub fn squeeze(&mut self, ctx: &mut RegionCtx<'_, '_, F>) -> Result<AssignedValue<F>, Error> {
+        let buf = mem::take(&mut self.buf);
         if let Some(buf) = buf
             .iter()
             .map(|val| val.value().unwrap())
@@ -389,52 +552,345 @@ impl<F: PrimeField + PrimeFieldBits, const T: usize, const RATE: usize> Poseidon
             debug!("On circuit input of hash: {buf:?}",);
         }
 
-        let exact = buf.len() % RATE == 0;
-        let state0: [F; T] = poseidon::State::default().words();
+        let state = self.sponge_state();
+        let state = self.absorb_padded(ctx, state, &buf)?;
+
+        let output = state[1].clone();
+        self.state = Some(state);
+
+        Ok(output)
+    }
+
+    /// Squeeze `n` challenges, one [`PoseidonChip::squeeze`] & permutation per challenge
+    pub fn squeeze_n(
+        &mut self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        n: usize,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        (0..n).map(|_| self.squeeze(ctx)).collect()
+    }
+
+    /// Squeeze a challenge truncated to its low `num_bits` bits
+    ///
+    /// Returns the bits & the truncated value composed back from them. The squeezed element is
+    /// decomposed by [`MainGate::to_le_bits`] of `F::NUM_BITS` bits, so the bits are the
+    /// canonical ones & match [`super::PoseidonHash::output`] with the same `num_bits`. Fails with
+    /// [`Error::Synthesis`] if `num_bits` exceeds `F::NUM_BITS`.
+    pub fn squeeze_challenge_bits(
+        &mut self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        num_bits: NonZeroUsize,
+    ) -> Result<(Vec<AssignedBit<F>>, AssignedValue<F>), Error> {
+        if num_bits.get() > F::NUM_BITS as usize {
+            error!(
+                "can't truncate a challenge to {num_bits} bits, up to {} supported",
+                F::NUM_BITS
+            );
+            return Err(Error::Synthesis);
+        }
+
+        let challenge = self.squeeze(ctx)?;
+        self.truncate(ctx, &challenge, num_bits)
+    }
 
-        let mut state: [AssignedValue<F>; T] = self
+    fn truncate(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        value: &AssignedValue<F>,
+        num_bits: NonZeroUsize,
+    ) -> Result<(Vec<AssignedBit<F>>, AssignedValue<F>), Error> {
+        let mut bits = self
             .main_gate
-            .config()
-            .state
-            .into_iter()
-            .zip(state0.into_iter().map(Value::known))
-            .map(|(state_column, state0_value)| {
-                ctx.assign_advice(|| "initial state", state_column, state0_value)
-            })
-            .collect::<Result<Vec<_>, _>>()?
-            .try_into()
-            .expect("Unreachable, because zip two arrays with same size");
+            .to_le_bits(ctx, value, F::NUM_BITS as usize)?;
+        bits.truncate(num_bits.get());
+
+        let truncated = self.main_gate.from_le_bits(ctx, &bits)?;
+        Ok((bits, truncated))
+    }
 
-        for chunk in buf.chunks(RATE) {
-            state = self.permutation(ctx, chunk.to_vec(), &state)?;
+    /// [`PoseidonChip::squeeze_challenge_bits`] of [`NUM_CHALLENGE_BITS`], returning the value
+    ///
+    /// Equal to [`super::ROTrait::squeeze`] of the same transcript off-circuit
+    pub fn squeeze_challenge(
+        &mut self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+    ) -> Result<AssignedValue<F>, Error> {
+        let (_bits, challenge) = self.squeeze_challenge_bits(ctx, NUM_CHALLENGE_BITS)?;
+        Ok(challenge)
+    }
+
+    /// Squeeze `k` challenges of [`NUM_CHALLENGE_BITS`], reading `RATE` of them per permutation
+    ///
+    /// The first permutation is the one of [`PoseidonChip::squeeze`] & `state[1..=RATE]` are
+    /// the first challenges, every next `RATE` ones follow an empty padded permutation, so it
+    /// lays out `ceil(k / RATE)` permutations instead of `k`. The first challenge is the one of
+    /// [`PoseidonChip::squeeze_challenge`], the later ones are not those of repeated squeezes.
+    /// Equal to [`super::PoseidonHash::squeeze_n_challenges`] off-circuit.
+    pub fn squeeze_n_challenges(
+        &mut self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        k: usize,
+    ) -> Result<Vec<AssignedValue<F>>, Error> {
+        let mut challenges = Vec::with_capacity(k);
+        while challenges.len() < k {
+            self.squeeze(ctx)?;
+            let state = self
+                .state
+                .clone()
+                .expect("Unreachable, because squeeze stores the state");
+
+            for value in state[1..=RATE].iter().take(k - challenges.len()) {
+                let (_bits, challenge) = self.truncate(ctx, value, NUM_CHALLENGE_BITS)?;
+                challenges.push(challenge);
+            }
+        }
+        Ok(challenges)
+    }
+
+    /// Fixed-length hash of `inputs`, the in-circuit twin of [`super::poseidon_hash_n`]
+    ///
+    /// Independent of the sponge state & buffer of `self`: a fresh state with the capacity
+    /// element of [`Domain::const_length_capacity`] absorbs `inputs` & `state[1]` is returned.
+    pub fn hash_n<const L: usize>(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        domain: Domain,
+        inputs: [AssignedValue<F>; L],
+    ) -> Result<AssignedValue<F>, Error> {
+        self.hash_with_capacity(
+            ctx,
+            domain.const_length_capacity(L),
+            inputs.map(WrapValue::Assigned).to_vec(),
+        )
+    }
+
+    /// Variable-length hash of `inputs`, the in-circuit twin of [`super::poseidon_hash_var`]
+    ///
+    /// Like [`PoseidonChip::hash_n`], but the capacity element encodes `inputs.len()` as a
+    /// variable length, see [`super::poseidon_hash_var`] for the padding rule. Costs
+    /// `inputs.len() / RATE + 1` permutations.
+    pub fn hash_var(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        domain: Domain,
+        inputs: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        self.hash_with_capacity(
+            ctx,
+            domain.var_length_capacity(inputs.len()),
+            inputs.iter().cloned().map(WrapValue::Assigned).collect(),
+        )
+    }
+
+    /// Keyed hash of `inputs` under `key`, the in-circuit twin of [`super::poseidon_hash_keyed`]
+    ///
+    /// Like [`PoseidonChip::hash_var`] of `key` followed by `inputs`, but the capacity element
+    /// is [`Domain::keyed_capacity`], which no unkeyed hash uses
+    pub fn hash_keyed(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        key: &AssignedValue<F>,
+        inputs: &[AssignedValue<F>],
+    ) -> Result<AssignedValue<F>, Error> {
+        self.hash_with_capacity(
+            ctx,
+            Domain::keyed_capacity(inputs.len()),
+            iter::once(key)
+                .chain(inputs)
+                .cloned()
+                .map(WrapValue::Assigned)
+                .collect(),
+        )
+    }
+
+    /// Constrain `leaf` at the end of `path` to hash up to `root` of a tree of `num_leaves`
+    /// leaves, see [`crate::merkle`]
+    ///
+    /// The leaf is hashed in [`merkle::LEAF_DOMAIN`] first. Each path element is a sibling &
+    /// its direction bit, from the leaf up. The bit is zero if the current node is the left
+    /// child: the pair is ordered by [`MainGate::cond_swap`], which also constrains the bit to
+    /// be boolean, & hashed by [`PoseidonChip::hash_n`] in the [`Domain::MerkleNode`] domain of
+    /// arity 2. The top node & `num_leaves` are hashed in [`merkle::ROOT_DOMAIN`] into the root.
+    pub fn verify_merkle_path(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        leaf: &AssignedValue<F>,
+        path: &[(AssignedValue<F>, AssignedValue<F>)],
+        num_leaves: &AssignedValue<F>,
+        root: &AssignedValue<F>,
+    ) -> Result<(), Error> {
+        let mut node = self.hash_n(ctx, merkle::LEAF_DOMAIN, [leaf.clone()])?;
+        for (sibling, direction) in path {
+            let (left, right) = self.main_gate.cond_swap(ctx, direction, &node, sibling)?;
+            node = self.hash_n(ctx, Domain::MerkleNode { arity: 2 }, [left, right])?;
+        }
+        let top = self.hash_n(ctx, merkle::ROOT_DOMAIN, [node, num_leaves.clone()])?;
+
+        Ok(ctx.constrain_cells_equal(&top, root)?)
+    }
+
+    /// Start a [`PoseidonChip::hash_var`] of `len` elements fed across several regions
+    ///
+    /// `len` is encoded into the initial state, so it is fixed upfront, see [`IncrementalHash`]
+    pub fn incremental_hash(
+        &self,
+        domain: Domain,
+        len: usize,
+    ) -> IncrementalHash<'_, F, T, RATE, S> {
+        IncrementalHash {
+            chip: self,
+            capacity: domain.var_length_capacity(len),
+            len,
+            absorbed: 0,
+            buf: Vec::new(),
+            state: None,
+        }
+    }
+
+    fn hash_with_capacity(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        capacity: F,
+        inputs: Vec<WrapValue<F>>,
+    ) -> Result<AssignedValue<F>, Error> {
+        let state = self.absorb_padded(ctx, SpongeState::Initial(capacity), &inputs)?;
+
+        Ok(state[1].clone())
+    }
+}
+
+/// [`PoseidonChip::hash_var`] fed by [`IncrementalHash::update`] in as many regions as needed
+///
+/// Every full `RATE` chunk is permuted right away & the state cells are copied into the next
+/// permutation, even from another region. [`IncrementalHash::finalize`] absorbs the rest with
+/// padding, so the output is the one of a single-shot [`PoseidonChip::hash_var`].
+///
+/// `update` returns the next state instead of changing `self`: a floor planner may run a region
+/// closure twice, so it must start from the same state both times. Keep the returned value out
+/// of `layouter.assign_region` & pass it to the next one.
+#[derive(Clone)]
+pub struct IncrementalHash<
+    'c,
+    F: PrimeFieldBits,
+    const T: usize,
+    const RATE: usize,
+    S = Spec<F, T, RATE>,
+> {
+    chip: &'c PoseidonChip<F, T, RATE, S>,
+    capacity: F,
+    len: usize,
+    absorbed: usize,
+    /// Absorbed values of the chunk not permuted yet
+    buf: Vec<AssignedValue<F>>,
+    /// State after the last permutation, `None` before the first one like in [`PoseidonChip`]
+    state: Option<[AssignedValue<F>; T]>,
+}
+
+impl<F: PrimeFieldBits, const T: usize, const RATE: usize, S> IncrementalHash<'_, F, T, RATE, S>
+where
+    S: PermutationSpec<F, T> + Clone,
+{
+    /// Absorb `values`, fails with [`Error::Synthesis`] if they exceed the declared length
+    pub fn update(
+        &self,
+        ctx: &mut RegionCtx<'_, '_, F>,
+        values: &[AssignedValue<F>],
+    ) -> Result<Self, Error> {
+        let absorbed = self.absorbed + values.len();
+        if absorbed > self.len {
+            error!(
+                "incremental hash of {} elements can't absorb {absorbed}",
+                self.len
+            );
+            return Err(Error::Synthesis);
+        }
+
+        let mut next = Self {
+            absorbed,
+            ..self.clone()
+        };
+        next.buf.extend_from_slice(values);
+
+        while next.buf.len() >= RATE {
+            let chunk = next
+                .buf
+                .drain(..RATE)
+                .map(WrapValue::Assigned)
+                .collect::<Vec<_>>();
+            let state = next.state();
+            next.state = Some(next.chip.permutation_with_inputs(ctx, chunk, &state)?);
         }
 
-        if exact {
-            state = self.permutation(ctx, Vec::new(), &state)?;
+        Ok(next)
+    }
+
+    /// Pad & squeeze the hash, fails with [`Error::Synthesis`] if fewer values than the declared
+    /// length were absorbed
+    pub fn finalize(&self, ctx: &mut RegionCtx<'_, '_, F>) -> Result<AssignedValue<F>, Error> {
+        if self.absorbed != self.len {
+            error!(
+                "incremental hash of {} elements finalized after {}",
+                self.len, self.absorbed
+            );
+            return Err(Error::Synthesis);
         }
 
+        let buf = self
+            .buf
+            .iter()
+            .cloned()
+            .map(WrapValue::Assigned)
+            .collect::<Vec<_>>();
+        let state = self.chip.absorb_padded(ctx, self.state(), &buf)?;
+
         Ok(state[1].clone())
     }
+
+    fn state(&self) -> SpongeState<F, T> {
+        match &self.state {
+            Some(state) => SpongeState::Assigned(state.clone()),
+            None => SpongeState::Initial(self.capacity),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{array, iter};
+
     use halo2_proofs::{
-        circuit::{Layouter, SimpleFloorPlanner},
+        circuit::{Cell, Layouter, SimpleFloorPlanner},
+        dev::{MockProver, VerifyFailure},
         plonk::{Circuit, Column, ConstraintSystem, Instance},
     };
+    use rand::{rngs::StdRng, SeedableRng};
     use tracing_test::traced_test;
 
     use super::*;
     use crate::{
         create_and_verify_proof,
+        ff::Field,
+        gadgets::ecc::EccChip,
         halo2curves::{
-            group::ff::FromUniformBytes,
-            pasta::{EqAffine, Fp},
+            group::{ff::FromUniformBytes, prime::PrimeCurveAffine},
+            pasta::{EpAffine, EqAffine, Fp, Fq},
+        },
+        main_gate::{
+            tests::{tamper, verify_gadget},
+            MainGateConfig,
+        },
+        merkle::MerkleTree,
+        poseidon::{
+            poseidon_hash::tests::{
+                DOMAINS, HASH_KEYED_KNOWN_ANSWERS, HASH_N_KNOWN_ANSWERS, HASH_VAR_KNOWN_ANSWERS,
+                TRANSCRIPT_KNOWN_ANSWERS,
+            },
+            poseidon_hash_keyed, poseidon_hash_n, poseidon_hash_var,
+            testing::{self, native_challenges, point, TranscriptOp},
+            PoseidonHash, ROTrait, Spec,
         },
-        main_gate::MainGateConfig,
-        poseidon::Spec,
         run_mock_prover_test,
+        util::fe_to_fe_safe,
     };
 
     const T: usize = 3;
@@ -451,6 +907,7 @@ mod tests {
     struct TestCircuit<F: PrimeField + PrimeFieldBits> {
         inputs: Vec<WrapValue<F>>,
         num_bits: NonZeroUsize,
+        rounds: (usize, usize),
     }
 
     impl<F: PrimeField + PrimeFieldBits> TestCircuit<F> {
@@ -461,6 +918,14 @@ mod tests {
                     .map(|v| Value::known(v).into())
                     .collect::<Vec<_>>(),
                 num_bits,
+                rounds: (R_F, R_P),
+            }
+        }
+
+        fn with_rounds(self, r_f: usize, r_p: usize) -> Self {
+            Self {
+                rounds: (r_f, r_p),
+                ..self
             }
         }
     }
@@ -473,6 +938,7 @@ mod tests {
             Self {
                 inputs: Vec::new(),
                 num_bits: NonZeroUsize::new(1).unwrap(),
+                rounds: self.rounds,
             }
         }
 
@@ -488,13 +954,14 @@ mod tests {
             config: Self::Config,
             mut layouter: impl Layouter<F>,
         ) -> Result<(), Error> {
-            let spec = Spec::<F, T, RATE>::new(R_F, R_P);
-            let mut pchip = PoseidonChip::new(config.pconfig, spec);
-            pchip.update(&self.inputs[..]);
+            let (r_f, r_p) = self.rounds;
+            let spec = Spec::<F, T, RATE>::new(r_f, r_p);
             let output = layouter.assign_region(
                 || "poseidon hash",
                 |region| {
                     let ctx = &mut RegionCtx::new(region, 0);
+                    let mut pchip = PoseidonChip::new(config.pconfig.clone(), spec.clone());
+                    pchip.update(&self.inputs[..]);
                     let bits = pchip.squeeze_n_bits(ctx, self.num_bits)?;
                     pchip.main_gate.le_bits_to_num(ctx, &bits)
                 },
@@ -538,4 +1005,1000 @@ mod tests {
 
         run_mock_prover_test!(K, circuit, public_inputs);
     }
+
+    #[test]
+    fn rounds_prove() {
+        const K: u32 = 11;
+        let (r_f, r_p) = (8, 56);
+        let inputs = (0..5u64).map(Fp::from).collect::<Vec<_>>();
+        let num_bits = NonZeroUsize::new(128).unwrap();
+
+        let spec = Spec::<Fp, T, RATE>::with_rounds(r_f, r_p).unwrap();
+        let out_hash: Fp = PoseidonHash::digest(spec, &inputs, num_bits);
+        // the circuit has to follow the rounds of its spec, not the default ones
+        let default_spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let default_hash: Fp = PoseidonHash::digest(default_spec, &inputs, num_bits);
+        assert_ne!(out_hash, default_hash);
+
+        let circuit = TestCircuit::new(inputs, num_bits).with_rounds(r_f, r_p);
+        create_and_verify_proof!(IPA, K, circuit, &[&[out_hash]], EqAffine);
+    }
+
+    fn assign_state(
+        main_gate: &MainGate<Fp, T>,
+        ctx: &mut RegionCtx<'_, '_, Fp>,
+        state: [Fp; T],
+    ) -> Result<[AssignedValue<Fp>; T], Error> {
+        Ok(state
+            .iter()
+            .map(|s| main_gate.assign_value(ctx, Value::known(*s)))
+            .collect::<Result<Vec<_>, _>>()?
+            .try_into()
+            .unwrap())
+    }
+
+    #[test]
+    fn apply_mds() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let state = [1, 2, 3].map(Fp::from);
+
+        let mds = spec.mds_matrices().mds().rows();
+        let expected = mds
+            .iter()
+            .map(|row| row.iter().zip(state.iter()).map(|(m, s)| m * s).sum())
+            .collect::<Vec<Fp>>();
+
+        assert_eq!(
+            verify_gadget::<T>(5, expected, move |main_gate, ctx| {
+                let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let state = assign_state(main_gate, ctx, state)?;
+                Ok(chip.apply_mds(ctx, &state)?.to_vec())
+            }),
+            Ok(())
+        );
+    }
+
+    fn native_full_round(state: [Fp; T], constants: &[Fp; T], mds: &[[Fp; T]; T]) -> [Fp; T] {
+        let sbox = state
+            .iter()
+            .zip(constants)
+            .map(|(s, c)| s.square().square() * s + c)
+            .collect::<Vec<_>>();
+        mds.map(|row| row.iter().zip(sbox.iter()).map(|(m, s)| m * s).sum())
+    }
+
+    #[test]
+    fn full_round() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let state = [1, 2, 3].map(Fp::from);
+        let constants = spec.constants().start()[1];
+        let mds = spec.mds_matrices().mds().rows();
+
+        let expected = native_full_round(state, &constants, &mds);
+
+        let check = |corrupted: Option<usize>| {
+            let spec = spec.clone();
+            verify_gadget::<T>(5, expected.to_vec(), move |main_gate, ctx| {
+                let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let state = assign_state(main_gate, ctx, state)?;
+                let next = chip.full_round(ctx, &state, &constants, &mds)?;
+
+                // overwrite the S-box & mix output of one row
+                if let Some(i) = corrupted {
+                    tamper(ctx, next[i].cell(), next[i].value().map(|v| *v + Fp::ONE))?;
+                }
+
+                Ok(next.to_vec())
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        for i in 0..T {
+            assert!(check(Some(i)).is_err(), "{i}");
+        }
+    }
+
+    #[test]
+    fn partial_rounds() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let init = [1, 2, 3].map(Fp::from);
+        assert_eq!(spec.constants().partial().len(), R_P);
+
+        // the partial rounds between the full rounds of the chip, against the whole permutation
+        let expected = PoseidonHash::permute(spec.clone(), init).to_vec();
+
+        assert_eq!(
+            verify_gadget::<T>(6, expected, |main_gate, ctx| {
+                let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let start = spec.constants().start();
+                let mut state =
+                    assign_state(main_gate, ctx, array::from_fn(|i| init[i] + start[0][i]))?;
+
+                let mds = spec.mds_matrices().mds().rows();
+                let pre_sparse_mds = spec.mds_matrices().pre_sparse_mds().rows();
+                for round_idx in 1..=R_F / 2 {
+                    let mds = if round_idx == R_F / 2 {
+                        &pre_sparse_mds
+                    } else {
+                        &mds
+                    };
+                    state = chip.full_round(ctx, &state, &start[round_idx], mds)?;
+                }
+
+                let constants = spec.constants().partial();
+                let sparse_matrices = spec.mds_matrices().sparse_matrices();
+                for (constant, sparse_mds) in constants.iter().zip(sparse_matrices.iter()) {
+                    state = chip.partial_round(ctx, &state, *constant, sparse_mds)?;
+                }
+
+                let end = spec.constants().end();
+                for round_idx in 0..R_F / 2 {
+                    let constants = end.get(round_idx).copied().unwrap_or([Fp::ZERO; T]);
+                    state = chip.full_round(ctx, &state, &constants, &mds)?;
+                }
+
+                Ok(state.to_vec())
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn permutation() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let state = [(); T].map(|_| Fp::random(&mut rng));
+
+        let expected = PoseidonHash::permute(spec.clone(), state);
+
+        assert_eq!(
+            verify_gadget::<T>(6, expected.to_vec(), |main_gate, ctx| {
+                let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let state = assign_state(main_gate, ctx, state)?;
+
+                let offset = ctx.offset();
+                let next = chip.permutation(ctx, &state)?;
+                assert_eq!(ctx.offset() - offset, chip.permutation_row_cost());
+
+                Ok(next.to_vec())
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn absorb() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+
+        let mut check = |len: usize, corrupted: Option<usize>| {
+            let inputs = (0..len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+            let expected = PoseidonHash::digest::<Fp>(
+                spec.clone(),
+                &inputs,
+                NonZeroUsize::new(Fp::NUM_BITS as usize).unwrap(),
+            );
+
+            let spec = spec.clone();
+            verify_gadget::<T>(8, vec![expected], move |main_gate, ctx| {
+                let mut chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+
+                let assigned = inputs
+                    .iter()
+                    .map(|input| main_gate.assign_value(ctx, Value::known(*input)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                for (i, input) in assigned.iter().enumerate() {
+                    let offset = ctx.offset();
+                    chip.absorb(ctx, input)?;
+
+                    // the permutation is laid out as soon as the rate is full
+                    let permuted = (i + 1) % RATE == 0;
+                    let rows = if permuted {
+                        chip.permutation_row_cost()
+                    } else {
+                        0
+                    };
+                    assert_eq!(ctx.offset() - offset, rows, "{i}");
+                    assert_eq!(chip.buf.is_empty(), permuted, "{i}");
+                }
+
+                // absorbed inputs are copied into the permutation
+                if let Some(i) = corrupted {
+                    tamper(ctx, assigned[i].cell(), Value::known(inputs[i] + Fp::ONE))?;
+                }
+
+                Ok(vec![chip.squeeze(ctx)?])
+            })
+        };
+
+        for len in [0, 1, 2, 5] {
+            assert_eq!(check(len, None), Ok(()), "{len}");
+        }
+        for i in [0, 3, 4] {
+            assert!(check(5, Some(i)).is_err(), "{i}");
+        }
+    }
+
+    #[test]
+    fn transcript() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let num_bits = NonZeroUsize::new(Fp::NUM_BITS as usize).unwrap();
+
+        // absorbed values, then the number of challenges squeezed after them
+        let script = [(3, 1), (0, 3), (2, 2), (1, 1)];
+        let inputs = script
+            .iter()
+            .map(|(len, _)| (0..*len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut native = PoseidonHash::new(spec.clone());
+        let mut expected = vec![];
+        for (absorbed, (_, squeezed)) in inputs.iter().zip(script) {
+            for input in absorbed {
+                native.absorb_field(*input);
+            }
+            for _ in 0..squeezed {
+                expected.push(native.output::<Fp>(num_bits));
+            }
+        }
+
+        assert_eq!(
+            verify_gadget::<T>(9, expected, move |main_gate, ctx| {
+                let mut chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+
+                let mut challenges = vec![];
+                for (absorbed, (_, squeezed)) in inputs.iter().zip(script) {
+                    for input in absorbed {
+                        let input = main_gate.assign_value(ctx, Value::known(*input))?;
+                        chip.absorb(ctx, &input)?;
+                    }
+                    challenges.extend(chip.squeeze_n(ctx, squeezed)?);
+                }
+
+                Ok(challenges)
+            }),
+            Ok(())
+        );
+    }
+
+    fn check_hash_n<const L: usize>() {
+        let (_, expected) = HASH_N_KNOWN_ANSWERS
+            .iter()
+            .find(|(len, _)| *len == L)
+            .unwrap();
+        let expected = Fp::from_str_vartime(expected).unwrap();
+
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let inputs: [Fp; L] = array::from_fn(|i| Fp::from(i as u64 + 1));
+        assert_eq!(
+            poseidon_hash_n(spec.clone(), Domain::Transcript, inputs),
+            expected
+        );
+
+        assert_eq!(
+            verify_gadget::<T>(8, vec![expected], |main_gate, ctx| {
+                let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let inputs = inputs
+                    .iter()
+                    .map(|input| main_gate.assign_value(ctx, Value::known(*input)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(vec![chip.hash_n::<L>(
+                    ctx,
+                    Domain::Transcript,
+                    inputs.try_into().unwrap(),
+                )?])
+            }),
+            Ok(()),
+            "{L}"
+        );
+    }
+
+    #[test]
+    fn hash_n() {
+        check_hash_n::<1>();
+        check_hash_n::<2>();
+        check_hash_n::<5>();
+    }
+
+    #[test]
+    fn initial_state_tampered() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let inputs = [1, 2].map(Fp::from);
+        let domain = Domain::Transcript;
+
+        // the circuit of `domain`, run on the witness of a hash in `witness_domain`
+        let check = |witness_domain: Domain| {
+            let expected = poseidon_hash_n(spec.clone(), witness_domain, inputs);
+            let spec = spec.clone();
+            verify_gadget::<T>(8, vec![expected], move |main_gate, ctx| {
+                let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let inputs = inputs
+                    .iter()
+                    .map(|input| main_gate.assign_value(ctx, Value::known(*input)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let offset = ctx.offset();
+                let hash = chip.hash_n::<2>(ctx, witness_domain, inputs.try_into().unwrap())?;
+
+                // the capacity element is folded into `rc` of the first pre-round row
+                let rc = Cell {
+                    column: main_gate.config().rc.into(),
+                    row_offset: offset,
+                    ..hash.cell()
+                };
+                tamper(
+                    ctx,
+                    rc,
+                    Value::known(spec.constants().start()[0][0] + domain.const_length_capacity(2)),
+                )?;
+
+                Ok(vec![hash])
+            })
+        };
+
+        assert_eq!(check(domain), Ok(()));
+        assert!(check(Domain::MerkleNode { arity: 2 }).is_err());
+    }
+
+    #[test]
+    fn new_with_rounds() {
+        let inputs = [1, 2].map(Fp::from);
+        let rounds = [(8, 56), (8, 57), (10, 56)];
+
+        let digests = rounds.map(|(r_f, r_p)| {
+            let spec = Spec::<Fp, T, RATE>::with_rounds(r_f, r_p).unwrap();
+            poseidon_hash_n(spec, Domain::Transcript, inputs)
+        });
+        for (i, a) in digests.iter().enumerate() {
+            for b in &digests[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+
+        for ((r_f, r_p), expected) in rounds.into_iter().zip(digests) {
+            assert_eq!(
+                verify_gadget::<T>(10, vec![expected], |main_gate, ctx| {
+                    let chip = PoseidonChip::<Fp, T, RATE>::new_with_rounds(
+                        main_gate.config().clone(),
+                        r_f,
+                        r_p,
+                    )
+                    .unwrap();
+                    let inputs = inputs
+                        .map(|input| main_gate.assign_value(ctx, Value::known(input)))
+                        .into_iter()
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    Ok(vec![chip.hash_n::<2>(
+                        ctx,
+                        Domain::Transcript,
+                        inputs.try_into().unwrap(),
+                    )?])
+                }),
+                Ok(()),
+                "{r_f} {r_p}"
+            );
+        }
+    }
+
+    #[test]
+    fn hash_var() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+
+        for (len, expected) in HASH_VAR_KNOWN_ANSWERS {
+            let inputs = (1..=len as u64).map(Fp::from).collect::<Vec<_>>();
+            let expected = Fp::from_str_vartime(expected).unwrap();
+
+            assert_eq!(
+                verify_gadget::<T>(8, vec![expected], |main_gate, ctx| {
+                    let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                    let inputs = inputs
+                        .iter()
+                        .map(|input| main_gate.assign_value(ctx, Value::known(*input)))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    let offset = ctx.offset();
+                    let hash = chip.hash_var(ctx, Domain::Transcript, &inputs)?;
+                    assert_eq!(
+                        ctx.offset() - offset,
+                        (len / RATE + 1) * chip.permutation_row_cost()
+                    );
+
+                    Ok(vec![hash])
+                }),
+                Ok(()),
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn domains() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let inputs = [1, 2, 3].map(Fp::from);
+        let num_bits = NonZeroUsize::new(Fp::NUM_BITS as usize).unwrap();
+
+        for domain in DOMAINS {
+            let expected = vec![
+                PoseidonHash::with_domain(spec.clone(), domain)
+                    .absorb_field_iter(inputs.iter().copied())
+                    .output::<Fp>(num_bits),
+                poseidon_hash_n(spec.clone(), domain, inputs),
+                poseidon_hash_var(spec.clone(), domain, &inputs),
+            ];
+
+            assert_eq!(
+                verify_gadget::<T>(9, expected, |main_gate, ctx| {
+                    let mut chip =
+                        PoseidonChip::with_domain(main_gate.config().clone(), spec.clone(), domain);
+                    let inputs = inputs
+                        .iter()
+                        .map(|input| main_gate.assign_value(ctx, Value::known(*input)))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    for input in &inputs {
+                        chip.absorb(ctx, input)?;
+                    }
+                    Ok(vec![
+                        chip.squeeze(ctx)?,
+                        chip.hash_n::<3>(ctx, domain, inputs.clone().try_into().unwrap())?,
+                        chip.hash_var(ctx, domain, &inputs)?,
+                    ])
+                }),
+                Ok(()),
+                "{domain:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn absorb_many_binding() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let inputs = [(); 3].map(|_| Fp::random(&mut rng));
+        let expected = PoseidonHash::new(spec.clone())
+            .absorb_field_iter(inputs.iter().copied())
+            .output::<Fp>(NonZeroUsize::new(Fp::NUM_BITS as usize).unwrap());
+
+        let check = |tampered: Option<usize>| {
+            verify_gadget::<T>(8, vec![expected], |main_gate, ctx| {
+                let mut chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+
+                let cells = inputs
+                    .iter()
+                    .map(|input| main_gate.assign_value(ctx, Value::known(*input)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                chip.absorb_many(ctx, &cells)?;
+                let challenge = chip.squeeze(ctx)?;
+
+                // change the upstream cell, the sponge keeps the absorbed value
+                if let Some(i) = tampered {
+                    tamper(ctx, cells[i].cell(), Value::known(inputs[i].double()))?;
+                }
+
+                Ok(vec![challenge])
+            })
+        };
+
+        assert_eq!(check(None), Ok(()));
+        for i in 0..inputs.len() {
+            let failures = check(Some(i)).unwrap_err();
+            assert!(
+                failures
+                    .iter()
+                    .all(|failure| matches!(failure, VerifyFailure::Permutation { .. })),
+                "{i}: {failures:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn transcript_known_answers() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let expected = TRANSCRIPT_KNOWN_ANSWERS
+            .iter()
+            .map(|v| Fp::from_str_vartime(v).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            verify_gadget::<T>(8, expected, |main_gate, ctx| {
+                let mut chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let inputs = (1..=4u64)
+                    .map(|input| main_gate.assign_value(ctx, Value::known(Fp::from(input))))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut challenges = chip.absorb_many(ctx, &inputs[..3])?.squeeze_n(ctx, 2)?;
+                challenges.extend(chip.absorb(ctx, &inputs[3])?.squeeze_n(ctx, 1)?);
+
+                Ok(challenges)
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn squeeze_challenge_bits() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let inputs = [(); 3].map(|_| Fp::random(&mut rng));
+
+        for num_bits in [1, 128, Fp::NUM_BITS as usize] {
+            let num_bits = NonZeroUsize::new(num_bits).unwrap();
+
+            let mut native = PoseidonHash::new(spec.clone());
+            native.absorb_field_iter(inputs.iter().copied());
+            let full = native.clone().output::<Fp>(MAX_BITS);
+            let truncated = native.output::<Fp>(num_bits);
+            let low_bits = |value: Fp| {
+                value
+                    .to_le_bits()
+                    .into_iter()
+                    .take(num_bits.get())
+                    .collect::<Vec<bool>>()
+            };
+            // the high bits are dropped, not reduced
+            assert_eq!(low_bits(truncated), low_bits(full));
+            assert_eq!(truncated == full, num_bits == MAX_BITS, "{num_bits}");
+
+            let expected = iter::once(truncated)
+                .chain(
+                    low_bits(truncated)
+                        .into_iter()
+                        .map(|bit| Fp::from(bit as u64)),
+                )
+                .collect();
+            assert_eq!(
+                verify_gadget::<T>(12, expected, |main_gate, ctx| {
+                    let mut chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                    for input in inputs {
+                        let input = main_gate.assign_value(ctx, Value::known(input))?;
+                        chip.absorb(ctx, &input)?;
+                    }
+
+                    let (bits, value) = chip.squeeze_challenge_bits(ctx, num_bits)?;
+                    assert_eq!(bits.len(), num_bits.get());
+                    Ok(iter::once(value).chain(bits).collect())
+                }),
+                Ok(()),
+                "{num_bits}"
+            );
+        }
+    }
+
+    #[test]
+    fn truncate_rows() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+
+        assert_eq!(
+            verify_gadget::<T>(10, vec![], |main_gate, ctx| {
+                let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                let value = main_gate.assign_value(ctx, Value::known(-Fp::ONE))?;
+
+                let offset = ctx.offset();
+                chip.truncate(ctx, &value, NonZeroUsize::new(128).unwrap())?;
+                // 255 bits & 85 rows composing them, 436 rows of the canonicity check over
+                // 64 bits limbs, then 43 rows composing the 128 low bits
+                assert_eq!(ctx.offset() - offset, 255 + 85 + 436 + 43);
+                Ok(vec![])
+            }),
+            Ok(())
+        );
+    }
+
+    /// [`testing::check_transcript`] of `script` with the test spec
+    fn check_transcript(k: u32, script: &[TranscriptOp<EpAffine>]) {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        assert_eq!(testing::check_transcript(k, &spec, script), Ok(()));
+    }
+
+    #[test]
+    fn transcript_lockstep() {
+        let mut rng = StdRng::seed_from_u64(0);
+        check_transcript(
+            13,
+            &[
+                TranscriptOp::Scalar(Fp::random(&mut rng)),
+                TranscriptOp::Point(point(1)),
+                TranscriptOp::Squeeze,
+                TranscriptOp::Point(EpAffine::identity()),
+                TranscriptOp::Scalar(Fp::random(&mut rng)),
+                TranscriptOp::Point(point(2)),
+                TranscriptOp::Squeeze,
+                TranscriptOp::Squeeze,
+            ],
+        );
+    }
+
+    #[test]
+    fn folding_transcript_schedule() {
+        use TranscriptOp::*;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut scalar = || Fp::random(&mut rng);
+        // one step of vanilla folding on a single transcript: the NARK challenge after the
+        // witness commitment, then `r` after the accumulator, incoming instance & cross terms
+        check_transcript(
+            14,
+            &[
+                Scalar(scalar()),
+                Point(point(1)),
+                Squeeze,
+                Point(point(2)),
+                Point(point(3)),
+                Point(point(4)),
+                Scalar(scalar()),
+                Scalar(scalar()),
+                Scalar(scalar()),
+                Point(point(1)),
+                Scalar(scalar()),
+                Scalar(scalar()),
+                Point(point(5)),
+                Squeeze,
+                Point(point(6)),
+                Squeeze,
+            ],
+        );
+    }
+
+    #[test]
+    fn transcript_reorder() {
+        use TranscriptOp::*;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let (a, b) = (Fp::random(&mut rng), Fp::random(&mut rng));
+        let script = |first: Fp, second: Fp| {
+            native_challenges(
+                &spec,
+                &[
+                    Point(point(1)),
+                    Squeeze,
+                    Scalar(first),
+                    Scalar(second),
+                    Squeeze,
+                    Point(point(2)),
+                    Squeeze,
+                    Squeeze,
+                ],
+            )
+        };
+
+        let (ordered, reordered) = (script(a, b), script(b, a));
+        // the challenge before the swap is the same, every later one differs
+        assert_eq!(ordered[0], reordered[0]);
+        for (i, (ordered, reordered)) in ordered.iter().zip(&reordered).enumerate().skip(1) {
+            assert_ne!(ordered, reordered, "{i}");
+        }
+    }
+
+    #[test]
+    fn absorb_assigned_point_encoding() {
+        let g = EpAffine::generator();
+        let points = [EpAffine::identity(), g, -g];
+
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let mut native = PoseidonHash::new(spec.clone());
+        native.absorb_point_iter(points.iter());
+        let challenge = native.squeeze::<EpAffine>(NUM_CHALLENGE_BITS);
+
+        // the cells absorbed in-circuit are the native encoding, `(0, 0)` for the identity
+        let mut expected = vec![Fp::from(0), Fp::from(0)];
+        for point in &points[1..] {
+            let coordinates = point.coordinates().unwrap();
+            expected.extend([*coordinates.x(), *coordinates.y()]);
+        }
+        expected.push(fe_to_fe_safe::<Fq, Fp>(&challenge).unwrap());
+
+        assert_eq!(
+            verify_gadget::<T>(10, expected, |main_gate, ctx| {
+                let ecc = EccChip::<EpAffine, Fp, T>::new(main_gate.config().clone());
+                let mut chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+
+                let mut outputs = vec![];
+                for point in &points {
+                    let point = ecc.assign_from_curve(ctx, || "point", point)?;
+                    chip.absorb_assigned_point(ctx, &point)?;
+                    let (x, y) = point.coordinates();
+                    outputs.extend([x.clone(), y.clone()]);
+                }
+                outputs.push(chip.squeeze_challenge(ctx)?);
+
+                Ok(outputs)
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn squeeze_n_challenges() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let inputs = (1..=3u64).map(Fp::from).collect::<Vec<_>>();
+
+        // `k` spanning one, exactly `RATE` & `RATE + 1` challenges
+        for k in [1, RATE, RATE + 1] {
+            let mut native = PoseidonHash::new(spec.clone());
+            native.absorb_field_iter(inputs.iter().copied());
+            let mut expected = native.squeeze_n_challenges::<Fp>(k, NUM_CHALLENGE_BITS);
+            // the next challenge checks the sponge states after the batch agree
+            expected.push(native.output(NUM_CHALLENGE_BITS));
+
+            assert_eq!(
+                verify_gadget::<T>(13, expected, |main_gate, ctx| {
+                    let mut chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                    for input in &inputs {
+                        let input = main_gate.assign_value(ctx, Value::known(*input))?;
+                        chip.absorb(ctx, &input)?;
+                    }
+
+                    let mut challenges = chip.squeeze_n_challenges(ctx, k)?;
+                    challenges.push(chip.squeeze_challenge(ctx)?);
+                    Ok(challenges)
+                }),
+                Ok(()),
+                "{k}"
+            );
+        }
+    }
+
+    /// Verify `leaf` against `path` of `(sibling, direction)` & the root of `tree` in-circuit
+    fn check_merkle_path(
+        tree: &MerkleTree<Fp>,
+        leaf: Fp,
+        path: &[(Fp, Fp)],
+        num_leaves: usize,
+        root: Fp,
+    ) -> Result<(), Vec<VerifyFailure>> {
+        verify_gadget::<T>(10, vec![], |main_gate, ctx| {
+            let chip = PoseidonChip::new(main_gate.config().clone(), tree.spec().clone());
+            let mut assign = |value: Fp| main_gate.assign_value(ctx, Value::known(value));
+
+            let leaf = assign(leaf)?;
+            let num_leaves = assign(Fp::from(num_leaves as u64))?;
+            let root = assign(root)?;
+            let path = path
+                .iter()
+                .map(|(sibling, direction)| Ok((assign(*sibling)?, assign(*direction)?)))
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            chip.verify_merkle_path(ctx, &leaf, &path, &num_leaves, &root)?;
+            Ok(vec![])
+        })
+    }
+
+    /// Native path of the leaf at `index`, with the direction bits
+    fn merkle_path(tree: &MerkleTree<Fp>, index: usize) -> Vec<(Fp, Fp)> {
+        let path = tree.path(index).unwrap();
+        path.siblings()
+            .iter()
+            .enumerate()
+            .map(|(height, sibling)| (*sibling, Fp::from(((index >> height) & 1) as u64)))
+            .collect()
+    }
+
+    #[test]
+    fn verify_merkle_path() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for len in [1, 2, 5, 64] {
+            let leaves = (0..len).map(|_| Fp::random(&mut rng)).collect::<Vec<_>>();
+            let tree = MerkleTree::new(Spec::new(R_F, R_P), leaves.clone()).unwrap();
+
+            for index in [0, len / 2, len - 1] {
+                let path = merkle_path(&tree, index);
+                assert_eq!(
+                    check_merkle_path(&tree, leaves[index], &path, len, tree.root()),
+                    Ok(()),
+                    "{len} {index}"
+                );
+                // the number of leaves is bound into the root
+                assert!(
+                    check_merkle_path(&tree, leaves[index], &path, len + 1, tree.root()).is_err()
+                );
+                if path.is_empty() {
+                    continue;
+                }
+
+                let mut flipped = path.clone();
+                flipped[0].1 = Fp::ONE - flipped[0].1;
+                assert!(
+                    check_merkle_path(&tree, leaves[index], &flipped, len, tree.root()).is_err()
+                );
+
+                let mut corrupted = path.clone();
+                let last = corrupted.len() - 1;
+                corrupted[last].0 += Fp::ONE;
+                assert!(
+                    check_merkle_path(&tree, leaves[index], &corrupted, len, tree.root()).is_err()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn verify_merkle_path_non_boolean_direction() {
+        let tree = MerkleTree::new(Spec::new(R_F, R_P), vec![Fp::from(1), Fp::from(2)]).unwrap();
+        let spec = tree.spec();
+        let path = merkle_path(&tree, 0);
+        assert_eq!(
+            check_merkle_path(&tree, Fp::from(1), &path, 2, tree.root()),
+            Ok(())
+        );
+
+        // direction 2 swaps `(a, b)` into `(2b - a, 2a - b)`, the root of that pair only fails
+        // on the booleanity of the direction
+        let (a, b) = (merkle::hash_leaf(spec, Fp::from(1)), path[0].0);
+        let root = merkle::hash_root(
+            spec,
+            merkle::hash_node(spec, b.double() - a, a.double() - b),
+            2,
+        );
+        let mut wrong = path;
+        wrong[0].1 = Fp::from(2);
+        assert!(check_merkle_path(&tree, Fp::from(1), &wrong, 2, root).is_err());
+    }
+
+    #[test]
+    fn hash_keyed() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+
+        for ((key, len), expected) in HASH_KEYED_KNOWN_ANSWERS {
+            let key = Fp::from(key);
+            let inputs = (1..=len as u64).map(Fp::from).collect::<Vec<_>>();
+            let expected = Fp::from_str_vartime(expected).unwrap();
+            assert_eq!(poseidon_hash_keyed(spec.clone(), key, &inputs), expected);
+
+            assert_eq!(
+                verify_gadget::<T>(9, vec![expected], |main_gate, ctx| {
+                    let chip = PoseidonChip::new(main_gate.config().clone(), spec.clone());
+                    let key = main_gate.assign_value(ctx, Value::known(key))?;
+                    let inputs = inputs
+                        .iter()
+                        .map(|input| main_gate.assign_value(ctx, Value::known(*input)))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    Ok(vec![chip.hash_keyed(ctx, &key, &inputs)?])
+                }),
+                Ok(()),
+                "{len}"
+            );
+        }
+    }
+
+    /// Hash of `inputs` by [`IncrementalHash`], updated in a region per segment, & by a
+    /// single-shot [`PoseidonChip::hash_var`], both public
+    struct IncrementalCircuit {
+        inputs: Vec<Value<Fp>>,
+        segments: Vec<usize>,
+        len: usize,
+    }
+
+    impl IncrementalCircuit {
+        fn new(segments: &[usize]) -> Self {
+            let len = segments.iter().sum();
+            Self {
+                inputs: Self::native_inputs(len)
+                    .into_iter()
+                    .map(Value::known)
+                    .collect(),
+                segments: segments.to_vec(),
+                len,
+            }
+        }
+
+        /// `1..=len`, the inputs of [`IncrementalCircuit::new`]
+        fn native_inputs(len: usize) -> Vec<Fp> {
+            (1..=len as u64).map(Fp::from).collect()
+        }
+
+        fn assign_inputs(
+            main_gate: &MainGate<Fp, T>,
+            ctx: &mut RegionCtx<'_, '_, Fp>,
+            inputs: &[Value<Fp>],
+        ) -> Result<Vec<AssignedValue<Fp>>, Error> {
+            inputs
+                .iter()
+                .map(|input| main_gate.assign_value(ctx, *input))
+                .collect()
+        }
+    }
+
+    impl Circuit<Fp> for IncrementalCircuit {
+        type Config = TestCircuitConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                inputs: vec![Value::unknown(); self.inputs.len()],
+                segments: self.segments.clone(),
+                len: self.len,
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            TestCircuit::<Fp>::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let main_gate = MainGate::<Fp, T>::new(config.pconfig.clone());
+            let chip =
+                PoseidonChip::<Fp, T, RATE>::new(config.pconfig.clone(), Spec::new(R_F, R_P));
+
+            let mut hash = chip.incremental_hash(Domain::Transcript, self.len);
+            let mut rest = &self.inputs[..];
+            for segment in &self.segments {
+                let (values, next) = rest.split_at(*segment);
+                hash = layouter.assign_region(
+                    || "update",
+                    |region| {
+                        let ctx = &mut RegionCtx::new(region, 0);
+                        let values = Self::assign_inputs(&main_gate, ctx, values)?;
+                        hash.update(ctx, &values)
+                    },
+                )?;
+                rest = next;
+            }
+
+            let incremental = layouter.assign_region(
+                || "finalize",
+                |region| hash.finalize(&mut RegionCtx::new(region, 0)),
+            )?;
+            let single_shot = layouter.assign_region(
+                || "hash_var",
+                |region| {
+                    let ctx = &mut RegionCtx::new(region, 0);
+                    let inputs = Self::assign_inputs(&main_gate, ctx, &self.inputs)?;
+                    chip.hash_var(ctx, Domain::Transcript, &inputs)
+                },
+            )?;
+
+            layouter.constrain_instance(incremental.cell(), config.instance, 0)?;
+            layouter.constrain_instance(single_shot.cell(), config.instance, 1)
+        }
+    }
+
+    #[test]
+    fn incremental_hash() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+
+        // splits mid-block, on the rate, with empty updates & with nothing to absorb at all
+        for segments in [
+            vec![],
+            vec![0],
+            vec![1],
+            vec![5],
+            vec![1, 2, 2],
+            vec![3, 0, 1],
+            vec![2, 2],
+            vec![1, 1, 1, 1, 1],
+        ] {
+            let circuit = IncrementalCircuit::new(&segments);
+            let inputs = IncrementalCircuit::native_inputs(circuit.len);
+            let expected = poseidon_hash_var(spec.clone(), Domain::Transcript, &inputs);
+
+            assert_eq!(
+                MockProver::run(10, &circuit, vec![vec![expected, expected]])
+                    .unwrap()
+                    .verify(),
+                Ok(()),
+                "{segments:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_hash_length() {
+        let mut longer = IncrementalCircuit::new(&[2, 1]);
+        longer.len = 2;
+        assert!(MockProver::run(10, &longer, vec![vec![]]).is_err());
+
+        let mut shorter = IncrementalCircuit::new(&[1, 2]);
+        shorter.len = 4;
+        assert!(MockProver::run(10, &shorter, vec![vec![]]).is_err());
+    }
 }
@@ -1,10 +1,10 @@
 use std::{iter, mem, num::NonZeroUsize};
 
 use halo2_proofs::{arithmetic::CurveAffine, halo2curves::ff::PrimeFieldBits};
-use poseidon::{self, SparseMDSMatrix};
+use poseidon::SparseMDSMatrix;
 use tracing::*;
 
-use super::Spec;
+use super::{Domain, PermutationSpec, Spec};
 use crate::{
     halo2curves::group::ff::{FromUniformBytes, PrimeField},
     poseidon::{ROConstantsTrait, ROTrait},
@@ -35,31 +35,24 @@ impl<F: PrimeField + FromUniformBytes<64>, const T: usize, const RATE: usize> St
         self.inner[0] = pow5(&self.inner[0]) + *constant;
     }
 
-    fn pre_round(&mut self, inputs: &[F], pre_constants: &[F; T]) {
+    /// Add `inputs` to the rate part, followed by a one if they don't fill it
+    fn absorb(&mut self, inputs: &[F]) {
         assert!(RATE == T - 1);
         assert!(inputs.len() <= RATE);
 
-        self.inner[0] += pre_constants[0];
-        self.inner
+        let padding = iter::once(F::ONE).chain(iter::repeat(F::ZERO));
+        for (state, input) in self.inner[1..]
             .iter_mut()
-            .zip(pre_constants.iter())
-            .skip(1)
-            .zip(inputs)
-            .for_each(|((state, constant), input)| {
-                *state = *state + *input + *constant;
-            });
-        self.inner
-            .iter_mut()
-            .zip(pre_constants.iter())
-            .skip(1 + inputs.len())
-            .enumerate()
-            .for_each(|(idx, (state, constant))| {
-                *state = if idx == 0 {
-                    *state + F::ONE + *constant
-                } else {
-                    *state + *constant
-                };
-            });
+            .zip(inputs.iter().copied().chain(padding))
+        {
+            *state += input;
+        }
+    }
+
+    fn add_constants(&mut self, constants: &[F; T]) {
+        for (state, constant) in self.inner.iter_mut().zip(constants.iter()) {
+            *state += *constant;
+        }
     }
 
     fn apply_mds(&mut self, mds: &[[F; T]; T]) {
@@ -94,6 +87,38 @@ impl<F: PrimeField + FromUniformBytes<64>, const T: usize, const RATE: usize> St
         .try_into()
         .unwrap();
     }
+
+    fn rounds(&mut self, spec: &Spec<F, T, RATE>) {
+        let r_f = spec.r_f() / 2;
+        let mds = spec.mds_matrices().mds().rows();
+        let pre_sparse_mds = spec.mds_matrices().pre_sparse_mds().rows();
+        let sparse_matrices = spec.mds_matrices().sparse_matrices();
+
+        // First half of the full rounds
+        let constants = spec.constants().start();
+        for constants in constants.iter().skip(1).take(r_f - 1) {
+            self.sbox_full(constants);
+            self.apply_mds(&mds);
+        }
+        self.sbox_full(constants.last().unwrap());
+        self.apply_mds(&pre_sparse_mds);
+
+        // Partial rounds
+        let constants = spec.constants().partial();
+        for (constant, sparse_mds) in constants.iter().zip(sparse_matrices.iter()) {
+            self.sbox_part(constant);
+            self.apply_sparse_mds(sparse_mds);
+        }
+
+        // Second half of the full rounds
+        let constants = spec.constants().end();
+        for constants in constants.iter() {
+            self.sbox_full(constants);
+            self.apply_mds(&mds);
+        }
+        self.sbox_full(&[F::ZERO; T]);
+        self.apply_mds(&mds);
+    }
 }
 
 impl<F, const T: usize, const RATE: usize> ROConstantsTrait for Spec<F, T, RATE>
@@ -105,18 +130,15 @@ where
     }
 }
 
-impl<F: PrimeField, const T: usize, const RATE: usize> ROTrait<F> for PoseidonHash<F, T, RATE>
+impl<F: PrimeField, const T: usize, const RATE: usize, S> ROTrait<F> for PoseidonHash<F, T, RATE, S>
 where
     F: PrimeFieldBits + FromUniformBytes<64>,
+    S: PermutationSpec<F, T> + ROConstantsTrait,
 {
-    type Constants = Spec<F, T, RATE>;
+    type Constants = S;
 
     fn new(constants: Self::Constants) -> Self {
-        Self {
-            spec: constants,
-            state: State::new(poseidon::State::default().words()),
-            buf: Vec::new(),
-        }
+        Self::with_spec(constants, Domain::Transcript)
     }
 
     fn absorb_field(&mut self, base: F) -> &mut Self {
@@ -151,32 +173,37 @@ where
     }
 }
 
+/// Sponge over the permutation of `S`, [`Spec`] by default
 #[derive(Clone, Debug)]
-pub struct PoseidonHash<F: PrimeField, const T: usize, const RATE: usize>
+pub struct PoseidonHash<F: PrimeField, const T: usize, const RATE: usize, S = Spec<F, T, RATE>>
 where
     F: PrimeFieldBits + FromUniformBytes<64>,
 {
-    spec: Spec<F, T, RATE>,
+    spec: S,
     state: State<F, T, RATE>,
     buf: Vec<F>,
 }
 
-impl<F: PrimeField, const T: usize, const RATE: usize> PoseidonHash<F, T, RATE>
+impl<F: PrimeField, const T: usize, const RATE: usize, S> PoseidonHash<F, T, RATE, S>
 where
     F: PrimeFieldBits + FromUniformBytes<64>,
+    S: PermutationSpec<F, T>,
 {
-    fn update(&mut self, elements: &[F]) {
-        self.buf.extend_from_slice(elements);
+    /// Empty sponge over the permutation of `spec`, with the capacity element of `domain`
+    ///
+    /// [`ROTrait::new`] is the one of [`Domain::Transcript`]
+    pub fn with_spec(spec: S, domain: Domain) -> Self {
+        let mut inner = [F::ZERO; T];
+        inner[0] = domain.sponge_capacity();
+        Self {
+            spec,
+            state: State::new(inner),
+            buf: Vec::new(),
+        }
     }
 
-    pub fn digest<F1: PrimeField>(
-        spec: Spec<F, T, RATE>,
-        elements: &[F],
-        num_bits: NonZeroUsize,
-    ) -> F1 {
-        let mut s = Self::new(spec);
-        s.update(elements);
-        s.output(num_bits)
+    fn update(&mut self, elements: &[F]) {
+        self.buf.extend_from_slice(elements);
     }
 
     pub fn output<F1: PrimeField>(&mut self, num_bits: NonZeroUsize) -> F1 {
@@ -192,54 +219,192 @@ where
             self.permutation(&[]);
         }
 
-        let output = self.state.inner[1];
-        let mut bits = fe_to_bits_le(&output);
+        Self::truncate(&self.state.inner[1], num_bits)
+    }
+
+    fn truncate<F1: PrimeField>(value: &F, num_bits: NonZeroUsize) -> F1 {
+        let mut bits = fe_to_bits_le(value);
         if bits.len() < num_bits.get() {
             bits.resize(num_bits.get(), false);
         }
         bits_to_fe_le(bits[..num_bits.get()].to_vec())
     }
 
-    fn permutation(&mut self, inputs: &[F]) {
-        let r_f = self.spec.r_f() / 2;
-        let mds = self.spec.mds_matrices().mds().rows();
-        let pre_sparse_mds = self.spec.mds_matrices().pre_sparse_mds().rows();
-        let sparse_matrices = self.spec.mds_matrices().sparse_matrices();
+    /// Squeeze `n` challenges, each the full `state[1]` of [`PoseidonHash::output`]
+    ///
+    /// Native twin of [`super::poseidon_circuit::PoseidonChip::squeeze_n`]: one permutation
+    /// per challenge, values absorbed afterwards start a new padded chunk.
+    pub fn squeeze_n(&mut self, n: usize) -> Vec<F> {
+        (0..n)
+            .map(|_| self.output(NonZeroUsize::new(F::NUM_BITS as usize).unwrap()))
+            .collect()
+    }
 
-        // First half of the full rounds
-        let constants = self.spec.constants().start();
-        self.state.pre_round(inputs, &constants[0]);
-        for constants in constants.iter().skip(1).take(r_f - 1) {
-            self.state.sbox_full(constants);
-            self.state.apply_mds(&mds);
+    /// Squeeze `k` challenges truncated to `num_bits`, reading `RATE` of them per permutation
+    ///
+    /// The first permutation is the one of [`PoseidonHash::output`] & `state[1..=RATE]` are the
+    /// first challenges, every next `RATE` ones follow an empty padded permutation, so it costs
+    /// `ceil(k / RATE)` permutations. The first challenge is the one of
+    /// [`PoseidonHash::output`], the later ones are not those of repeated outputs, which read
+    /// `state[1]` only. Native twin of
+    /// [`super::poseidon_circuit::PoseidonChip::squeeze_n_challenges`].
+    pub fn squeeze_n_challenges<F1: PrimeField>(
+        &mut self,
+        k: usize,
+        num_bits: NonZeroUsize,
+    ) -> Vec<F1> {
+        let mut challenges = Vec::with_capacity(k);
+        while challenges.len() < k {
+            let _: F = self.output(NonZeroUsize::new(F::NUM_BITS as usize).unwrap());
+            let rate = &self.state.inner[1..=RATE];
+            let left = k - challenges.len();
+            challenges.extend(
+                rate.iter()
+                    .take(left)
+                    .map(|value| Self::truncate::<F1>(value, num_bits)),
+            );
         }
-        self.state.sbox_full(constants.last().unwrap());
-        self.state.apply_mds(&pre_sparse_mds);
+        challenges
+    }
 
-        // Partial rounds
-        let constants = self.spec.constants().partial();
-        for (constant, sparse_mds) in constants.iter().zip(sparse_matrices.iter()) {
-            self.state.sbox_part(constant);
-            self.state.apply_sparse_mds(sparse_mds);
-        }
+    /// Add `inputs` & the padding to the state, then permute it
+    fn permutation(&mut self, inputs: &[F]) {
+        self.state.absorb(inputs);
+        self.state.inner = self.spec.permute(self.state.inner);
+    }
+}
 
-        // Second half of the full rounds
-        let constants = self.spec.constants().end();
-        for constants in constants.iter() {
-            self.state.sbox_full(constants);
-            self.state.apply_mds(&mds);
-        }
-        self.state.sbox_full(&[F::ZERO; T]);
-        self.state.apply_mds(&mds);
+impl<F: PrimeField, const T: usize, const RATE: usize> PoseidonHash<F, T, RATE>
+where
+    F: PrimeFieldBits + FromUniformBytes<64>,
+{
+    /// Empty sponge of [`Domain::Transcript`], same as [`ROTrait::new`]
+    pub fn new(spec: Spec<F, T, RATE>) -> Self {
+        Self::with_spec(spec, Domain::Transcript)
+    }
+
+    /// Empty sponge with the capacity element of `domain`, [`PoseidonHash::new`] is the one of
+    /// [`Domain::Transcript`]
+    pub fn with_domain(spec: Spec<F, T, RATE>, domain: Domain) -> Self {
+        Self::with_spec(spec, domain)
+    }
+
+    pub fn digest<F1: PrimeField>(
+        spec: Spec<F, T, RATE>,
+        elements: &[F],
+        num_bits: NonZeroUsize,
+    ) -> F1 {
+        let mut s = Self::new(spec);
+        s.update(elements);
+        s.output(num_bits)
     }
+
+    /// Poseidon permutation of `state` by `spec`, with nothing absorbed: the first round
+    /// constants are added without any inputs or padding
+    pub fn permute(spec: Spec<F, T, RATE>, state: [F; T]) -> [F; T] {
+        permute(&spec, state)
+    }
+}
+
+/// [`PermutationSpec::permute`] of [`Spec`], see [`PoseidonHash::permute`]
+pub(super) fn permute<F, const T: usize, const RATE: usize>(
+    spec: &Spec<F, T, RATE>,
+    state: [F; T],
+) -> [F; T]
+where
+    F: PrimeFieldBits + FromUniformBytes<64>,
+{
+    let mut state = State::<F, T, RATE>::new(state);
+    state.add_constants(&spec.constants().start()[0]);
+    state.rounds(spec);
+    state.inner
+}
+
+/// [`PoseidonHash`] sponge started from `capacity`, absorbing `inputs` & returning `state[1]`
+///
+/// Inputs are padded by the `10*` rule: a one right after them & zeros up to the rate, a whole
+/// padding chunk if they fill the rate exactly.
+fn hash_with_capacity<F, const T: usize, const RATE: usize>(
+    spec: Spec<F, T, RATE>,
+    capacity: F,
+    inputs: &[F],
+) -> F
+where
+    F: PrimeFieldBits + FromUniformBytes<64>,
+{
+    let mut hash = PoseidonHash::new(spec);
+    hash.state.inner[0] = capacity;
+    hash.update(inputs);
+    hash.output(NonZeroUsize::new(F::NUM_BITS as usize).unwrap())
+}
+
+/// Fixed-length Poseidon hash of `inputs`, the full `state[1]` after absorbing them
+///
+/// The sponge is the one of [`PoseidonHash`], only the capacity element is set to
+/// [`Domain::const_length_capacity`] of `L`, so hashes of different lengths are domain
+/// separated. In-circuit twin is [`super::poseidon_circuit::PoseidonChip::hash_n`].
+pub fn poseidon_hash_n<F, const T: usize, const RATE: usize, const L: usize>(
+    spec: Spec<F, T, RATE>,
+    domain: Domain,
+    inputs: [F; L],
+) -> F
+where
+    F: PrimeFieldBits + FromUniformBytes<64>,
+{
+    hash_with_capacity(spec, domain.const_length_capacity(L), &inputs)
+}
+
+/// Variable-length Poseidon hash of `inputs`, the full `state[1]` after absorbing them
+///
+/// The capacity element is [`Domain::var_length_capacity`] of `inputs.len()`, so inputs that
+/// only differ by trailing zeros never collide. The empty input is hashed as a single padding
+/// chunk. In-circuit twin is [`super::poseidon_circuit::PoseidonChip::hash_var`].
+pub fn poseidon_hash_var<F, const T: usize, const RATE: usize>(
+    spec: Spec<F, T, RATE>,
+    domain: Domain,
+    inputs: &[F],
+) -> F
+where
+    F: PrimeFieldBits + FromUniformBytes<64>,
+{
+    hash_with_capacity(spec, domain.var_length_capacity(inputs.len()), inputs)
+}
+
+/// Keyed Poseidon hash of `inputs` under `key`, a MAC when `key` is secret
+///
+/// `key` is absorbed into the first rate slot, before `inputs`, by a sponge with the capacity
+/// element of [`Domain::keyed_capacity`] of `inputs.len()`. No [`Domain`] reaches that
+/// capacity, so it never collides with [`poseidon_hash_var`] or [`poseidon_hash_n`] by
+/// construction. In-circuit twin is [`super::poseidon_circuit::PoseidonChip::hash_keyed`].
+pub fn poseidon_hash_keyed<F, const T: usize, const RATE: usize>(
+    spec: Spec<F, T, RATE>,
+    key: F,
+    inputs: &[F],
+) -> F
+where
+    F: PrimeFieldBits + FromUniformBytes<64>,
+{
+    let keyed = iter::once(key)
+        .chain(inputs.iter().copied())
+        .collect::<Vec<_>>();
+    hash_with_capacity(spec, Domain::keyed_capacity(inputs.len()), &keyed)
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use std::array;
+
     use tracing_test::traced_test;
 
     use super::*;
-    use crate::halo2curves::pasta::{EpAffine, Fp, Fq};
+    use crate::{
+        constants::NUM_CHALLENGE_BITS,
+        halo2curves::{
+            group::prime::PrimeCurveAffine,
+            pasta::{EpAffine, EqAffine, Fp, Fq},
+        },
+        util::modulus,
+    };
 
     #[traced_test]
     #[test]
@@ -260,4 +425,348 @@ mod tests {
             Fq::from_str_vartime("277726250230731218669330566268314254439").unwrap()
         );
     }
+
+    /// `poseidon_hash_n` of `1..=L` for `T = 3, RATE = 2, R_F = 4, R_P = 3`
+    pub(crate) const HASH_N_KNOWN_ANSWERS: [(usize, &str); 3] = [
+        (
+            1,
+            "11838012651834579569082539792862662971708431143879090100909995452124535448408",
+        ),
+        (
+            2,
+            "23434936586762810467499755501751512949798714175389453936467708160579263199502",
+        ),
+        (
+            5,
+            "12730630939745813317722666506302917456293292577648942855654447773984374575740",
+        ),
+    ];
+
+    /// `poseidon_hash_var` of `1..=len` for `T = 3, RATE = 2, R_F = 4, R_P = 3`
+    pub(crate) const HASH_VAR_KNOWN_ANSWERS: [(usize, &str); 4] = [
+        (
+            0,
+            "27310505765401175767217382450707728063944610968410766316476569672454719489182",
+        ),
+        (
+            1,
+            "23213804004593374489569162729915122572893537122211385429178683915373026194109",
+        ),
+        (
+            2,
+            "4751671277116734466366481912117478710209956049414847085439748682790194808303",
+        ),
+        (
+            3,
+            "11385055279214678152886780731194736469812413259543982068522701159834118348316",
+        ),
+    ];
+
+    fn check_hash_n<const L: usize>() {
+        let (_, expected) = HASH_N_KNOWN_ANSWERS
+            .iter()
+            .find(|(len, _)| *len == L)
+            .unwrap();
+
+        let inputs: [Fp; L] = array::from_fn(|i| Fp::from(i as u64 + 1));
+        assert_eq!(
+            poseidon_hash_n(Spec::<Fp, 3, 2>::new(4, 3), Domain::Transcript, inputs),
+            Fp::from_str_vartime(expected).unwrap(),
+        );
+    }
+
+    #[test]
+    fn hash_n() {
+        check_hash_n::<1>();
+        check_hash_n::<2>();
+        check_hash_n::<5>();
+    }
+
+    #[test]
+    fn hash_n_domain_separation() {
+        fn check<const L: usize>() {
+            let spec = Spec::<Fp, 3, 2>::new(4, 3);
+
+            // the same elements hashed as a fixed length input & by the sponge
+            let inputs: [Fp; L] = array::from_fn(|i| Fp::from(i as u64 + 1));
+            assert_ne!(
+                poseidon_hash_n(spec.clone(), Domain::Transcript, inputs),
+                PoseidonHash::digest::<Fp>(
+                    spec,
+                    &inputs,
+                    NonZeroUsize::new(Fp::NUM_BITS as usize).unwrap()
+                ),
+                "{L}"
+            );
+        }
+
+        check::<1>();
+        check::<2>();
+    }
+
+    #[test]
+    fn hash_var() {
+        let spec = Spec::<Fp, 3, 2>::new(4, 3);
+
+        for (len, expected) in HASH_VAR_KNOWN_ANSWERS {
+            let inputs = (1..=len as u64).map(Fp::from).collect::<Vec<_>>();
+            let hash = poseidon_hash_var(spec.clone(), Domain::Transcript, &inputs);
+            assert_eq!(hash, Fp::from_str_vartime(expected).unwrap(), "{len}");
+
+            let extended = inputs.iter().copied().chain(iter::once(Fp::from(0)));
+            assert_ne!(
+                hash,
+                poseidon_hash_var(
+                    spec.clone(),
+                    Domain::Transcript,
+                    &extended.collect::<Vec<_>>()
+                ),
+                "{len}"
+            );
+        }
+
+        // the same elements hashed as a fixed & variable length input
+        let inputs = [Fp::from(1), Fp::from(2)];
+        assert_ne!(
+            poseidon_hash_var(spec.clone(), Domain::Transcript, &inputs),
+            poseidon_hash_n(spec, Domain::Transcript, inputs)
+        );
+    }
+
+    /// Transcript of `T = 3, RATE = 2, R_F = 4, R_P = 3`: absorb `1, 2, 3`, squeeze two
+    /// challenges, absorb `4` & squeeze one more
+    pub(crate) const TRANSCRIPT_KNOWN_ANSWERS: [&str; 3] = [
+        "12423016053453710316247484459536058278092769802172847923883988848632334029723",
+        "21395767628406857276594823562964664019548506447390690746770556889655821373001",
+        "22814111721402260255647705995701487971250778407759251615756437872421813887062",
+    ];
+
+    #[test]
+    fn transcript() {
+        let mut ro = PoseidonHash::new(Spec::<Fp, 3, 2>::new(4, 3));
+
+        let mut challenges = ro.absorb_field_iter((1..=3u64).map(Fp::from)).squeeze_n(2);
+        challenges.extend(ro.absorb_field(Fp::from(4)).squeeze_n(1));
+
+        assert_eq!(
+            challenges,
+            TRANSCRIPT_KNOWN_ANSWERS.map(|v| Fp::from_str_vartime(v).unwrap())
+        );
+    }
+
+    /// [`poseidon_hash_keyed`] of `1..=len` by `(key, len)`
+    pub(crate) const HASH_KEYED_KNOWN_ANSWERS: [((u64, usize), &str); 4] = [
+        (
+            (1, 0),
+            "20618724077515313556016567183688921751401411696233290149640261073428142073441",
+        ),
+        (
+            (1, 1),
+            "25911993442923596189522062771727165078163758539025671703389552520029702697581",
+        ),
+        (
+            (1, 3),
+            "28911083046213419398855939783389278543715488871684629736936620000116127327571",
+        ),
+        (
+            (2, 3),
+            "2130449132088412789876727014663837460042781026866186710862029554740111179530",
+        ),
+    ];
+
+    /// One domain of each kind, for cross-domain tests
+    pub(crate) const DOMAINS: [Domain; 4] = [
+        Domain::Transcript,
+        Domain::MerkleNode { arity: 2 },
+        Domain::Custom(0),
+        Domain::Custom(1),
+    ];
+
+    #[test]
+    fn domains() {
+        let spec = Spec::<Fp, 3, 2>::new(4, 3);
+        let inputs = [1, 2, 3].map(Fp::from);
+
+        let outputs = DOMAINS
+            .iter()
+            .map(|domain| {
+                let sponge = PoseidonHash::with_domain(spec.clone(), *domain)
+                    .absorb_field_iter(inputs.iter().copied())
+                    .output::<Fp>(NonZeroUsize::new(Fp::NUM_BITS as usize).unwrap());
+                [
+                    sponge,
+                    poseidon_hash_n(spec.clone(), *domain, inputs),
+                    poseidon_hash_var(spec.clone(), *domain, &inputs),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        for (i, a) in outputs.iter().enumerate() {
+            for b in &outputs[i + 1..] {
+                for (a, b) in a.iter().zip(b) {
+                    assert_ne!(a, b);
+                }
+            }
+        }
+    }
+
+    fn new_transcript() -> PoseidonHash<Fp, 3, 2> {
+        PoseidonHash::new(Spec::new(4, 3))
+    }
+
+    #[test]
+    fn transcript_order() {
+        let [p, q] = [1, 2].map(|k| EpAffine::from(EpAffine::generator() * Fq::from(k)));
+
+        let challenge = |first: &EpAffine, second: &EpAffine| {
+            new_transcript()
+                .absorb_field(Fp::from(3))
+                .absorb_point(first)
+                .absorb_point(second)
+                .squeeze::<EpAffine>(NUM_CHALLENGE_BITS)
+        };
+
+        assert_eq!(challenge(&p, &q), challenge(&p, &q));
+        assert_ne!(challenge(&p, &q), challenge(&q, &p));
+    }
+
+    #[test]
+    fn transcript_identity_point() {
+        let challenge = |absorb: &dyn Fn(&mut PoseidonHash<Fp, 3, 2>)| {
+            let mut ro = new_transcript();
+            absorb(&mut ro);
+            ro.squeeze::<EpAffine>(NUM_CHALLENGE_BITS)
+        };
+
+        // the identity has no coordinates, it's absorbed as `(0, 0)`
+        let identity = challenge(&|ro| {
+            ro.absorb_point(&EpAffine::identity());
+        });
+        assert_eq!(
+            identity,
+            challenge(&|ro| {
+                ro.absorb_field(Fp::from(0)).absorb_field(Fp::from(0));
+            })
+        );
+        assert_ne!(identity, challenge(&|_| {}));
+        assert_ne!(
+            identity,
+            challenge(&|ro| {
+                ro.absorb_point(&EpAffine::generator());
+            })
+        );
+    }
+
+    fn check_point_encoding<C: CurveAffine>()
+    where
+        C::Base: PrimeFieldBits + FromUniformBytes<64>,
+    {
+        // `(0, 0)` is not on the curve, it's left for the identity
+        assert!(bool::from(
+            C::from_xy(C::Base::ZERO, C::Base::ZERO).is_none()
+        ));
+
+        // no point has `y = 0`: `x^3 = -b` has no root, as `-b` is not a cube
+        let p = modulus::<C::Base>();
+        assert_eq!((&p % 3u32).to_u64_digits(), vec![1]);
+        let exp = ((p - 1u32) / 3u32).to_u64_digits();
+        assert_ne!((-C::b()).pow_vartime(exp), C::Base::ONE);
+
+        let challenge = |absorb: &dyn Fn(&mut PoseidonHash<C::Base, 3, 2>)| {
+            let mut ro = PoseidonHash::new(Spec::new(4, 3));
+            absorb(&mut ro);
+            ro.squeeze::<C>(NUM_CHALLENGE_BITS)
+        };
+
+        let g = C::generator();
+        let points = [C::identity(), g, (g * C::Scalar::from(2)).into(), -g];
+        let challenges = points.map(|point| {
+            let by_point = challenge(&|ro| {
+                ro.absorb_point(&point);
+            });
+            let coordinates: Option<_> = point.coordinates().map(|c| (*c.x(), *c.y())).into();
+            let (x, y) = coordinates.unwrap_or((C::Base::ZERO, C::Base::ZERO));
+            assert_eq!(
+                by_point,
+                challenge(&|ro| {
+                    ro.absorb_field(x).absorb_field(y);
+                })
+            );
+            by_point
+        });
+
+        for (i, a) in challenges.iter().enumerate() {
+            for b in &challenges[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn point_encoding() {
+        check_point_encoding::<EpAffine>();
+        check_point_encoding::<EqAffine>();
+    }
+
+    #[test]
+    fn squeeze_n_challenges() {
+        let squeezes = |batched: usize, single: usize| {
+            let mut ro = new_transcript();
+            ro.absorb_field_iter((1..=3u64).map(Fp::from));
+            let mut challenges = ro.squeeze_n_challenges::<Fp>(batched, NUM_CHALLENGE_BITS);
+            for _ in 0..single {
+                challenges.push(ro.output(NUM_CHALLENGE_BITS));
+            }
+            challenges
+        };
+
+        let all = squeezes(3, 0);
+        assert_ne!(all[0], all[1]);
+        assert_ne!(all[1], all[2]);
+        // the first challenge is the one of a single squeeze
+        assert_eq!(all[0], squeezes(0, 1)[0]);
+
+        // one permutation for `k = 1` & `k = RATE`, two for `k = RATE + 1`
+        for (k, permutations) in [(1, 1), (2, 1), (3, 2)] {
+            let batched = squeezes(k, 1);
+            assert_eq!(batched[..k], all[..k], "{k}");
+            assert_eq!(
+                batched[k],
+                squeezes(0, permutations + 1)[permutations],
+                "{k}"
+            );
+        }
+    }
+
+    #[test]
+    fn hash_keyed() {
+        let spec = Spec::<Fp, 3, 2>::new(4, 3);
+
+        for ((key, len), expected) in HASH_KEYED_KNOWN_ANSWERS {
+            let (key, inputs) = (
+                Fp::from(key),
+                (1..=len as u64).map(Fp::from).collect::<Vec<_>>(),
+            );
+            let hash = poseidon_hash_keyed(spec.clone(), key, &inputs);
+            assert_eq!(hash, Fp::from_str_vartime(expected).unwrap(), "{len}");
+
+            // the key & message laid out as a plain input don't give the keyed hash
+            let keyed = iter::once(key)
+                .chain(inputs.iter().copied())
+                .collect::<Vec<_>>();
+            for domain in DOMAINS {
+                assert_ne!(hash, poseidon_hash_var(spec.clone(), domain, &keyed));
+                assert_ne!(hash, poseidon_hash_var(spec.clone(), domain, &inputs));
+            }
+        }
+
+        let message = [Fp::from(7), Fp::from(8)];
+        let keys = [0, 1, 2].map(Fp::from);
+        let hashes = keys.map(|key| poseidon_hash_keyed(spec.clone(), key, &message));
+        for (i, a) in hashes.iter().enumerate() {
+            for b in &hashes[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
 }
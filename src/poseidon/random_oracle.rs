@@ -45,7 +45,10 @@ pub trait ROTrait<F: PrimeField> {
         self
     }
 
-    /// Adds a point to the internal state
+    /// Adds a point to the internal state as its affine `(x, y)`, the identity as `(0, 0)`
+    ///
+    /// The encoding is injective on curves `y^2 = x^3 + b` with `b != 0`, such as the pasta
+    /// curves: `(0, 0)` is not on the curve, so the identity can't collide with a point.
     fn absorb_point<C: CurveAffine<Base = F>>(&mut self, p: &C) -> &mut Self;
 
     fn absorb_point_iter<'item, C: CurveAffine<Base = F>>(
@@ -103,7 +106,7 @@ pub trait ROCircuitTrait<F: PrimeFieldBits + FromUniformBytes<64>> {
     /// Returns a challenge of `num_bits` by hashing the internal state
     fn squeeze_n_bits(
         &mut self,
-        ctx: &mut RegionCtx<'_, F>,
+        ctx: &mut RegionCtx<'_, '_, F>,
         num_bits: NonZeroUsize,
     ) -> Result<Vec<AssignedBit<F>>, Error>;
 }
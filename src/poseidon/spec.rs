@@ -1,4 +1,4 @@
-use std::ops;
+use std::{cmp, ops};
 
 use serde::Serialize;
 
@@ -7,13 +7,64 @@ use crate::ff::{FromUniformBytes, PrimeField};
 #[derive(Clone, Debug)]
 pub struct Spec<F: PrimeField, const T: usize, const RATE: usize>(pub poseidon::Spec<F, T, RATE>);
 
+/// Security level targeted by [`Spec::with_rounds`], in bits
+pub const SECURITY_BITS: usize = 128;
+
+/// Minimal number of full rounds accepted by [`Spec::with_rounds`], the statistical bound
+pub const MIN_FULL_ROUNDS: usize = 6;
+
+/// Round numbers rejected by [`Spec::with_rounds`]
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SpecError {
+    #[error("number of full rounds must be even, got {r_f}")]
+    OddFullRounds { r_f: usize },
+    #[error("{r_f} full rounds are insecure, at least {min} required")]
+    TooFewFullRounds { r_f: usize, min: usize },
+    #[error("{rounds} rounds in total are insecure, at least {min} required")]
+    TooFewRounds { rounds: usize, min: usize },
+}
+
 impl<F: PrimeField, const T: usize, const RATE: usize> Spec<F, T, RATE>
 where
     F: FromUniformBytes<64>,
 {
+    /// Constants & MDS matrices for `r_f` full & `r_p` partial rounds, without any checks
     pub fn new(r_f: usize, r_p: usize) -> Self {
         Self(poseidon::Spec::new(r_f, r_p))
     }
+
+    /// [`Spec::new`] for round numbers that meet the `x^5` security bounds of the Poseidon paper
+    ///
+    /// `r_f` must be even & at least [`MIN_FULL_ROUNDS`] (statistical attacks), and `r_f + r_p`
+    /// must be at least [`Spec::min_rounds`] (interpolation attacks). The bounds carry no
+    /// security margin, the caller adds it.
+    pub fn with_rounds(r_f: usize, r_p: usize) -> Result<Self, SpecError> {
+        if r_f % 2 != 0 {
+            return Err(SpecError::OddFullRounds { r_f });
+        }
+        if r_f < MIN_FULL_ROUNDS {
+            return Err(SpecError::TooFewFullRounds {
+                r_f,
+                min: MIN_FULL_ROUNDS,
+            });
+        }
+        let min = Self::min_rounds();
+        if r_f + r_p < min {
+            return Err(SpecError::TooFewRounds {
+                rounds: r_f + r_p,
+                min,
+            });
+        }
+
+        Ok(Self::new(r_f, r_p))
+    }
+
+    /// `ceil(log_5(2) * min(SECURITY_BITS, F::NUM_BITS)) + ceil(log_5(T))`
+    pub fn min_rounds() -> usize {
+        let log_5 = |x: f64| x.ln() / 5f64.ln();
+        let bits = cmp::min(SECURITY_BITS, F::NUM_BITS as usize) as f64;
+        (log_5(2.0) * bits).ceil() as usize + log_5(T as f64).ceil() as usize
+    }
 }
 
 impl<F: PrimeField, const T: usize, const RATE: usize> ops::Deref for Spec<F, T, RATE> {
@@ -133,7 +184,7 @@ mod tests {
     use tracing_test::traced_test;
 
     use super::*;
-    use crate::halo2curves::bn256::Fr;
+    use crate::halo2curves::{bn256::Fr, pasta::Fp};
 
     #[traced_test]
     #[test]
@@ -141,4 +192,28 @@ mod tests {
         let spec = Spec::<Fr, 10, 9>::new(10, 10);
         bincode::serialize(&spec).unwrap();
     }
+
+    #[test]
+    fn with_rounds() {
+        type S = Spec<Fp, 3, 2>;
+        assert_eq!(S::min_rounds(), 57);
+
+        assert!(S::with_rounds(8, 56).is_ok());
+        assert!(S::with_rounds(6, 51).is_ok());
+        assert_eq!(
+            S::with_rounds(7, 56).unwrap_err(),
+            SpecError::OddFullRounds { r_f: 7 }
+        );
+        assert_eq!(
+            S::with_rounds(4, 60).unwrap_err(),
+            SpecError::TooFewFullRounds { r_f: 4, min: 6 }
+        );
+        assert_eq!(
+            S::with_rounds(8, 48).unwrap_err(),
+            SpecError::TooFewRounds {
+                rounds: 56,
+                min: 57
+            }
+        );
+    }
 }
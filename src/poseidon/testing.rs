@@ -0,0 +1,303 @@
+//! Harness checking [`PoseidonHash`] & [`PoseidonChip`] squeeze the same challenges
+//!
+//! A script of [`TranscriptOp`] runs through the off-circuit [`ROTrait`] sponge & through a
+//! [`MockProver`] circuit with the chip, whose challenges are the public inputs. Any drift of
+//! the two sponges fails [`check_transcript`]. Compiled for tests & with the `testing` feature.
+
+use std::marker::PhantomData;
+
+use halo2_proofs::{
+    arithmetic::CurveAffine,
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    dev::{MockProver, VerifyFailure},
+    plonk::{Circuit, Column, ConstraintSystem, Error, Instance},
+};
+use proptest::prelude::*;
+
+use super::{poseidon_circuit::PoseidonChip, PoseidonHash, ROTrait, Spec};
+use crate::{
+    constants::NUM_CHALLENGE_BITS,
+    ff::{FromUniformBytes, PrimeFieldBits},
+    gadgets::ecc::EccChip,
+    main_gate::{MainGate, MainGateConfig, RegionCtx},
+    util::fe_to_fe_safe,
+};
+
+/// One step of a transcript script
+#[derive(Clone, Debug)]
+pub enum TranscriptOp<C: CurveAffine> {
+    Scalar(C::Base),
+    Point(C),
+    /// Squeeze a challenge of [`NUM_CHALLENGE_BITS`]
+    Squeeze,
+}
+
+/// `k * G` for the generator `G` of `C`, the identity for `k = 0`
+pub fn point<C: CurveAffine>(k: u64) -> C {
+    (C::generator() * C::Scalar::from(k)).into()
+}
+
+/// Challenges of `script` squeezed by the off-circuit [`PoseidonHash`], in the base field
+pub fn native_challenges<C, const T: usize, const RATE: usize>(
+    spec: &Spec<C::Base, T, RATE>,
+    script: &[TranscriptOp<C>],
+) -> Vec<C::Base>
+where
+    C: CurveAffine,
+    C::Base: PrimeFieldBits + FromUniformBytes<64>,
+{
+    let mut native = PoseidonHash::new(spec.clone());
+    let mut challenges = vec![];
+    for op in script {
+        match op {
+            TranscriptOp::Scalar(scalar) => {
+                native.absorb_field(*scalar);
+            }
+            TranscriptOp::Point(point) => {
+                native.absorb_point(point);
+            }
+            TranscriptOp::Squeeze => {
+                let challenge = native.squeeze::<C>(NUM_CHALLENGE_BITS);
+                challenges.push(
+                    fe_to_fe_safe(&challenge)
+                        .expect("challenge of `NUM_CHALLENGE_BITS` fits the base field"),
+                );
+            }
+        }
+    }
+    challenges
+}
+
+#[derive(Clone, Debug)]
+pub struct TranscriptCircuitConfig<const T: usize> {
+    main_gate: MainGateConfig<T>,
+    instance: Column<Instance>,
+}
+
+/// Circuit running a script through [`PoseidonChip`], the challenges are its public inputs
+#[derive(Clone, Debug)]
+pub struct TranscriptCircuit<C: CurveAffine, const T: usize, const RATE: usize>
+where
+    C::Base: PrimeFieldBits + FromUniformBytes<64>,
+{
+    spec: Spec<C::Base, T, RATE>,
+    script: Vec<TranscriptOp<C>>,
+    _p: PhantomData<C>,
+}
+
+impl<C: CurveAffine, const T: usize, const RATE: usize> TranscriptCircuit<C, T, RATE>
+where
+    C::Base: PrimeFieldBits + FromUniformBytes<64>,
+{
+    pub fn new(spec: Spec<C::Base, T, RATE>, script: Vec<TranscriptOp<C>>) -> Self {
+        Self {
+            spec,
+            script,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<C: CurveAffine, const T: usize, const RATE: usize> Circuit<C::Base>
+    for TranscriptCircuit<C, T, RATE>
+where
+    C::Base: PrimeFieldBits + FromUniformBytes<64>,
+{
+    type Config = TranscriptCircuitConfig<T>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<C::Base>) -> Self::Config {
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+
+        let constants = meta.fixed_column();
+        meta.enable_constant(constants);
+
+        Self::Config {
+            main_gate: MainGate::configure_with_instance(meta, instance),
+            instance,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<C::Base>,
+    ) -> Result<(), Error> {
+        let main_gate = MainGate::<C::Base, T>::new(config.main_gate.clone());
+        let ecc = EccChip::<C, C::Base, T>::new(config.main_gate.clone());
+
+        let challenges = layouter.assign_region(
+            || "transcript",
+            |region| {
+                let ctx = &mut RegionCtx::new(region, 0);
+                let mut chip = PoseidonChip::new(config.main_gate.clone(), self.spec.clone());
+
+                let mut challenges = vec![];
+                for op in &self.script {
+                    match op {
+                        TranscriptOp::Scalar(scalar) => {
+                            let scalar = main_gate.assign_value(ctx, Value::known(*scalar))?;
+                            chip.absorb(ctx, &scalar)?;
+                        }
+                        TranscriptOp::Point(point) => {
+                            let point = ecc.assign_from_curve(ctx, || "point", point)?;
+                            chip.absorb_assigned_point(ctx, &point)?;
+                        }
+                        TranscriptOp::Squeeze => challenges.push(chip.squeeze_challenge(ctx)?),
+                    }
+                }
+
+                Ok(challenges)
+            },
+        )?;
+
+        for (row, challenge) in challenges.iter().enumerate() {
+            layouter.constrain_instance(challenge.cell(), config.instance, row)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Run `script` through both sponges, `Ok` if the chip squeezes the off-circuit challenges
+///
+/// `k` must fit the script: a squeeze costs a bit decomposition on top of the permutations
+pub fn check_transcript<C, const T: usize, const RATE: usize>(
+    k: u32,
+    spec: &Spec<C::Base, T, RATE>,
+    script: &[TranscriptOp<C>],
+) -> Result<(), Vec<VerifyFailure>>
+where
+    C: CurveAffine,
+    C::Base: PrimeFieldBits + FromUniformBytes<64>,
+{
+    let expected = native_challenges(spec, script);
+
+    MockProver::run(
+        k,
+        &TranscriptCircuit::new(spec.clone(), script.to_vec()),
+        vec![expected],
+    )
+    .expect("synthesize of transcript circuit failed")
+    .verify()
+}
+
+/// Fixed scripts every sponge pair must agree on, by name
+///
+/// Covers the empty input, a single element, inputs spanning several `RATE` blocks, the
+/// identity & squeezes with nothing absorbed in between
+pub fn fixed_scripts<C: CurveAffine>() -> Vec<(&'static str, Vec<TranscriptOp<C>>)> {
+    use TranscriptOp::*;
+
+    let scalar = |v: u64| Scalar(C::Base::from(v));
+
+    vec![
+        ("empty", vec![Squeeze]),
+        ("single", vec![scalar(1), Squeeze]),
+        (
+            "multi block",
+            vec![
+                scalar(1),
+                scalar(2),
+                scalar(3),
+                scalar(4),
+                scalar(5),
+                Squeeze,
+            ],
+        ),
+        (
+            "points",
+            vec![Point(point(1)), Point(C::identity()), Squeeze],
+        ),
+        (
+            "duplex",
+            vec![
+                scalar(1),
+                Squeeze,
+                Squeeze,
+                Point(point(2)),
+                scalar(3),
+                Squeeze,
+            ],
+        ),
+    ]
+}
+
+/// Random [`TranscriptOp`], the identity is drawn on its own as `point(0)` is rare
+pub fn arb_op<C: CurveAffine>() -> impl Strategy<Value = TranscriptOp<C>>
+where
+    C::Base: FromUniformBytes<64>,
+{
+    prop_oneof![
+        (any::<[u8; 32]>(), any::<[u8; 32]>()).prop_map(|(low, high)| {
+            let mut bytes = [0u8; 64];
+            bytes[..32].copy_from_slice(&low);
+            bytes[32..].copy_from_slice(&high);
+            TranscriptOp::Scalar(C::Base::from_uniform_bytes(&bytes))
+        }),
+        any::<u64>().prop_map(|k| TranscriptOp::Point(point(k))),
+        Just(TranscriptOp::Point(C::identity())),
+        Just(TranscriptOp::Squeeze),
+    ]
+}
+
+/// Random script of up to `max_len` ops, ending with a squeeze so there's always a challenge
+pub fn arb_script<C: CurveAffine>(max_len: usize) -> impl Strategy<Value = Vec<TranscriptOp<C>>>
+where
+    C::Base: FromUniformBytes<64>,
+{
+    prop::collection::vec(arb_op::<C>(), 0..max_len).prop_map(|mut script| {
+        script.push(TranscriptOp::Squeeze);
+        script
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo2curves::pasta::{EpAffine, Fp};
+
+    const T: usize = 3;
+    const RATE: usize = 2;
+    const R_F: usize = 4;
+    const R_P: usize = 3;
+
+    #[test]
+    fn fixed() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        for (name, script) in fixed_scripts::<EpAffine>() {
+            assert_eq!(check_transcript(13, &spec, &script), Ok(()), "{name}");
+        }
+    }
+
+    #[test]
+    fn drift_is_caught() {
+        let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+        let script = vec![TranscriptOp::Scalar(Fp::from(1)), TranscriptOp::Squeeze];
+
+        let mut wrong = native_challenges(&spec, &script);
+        wrong[0] += Fp::from(1);
+        let prover = MockProver::run(
+            13,
+            &TranscriptCircuit::<EpAffine, T, RATE>::new(spec, script),
+            vec![wrong],
+        )
+        .unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(8))]
+
+        #[test]
+        fn random_scripts(script in arb_script::<EpAffine>(12)) {
+            let spec = Spec::<Fp, T, RATE>::new(R_F, R_P);
+            prop_assert_eq!(check_transcript(14, &spec, &script), Ok(()));
+        }
+    }
+}
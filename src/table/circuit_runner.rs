@@ -5,7 +5,7 @@ use super::{circuit_data::CircuitData, ConstraintSystemMetainfo, WitnessCollecto
 use crate::{
     ff::PrimeField,
     plonk::{self, PlonkStructure},
-    polynomial::sparse::SparseMatrix,
+    polynomial::{sparse::SparseMatrix, LiftError},
     util::batch_invert_assigned,
 };
 
@@ -36,13 +36,18 @@ impl<F: PrimeField, CT: Circuit<F>> CircuitRunner<F, CT> {
     #[instrument(name = "circuit_collect_plonk_struct", skip_all)]
     pub fn try_collect_plonk_structure(&self) -> Result<PlonkStructure<F>, Error> {
         debug!("start build metainfo");
+        let lift_error = |err: LiftError| {
+            error!("while lift the constraint system: {err}");
+            Error::Synthesis
+        };
         let ConstraintSystemMetainfo {
             num_challenges,
             round_sizes,
             gates,
             custom_gates_lookup_compressed,
+            num_instance,
             ..
-        } = ConstraintSystemMetainfo::build(self.k as usize, &self.cs);
+        } = ConstraintSystemMetainfo::build(self.k as usize, &self.cs).map_err(lift_error)?;
         debug!("meta info is ready");
 
         debug!("start preprocessing");
@@ -59,12 +64,14 @@ impl<F: PrimeField, CT: Circuit<F>> CircuitRunner<F, CT> {
             selectors,
             fixed_columns,
             num_advice_columns: self.cs.num_advice_columns(),
+            num_instance,
             num_challenges,
             round_sizes,
             custom_gates_lookup_compressed,
             gates,
             permutation_matrix,
-            lookup_arguments: plonk::lookup::Arguments::compress_from(&self.cs),
+            lookup_arguments: plonk::lookup::Arguments::compress_from(&self.cs)
+                .map_err(lift_error)?,
         })
     }
 
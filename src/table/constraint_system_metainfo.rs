@@ -1,16 +1,18 @@
-use halo2_proofs::plonk::ConstraintSystem;
+use halo2_proofs::plonk::{ConstraintSystem, Expression as PE};
 use tracing::*;
 
 use crate::{
     ff::PrimeField,
     plonk::{lookup, CompressedGates},
-    polynomial::{expression::QueryIndexContext, Expression},
+    polynomial::{expression::QueryIndexContext, Expression, LiftError},
 };
 
 pub(crate) struct ConstraintSystemMetainfo<F: PrimeField> {
     pub num_challenges: usize,
     pub round_sizes: Vec<usize>,
     pub folding_degree: usize,
+    /// see [`QueryIndexContext::num_instance`]
+    pub num_instance: usize,
     pub gates: Vec<Expression<F>>,
     pub custom_gates_lookup_compressed: CompressedGates<F>,
 }
@@ -18,14 +20,16 @@ pub(crate) struct ConstraintSystemMetainfo<F: PrimeField> {
 impl<F: PrimeField> ConstraintSystemMetainfo<F> {
     /// The separation of this function from circuit_info is to remove dependency on [`PlonkStructure`]
     /// it is used to kickstart the Folding Circuit initialization
+    ///
+    /// Fails if a lookup queries an instance column
     pub(crate) fn build(
         k_table_size: usize,
         cs: &ConstraintSystem<F>,
-    ) -> ConstraintSystemMetainfo<F> {
+    ) -> Result<ConstraintSystemMetainfo<F>, LiftError> {
         let num_gates: usize = cs.gates().iter().map(|gate| gate.polynomials().len()).sum();
         info!("start build constraint system metainfo with {num_gates} custom gates");
 
-        let (num_lookups, has_vector_lookup, lookup_exprs) = lookup::Arguments::compress_from(cs)
+        let (num_lookups, has_vector_lookup, lookup_exprs) = lookup::Arguments::compress_from(cs)?
             .as_ref()
             .map(|arg| {
                 (
@@ -45,14 +49,6 @@ impl<F: PrimeField> ConstraintSystemMetainfo<F> {
             }
         );
 
-        let gates = cs
-            .gates()
-            .iter()
-            .flat_map(|gate| gate.polynomials().iter())
-            .map(|expr| Expression::from_halo2_expr(expr, cs.num_selectors, cs.num_fixed_columns()))
-            .chain(lookup_exprs)
-            .collect::<Vec<_>>();
-
         // we have at most 3 prover rounds
         let nrow = 1 << k_table_size;
 
@@ -79,6 +75,14 @@ impl<F: PrimeField> ConstraintSystemMetainfo<F> {
             round_sizes.push(cs.num_advice_columns() * nrow);
         };
 
+        // instance columns get fold variables only if a gate queries them, public inputs that
+        // are just copied from the instance column keep the layout of the gates unchanged
+        let gates_query_instance = cs
+            .gates()
+            .iter()
+            .flat_map(|gate| gate.polynomials())
+            .any(queries_instance);
+
         // we use r3 to combine all custom gates and lookup expressions
         // find the challenge index of r3
         let mut ctx = QueryIndexContext {
@@ -93,18 +97,38 @@ impl<F: PrimeField> ConstraintSystemMetainfo<F> {
             } else {
                 0
             },
+            num_instance: if gates_query_instance {
+                cs.num_instance_columns()
+            } else {
+                0
+            },
         };
 
+        let gates = Expression::lift_all_gates_with_instance(cs, &ctx)?
+            .into_iter()
+            .chain(lookup_exprs)
+            .collect::<Vec<_>>();
+
         let custom_gates_lookup_compressed = CompressedGates::new(&gates, &mut ctx);
 
         let folding_degree = custom_gates_lookup_compressed.grouped().len();
 
-        ConstraintSystemMetainfo {
+        Ok(ConstraintSystemMetainfo {
             num_challenges: custom_gates_lookup_compressed.compressed().num_challenges(),
             round_sizes,
             folding_degree,
+            num_instance: ctx.num_instance,
             gates,
             custom_gates_lookup_compressed,
-        }
+        })
+    }
+}
+
+fn queries_instance<F: PrimeField>(expr: &PE<F>) -> bool {
+    match expr {
+        PE::Instance(_) => true,
+        PE::Negated(a) | PE::Scaled(a, _) => queries_instance(a),
+        PE::Sum(a, b) | PE::Product(a, b) => queries_instance(a) || queries_instance(b),
+        _ => false,
     }
 }